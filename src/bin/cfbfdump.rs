@@ -19,14 +19,45 @@ along with this program.  If not, see <http://www.gnu.org/licenses/>.
 #[macro_use] // enable value_t! macro
 extern crate clap;
 extern crate evrecovery;
+#[cfg(feature = "shell")]
+extern crate rustyline;
 
-use std::io::{Read, Seek, Write, stdin, stdout, stderr};
-use std::io::Error;
-use std::fs::File;
-use clap::{Arg, App, AppSettings, SubCommand, ArgMatches};
-use evrecovery::cfbf::{Container, Object, ObjectResult, ObjectType};
+use std::io::{Read, Seek, Write, Cursor, sink, stdin, stdout, stderr};
+use std::io::{Error, ErrorKind};
+use std::collections::HashMap;
+use std::fs::{File, create_dir_all};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use clap::{Arg, ArgGroup, App, AppSettings, SubCommand, ArgMatches};
+use evrecovery::catalogue::{csv_escape, json_escape};
+use evrecovery::cfbf::{Chain, ChainEnd, Container, Object, ObjectResult, ObjectType, follow_chain};
+use evrecovery::cfbf::structures::{MAXREGSECT, DIFSECT, FATSECT, ENDOFCHAIN, FREESECT};
 use evrecovery::io::SeekableRead;
-use evrecovery::io::Debug;
+use evrecovery::io::{Debug, escape_file_name, format_guid, sanitize_file_name, set_dir_modified, unique_path};
+use evrecovery::embedded::{EmbeddedObject, ObjectKind, MAX_DEPTH, escape_name, find_objects};
+use evrecovery::exchange::mime::format_mailbox;
+use evrecovery::metadata::{decode_string, filetime_to_unix, format_timestamp};
+use evrecovery::metadata::interpret::interpret;
+use evrecovery::msg::Message;
+use evrecovery::propset::PropertySetStream;
+use evrecovery::restore::open_payload;
+use evrecovery::sniff::sniff;
+use evrecovery::vba::read_projects;
+use evrecovery::msg::properties::{Properties, property_name, type_name};
+#[cfg(feature = "shell")]
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+#[cfg(feature = "shell")]
+use rustyline::completion::{Completer, Pair};
+#[cfg(feature = "shell")]
+use rustyline::error::ReadlineError;
+#[cfg(feature = "shell")]
+use rustyline::highlight::Highlighter;
+#[cfg(feature = "shell")]
+use rustyline::hint::Hinter;
+#[cfg(feature = "shell")]
+use rustyline::history::DefaultHistory;
+#[cfg(feature = "shell")]
+use rustyline::validate::Validator;
 
 trait ReadSeek: Read + Seek { }
 impl<T> ReadSeek for T where T: Read + Seek { }
@@ -44,6 +75,65 @@ fn main() {
 			.takes_value(false))
 		.subcommand(SubCommand::with_name("list")
 			.about("Lists all files contained in the CFBF file. Each output line represents a file, and contains the internal file ID and the file path, separated by a space.")
+			.arg(Arg::with_name("long")
+				.short("l")
+				.long("long")
+				.help("If set, the type, size, location (ministream or normal sectors), starting sector, CLSID and creation and modification times are listed as well.")
+				.takes_value(false)
+				.required(false))
+			.arg(Arg::with_name("format")
+				.value_name("FORMAT")
+				.help("The output format: 'text' (one line per file), 'csv' (comma-separated values with a header line) or 'json' (an array of objects). The 'csv' and 'json' formats always hold all columns.")
+				.long("format")
+				.possible_values(&["text", "csv", "json"])
+				.default_value("text"))
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("tree")
+			.about("Prints the storages and streams of the CFBF file as a tree.")
+			.arg(Arg::with_name("long")
+				.short("l")
+				.long("long")
+				.help("If set, the internal file ID and size of every stream are printed as well.")
+				.takes_value(false)
+				.required(false))
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("header")
+			.about("Prints all fields of the CFBF header, including the DIFAT entries that follow it.")
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("fat")
+			.about("Prints the FAT and the miniFAT: the next sector of every sector (or its special value, such as FREESECT, ENDOFCHAIN, FATSECT or DIFSECT), and the stream or structure that the sector belongs to.")
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("chain")
+			.about("Prints the sector chains of the directory, the miniFAT, the ministream and all streams, and reports chains that are broken, cyclic or do not match the size of their stream.")
+			.arg(Arg::with_name("id")
+				.value_name("STREAMID")
+				.help("Only prints the sector chain of the stream with this ID.")
+				.long("id")
+				.required(false))
 			.arg(Arg::with_name("input")
 				.value_name("FILE")
 				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
@@ -52,11 +142,17 @@ fn main() {
 				.required(false))
 		)
 		.subcommand(SubCommand::with_name("dump")
-			.about("Dumps a stream from the CFBF file.")
+			.about("Dumps a stream from the CFBF file, by its ID or by its path.")
 			.arg(Arg::with_name("id")
 				.value_name("STREAMID")
 				.help("The ID of the stream that shall be dumped.")
-				.long("id")
+				.long("id"))
+			.arg(Arg::with_name("path")
+				.value_name("PATH")
+				.help("The path of the streams that shall be dumped (e.g. '/User Information/*/User Archivable Item/Title'). In each path segment, '?' matches a single character and '*' matches any sequence of characters; a segment '**' matches any number of storages. Names are matched case-insensitively, and control characters are written as '\\001' etc. (as in the manifest of 'extract-all'), or as they are. All matching streams are dumped.")
+				.long("path"))
+			.group(ArgGroup::with_name("stream")
+				.args(&["id", "path"])
 				.required(true))
 			.arg(Arg::with_name("output")
 				.value_name("FILE")
@@ -64,6 +160,17 @@ fn main() {
 				.short("o")
 				.long("output")
 				.required(false))
+			.arg(Arg::with_name("output-dir")
+				.value_name("DIR")
+				.help("Writes every matching stream to a file of its own, beneath this directory, which mirrors the CFBF path (escaped as with 'extract-all').")
+				.long("output-dir")
+				.conflicts_with("output")
+				.required(false))
+			.arg(Arg::with_name("decode-string")
+				.long("decode-string")
+				.help("If set, the streams are decoded as length-prefixed UTF-16 strings (as used by Enterprise Vault, e.g. in 'Title' streams) and written as UTF-8 text, followed by a line break. Streams that are not in this format are written as they are.")
+				.takes_value(false)
+				.required(false))
 			.arg(Arg::with_name("input")
 				.value_name("FILE")
 				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
@@ -71,6 +178,129 @@ fn main() {
 				.long("input")
 				.required(false))
 		)
+		.subcommand(SubCommand::with_name("decode")
+			.about("Tries all known encodings (length-prefixed UTF-16 strings, XML, GUIDs, FILETIMEs, UNIX timestamps, text and integers) on streams of the CFBF file, and prints the candidate interpretations next to an annotated hexdump.")
+			.arg(Arg::with_name("id")
+				.value_name("STREAMID")
+				.help("The ID of the stream that shall be decoded.")
+				.long("id"))
+			.arg(Arg::with_name("path")
+				.value_name("PATH")
+				.help("The path of the streams that shall be decoded, with glob segments (see 'dump --path'), e.g. '/User Information/**'.")
+				.long("path"))
+			.group(ArgGroup::with_name("stream")
+				.args(&["id", "path"])
+				.required(true))
+			.arg(Arg::with_name("max-bytes")
+				.value_name("N")
+				.help("The maximum number of bytes of every stream that are shown in the hexdump (256 by default).")
+				.long("max-bytes")
+				.required(false))
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF), or a DVS file (whose payload is decompressed first). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("extract-all")
+			.about("Writes all storages and streams of the CFBF file into a directory tree that mirrors the CFBF tree, and outputs a manifest (one tab-separated line per storage or stream: ID, type, size, CLSID, creation time, modification time, CFBF path and file path).")
+			.arg(Arg::with_name("output")
+				.value_name("DIR")
+				.help("The directory that represents the root storage. Control characters, characters that are not allowed in file names and '%' are escaped as '%XX' (e.g. '\x05SummaryInformation' becomes '%05SummaryInformation').")
+				.short("o")
+				.long("output")
+				.required(true))
+			.arg(Arg::with_name("manifest")
+				.value_name("FILE")
+				.help("The file where the manifest shall be written to. If this parameter is not specified (or has the value '-'), the manifest will be written to STDOUT instead.")
+				.long("manifest")
+				.required(false))
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("extract")
+			.about("Finds the objects embedded in the CFBF file (packaged files, OOXML packages, embedded storages in 'ObjectPool' and 'MBD*' storages, and streams holding whole CFBF files), and lists or extracts them.")
+			.arg(Arg::with_name("output")
+				.value_name("DIR")
+				.help("The directory where the embedded objects shall be extracted to. Packaged files keep their original file names; embedded storages are written as directories holding their streams. If omitted, the objects are only listed.")
+				.short("o")
+				.long("output")
+				.required(false))
+			.arg(Arg::with_name("recursive")
+				.short("r")
+				.long("recursive")
+				.help("If set, embedded objects that are CFBF files themselves are searched for embedded objects as well.")
+				.takes_value(false)
+				.required(false))
+			.arg(Arg::with_name("max-depth")
+				.value_name("N")
+				.help("The maximum number of nested CFBF files that are searched (with --recursive).")
+				.long("max-depth")
+				.requires("recursive")
+				.required(false))
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("vba")
+			.about("Finds the VBA projects in the CFBF file (in 'VBA' storages, such as '/Macros/VBA' or '/_VBA_PROJECT_CUR/VBA'), and lists or exports the source code of their modules.")
+			.arg(Arg::with_name("output")
+				.value_name("DIR")
+				.help("The directory where the modules shall be written to, as '<module name>.bas' (standard modules) or '<module name>.cls' (document and class modules). If omitted, the modules are only listed.")
+				.short("o")
+				.long("output")
+				.required(false))
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("props")
+			.about("Decodes all OLE property set streams (such as '\\x05SummaryInformation' and '\\x05DocumentSummaryInformation') in the CFBF file, and prints their properties.")
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("msg")
+			.about("Decodes an Outlook message (.msg file): prints its subject, sender, recipients and attachments, or converts it into an RFC 5322 message (.eml file).")
+			.arg(Arg::with_name("properties")
+				.long("properties")
+				.help("If set, all MAPI properties of the message, its recipients and its attachments are printed.")
+				.takes_value(false)
+				.required(false))
+			.arg(Arg::with_name("eml")
+				.value_name("FILE")
+				.help("Converts the message into an RFC 5322 message (with all attachments), and writes it to this file ('-' for STDOUT).")
+				.long("eml")
+				.conflicts_with("properties")
+				.required(false))
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("An Outlook message (.msg file). If omitted, the file will be read from STDIN instead.")
+				.short("i")
+				.long("input")
+				.required(false))
+		)
+		.subcommand(SubCommand::with_name("shell")
+			.about("Opens an interactive shell for browsing the CFBF file, with the commands ls, cd, pwd, stat, cat, hexdump, strings, decode and extract (and tab completion of names, if built with the 'shell' feature).")
+			.arg(Arg::with_name("input")
+				.value_name("FILE")
+				.help("A file in Compound File Binary File Format (CFBF), or a DVS file (whose payload is decompressed first).")
+				.required(true))
+		)
 	.get_matches();
 
 	let verbose = matches.occurrences_of("verbose") as i8;
@@ -85,27 +315,74 @@ fn main() {
 fn dispatch(matches: ArgMatches, debug: &mut Debug) -> Result<(), Error> {
 	match matches.subcommand() {
 		("list", Some(submatches)) => dispatch_list(submatches, debug),
+		("tree", Some(submatches)) => dispatch_tree(submatches, debug),
+		("header", Some(submatches)) => dispatch_header(submatches, debug),
+		("fat", Some(submatches)) => dispatch_fat(submatches, debug),
+		("chain", Some(submatches)) => dispatch_chain(submatches, debug),
 		("dump", Some(submatches)) => dispatch_dump(submatches, debug),
+		("decode", Some(submatches)) => dispatch_decode(submatches, debug),
+		("extract", Some(submatches)) => dispatch_extract(submatches, debug),
+		("extract-all", Some(submatches)) => dispatch_extract_all(submatches, debug),
+		("props", Some(submatches)) => dispatch_props(submatches, debug),
+		("vba", Some(submatches)) => dispatch_vba(submatches, debug),
+		("msg", Some(submatches)) => dispatch_msg(submatches, debug),
+		("shell", Some(submatches)) => dispatch_shell(submatches, debug),
 		_ => panic!("Unrecognised subcommand"),
 	}
 }
 
 fn dispatch_list(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
 	let inputfile = matches.value_of("input").unwrap_or("");
+	let long = matches.occurrences_of("long") > 0;
 	let input: Box<ReadSeek> = match inputfile {
 		"" | "-" => Box::new(SeekableRead::new(stdin())?),
 		_ => Box::new(File::open(inputfile).unwrap())
 	};
 	let mut container = Container::new(input, debug)?;
 	let root = container.get_root_object(debug)?;
-	list_recursive(&mut container, &root, &mut String::from(""), debug)?;
+	let format = matches.value_of("format").unwrap();
+	// The plain list keeps the order of the directory entries
+	if format == "text" && !long {
+		return list_recursive(&mut container, &root, &mut String::from(""), debug);
+	}
+	let mut objects = Vec::new();
+	collect_recursive(&mut container, root, "", &mut objects, debug)?;
+
+	// Control characters in the paths are escaped, except in JSON (which escapes them by itself)
+	match format {
+		"csv" => {
+			println!("{}", LIST_COLUMNS.join(","));
+			for (path, object) in &objects {
+				let fields = list_fields(&container, object, &escape_name(path));
+				println!("{}", fields.iter().map(|field| field.as_ref().map(|field| csv_escape(field)).unwrap_or_default()).collect::<Vec<String>>().join(","));
+			}
+		},
+		"json" => {
+			println!("[");
+			for (index, (path, object)) in objects.iter().enumerate() {
+				let fields = list_fields(&container, object, path).iter().zip(LIST_COLUMNS.iter()).map(|(field, column)| format!("{}:{}", json_escape(column), match field {
+					None => "null".to_owned(),
+					Some(field) if ["id", "size", "start_sector"].contains(column) => field.clone(),
+					Some(field) => json_escape(field),
+				})).collect::<Vec<String>>();
+				println!("{{{}}}{}", fields.join(","), if index + 1 < objects.len() { "," } else { "" });
+			}
+			println!("]");
+		},
+		_ => {
+			for (path, object) in &objects {
+				let fields = list_fields(&container, object, &escape_name(path)).into_iter().map(|field| field.unwrap_or_else(|| "-".to_owned())).collect::<Vec<String>>();
+				println!("{:>5} {:<7} {:>10} {:<10} {:>12} {:<38} {:<20} {:<20} {}", fields[0], fields[1], fields[2], fields[3], fields[4], fields[5], fields[6], fields[7], fields[8]);
+			}
+		},
+	}
 	Ok(())
 }
 
 fn list_recursive<TFile>(container: &mut Container<TFile>, object: &Object, pathprefix: &mut String, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
 	let mut path = pathprefix.clone();
 	if object.object_type != ObjectType::RootStorage {
-		path.push_str("/");
+		path.push('/');
 		path.push_str(&object.name);
 	}
 
@@ -113,7 +390,7 @@ fn list_recursive<TFile>(container: &mut Container<TFile>, object: &Object, path
 	print!("{} {}", object.id, path);
 	match object.object_type {
 		ObjectType::Storage | ObjectType::RootStorage => println!("/"),
-		ObjectType::Stream | _ => println!(),
+		_ => println!(),
 	}
 
 	// Output left sibling, if it exists
@@ -134,22 +411,1174 @@ fn list_recursive<TFile>(container: &mut Container<TFile>, object: &Object, path
 	Ok(())
 }
 
+/// The columns of the `list` output in the CSV and JSON formats (and, in this order, in the long text format).
+const LIST_COLUMNS: [&str; 9] = ["id", "type", "size", "location", "start_sector", "clsid", "created", "modified", "path"];
+
+/// Collects an object and (if it is a storage) all objects beneath it, depth-first, along with their paths.
+fn collect_recursive<TFile>(container: &mut Container<TFile>, object: Object, path: &str, result: &mut Vec<(String, Object)>, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+	let children = match object.object_type {
+		ObjectType::Storage | ObjectType::RootStorage => container.get_children(&object, debug)?,
+		_ => Vec::new(),
+	};
+	result.push((path.to_owned(), object));
+	for child in children {
+		let child_path = format!("{}/{}", path, child.name);
+		collect_recursive(container, child, &child_path, result, debug)?;
+	}
+	Ok(())
+}
+
+/// Formats the values of the `LIST_COLUMNS` for an object; values that do not apply to the object are `None`.
+fn list_fields<TFile>(container: &Container<TFile>, object: &Object, path: &str) -> Vec<Option<String>> where TFile: Read + Seek {
+	let timestamp = |filetime: u64| filetime_to_unix(filetime).filter(|_| filetime != 0).map(format_timestamp);
+	let has_content = object.object_type == ObjectType::RootStorage || object.object_type == ObjectType::Stream;
+	vec![
+		Some(object.id.to_string()),
+		Some(match object.object_type {
+			ObjectType::RootStorage => "root",
+			ObjectType::Storage => "storage",
+			ObjectType::Stream => "stream",
+			ObjectType::Unknown => "unknown",
+		}.to_owned()),
+		Some(object.stream_size.to_string()).filter(|_| has_content),
+		Some(if container.is_in_ministream(object) { "mini" } else { "normal" }.to_owned()).filter(|_| has_content),
+		Some(object.starting_sector_location.to_string()).filter(|_| has_content && object.starting_sector_location <= 0xFFFFFFFA),
+		Some(format_guid(&object.clsid)).filter(|_| object.clsid != [0; 16]),
+		timestamp(object.creation_time),
+		timestamp(object.modified_time),
+		Some(if path.is_empty() { "/".to_owned() } else { path.to_owned() }),
+	]
+}
+
+fn dispatch_tree(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let long = matches.occurrences_of("long") > 0;
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
+	};
+	let mut container = Container::new(input, debug)?;
+	let root = container.get_root_object(debug)?;
+	println!("{}/", root.name);
+	tree_recursive(&mut container, &root, "", long, debug)
+}
+
+/// Prints the children of a storage as branches of a tree (storages first, each sorted by name), indented by the given prefix.
+fn tree_recursive<TFile>(container: &mut Container<TFile>, storage: &Object, prefix: &str, long: bool, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+	let mut children = container.get_children(storage, debug)?;
+	children.sort_by_key(|child| (child.object_type != ObjectType::Storage, child.name.to_uppercase()));
+	let count = children.len();
+	for (index, child) in children.into_iter().enumerate() {
+		let is_last = index + 1 == count;
+		let branch = if is_last { "└─ " } else { "├─ " };
+		match child.object_type {
+			ObjectType::Storage => {
+				println!("{}{}{}/", prefix, branch, escape_name(&child.name));
+				let child_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+				tree_recursive(container, &child, &child_prefix, long, debug)?;
+			},
+			_ if long => println!("{}{}{} (#{}, {} bytes)", prefix, branch, escape_name(&child.name), child.id, child.stream_size),
+			_ => println!("{}{}{}", prefix, branch, escape_name(&child.name)),
+		}
+	}
+	Ok(())
+}
+
+fn dispatch_header(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
+	};
+	let mut container = Container::new(input, debug)?;
+	let difat = container.get_header_difat()?;
+	let header = container.header();
+	println!("Signature:                    {:#018X} ({})", header.signature, if header.signature == 0xE11AB1A1E011CFD0 { "valid" } else { "invalid" });
+	println!("CLSID:                        {}", format_guid(&header.clsid));
+	println!("Minor version:                {:#06X}", header.minor_version);
+	println!("Major version:                {:#06X}", header.major_version);
+	println!("Byte order:                   {:#06X}{}", header.byte_order, if header.byte_order == 0xFFFE { " (little endian)" } else { "" });
+	println!("Sector shift:                 {} ({} bytes)", header.sector_shift, header.sector_size);
+	println!("Mini sector shift:            {} ({} bytes)", header.mini_sector_shift, header.mini_sector_size);
+	println!("Number of directory sectors:  {}", header.number_of_directory_sectors);
+	println!("Number of FAT sectors:        {}", header.number_of_fat_sectors);
+	println!("First directory sector:       {}", format_sector(header.first_directory_sector_location.0));
+	println!("Transaction signature number: {}", header.transaction_signature_number);
+	println!("Mini stream cutoff size:      {}", header.mini_stream_cutoff_size);
+	println!("First miniFAT sector:         {}", format_sector(header.first_mini_fat_sector_location.0));
+	println!("Number of miniFAT sectors:    {}", header.number_of_mini_fat_sectors);
+	println!("First DIFAT sector:           {}", format_sector(header.first_difat_sector_location.0));
+	println!("Number of DIFAT sectors:      {}", header.number_of_difat_sectors);
+	println!("DIFAT:");
+	print_table(&difat, |_| None);
+	Ok(())
+}
+
+fn dispatch_fat(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
+	};
+	let mut container = Container::new(input, debug)?;
+	let tables = Tables::read(&mut container, debug)?;
+
+	// Determine which stream or structure every sector and mini-sector belongs to
+	let mut owners = vec![None; tables.fat.len()];
+	let mut mini_owners = vec![None; tables.mini_fat.len()];
+	for (name, chain, is_mini) in tables.chains(&container) {
+		let owners = if is_mini { &mut mini_owners } else { &mut owners };
+		for &sector in &chain.sectors {
+			owners[sector as usize] = Some(name.clone());
+		}
+	}
+	for &(sectors, name) in &[(&tables.fat_sectors, "FAT"), (&tables.difat_sectors, "DIFAT")] {
+		for &sector in sectors {
+			if let Some(owner) = owners.get_mut(sector as usize) {
+				*owner = Some(name.to_owned());
+			}
+		}
+	}
+
+	println!("FAT ({} sectors, {} entries, {} free):", tables.fat_sectors.len(), tables.fat.len(), tables.fat.iter().filter(|&&next| next == FREESECT).count());
+	print_table(&tables.fat, |sector| owners[sector].clone());
+	println!("miniFAT ({} entries, {} free):", tables.mini_fat.len(), tables.mini_fat.iter().filter(|&&next| next == FREESECT).count());
+	print_table(&tables.mini_fat, |sector| mini_owners[sector].clone());
+	Ok(())
+}
+
+fn dispatch_chain(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let id = matches.value_of("id").map(|_| value_t!(matches, "id", u32).unwrap_or_else(|e| e.exit()));
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
+	};
+	let mut container = Container::new(input, debug)?;
+	let tables = Tables::read(&mut container, debug)?;
+	let header = container.header();
+	for (index, (name, chain, is_mini)) in tables.chains(&container).into_iter().enumerate() {
+		// The first three chains are the directory, the miniFAT and the ministream; all others belong to the streams, in order
+		let object = index.checked_sub(3).map(|index| &tables.streams[index].1);
+		if id.is_some() && object.map(|object| object.id) != id {
+			continue;
+		}
+		let sector_size = if is_mini { header.mini_sector_size } else { header.sector_size };
+		println!("{} ({}{} sectors): {}", name, if is_mini { "mini, " } else { "" }, chain.sectors.len(), format_chain(&chain.sectors));
+		match chain.end {
+			ChainEnd::EndOfChain => {},
+			ChainEnd::Loop(sector) => println!("  WARNING: the chain loops back to sector {}", sector),
+			ChainEnd::Invalid(next) => println!("  WARNING: the chain is broken; it leads to {}", format_sector(next)),
+		}
+		let expected = match object {
+			Some(object) => object.stream_size.div_ceil(sector_size),
+			None if index == 1 => header.number_of_mini_fat_sectors as u64,
+			None if index == 2 => tables.root.stream_size.div_ceil(sector_size),
+			None => chain.sectors.len() as u64,
+		};
+		if expected != chain.sectors.len() as u64 {
+			println!("  WARNING: expected {} sectors", expected);
+		}
+	}
+	Ok(())
+}
+
+/// The allocation tables of a CFBF file, along with the objects whose chains they hold.
+struct Tables {
+	fat: Vec<u32>,
+	mini_fat: Vec<u32>,
+	fat_sectors: Vec<u32>,
+	difat_sectors: Vec<u32>,
+	root: Object,
+	streams: Vec<(String, Object)>,
+}
+
+impl Tables {
+	fn read<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Tables, Error> where TFile: Read + Seek {
+		let fat = container.get_fat(debug)?;
+		let mini_fat = container.get_mini_fat(&fat, debug)?;
+		let fat_sectors = container.get_fat_sectors(debug)?;
+		let difat_sectors = container.get_difat_sectors(debug)?;
+		let root = container.get_root_object(debug)?;
+		let mut objects = Vec::new();
+		collect_recursive(container, root.clone(), "", &mut objects, debug)?;
+		let streams = objects.into_iter().filter(|(_, object)| object.object_type == ObjectType::Stream).collect();
+		Ok(Tables { fat, mini_fat, fat_sectors, difat_sectors, root, streams })
+	}
+
+	/// Follows the chains of the directory, the miniFAT, the ministream and all streams (in this order).
+	/// Every chain is named, and flagged if it is a chain of mini-sectors.
+	fn chains<TFile>(&self, container: &Container<TFile>) -> Vec<(String, Chain, bool)> where TFile: Read + Seek {
+		let header = container.header();
+		let mut result = vec![
+			("directory".to_owned(), follow_chain(&self.fat, header.first_directory_sector_location.0), false),
+			("miniFAT".to_owned(), follow_chain(&self.fat, header.first_mini_fat_sector_location.0), false),
+			("ministream".to_owned(), follow_chain(&self.fat, self.root.starting_sector_location), false),
+		];
+		for (path, object) in &self.streams {
+			let is_mini = container.is_in_ministream(object);
+			let table = if is_mini { &self.mini_fat } else { &self.fat };
+			result.push((format!("#{} {}", object.id, escape_name(path)), follow_chain(table, object.starting_sector_location), is_mini));
+		}
+		result
+	}
+}
+
+/// Formats a sector number, or the name of a special value.
+fn format_sector(sector: u32) -> String {
+	match sector {
+		FREESECT => "FREESECT".to_owned(),
+		ENDOFCHAIN => "ENDOFCHAIN".to_owned(),
+		FATSECT => "FATSECT".to_owned(),
+		DIFSECT => "DIFSECT".to_owned(),
+		sector if sector > MAXREGSECT => format!("{:#010X}", sector),
+		sector => sector.to_string(),
+	}
+}
+
+/// Formats a chain of sectors, abbreviating consecutive sectors as ranges (e.g. `3-7, 12, 9`).
+fn format_chain(sectors: &[u32]) -> String {
+	let mut ranges: Vec<(u32, u32)> = Vec::new();
+	for &sector in sectors {
+		match ranges.last_mut() {
+			Some(range) if range.1.checked_add(1) == Some(sector) => range.1 = sector,
+			_ => ranges.push((sector, sector)),
+		}
+	}
+	ranges.iter().map(|&(first, last)| if first == last { first.to_string() } else { format!("{}-{}", first, last) }).collect::<Vec<String>>().join(", ")
+}
+
+/// Prints the entries of an allocation table with their index and owner, one per line.
+/// Runs of unowned entries with the same value (typically `FREESECT`) are printed as a single line.
+fn print_table<F>(table: &[u32], owner: F) where F: Fn(usize) -> Option<String> {
+	let mut index = 0;
+	while index < table.len() {
+		let count = match owner(index) {
+			Some(owner_name) => {
+				println!("{:>10}  {:<10}  {}", index, format_sector(table[index]), owner_name);
+				1
+			},
+			None => {
+				let count = table[index..].iter().enumerate().take_while(|&(offset, &next)| next == table[index] && owner(index + offset).is_none()).count();
+				let indices = if count > 1 { format!("{}-{}", index, index + count - 1) } else { index.to_string() };
+				println!("{:>10}  {}", indices, format_sector(table[index]));
+				count
+			},
+		};
+		index += count;
+	}
+}
+
 fn dispatch_dump(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
 	let inputfile = matches.value_of("input").unwrap_or("");
 	let outputfile = matches.value_of("output").unwrap_or("");
-	let id = value_t!(matches, "id", u32).unwrap_or_else(|e| e.exit());
+	let output_dir = matches.value_of("output-dir").map(PathBuf::from);
+	let decode = matches.occurrences_of("decode-string") > 0;
 
 	let input: Box<ReadSeek> = match inputfile {
 		"" | "-" => Box::new(SeekableRead::new(stdin())?),
 		_ => Box::new(File::open(inputfile).unwrap())
 	};
-	let mut output: Box<Write> = match outputfile {
+	let mut container = Container::new(input, debug)?;
+	let objects = select_streams(&mut container, matches, debug)?;
+
+	match output_dir {
+		Some(output_dir) => {
+			for (path, object) in objects {
+				let target = path.split('/').filter(|segment| !segment.is_empty()).fold(output_dir.clone(), |target, segment| target.join(escape_file_name(&unescape_name(segment))));
+				if let Some(parent) = target.parent() {
+					create_dir_all(parent)?;
+				}
+				debug.logln(1, format!("Writing {} to {:?} ...", path, target));
+				let mut output = File::create(&target)?;
+				dump_object(&mut container, &object, decode, &mut output, debug)?;
+			}
+		},
+		None => {
+			let mut output: Box<dyn Write> = match outputfile {
+				"" | "-" => Box::new(stdout()),
+				_ => Box::new(File::create(outputfile).unwrap())
+			};
+			for (path, object) in objects {
+				debug.logln(1, format!("Dumping {} ...", path));
+				dump_object(&mut container, &object, decode, &mut output, debug)?;
+			}
+		},
+	}
+	Ok(())
+}
+
+/// Determines the streams given by the `--id` or `--path` argument, along with their (escaped) paths.
+fn select_streams<TFile>(container: &mut Container<TFile>, matches: &ArgMatches, debug: &mut Debug) -> Result<Vec<(String, Object)>, Error> where TFile: Read + Seek {
+	match matches.value_of("path") {
+		Some(path) => {
+			let segments = path.split('/').filter(|segment| !segment.is_empty()).map(escape_name).collect::<Vec<String>>();
+			let mut objects = Vec::new();
+			let root = container.get_root_object(debug)?;
+			find_by_pattern(container, &root, "", &segments, &mut objects, debug)?;
+			if objects.is_empty() {
+				return Err(Error::new(ErrorKind::NotFound, format!("No stream matches the path '{}'", path)));
+			}
+			Ok(objects)
+		},
+		None => {
+			let id = value_t!(matches, "id", u32).unwrap_or_else(|e| e.exit());
+			let object = container.get_object(id, debug)?;
+			Ok(vec![(format!("/{}", escape_name(&object.name)), object)])
+		},
+	}
+}
+
+/// Writes a stream, decoding it as a length-prefixed UTF-16 string if requested (and possible).
+fn dump_object<TFile>(container: &mut Container<TFile>, object: &Object, decode: bool, output: &mut dyn Write, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+	if !decode {
+		return container.dump_stream(object, output, debug);
+	}
+	let mut data = Vec::new();
+	container.dump_stream(object, &mut data, debug)?;
+	match decode_string(&data) {
+		Some(text) => writeln!(output, "{}", text),
+		None => {
+			debug.logln(0, format!("Stream {} ('{}') is not a length-prefixed string; writing it as it is", object.id, escape_name(&object.name)));
+			output.write_all(&data)
+		},
+	}
+}
+
+/// Collects all streams beneath the storage whose paths match the given (escaped) pattern segments, along with their (escaped) paths.
+fn find_by_pattern<TFile>(container: &mut Container<TFile>, storage: &Object, path: &str, segments: &[String], result: &mut Vec<(String, Object)>, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+	let (segment, rest) = match segments.split_first() {
+		Some(split) => split,
+		None => return Ok(()),
+	};
+	// A '**' segment covers no storage at all (and the rest has to match here), or this storage and possibly more
+	if segment == "**" && !rest.is_empty() {
+		find_by_pattern(container, storage, path, rest, result, debug)?;
+	}
+	for child in container.get_children(storage, debug)? {
+		let child_path = format!("{}/{}", path, escape_name(&child.name));
+		if segment == "**" {
+			match child.object_type {
+				ObjectType::Storage => find_by_pattern(container, &child, &child_path, segments, result, debug)?,
+				ObjectType::Stream if rest.is_empty() && !result.iter().any(|(_, object)| object.id == child.id) => result.push((child_path, child)),
+				_ => {},
+			}
+		}
+		else if matches_name(segment, &escape_name(&child.name)) {
+			match child.object_type {
+				ObjectType::Storage if !rest.is_empty() => find_by_pattern(container, &child, &child_path, rest, result, debug)?,
+				ObjectType::Stream if rest.is_empty() && !result.iter().any(|(_, object)| object.id == child.id) => result.push((child_path, child)),
+				_ => debug.logln(2, format!("Skipping {}, which does not match the remaining path", child_path)),
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Matches a name against a pattern segment, case-insensitively; `?` matches a single character and `*` any sequence of characters.
+fn matches_name(pattern: &str, name: &str) -> bool {
+	fn matches_at(pattern: &[char], name: &[char]) -> bool {
+		match pattern.first() {
+			None => name.is_empty(),
+			Some(&'*') => (0..=name.len()).any(|index| matches_at(&pattern[1..], &name[index..])),
+			Some(&'?') => !name.is_empty() && matches_at(&pattern[1..], &name[1..]),
+			Some(&c) => !name.is_empty() && name[0] == c && matches_at(&pattern[1..], &name[1..]),
+		}
+	}
+	let pattern = pattern.to_lowercase().chars().collect::<Vec<char>>();
+	let name = name.to_lowercase().chars().collect::<Vec<char>>();
+	matches_at(&pattern, &name)
+}
+
+/// Reverts `escape_name`, i.e. turns `\001` etc. back into control characters.
+fn unescape_name(name: &str) -> String {
+	let mut result = String::new();
+	let mut rest = name;
+	while let Some(index) = rest.find('\\') {
+		result.push_str(&rest[..index]);
+		let code = rest.get(index + 1..index + 4).and_then(|digits| u32::from_str_radix(digits, 8).ok()).filter(|&code| code < 0x20);
+		match code.and_then(std::char::from_u32) {
+			Some(c) => {
+				result.push(c);
+				rest = &rest[index + 4..];
+			},
+			None => {
+				result.push('\\');
+				rest = &rest[index + 1..];
+			},
+		}
+	}
+	result.push_str(rest);
+	result
+}
+
+fn dispatch_decode(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let max_bytes = match matches.value_of("max-bytes") {
+		Some(_) => value_t!(matches, "max-bytes", usize).unwrap_or_else(|e| e.exit()),
+		None => 256,
+	};
+	let mut container = open_cfbf_or_dvs(matches.value_of("input").unwrap_or(""), debug)?;
+	for (path, object) in select_streams(&mut container, matches, debug)? {
+		let mut data = Vec::new();
+		container.dump_stream(&object, &mut data, debug)?;
+		println!("{} (#{}, {} bytes)", path, object.id, data.len());
+		print_interpretations(&data, max_bytes);
+		println!();
+	}
+	Ok(())
+}
+
+/// Reads a CFBF file, or the payload of a DVS file (which is decompressed first), from a file or STDIN.
+fn open_cfbf_or_dvs(inputfile: &str, debug: &mut Debug) -> Result<Container<SeekableRead>, Error> {
+	let mut data = Vec::new();
+	match inputfile {
+		"" | "-" => stdin().read_to_end(&mut data)?,
+		_ => File::open(inputfile)?.read_to_end(&mut data)?,
+	};
+	if data.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+		Container::new(SeekableRead::new(&data[..])?, debug)
+	}
+	else {
+		open_payload(&data[..], debug)
+	}
+}
+
+/// Prints the candidate interpretations of a stream, and a hexdump of (at most `max_bytes` of) the stream that is annotated with the parts of the first interpretation.
+fn print_interpretations(data: &[u8], max_bytes: usize) {
+	let interpretations = interpret(data);
+	if interpretations.is_empty() {
+		println!("  (no known encoding)");
+	}
+	for interpretation in &interpretations {
+		println!("  {:<30} {}", format!("{}:", interpretation.encoding), interpretation.value);
+	}
+	let spans = interpretations.first().map(|interpretation| &interpretation.spans[..]).unwrap_or(&[]);
+	for (index, line) in data[..data.len().min(max_bytes)].chunks(16).enumerate() {
+		let offset = index * 16;
+		let hex = line.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ");
+		let text = line.iter().map(|&byte| if (0x20..0x7F).contains(&byte) { byte as char } else { '.' }).collect::<String>();
+		// Annotate the spans that start on this line
+		let notes = spans.iter().filter(|span| span.start >= offset && span.start < offset + 16).map(|span| format!("[{}-{}] {}", span.start, span.end.max(span.start + 1) - 1, span.description)).collect::<Vec<String>>();
+		println!("{}", format!("  {:08x}  {:<47}  |{:<16}|  {}", offset, hex, text, notes.join("; ")).trim_end());
+	}
+	if data.len() > max_bytes {
+		println!("  ... ({} more bytes)", data.len() - max_bytes);
+	}
+}
+
+fn dispatch_extract_all(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let output_dir = PathBuf::from(matches.value_of("output").unwrap());
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
+	};
+	let mut manifest: Box<dyn Write> = match matches.value_of("manifest").unwrap_or("") {
 		"" | "-" => Box::new(stdout()),
-		_ => Box::new(File::create(outputfile).unwrap())
+		manifestfile => Box::new(File::create(manifestfile)?),
+	};
+	let mut container = Container::new(input, debug)?;
+	let root = container.get_root_object(debug)?;
+	writeln!(manifest, "# id\ttype\tsize\tclsid\tcreated\tmodified\tpath\tfile")?;
+	extract_all_recursive(&mut container, &root, "", &output_dir, Path::new(""), &mut manifest, debug)
+}
+
+/// Writes a storage (with all of its children) or a stream below the output directory, and records it in the manifest.
+/// Neither the (escaped) CFBF path nor the file path can contain tabs or line breaks.
+/// The modification time of the written directory or file is taken from the CFBF entry, if it is set.
+fn extract_all_recursive<TFile>(container: &mut Container<TFile>, object: &Object, path: &str, output_dir: &Path, file: &Path, manifest: &mut dyn Write, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+	let target = output_dir.join(file);
+	let timestamp = |filetime: u64| filetime_to_unix(filetime).filter(|_| filetime != 0);
+	let (object_type, size) = match object.object_type {
+		ObjectType::RootStorage => ("root", "-".to_owned()),
+		ObjectType::Storage => ("storage", "-".to_owned()),
+		ObjectType::Stream => ("stream", object.stream_size.to_string()),
+		ObjectType::Unknown => return Ok(()),
+	};
+	writeln!(manifest, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+		object.id,
+		object_type,
+		size,
+		match object.clsid { clsid if clsid == [0; 16] => "-".to_owned(), ref clsid => format_guid(clsid) },
+		timestamp(object.creation_time).map(format_timestamp).unwrap_or_else(|| "-".to_owned()),
+		timestamp(object.modified_time).map(format_timestamp).unwrap_or_else(|| "-".to_owned()),
+		if path.is_empty() { "/" } else { path },
+		file.to_string_lossy())?;
+
+	let modified = timestamp(object.modified_time).or_else(|| timestamp(object.creation_time)).map(|timestamp| UNIX_EPOCH + Duration::from_secs(timestamp));
+	if object.object_type == ObjectType::Stream {
+		debug.logln(1, format!("Writing {:?} ...", target));
+		let mut output = File::create(&target)?;
+		container.dump_stream(object, &mut output, debug)?;
+		if let Some(modified) = modified {
+			output.set_modified(modified)?;
+		}
+		return Ok(());
+	}
+
+	create_dir_all(&target)?;
+	for child in container.get_children(object, debug)? {
+		let child_path = format!("{}/{}", path, escape_name(&child.name));
+		let child_file = file.join(escape_file_name(&child.name));
+		extract_all_recursive(container, &child, &child_path, output_dir, &child_file, manifest, debug)?;
+	}
+	// The timestamp of a directory can only be set once all of its children have been written; not all file systems permit this
+	if let Some(modified) = modified {
+		if let Err(e) = set_dir_modified(&target, modified) {
+			debug.logln(0, format!("Unable to set the modification time of {:?}: {}", target, e));
+		}
+	}
+	Ok(())
+}
+
+fn dispatch_extract(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let max_depth = match matches.occurrences_of("recursive") > 0 {
+		true if matches.is_present("max-depth") => value_t!(matches, "max-depth", usize).unwrap_or_else(|e| e.exit()),
+		true => MAX_DEPTH,
+		false => 0,
+	};
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
+	};
+	let mut container = Container::new(input, debug)?;
+	let objects = find_objects(&mut container, max_depth, debug)?;
+
+	let output_dir = matches.value_of("output").map(PathBuf::from);
+	if let Some(ref output_dir) = output_dir {
+		create_dir_all(output_dir)?;
+	}
+	for object in &objects {
+		let size = match object.kind {
+			ObjectKind::Storage => object.streams.iter().map(|(_, data)| data.len()).sum(),
+			_ => object.data.len(),
+		};
+		print!("{}{} ({}, {} bytes", "  ".repeat(object.depth), object.path, object.kind, size);
+		if let Some(ref file_name) = object.file_name {
+			print!(", '{}'", file_name);
+		}
+		print!(")");
+		if let Some(ref output_dir) = output_dir {
+			let target = extract_object(object, output_dir, debug)?;
+			print!(" -> {}", target.to_string_lossy());
+		}
+		println!();
+	}
+	Ok(())
+}
+
+/// Writes an embedded object into the output directory, and returns the path of the created file (or directory, for embedded storages).
+/// Objects without an original file name are named after their storage or stream, with an extension that matches their content.
+fn extract_object(object: &EmbeddedObject, output_dir: &Path, debug: &mut Debug) -> Result<PathBuf, Error> {
+	let components = object.path.rsplit(['/', '!']).filter(|component| !component.is_empty()).collect::<Vec<&str>>();
+	let base_name = match object.kind {
+		ObjectKind::Package | ObjectKind::Contents => components.get(1),
+		_ => components.first(),
+	}.map(|name| sanitize_file_name(name)).unwrap_or_else(|| sanitize_file_name(""));
+
+	if object.kind == ObjectKind::Storage {
+		let target = unique_path(output_dir, &base_name);
+		for (path, data) in &object.streams {
+			let stream_path = path.split('/').filter(|component| !component.is_empty()).map(sanitize_file_name).fold(target.clone(), |path, component| path.join(component));
+			if let Some(dir) = stream_path.parent() {
+				create_dir_all(dir)?;
+			}
+			File::create(&stream_path)?.write_all(data)?;
+		}
+		create_dir_all(&target)?;
+		return Ok(target);
+	}
+
+	let file_name = match object.file_name {
+		Some(ref file_name) => sanitize_file_name(file_name),
+		None => match sniff(&mut Cursor::new(&object.data[..]), debug)?.and_then(|file_type| file_type.extensions.first()) {
+			Some(extension) => format!("{}.{}", base_name, extension),
+			None => format!("{}.bin", base_name),
+		},
+	};
+	let target = unique_path(output_dir, &file_name);
+	File::create(&target)?.write_all(&object.data)?;
+	Ok(target)
+}
+
+fn dispatch_props(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
+	};
+	let mut container = Container::new(input, debug)?;
+	let root = container.get_root_object(debug)?;
+	let mut streams = Vec::new();
+	find_property_set_streams(&mut container, &root, "", &mut streams, debug)?;
+
+	for (path, object) in streams {
+		let mut data: Vec<u8> = Vec::new();
+		container.dump_stream(&object, &mut data, debug)?;
+		println!("{} {}", object.id, path);
+		let stream = match PropertySetStream::parse(&data) {
+			Ok(stream) => stream,
+			Err(e) => {
+				println!("  (unable to decode: {})", e);
+				continue;
+			},
+		};
+		for set in &stream.sets {
+			println!("  Property set {} ({})", format_guid(&set.fmtid), set.format_name().unwrap_or("unknown format"));
+			if let Some(code_page) = set.code_page {
+				println!("    1 CodePage = {}", code_page);
+			}
+			for property in &set.properties {
+				println!("    {} {} (VT 0x{:04X}) = {}", property.id, set.property_name(property.id).unwrap_or_else(|| "-".to_owned()), property.value_type, property.value);
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Finds all property set streams (whose names start with '\x05') beneath the given storage, with their paths.
+/// In the paths, the character '\x05' is written as '\005'.
+fn find_property_set_streams<TFile>(container: &mut Container<TFile>, storage: &Object, prefix: &str, result: &mut Vec<(String, Object)>, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+	for child in container.get_children(storage, debug)? {
+		let path = format!("{}/{}", prefix, child.name.replace('\u{5}', "\\005"));
+		match child.object_type {
+			ObjectType::Stream if child.name.starts_with('\u{5}') => result.push((path, child)),
+			ObjectType::Storage => find_property_set_streams(container, &child, &path, result, debug)?,
+			_ => {},
+		}
+	}
+	Ok(())
+}
+
+fn dispatch_vba(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
+	};
+	let mut container = Container::new(input, debug)?;
+	let projects = read_projects(&mut container, debug)?;
+	if projects.is_empty() {
+		eprintln!("No VBA project found.");
+		return Ok(());
+	}
+
+	let output_dir = matches.value_of("output").map(PathBuf::from);
+	for project in &projects {
+		println!("{} (project '{}', code page {})", project.path, project.name, project.code_page);
+		// Several projects are written into sub-directories named after their storages
+		let project_dir = output_dir.as_ref().map(|output_dir| match projects.len() {
+			1 => output_dir.clone(),
+			_ => output_dir.join(sanitize_file_name(project.path.trim_start_matches('/'))),
+		});
+		let targets = match project_dir {
+			Some(ref project_dir) => project.write_modules(project_dir)?.into_iter().map(Option::Some).collect(),
+			None => vec![Option::None; project.modules.len()],
+		};
+		for (module, target) in project.modules.iter().zip(targets) {
+			print!("  {} ({} lines)", module.file_name(), module.source.lines().count());
+			if let Some(target) = target {
+				print!(" -> {}", target.to_string_lossy());
+			}
+			println!();
+		}
+	}
+	Ok(())
+}
+
+fn dispatch_msg(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let inputfile = matches.value_of("input").unwrap_or("");
+	let input: Box<dyn ReadSeek> = match inputfile {
+		"" | "-" => Box::new(SeekableRead::new(stdin())?),
+		_ => Box::new(File::open(inputfile)?),
 	};
 	let mut container = Container::new(input, debug)?;
+	if !evrecovery::msg::is_msg_container(&mut container, debug) {
+		return Err(Error::new(ErrorKind::InvalidData, "The CFBF file is not an Outlook message (no MAPI property streams)"));
+	}
+	let message = Message::read(&mut container, debug)?;
+
+	if let Some(outputfile) = matches.value_of("eml") {
+		let mut output: Box<dyn Write> = match outputfile {
+			"" | "-" => Box::new(stdout()),
+			_ => Box::new(File::create(outputfile)?),
+		};
+		return message.write_eml(&mut output);
+	}
+	if matches.occurrences_of("properties") > 0 {
+		print_message_properties(&message, "");
+		return Ok(());
+	}
+	print_message_summary(&message, "");
+	Ok(())
+}
+
+fn print_message_summary(message: &Message, indent: &str) {
+	println!("{}Subject: {}", indent, message.subject().unwrap_or(""));
+	println!("{}From: {}", indent, format_mailbox(message.sender_name(), message.sender_address()).unwrap_or_default());
+	if let Some(date) = message.date() {
+		println!("{}Date: {}", indent, format_timestamp(date));
+	}
+	for recipient in &message.recipients {
+		println!("{}{}: {}", indent, recipient.kind().name(), format_mailbox(recipient.name(), recipient.address()).unwrap_or_default());
+	}
+	for (index, attachment) in message.attachments.iter().enumerate() {
+		match (&attachment.data, &attachment.embedded_message) {
+			(Some(data), _) => println!("{}Attachment {}: {} ({}, {} bytes)", indent, index + 1, attachment.filename().unwrap_or("(no name)"), attachment.mime_type().unwrap_or("unknown type"), data.len()),
+			(None, Some(embedded_message)) => {
+				println!("{}Attachment {}: embedded message", indent, index + 1);
+				print_message_summary(embedded_message, &format!("{}  ", indent));
+			},
+			(None, None) => println!("{}Attachment {}: {} (no data, e.g. an OLE object)", indent, index + 1, attachment.filename().unwrap_or("(no name)")),
+		}
+	}
+}
+
+fn print_message_properties(message: &Message, indent: &str) {
+	print_properties(&message.properties, indent);
+	for (index, recipient) in message.recipients.iter().enumerate() {
+		println!("{}Recipient {}:", indent, index + 1);
+		print_properties(&recipient.properties, &format!("{}  ", indent));
+	}
+	for (index, attachment) in message.attachments.iter().enumerate() {
+		println!("{}Attachment {}:", indent, index + 1);
+		print_properties(&attachment.properties, &format!("{}  ", indent));
+		if let Some(ref data) = attachment.data {
+			println!("{}  0x3701 PT_BINARY PidTagAttachDataBinary = {} bytes", indent, data.len());
+		}
+		if let Some(ref embedded_message) = attachment.embedded_message {
+			println!("{}  Embedded message:", indent);
+			print_message_properties(embedded_message, &format!("{}    ", indent));
+		}
+	}
+}
+
+fn print_properties(properties: &Properties, indent: &str) {
+	for (id, property_type, value) in properties.iter() {
+		println!("{}0x{:04X} {} {} = {}", indent, id, type_name(property_type), property_name(id).unwrap_or("-"), value);
+	}
+}
 
-	let object = container.get_object(id, debug)?;
-	container.dump_stream(&object, &mut output, debug)?;
+fn dispatch_shell(matches: &ArgMatches, debug: &mut Debug) -> Result<(), Error> {
+	let mut container = open_cfbf_or_dvs(matches.value_of("input").unwrap(), debug)?;
+	let tree = Tree::read(&mut container, debug)?;
+
+	let mut editor = LineEditor::new(&tree)?;
+	let mut shell = Shell { container, tree, cwd: vec![0] };
+	while let Some(line) = editor.read_line(&format!("{}> ", shell.tree.path(&shell.cwd)))? {
+		let words = split_words(&line).0;
+		match words.first().map(|word| word.as_str()) {
+			None => continue,
+			Some("exit") | Some("quit") => break,
+			Some(_) => if let Err(e) = shell.execute(&words, debug) {
+				eprintln!("ERROR: {}", e);
+			},
+		}
+		editor.set_cwd(&shell.cwd);
+	}
 	Ok(())
 }
+
+/// Reads the command lines of the shell, with a history and tab completion.
+#[cfg(feature = "shell")]
+struct LineEditor {
+	editor: Editor<ShellHelper, DefaultHistory>,
+}
+
+#[cfg(feature = "shell")]
+impl LineEditor {
+	fn new(tree: &Tree) -> Result<LineEditor, Error> {
+		let config = Config::builder().completion_type(CompletionType::List).build();
+		let mut editor = Editor::<ShellHelper, DefaultHistory>::with_config(config).map_err(|e| Error::other(e.to_string()))?;
+		editor.set_helper(Some(ShellHelper { tree: tree.clone(), cwd: vec![0] }));
+		Ok(LineEditor { editor })
+	}
+
+	/// Reads a line; returns `None` at the end of the input (Ctrl-D).
+	fn read_line(&mut self, prompt: &str) -> Result<Option<String>, Error> {
+		loop {
+			match self.editor.readline(prompt) {
+				Ok(line) => {
+					let _ = self.editor.add_history_entry(line.as_str());
+					return Ok(Some(line));
+				},
+				Err(ReadlineError::Interrupted) => continue,
+				Err(ReadlineError::Eof) => return Ok(None),
+				Err(e) => return Err(Error::other(e.to_string())),
+			}
+		}
+	}
+
+	/// Updates the current storage, beneath which names are completed.
+	fn set_cwd(&mut self, cwd: &[u32]) {
+		if let Some(helper) = self.editor.helper_mut() {
+			helper.cwd = cwd.to_vec();
+		}
+	}
+}
+
+/// Reads the command lines of the shell from STDIN, without a history or tab completion (which require the `shell` feature).
+#[cfg(not(feature = "shell"))]
+struct LineEditor;
+
+#[cfg(not(feature = "shell"))]
+impl LineEditor {
+	fn new(_tree: &Tree) -> Result<LineEditor, Error> {
+		Ok(LineEditor)
+	}
+
+	/// Reads a line; returns `None` at the end of the input.
+	fn read_line(&mut self, prompt: &str) -> Result<Option<String>, Error> {
+		print!("{}", prompt);
+		stdout().flush()?;
+		let mut line = String::new();
+		match stdin().read_line(&mut line)? {
+			0 => Ok(None),
+			_ => Ok(Some(line.trim_end_matches(['\r', '\n']).to_owned())),
+		}
+	}
+
+	fn set_cwd(&mut self, _cwd: &[u32]) {}
+}
+
+#[cfg(feature = "shell")]
+const SHELL_COMMANDS: [&str; 12] = ["cat", "cd", "decode", "exit", "extract", "hexdump", "help", "ls", "pwd", "quit", "stat", "strings"];
+
+/// The directory tree of a CFBF file, held in memory for navigating it (and completing names).
+#[derive(Clone)]
+struct Tree {
+	objects: HashMap<u32, Object>,
+	children: HashMap<u32, Vec<u32>>,
+}
+
+impl Tree {
+	fn read<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Tree, Error> where TFile: Read + Seek {
+		let mut tree = Tree { objects: HashMap::new(), children: HashMap::new() };
+		let root = container.get_root_object(debug)?;
+		tree.add(container, root, debug)?;
+		Ok(tree)
+	}
+
+	fn add<TFile>(&mut self, container: &mut Container<TFile>, object: Object, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+		let id = object.id;
+		// Storages that contain themselves (in corrupt files) are only read once
+		if self.objects.contains_key(&id) {
+			return Ok(());
+		}
+		let is_storage = object.object_type != ObjectType::Stream;
+		self.objects.insert(id, object.clone());
+		if is_storage {
+			let children = container.get_children(&object, debug)?;
+			self.children.insert(id, children.iter().map(|child| child.id).collect());
+			for child in children {
+				self.add(container, child, debug)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn object(&self, path: &[u32]) -> &Object {
+		&self.objects[path.last().unwrap()]
+	}
+
+	/// The path to an object (given as the IDs of all objects from the root), with control characters escaped.
+	fn path(&self, path: &[u32]) -> String {
+		match path.len() {
+			1 => "/".to_owned(),
+			_ => path[1..].iter().map(|id| format!("/{}", escape_name(&self.objects[id].name))).collect(),
+		}
+	}
+
+	/// Finds the child of a storage with the given (escaped or raw) name, case-insensitively.
+	fn find_child(&self, id: u32, name: &str) -> Option<u32> {
+		let name = unescape_name(name).to_uppercase();
+		self.children.get(&id)?.iter().cloned().find(|child| self.objects[child].name.to_uppercase() == name)
+	}
+
+	/// Resolves a path, which is either absolute or relative to the current storage, and may contain `.` and `..`.
+	fn resolve(&self, cwd: &[u32], path: &str) -> Result<Vec<u32>, Error> {
+		let mut result = if path.starts_with('/') { vec![0] } else { cwd.to_vec() };
+		for segment in path.split('/') {
+			match segment {
+				"" | "." => {},
+				".." => if result.len() > 1 {
+					result.pop();
+				},
+				_ => match self.find_child(*result.last().unwrap(), segment) {
+					Some(child) => result.push(child),
+					None => return Err(Error::new(ErrorKind::NotFound, format!("'{}' does not exist", path))),
+				},
+			}
+		}
+		Ok(result)
+	}
+}
+
+/// The state of the interactive shell: the open CFBF file and the current storage.
+struct Shell<TFile: Read + Seek> {
+	container: Container<TFile>,
+	tree: Tree,
+	cwd: Vec<u32>,
+}
+
+impl<TFile> Shell<TFile> where TFile: Read + Seek {
+	fn execute(&mut self, words: &[String], debug: &mut Debug) -> Result<(), Error> {
+		let args = &words[1..];
+		match words[0].as_str() {
+			"help" => {
+				println!("ls [-l] [PATH]          lists a storage");
+				println!("cd [PATH]               changes the current storage (to the root, if no path is given)");
+				println!("pwd                     prints the path of the current storage");
+				println!("stat PATH               prints the properties of a storage or stream");
+				println!("cat PATH                writes a stream to STDOUT");
+				println!("hexdump PATH            prints a stream as a hexdump");
+				println!("strings [-n N] PATH     prints the ASCII and UTF-16 strings of a stream that have at least N (4) characters");
+				println!("decode PATH             tries all known encodings on a stream, and prints them next to an annotated hexdump");
+				println!("extract PATH [TARGET]   writes a stream (or a whole storage) to a local file (or directory)");
+				println!("exit                    leaves the shell");
+				println!("Names that contain spaces need to be quoted; control characters can be written as '\\001' etc.");
+				Ok(())
+			},
+			"pwd" => {
+				println!("{}", self.tree.path(&self.cwd));
+				Ok(())
+			},
+			"cd" => {
+				let path = self.tree.resolve(&self.cwd, args.first().map(|arg| arg.as_str()).unwrap_or("/"))?;
+				if self.tree.object(&path).object_type == ObjectType::Stream {
+					return Err(Error::new(ErrorKind::InvalidInput, format!("'{}' is not a storage", args[0])));
+				}
+				self.cwd = path;
+				Ok(())
+			},
+			"ls" => {
+				let long = args.iter().any(|arg| arg == "-l");
+				let path = match args.iter().find(|arg| *arg != "-l") {
+					Some(arg) => self.tree.resolve(&self.cwd, arg)?,
+					None => self.cwd.clone(),
+				};
+				let ids = match self.tree.children.get(path.last().unwrap()) {
+					Some(children) => children.clone(),
+					None => vec![*path.last().unwrap()],
+				};
+				for id in ids {
+					let object = &self.tree.objects[&id];
+					let name = format!("{}{}", escape_name(&object.name), if object.object_type == ObjectType::Stream { "" } else { "/" });
+					if long {
+						let fields = list_fields(&self.container, object, "");
+						println!("{:>5} {:<7} {:>10} {:<20} {}", id, fields[1].as_ref().unwrap(), fields[2].clone().unwrap_or_else(|| "-".to_owned()), fields[7].clone().unwrap_or_else(|| "-".to_owned()), name);
+					}
+					else {
+						println!("{}", name);
+					}
+				}
+				Ok(())
+			},
+			"stat" => {
+				let path = self.tree.resolve(&self.cwd, self.argument(args, 0)?)?;
+				let object = self.tree.object(&path);
+				let fields = list_fields(&self.container, object, &self.tree.path(&path));
+				for (column, field) in LIST_COLUMNS.iter().zip(fields.iter()) {
+					println!("{:<13} {}", format!("{}:", column), field.as_ref().map(|field| field.as_str()).unwrap_or("-"));
+				}
+				if let Some(children) = self.tree.children.get(&object.id) {
+					println!("{:<13} {}", "children:", children.len());
+				}
+				Ok(())
+			},
+			"cat" => {
+				let data = self.read_stream(args, debug)?;
+				let stdout = stdout();
+				let mut output = stdout.lock();
+				output.write_all(&data)?;
+				output.flush()
+			},
+			"hexdump" => {
+				let data = self.read_stream(args, debug)?;
+				print_hexdump(&data);
+				Ok(())
+			},
+			"strings" => {
+				let (min_length, args) = match args.first().map(|arg| arg.as_str()) {
+					Some("-n") => (self.argument(args, 1)?.parse::<usize>().map_err(|_| Error::new(ErrorKind::InvalidInput, "The minimum length is not a number"))?, &args[2..]),
+					_ => (4, args),
+				};
+				let data = self.read_stream(args, debug)?;
+				for (offset, text, is_utf16) in find_strings(&data, min_length) {
+					println!("{:#010x} {}{}", offset, if is_utf16 { "(UTF-16) " } else { "" }, text);
+				}
+				Ok(())
+			},
+			"decode" => {
+				let data = self.read_stream(args, debug)?;
+				print_interpretations(&data, 256);
+				Ok(())
+			},
+			"extract" => {
+				let path = self.tree.resolve(&self.cwd, self.argument(args, 0)?)?;
+				let object = self.tree.object(&path).clone();
+				let target = match args.get(1) {
+					Some(target) => PathBuf::from(target),
+					None => PathBuf::from(escape_file_name(&object.name)),
+				};
+				// A stream is written to a file, so the target is split into its directory and file name
+				let (output_dir, file) = match (target.parent(), target.file_name()) {
+					(Some(parent), Some(name)) => (parent.to_path_buf(), PathBuf::from(name)),
+					_ => (target.clone(), PathBuf::new()),
+				};
+				if !output_dir.as_os_str().is_empty() {
+					create_dir_all(&output_dir)?;
+				}
+				extract_all_recursive(&mut self.container, &object, &self.tree.path(&path), &output_dir, &file, &mut sink(), debug)?;
+				println!("Extracted {} to {:?}", self.tree.path(&path), target);
+				Ok(())
+			},
+			command => Err(Error::new(ErrorKind::InvalidInput, format!("Unknown command '{}' (try 'help')", command))),
+		}
+	}
+
+	fn argument<'a>(&self, args: &'a [String], index: usize) -> Result<&'a str, Error> {
+		args.get(index).map(|arg| arg.as_str()).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Missing argument (try 'help')"))
+	}
+
+	/// Reads the whole content of the stream given as the first argument.
+	fn read_stream(&mut self, args: &[String], debug: &mut Debug) -> Result<Vec<u8>, Error> {
+		let path = self.tree.resolve(&self.cwd, self.argument(args, 0)?)?;
+		let object = self.tree.object(&path).clone();
+		if object.object_type != ObjectType::Stream {
+			return Err(Error::new(ErrorKind::InvalidInput, format!("'{}' is not a stream", args[0])));
+		}
+		let mut data = Vec::new();
+		self.container.dump_stream(&object, &mut data, debug)?;
+		Ok(data)
+	}
+}
+
+/// Splits a command line into words, separated by spaces; double or single quotes protect spaces.
+/// Also returns the position in the line where the last word starts.
+fn split_words(line: &str) -> (Vec<String>, usize) {
+	let mut words = Vec::new();
+	let mut current: Option<String> = None;
+	let mut start = line.len();
+	let mut quote = None;
+	for (index, c) in line.char_indices() {
+		match (quote, c) {
+			(None, ' ') | (None, '\t') => if let Some(word) = current.take() {
+				words.push(word);
+			},
+			(None, '"') | (None, '\'') => {
+				quote = Some(c);
+				if current.is_none() {
+					start = index;
+				}
+				current.get_or_insert_with(String::new);
+			},
+			(Some(q), c) if q == c => quote = None,
+			(_, c) => {
+				if current.is_none() {
+					start = index;
+				}
+				current.get_or_insert_with(String::new).push(c);
+			},
+		}
+	}
+	match current {
+		Some(word) => words.push(word),
+		None => start = line.len(),
+	}
+	(words, start)
+}
+
+/// Completes the names of commands, storages and streams in the shell.
+#[cfg(feature = "shell")]
+struct ShellHelper {
+	tree: Tree,
+	cwd: Vec<u32>,
+}
+
+#[cfg(feature = "shell")]
+impl Completer for ShellHelper {
+	type Candidate = Pair;
+
+	fn complete(&self, line: &str, pos: usize, _context: &Context) -> rustyline::Result<(usize, Vec<Pair>)> {
+		let (words, start) = split_words(&line[..pos]);
+		let word = if start < pos { words.last().cloned().unwrap_or_default() } else { String::new() };
+		let preceding_words = if start < pos { words.len() - 1 } else { words.len() };
+		if preceding_words == 0 {
+			let candidates = SHELL_COMMANDS.iter().filter(|command| command.starts_with(word.as_str())).map(|command| Pair { display: command.to_string(), replacement: format!("{} ", command) }).collect();
+			return Ok((start, candidates));
+		}
+
+		// Complete the last segment of the path, within the storage that the preceding segments refer to
+		let (dir, prefix) = match word.rfind('/') {
+			Some(index) => (&word[..index + 1], &word[index + 1..]),
+			None => ("", word.as_str()),
+		};
+		let storage = match self.tree.resolve(&self.cwd, dir) {
+			Ok(storage) => storage,
+			Err(_) => return Ok((start, Vec::new())),
+		};
+		let prefix = unescape_name(prefix).to_uppercase();
+		let candidates = self.tree.children.get(storage.last().unwrap()).map(|children| children.iter().map(|id| &self.tree.objects[id]).filter(|object| object.name.to_uppercase().starts_with(&prefix)).map(|object| {
+			let is_storage = object.object_type != ObjectType::Stream;
+			let name = format!("{}{}", escape_name(&object.name), if is_storage { "/" } else { "" });
+			let path = format!("{}{}", dir, name);
+			let replacement = if path.contains([' ', '\t', '\'']) { format!("\"{}\"", path) } else { path };
+			Pair { display: name, replacement: if is_storage { replacement } else { format!("{} ", replacement) } }
+		}).collect()).unwrap_or_default();
+		Ok((start, candidates))
+	}
+}
+
+#[cfg(feature = "shell")]
+impl Hinter for ShellHelper {
+	type Hint = String;
+}
+
+#[cfg(feature = "shell")]
+impl Highlighter for ShellHelper {}
+
+#[cfg(feature = "shell")]
+impl Validator for ShellHelper {}
+
+#[cfg(feature = "shell")]
+impl Helper for ShellHelper {}
+
+/// Prints data as a hexdump, with 16 bytes per line (offset, bytes and printable ASCII characters).
+fn print_hexdump(data: &[u8]) {
+	for (index, line) in data.chunks(16).enumerate() {
+		let hex = line.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ");
+		let text = line.iter().map(|&byte| if (0x20..0x7F).contains(&byte) { byte as char } else { '.' }).collect::<String>();
+		println!("{:08x}  {:<47}  |{}|", index * 16, hex, text);
+	}
+}
+
+/// Finds the runs of printable ASCII characters and of printable UTF-16 (Little Endian) Latin-1 characters with at least the given length.
+/// Returns the offset, the text and whether it is UTF-16 encoded, ordered by offset.
+fn find_strings(data: &[u8], min_length: usize) -> Vec<(usize, String, bool)> {
+	let mut result = Vec::new();
+	let mut start = 0;
+	for index in 0..=data.len() {
+		if index == data.len() || !((0x20..0x7F).contains(&data[index]) || data[index] == b'\t') {
+			if index - start >= min_length {
+				result.push((start, String::from_utf8_lossy(&data[start..index]).into_owned(), false));
+			}
+			start = index + 1;
+		}
+	}
+	for alignment in 0..2 {
+		let units = data[alignment.min(data.len())..].chunks(2).map(|unit| if unit.len() == 2 { u16::from(unit[0]) | u16::from(unit[1]) << 8 } else { 0 }).collect::<Vec<u16>>();
+		let mut start = 0;
+		for index in 0..=units.len() {
+			if index == units.len() || !((0x20..0x7F).contains(&units[index]) || (0xA0..0x100).contains(&units[index]) || units[index] == 0x09) {
+				if index - start >= min_length {
+					result.push((alignment + start * 2, String::from_utf16_lossy(&units[start..index]), true));
+				}
+				start = index + 1;
+			}
+		}
+	}
+	result.sort_by_key(|&(offset, _, _)| offset);
+	result
+}
@@ -0,0 +1,257 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+extern crate clap;
+extern crate evrecovery;
+
+use std::io::{Write, BufWriter, stdout, stderr};
+use std::io::Error;
+use std::fs::{File, metadata};
+use std::path::{Path, PathBuf};
+use clap::{Arg, App};
+use evrecovery::catalogue::{Entry, Format, Writer};
+#[cfg(feature = "sqlite")]
+use evrecovery::catalogue::sqlite::SqliteWriter;
+use evrecovery::cab::CabinetCache;
+use evrecovery::dvf::Index as DvfIndex;
+use evrecovery::io::Debug;
+use evrecovery::metadata::{ContentLocation, Metadata};
+use evrecovery::propset::Summary;
+use evrecovery::restore::{Content, Source, find_inputs, open_input, open_payload, locate_content, read_document_summary, detect_content_encryption};
+
+/// The formats that the catalogue can be written in.
+#[cfg(feature = "sqlite")]
+const FORMATS: [&str; 3] = ["csv", "ndjson", "sqlite"];
+#[cfg(not(feature = "sqlite"))]
+const FORMATS: [&str; 2] = ["csv", "ndjson"];
+
+/// Writes the catalogue either as text (CSV or NDJSON), or into a SQLite database.
+enum CatalogueWriter {
+	Text(Writer<BufWriter<Box<dyn Write>>>),
+	#[cfg(feature = "sqlite")]
+	Sqlite(SqliteWriter),
+}
+
+impl CatalogueWriter {
+	fn write(&mut self, entry: &Entry) -> Result<(), Error> {
+		match *self {
+			CatalogueWriter::Text(ref mut writer) => writer.write(entry),
+			#[cfg(feature = "sqlite")]
+			CatalogueWriter::Sqlite(ref mut writer) => writer.write(entry),
+		}
+	}
+
+	fn finish(self) -> Result<(), Error> {
+		match self {
+			CatalogueWriter::Text(mut writer) => writer.flush(),
+			#[cfg(feature = "sqlite")]
+			CatalogueWriter::Sqlite(writer) => writer.finish(),
+		}
+	}
+}
+
+fn main() {
+	let matches = App::new("dvscatalogue")
+		.version("1.0")
+		.author("Steve Muller <steve.muller@outlook.com>")
+		.about("This utility walks Enterprise Vault partitions and writes an inventory of all archived items, reading only their metadata (the archived content is not extracted).")
+		.arg(Arg::with_name("verbose")
+			.short("v")
+			.help("Increases the debug verbosity. This will print a lot of debug messages to standard error (STDERR). Can be used up to 4 times.")
+			.multiple(true)
+			.takes_value(false))
+		.arg(Arg::with_name("input")
+			.value_name("PATH")
+			.help("One or more DVS files, or directories (such as vault partitions) that will be searched recursively for DVS files.")
+			.multiple(true)
+			.required(true))
+		.arg(Arg::with_name("output")
+			.value_name("FILE")
+			.help("The file where the catalogue shall be written to. If this parameter is not specified (or has the value '-'), the catalogue will be written to STDOUT instead.")
+			.short("o")
+			.long("output")
+			.required(false))
+		.arg(Arg::with_name("format")
+			.value_name("FORMAT")
+			.help("The format of the catalogue: 'csv' (comma-separated values with a header line), 'ndjson' (one JSON object per line) or 'sqlite' (a table 'catalogue' in a SQLite database, which requires --output; only available if built with the 'sqlite' feature).")
+			.short("f")
+			.long("format")
+			.possible_values(&FORMATS)
+			.default_value("csv")
+			.required(false))
+		.arg(Arg::with_name("ext")
+			.value_name("FILEEXT")
+			.help("The file extension of outsourced files.")
+			.long("ext")
+			.default_value("dvf")
+			.required(false))
+		.arg(Arg::with_name("dvf-root")
+			.value_name("DIR")
+			.help("An additional directory that is searched recursively for outsourced files. Can be specified several times.")
+			.long("dvf-root")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+		.arg(Arg::with_name("document-properties")
+			.long("document-properties")
+			.help("If set, the summary information (title, author, dates etc.) recorded in legacy Office documents is read as well, and written to the 'doc_*' columns. This requires reading the archived content.")
+			.takes_value(false)
+			.required(false))
+		.arg(Arg::with_name("detect-encryption")
+			.long("detect-encryption")
+			.help("If set, encrypted and IRM-protected Office documents are detected, and their encryption scheme and parameters are written to the 'encryption' column. This requires reading the archived content.")
+			.takes_value(false)
+			.required(false))
+	.get_matches();
+
+	let verbose = matches.occurrences_of("verbose") as i8 - 1;
+	let inputs = matches.values_of("input").unwrap().collect::<Vec<&str>>();
+	let outputfile = matches.value_of("output").unwrap_or("-");
+	let format = matches.value_of("format").unwrap();
+	let outsourced_extension = matches.value_of("ext").unwrap();
+	let dvf_roots = matches.values_of("dvf-root").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+	let document_properties = matches.occurrences_of("document-properties") > 0;
+	let detect_encryption = matches.occurrences_of("detect-encryption") > 0;
+
+	let mut debug = Debug::new(stderr(), verbose);
+	let mut writer = match create_writer(format, outputfile) {
+		Ok(writer) => writer,
+		Err(e) => {
+			eprintln!("I/O ERROR: Unable to create '{}': {}", outputfile, e);
+			std::process::exit(1);
+		},
+	};
+
+	let mut dvf_index = DvfIndex::new(outsourced_extension);
+	for dvf_root in dvf_roots {
+		if let Err(e) = dvf_index.add_root(Path::new(dvf_root), &mut debug) {
+			eprintln!("I/O ERROR: Unable to search '{}' for outsourced files: {}", dvf_root, e);
+			std::process::exit(1);
+		}
+	}
+
+	let mut cabinets = CabinetCache::new();
+	let mut num_failed = 0;
+	for input in inputs {
+		let inputfiles = match find_inputs(Path::new(input), &mut debug) {
+			Ok(inputfiles) => inputfiles,
+			Err(e) => {
+				eprintln!("I/O ERROR: {}: {}", input, e);
+				num_failed += 1;
+				continue;
+			},
+		};
+
+		for inputfile in inputfiles {
+			let entry = catalogue_file(&inputfile, &mut dvf_index, &mut cabinets, document_properties, detect_encryption, &mut debug);
+			if let Some(ref e) = entry.error {
+				eprintln!("I/O ERROR: {}: {}", entry.dvs_path, e);
+				num_failed += 1;
+			}
+			if let Err(e) = writer.write(&entry) {
+				eprintln!("I/O ERROR: Unable to write catalogue: {}", e);
+				std::process::exit(1);
+			}
+		}
+	}
+
+	if let Err(e) = writer.finish() {
+		eprintln!("I/O ERROR: Unable to write catalogue: {}", e);
+		std::process::exit(1);
+	}
+	if num_failed > 0 {
+		std::process::exit(1);
+	}
+}
+
+/// Reads the metadata of a single DVS file into a catalogue entry.
+/// Errors are not returned, but recorded in the entry.
+fn catalogue_file(inputfile: &Path, dvf_index: &mut DvfIndex, cabinets: &mut CabinetCache, document_properties: bool, detect_encryption: bool, debug: &mut Debug) -> Entry {
+	debug.logln(0, format!("Processing {:?} ...", inputfile));
+	let mut entry = Entry {
+		dvs_path: inputfile.to_string_lossy().into_owned(),
+		dvs_size: metadata(inputfile).map(|metadata| metadata.len()).unwrap_or(0),
+		dvf_path: Option::None,
+		metadata: Metadata::default(),
+		document: Summary::default(),
+		encryption: Option::None,
+		error: Option::None,
+		notes: Option::None,
+	};
+	if let Err(e) = read_entry(inputfile, &mut entry, dvf_index, cabinets, document_properties, detect_encryption, debug) {
+		entry.error = Option::Some(e.to_string());
+	}
+	entry
+}
+
+/// Creates the writer for the catalogue in the given format (see `FORMATS`).
+fn create_writer(format: &str, outputfile: &str) -> Result<CatalogueWriter, Error> {
+	#[cfg(feature = "sqlite")]
+	{
+		if format == "sqlite" {
+			return match outputfile {
+				"" | "-" => Err(Error::other("A SQLite catalogue cannot be written to STDOUT; use --output")),
+				_ => SqliteWriter::create(Path::new(outputfile)).map(CatalogueWriter::Sqlite),
+			};
+		}
+	}
+	let output: Box<dyn Write> = match outputfile {
+		"" | "-" => Box::new(stdout()),
+		_ => Box::new(File::create(outputfile)?),
+	};
+	Ok(CatalogueWriter::Text(Writer::new(BufWriter::new(output), Format::from_name(format).unwrap())))
+}
+
+/// Reads the metadata, the outsourced file and (if requested) the summary information and encryption of the archived file into the entry.
+fn read_entry(inputfile: &Path, entry: &mut Entry, dvf_index: &mut DvfIndex, cabinets: &mut CabinetCache, document_properties: bool, detect_encryption: bool, debug: &mut Debug) -> Result<(), Error> {
+	let mut container = open_payload(open_input(inputfile, cabinets, debug)?, debug)?;
+	entry.metadata = Metadata::read(&mut container, debug)?;
+
+	// Only look for an outsourced file if the metadata indicates one
+	if entry.metadata.content == ContentLocation::Outsourced {
+		entry.dvf_path = dvf_index.locate(inputfile, debug).map(|path| path.to_string_lossy().into_owned());
+	}
+
+	if !document_properties && !detect_encryption {
+		return Ok(());
+	}
+	let source = match entry.dvf_path {
+		Some(ref dvf_path) => Source::Outsourced(PathBuf::from(dvf_path)),
+		None => locate_content(&mut container, Option::None, debug)?,
+	};
+	let mut content = Content::new(&source);
+	// The content may be damaged or encrypted, which does not make the item unreadable
+	let mut notes = Vec::new();
+	if detect_encryption {
+		match detect_content_encryption(&mut container, &mut content, debug) {
+			Ok(encryption) => entry.encryption = encryption.map(|encryption| encryption.to_string()),
+			Err(e) => notes.push(format!("Unable to detect the encryption: {}", e)),
+		}
+	}
+	if document_properties {
+		match read_document_summary(&mut container, &mut content, debug) {
+			Ok(Some(summary)) => entry.document = summary,
+			Ok(None) => {},
+			Err(e) => notes.push(format!("Unable to read the summary information: {}", e)),
+		}
+	}
+	if !notes.is_empty() {
+		entry.notes = Option::Some(notes.join("; "));
+	}
+	Ok(())
+}
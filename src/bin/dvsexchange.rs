@@ -0,0 +1,211 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+extern crate clap;
+extern crate evrecovery;
+
+use std::io::{Write, stderr};
+use std::io::Error;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions, create_dir_all};
+use std::path::{Path, PathBuf};
+use clap::{Arg, App};
+use evrecovery::cab::CabinetCache;
+use evrecovery::dvf::Index as DvfIndex;
+use evrecovery::exchange::{is_mail_item, build_message, message_file_name, write_mbox_entry};
+use evrecovery::io::{Debug, unique_path};
+use evrecovery::metadata::Metadata;
+use evrecovery::restore::{Source, convert_to_path, find_inputs, open_input, open_payload, locate_content, locate_sharable, write_content};
+use evrecovery::sis::{Index as SisIndex, MissingPart};
+
+fn main() {
+	let matches = App::new("dvsexchange")
+		.version("1.0")
+		.author("Steve Muller <steve.muller@outlook.com>")
+		.about("This utility reads Enterprise Vault DVS files of mail items (archived from Exchange) and exports them as RFC 5322 messages (.eml files) or mbox files, placed according to their original folder.")
+		.arg(Arg::with_name("verbose")
+			.short("v")
+			.help("Increases the debug verbosity. This will print a lot of debug messages to standard error (STDERR). Can be used up to 4 times.")
+			.multiple(true)
+			.takes_value(false))
+		.arg(Arg::with_name("input")
+			.value_name("PATH")
+			.help("One or more DVS files, or directories (such as vault partitions) that will be searched recursively for DVS files. Items that are not mail items are skipped.")
+			.multiple(true)
+			.required(true))
+		.arg(Arg::with_name("target")
+			.value_name("TARGETDIR")
+			.help("The path of the directory where the messages shall be exported to, beneath their original folder path.")
+			.short("t")
+			.long("target")
+			.required(true))
+		.arg(Arg::with_name("format")
+			.value_name("FORMAT")
+			.help("The format of the export: 'eml' (one file per message, named after its subject) or 'mbox' (one mbox file per original folder, to which the messages are appended). Existing mbox files are overwritten.")
+			.short("f")
+			.long("format")
+			.possible_values(&["eml", "mbox"])
+			.default_value("eml")
+			.required(false))
+		.arg(Arg::with_name("ext")
+			.value_name("FILEEXT")
+			.help("The file extension of outsourced files.")
+			.long("ext")
+			.default_value("dvf")
+			.required(false))
+		.arg(Arg::with_name("dvf-root")
+			.value_name("DIR")
+			.help("An additional directory that is searched recursively for outsourced files. Can be specified several times.")
+			.long("dvf-root")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+		.arg(Arg::with_name("sis-root")
+			.value_name("DIR")
+			.help("An additional directory that is searched recursively for sharable parts ('.dvssp') of Single Instance Storage. Can be specified several times.")
+			.long("sis-root")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+	.get_matches();
+
+	let verbose = matches.occurrences_of("verbose") as i8 - 1;
+	let inputs = matches.values_of("input").unwrap().collect::<Vec<&str>>();
+	let target_dir = Path::new(matches.value_of("target").unwrap());
+	let is_mbox = matches.value_of("format") == Some("mbox");
+	let outsourced_extension = matches.value_of("ext").unwrap();
+	let dvf_roots = matches.values_of("dvf-root").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+	let sis_roots = matches.values_of("sis-root").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+
+	let mut debug = Debug::new(stderr(), verbose);
+
+	let mut dvf_index = DvfIndex::new(outsourced_extension);
+	for dvf_root in dvf_roots {
+		if let Err(e) = dvf_index.add_root(Path::new(dvf_root), &mut debug) {
+			eprintln!("I/O ERROR: Unable to search '{}' for outsourced files: {}", dvf_root, e);
+			std::process::exit(1);
+		}
+	}
+	let mut sis_index = SisIndex::new();
+	for sis_root in sis_roots {
+		if let Err(e) = sis_index.add_root(Path::new(sis_root), &mut debug) {
+			eprintln!("I/O ERROR: Unable to search '{}' for sharable parts: {}", sis_root, e);
+			std::process::exit(1);
+		}
+	}
+
+	// The mbox files written so far, which are appended to rather than overwritten
+	let mut mbox_files = match is_mbox {
+		true => Option::Some(HashSet::new()),
+		false => Option::None,
+	};
+	let mut cabinets = CabinetCache::new();
+	let mut num_exported = 0;
+	let mut num_skipped = 0;
+	let mut num_failed = 0;
+	let mut num_missing_parts = 0;
+	for input in inputs {
+		let inputfiles = match find_inputs(Path::new(input), &mut debug) {
+			Ok(inputfiles) => inputfiles,
+			Err(e) => {
+				eprintln!("I/O ERROR: {}: {}", input, e);
+				num_failed += 1;
+				continue;
+			},
+		};
+
+		for inputfile in inputfiles {
+			debug.logln(0, format!("Processing {:?} ...", inputfile));
+			match export_file(&inputfile, target_dir, mbox_files.as_mut(), &mut dvf_index, &mut sis_index, &mut cabinets, &mut debug) {
+				Ok(Some(output)) => {
+					debug.logln(0, format!("Exported to {:?}.", output));
+					num_exported += 1;
+				},
+				Ok(None) => {
+					debug.logln(0, format!("Skipping {:?} (not a mail item).", inputfile));
+					num_skipped += 1;
+				},
+				Err(ref e) if MissingPart::from_error(e).is_some() => {
+					eprintln!("MISSING PART ERROR: {}: {}", inputfile.to_string_lossy(), e);
+					num_missing_parts += 1;
+				},
+				Err(e) => {
+					eprintln!("I/O ERROR: {}: {}", inputfile.to_string_lossy(), e);
+					num_failed += 1;
+				},
+			}
+		}
+	}
+
+	eprintln!("{} messages exported, {} other items skipped, {} failed, {} with missing sharable parts", num_exported, num_skipped, num_failed, num_missing_parts);
+	if num_failed > 0 {
+		std::process::exit(1);
+	}
+	if num_missing_parts > 0 {
+		std::process::exit(3);
+	}
+}
+
+/// Exports a single DVS file, if it is a mail item.
+/// Returns the path of the file that the message has been written (or appended) to, or `None` if the item is not a mail item.
+/// With `mbox_files` (the mbox files written so far), the message is appended to the mbox file of its folder;
+/// an mbox file that has not been written so far is truncated first, so that exporting again does not duplicate messages.
+fn export_file(inputfile: &Path, target_dir: &Path, mbox_files: Option<&mut HashSet<PathBuf>>, dvf_index: &mut DvfIndex, sis_index: &mut SisIndex, cabinets: &mut CabinetCache, debug: &mut Debug) -> Result<Option<PathBuf>, Error> {
+	let mut container = open_payload(open_input(inputfile, cabinets, debug)?, debug)?;
+	if !is_mail_item(&mut container, debug)? {
+		return Ok(None);
+	}
+	let metadata = Metadata::read(&mut container, debug)?;
+
+	// Read the content of the item: an outsourced file, an embedded file, or a sharable part
+	let outsourced = dvf_index.locate(inputfile, debug);
+	let source = match locate_content(&mut container, outsourced.as_deref(), debug)? {
+		Source::Missing => locate_sharable(&mut container, sis_index, inputfile, debug)?,
+		source => source,
+	};
+	let mut content: Vec<u8> = Vec::new();
+	write_content(&mut container, &source, &mut content, debug)?;
+	let message = build_message(&content, &metadata, debug)?;
+
+	// Place the message beneath its original folder
+	let folder = convert_to_path(metadata.folder_path.as_deref().unwrap_or(""), false, true, &mut Vec::new());
+	match mbox_files {
+		Some(mbox_files) => {
+			let mbox_path = match folder.file_name() {
+				Some(name) => target_dir.join(&folder).with_file_name(format!("{}.mbox", name.to_string_lossy())),
+				None => target_dir.join("Mailbox.mbox"),
+			};
+			if let Some(dir) = mbox_path.parent() {
+				create_dir_all(dir)?;
+			}
+			let mut output = match mbox_files.insert(mbox_path.clone()) {
+				true => File::create(&mbox_path)?,
+				false => OpenOptions::new().append(true).open(&mbox_path)?,
+			};
+			write_mbox_entry(&mut output, &message, metadata.created_time.or(metadata.archived_date))?;
+			Ok(Some(mbox_path))
+		},
+		None => {
+			let dir = target_dir.join(&folder);
+			create_dir_all(&dir)?;
+			let eml_path = unique_path(&dir, message_file_name(&metadata));
+			File::create(&eml_path)?.write_all(&message)?;
+			Ok(Some(eml_path))
+		},
+	}
+}
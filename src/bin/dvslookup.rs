@@ -0,0 +1,283 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+extern crate clap;
+extern crate evrecovery;
+
+use std::io::{Error, ErrorKind, stderr};
+use std::env::current_dir;
+use std::fs::{File, create_dir_all, metadata};
+use std::path::{Path, PathBuf};
+use clap::{Arg, ArgGroup, App};
+use evrecovery::cab::CabinetCache;
+use evrecovery::catalogue::{Entry, read_file as read_catalogue};
+use evrecovery::dvf::Index as DvfIndex;
+use evrecovery::io::Debug;
+use evrecovery::lookup::{DateRange, Pattern, Query, group_versions};
+use evrecovery::metadata::{ContentLocation, Metadata, format_timestamp};
+use evrecovery::propset::Summary;
+use evrecovery::restore::{Content, Source, find_inputs, open_input, open_payload, read_original_path_or_title, locate_content, write_content};
+
+fn main() {
+	let matches = App::new("dvslookup")
+		.version("1.0")
+		.author("Steve Muller <steve.muller@outlook.com>")
+		.about("This utility finds the DVS files that archive a given original path, lists all of their versions, and optionally restores them.")
+		.arg(Arg::with_name("verbose")
+			.short("v")
+			.help("Increases the debug verbosity. This will print a lot of debug messages to standard error (STDERR). Can be used up to 4 times.")
+			.multiple(true)
+			.takes_value(false))
+		.arg(Arg::with_name("pattern")
+			.value_name("PATH")
+			.help("The original path to look for, e.g. 'F:\\Data\\Projects\\x.docx'. The comparison is case-insensitive. '?' matches a single character, '*' matches any characters within a directory, and '**' matches any characters across directories.")
+			.required(true))
+		.arg(Arg::with_name("catalogue")
+			.value_name("FILE")
+			.help("A catalogue written by dvscatalogue (CSV, NDJSON or SQLite), which serves as the metadata index. Can be specified several times.")
+			.short("c")
+			.long("catalogue")
+			.multiple(true)
+			.number_of_values(1))
+		.arg(Arg::with_name("partition")
+			.value_name("PATH")
+			.help("A DVS file, or a directory (such as a vault partition) that is searched recursively for DVS files, whose metadata is read on the fly. Can be specified several times.")
+			.short("p")
+			.long("partition")
+			.multiple(true)
+			.number_of_values(1))
+		.group(ArgGroup::with_name("index")
+			.args(&["catalogue", "partition"])
+			.multiple(true)
+			.required(true))
+		.arg(Arg::with_name("created")
+			.value_name("RANGE")
+			.help("Only matches items created within the given range of dates (UTC), in the format 'FROM..TO' (e.g. '2017-01-01..2017-06-30'). Either end may be omitted, and a single date matches that whole day.")
+			.long("created")
+			.required(false))
+		.arg(Arg::with_name("modified")
+			.value_name("RANGE")
+			.help("Only matches items last modified within the given range of dates (see --created).")
+			.long("modified")
+			.required(false))
+		.arg(Arg::with_name("archived")
+			.value_name("RANGE")
+			.help("Only matches items archived within the given range of dates (see --created).")
+			.long("archived")
+			.required(false))
+		.arg(Arg::with_name("target")
+			.value_name("TARGETDIR")
+			.help("If specified, the most recent version of every matching file is restored beneath this directory, under its original path/filename.")
+			.short("t")
+			.long("target")
+			.required(false))
+		.arg(Arg::with_name("all-versions")
+			.long("all-versions")
+			.help("If set, all versions of every matching file are restored. Older versions get a ' (version N)' suffix before their file extension.")
+			.takes_value(false)
+			.requires("target")
+			.required(false))
+		.arg(Arg::with_name("ext")
+			.value_name("FILEEXT")
+			.help("The file extension of outsourced files.")
+			.long("ext")
+			.default_value("dvf")
+			.required(false))
+		.arg(Arg::with_name("dvf-root")
+			.value_name("DIR")
+			.help("An additional directory that is searched recursively for outsourced files. Can be specified several times.")
+			.long("dvf-root")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+	.get_matches();
+
+	let verbose = matches.occurrences_of("verbose") as i8 - 1;
+	let catalogues = matches.values_of("catalogue").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+	let partitions = matches.values_of("partition").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+	let target_dir = matches.value_of("target");
+	let all_versions = matches.is_present("all-versions");
+	let outsourced_extension = matches.value_of("ext").unwrap();
+	let dvf_roots = matches.values_of("dvf-root").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+
+	let range = |name: &str| match matches.value_of(name) {
+		Some(text) => match DateRange::parse(text) {
+			Some(range) => range,
+			None => {
+				eprintln!("ERROR: Invalid range of dates for --{}: '{}'", name, text);
+				std::process::exit(1);
+			},
+		},
+		None => DateRange::default(),
+	};
+	let query = Query {
+		pattern: Pattern::new(matches.value_of("pattern").unwrap()),
+		created: range("created"),
+		modified: range("modified"),
+		archived: range("archived"),
+	};
+
+	let mut debug = Debug::new(stderr(), verbose);
+	let mut dvf_index = DvfIndex::new(outsourced_extension);
+	for dvf_root in dvf_roots {
+		if let Err(e) = dvf_index.add_root(Path::new(dvf_root), &mut debug) {
+			eprintln!("I/O ERROR: Unable to search '{}' for outsourced files: {}", dvf_root, e);
+			std::process::exit(1);
+		}
+	}
+
+	// Collect the matching entries from all catalogues and partitions
+	let mut num_failed = 0;
+	let mut entries: Vec<Entry> = Vec::new();
+	for catalogue in catalogues {
+		debug.logln(0, format!("Reading catalogue '{}' ...", catalogue));
+		match read_catalogue(Path::new(catalogue)) {
+			Ok(catalogue_entries) => entries.extend(catalogue_entries.into_iter().filter(|entry| entry.error.is_none() && query.matches(&entry.metadata))),
+			Err(e) => {
+				eprintln!("I/O ERROR: {}: {}", catalogue, e);
+				num_failed += 1;
+			},
+		}
+	}
+	let mut cabinets = CabinetCache::new();
+	for partition in partitions {
+		let inputfiles = match find_inputs(Path::new(partition), &mut debug) {
+			Ok(inputfiles) => inputfiles,
+			Err(e) => {
+				eprintln!("I/O ERROR: {}: {}", partition, e);
+				num_failed += 1;
+				continue;
+			},
+		};
+		for inputfile in inputfiles {
+			match read_metadata(&inputfile, &mut cabinets, &mut debug) {
+				Ok(metadata) => if query.matches(&metadata) {
+					entries.push(Entry {
+						dvs_path: inputfile.to_string_lossy().into_owned(),
+						dvs_size: std::fs::metadata(&inputfile).map(|metadata| metadata.len()).unwrap_or(0),
+						dvf_path: Option::None,
+						metadata,
+						document: Summary::default(),
+						encryption: Option::None,
+						error: Option::None,
+						notes: Option::None,
+					});
+				},
+				Err(e) => {
+					eprintln!("I/O ERROR: {}: {}", inputfile.to_string_lossy(), e);
+					num_failed += 1;
+				},
+			}
+		}
+	}
+
+	// Resolve the outsourced files that the catalogues do not know about
+	for entry in entries.iter_mut() {
+		if entry.dvf_path.is_none() && entry.metadata.content == ContentLocation::Outsourced {
+			entry.dvf_path = dvf_index.locate(Path::new(&entry.dvs_path), &mut debug).map(|path| path.to_string_lossy().into_owned());
+		}
+	}
+
+	let groups = group_versions(&entries);
+	if groups.is_empty() {
+		eprintln!("No matching items found.");
+	}
+	for versions in &groups {
+		println!("{} ({} version{})", versions.original_path, versions.entries.len(), if versions.entries.len() == 1 { "" } else { "s" });
+		for (index, entry) in versions.entries.iter().enumerate() {
+			let timestamp = |timestamp: Option<u64>| timestamp.map(format_timestamp).unwrap_or_else(|| "-".to_owned());
+			let size = entry.metadata.original_size.or(entry.metadata.large_file_size);
+			println!("  {:>3}  modified {}  archived {}  {} bytes  {}",
+				index + 1,
+				timestamp(entry.metadata.modified_time),
+				timestamp(entry.metadata.archived_date),
+				size.map(|size| size.to_string()).unwrap_or_else(|| "?".to_owned()),
+				entry.dvs_path);
+			match entry.dvf_path {
+				Some(ref dvf_path) => println!("       outsourced: {}", dvf_path),
+				None if entry.metadata.content == ContentLocation::Outsourced => println!("       outsourced: (not found)"),
+				None => {},
+			}
+		}
+	}
+
+	// Restore the matches, if requested
+	if let Some(target_dir) = target_dir {
+		for versions in &groups {
+			let num_versions = versions.entries.len();
+			for (index, entry) in versions.entries.iter().enumerate() {
+				let is_latest = index + 1 == num_versions;
+				if !is_latest && !all_versions {
+					continue;
+				}
+				let version = match is_latest { true => Option::None, false => Option::Some(index + 1) };
+				match restore_entry(entry, target_dir, version, &mut cabinets, &mut debug) {
+					Ok(target_path) => println!("Restored {} to {:?}", entry.dvs_path, target_path),
+					Err(e) => {
+						eprintln!("I/O ERROR: {}: {}", entry.dvs_path, e);
+						num_failed += 1;
+					},
+				}
+			}
+		}
+	}
+
+	if num_failed > 0 {
+		std::process::exit(1);
+	}
+}
+
+fn read_metadata(inputfile: &Path, cabinets: &mut CabinetCache, debug: &mut Debug) -> Result<Metadata, Error> {
+	debug.logln(0, format!("Reading {:?} ...", inputfile));
+	let mut container = open_payload(open_input(inputfile, cabinets, debug)?, debug)?;
+	Metadata::read(&mut container, debug)
+}
+
+/// Restores a single matching entry beneath the target directory.
+/// If a version number is given, it is appended to the file name, so that several versions can be restored side by side.
+fn restore_entry(entry: &Entry, target_dir: &str, version: Option<usize>, cabinets: &mut CabinetCache, debug: &mut Debug) -> Result<PathBuf, Error> {
+	let mut container = open_payload(open_input(Path::new(&entry.dvs_path), cabinets, debug)?, debug)?;
+	let source = locate_content(&mut container, entry.dvf_path.as_ref().map(Path::new), debug)?;
+	if let Source::Missing = source {
+		return Err(Error::new(ErrorKind::InvalidData, "Unable to find embedded file '/**/FileContentStream', and no outsourced file can be found!"));
+	}
+	let original_path = read_original_path_or_title(&mut container, &mut Content::new(&source), true, debug)?;
+
+	let target_root = PathBuf::from(target_dir);
+	create_dir_all(&target_root)?;
+	let target_root = target_root.canonicalize().or_else(|_| current_dir().map(|dir| dir.join(target_dir)))?;
+	let mut target_path = target_root.join(original_path.path());
+	if let Some(version) = version {
+		let stem = target_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+		let file_name = match target_path.extension() {
+			Some(extension) => format!("{} (version {}).{}", stem, version, extension.to_string_lossy()),
+			None => format!("{} (version {})", stem, version),
+		};
+		target_path.set_file_name(file_name);
+	}
+
+	if let Some(dir) = target_path.parent() {
+		create_dir_all(dir)?;
+	}
+	if metadata(&target_path).is_ok() {
+		return Err(Error::new(ErrorKind::AlreadyExists, format!("The target {:?} already exists", target_path)));
+	}
+	let mut target_file = File::create(&target_path)?;
+	write_content(&mut container, &source, &mut target_file, debug)?;
+	Ok(target_path)
+}
@@ -0,0 +1,150 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+extern crate clap;
+extern crate evrecovery;
+
+use std::io::{Read, Write, BufWriter, stdout, stderr};
+use std::fs::File;
+use std::path::Path;
+use clap::{Arg, App};
+use evrecovery::io::{Debug, decode_text_file};
+use evrecovery::reconcile::{Restored, Status, parse_placeholders, read_manifest, reconcile, write_report, write_script};
+
+fn main() {
+	let matches = App::new("dvsreconcile")
+		.version("1.0")
+		.author("Steve Muller <steve.muller@outlook.com>")
+		.about("This utility compares a list of placeholders on the file server against the restored originals, and generates a PowerShell script that puts the originals back in place.")
+		.arg(Arg::with_name("verbose")
+			.short("v")
+			.help("Increases the debug verbosity. This will print a lot of debug messages to standard error (STDERR). Can be used up to 4 times.")
+			.multiple(true)
+			.takes_value(false))
+		.arg(Arg::with_name("placeholders")
+			.value_name("PLACEHOLDERLIST")
+			.help("The list of placeholders: a list of paths (one per line), a CSV file with 'FullName' and 'Length' columns, or the output of 'fsutil reparsepoint query' for every placeholder (each preceded by a line with its path).")
+			.required(true))
+		.arg(Arg::with_name("manifest")
+			.value_name("FILE")
+			.help("A restore manifest: a journal written by 'dvsrestore --journal', or a plan written by 'dvsrestore --dry-run --plan'. Can be specified several times.")
+			.short("m")
+			.long("manifest")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+		.arg(Arg::with_name("restored")
+			.value_name("DIR")
+			.help("A directory beneath which files have been restored under their original paths (e.g. the target directory of dvsrestore). Placeholders without a match in any manifest are looked up in these directories, in the given order. Can be specified several times.")
+			.short("r")
+			.long("restored")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+		.arg(Arg::with_name("output")
+			.value_name("FILE")
+			.help("The file where the report shall be written to. If omitted, the report is written to STDOUT.")
+			.short("o")
+			.long("output")
+			.required(false))
+		.arg(Arg::with_name("script")
+			.value_name("FILE")
+			.help("If specified, a PowerShell script that replaces every matched placeholder by its restored original is written to this file.")
+			.long("script")
+			.required(false))
+	.get_matches();
+
+	let verbose = matches.occurrences_of("verbose") as i8 - 1;
+	let placeholder_file = matches.value_of("placeholders").unwrap();
+	let manifests = matches.values_of("manifest").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+	let restore_roots = matches.values_of("restored").map(|values| values.map(Path::new).collect::<Vec<&Path>>()).unwrap_or_default();
+	let outputfile = matches.value_of("output");
+	let scriptfile = matches.value_of("script");
+	let mut debug = Debug::new(stderr(), verbose);
+
+	if manifests.is_empty() && restore_roots.is_empty() {
+		eprintln!("ERROR: At least one restore manifest (--manifest) or directory of restored files (--restored) is needed.");
+		std::process::exit(1);
+	}
+
+	debug.logln(0, format!("Reading placeholder list '{}' ...", placeholder_file));
+	let placeholders = match read_file(placeholder_file).and_then(|data| parse_placeholders(&decode_text_file(&data))) {
+		Ok(placeholders) => placeholders,
+		Err(e) => {
+			eprintln!("I/O ERROR: {}: {}", placeholder_file, e);
+			std::process::exit(1);
+		},
+	};
+	debug.logln(0, format!("Read {} placeholders.", placeholders.len()));
+
+	let mut restored: Vec<Restored> = Vec::new();
+	for manifest in manifests {
+		debug.logln(0, format!("Reading restore manifest '{}' ...", manifest));
+		match File::open(manifest).and_then(read_manifest) {
+			Ok(manifest_restored) => restored.extend(manifest_restored),
+			Err(e) => {
+				eprintln!("I/O ERROR: {}: {}", manifest, e);
+				std::process::exit(1);
+			},
+		}
+	}
+	debug.logln(0, format!("Read {} restored files.", restored.len()));
+
+	let reconciliations = reconcile(&placeholders, &restored, &restore_roots);
+
+	let output: Box<dyn Write> = match outputfile {
+		None | Some("-") => Box::new(stdout()),
+		Some(outputfile) => match File::create(outputfile) {
+			Ok(file) => Box::new(file),
+			Err(e) => {
+				eprintln!("I/O ERROR: Unable to create '{}': {}", outputfile, e);
+				std::process::exit(1);
+			},
+		},
+	};
+	let mut output = BufWriter::new(output);
+	if let Err(e) = write_report(&reconciliations, &mut output).and_then(|_| output.flush()) {
+		eprintln!("I/O ERROR: Unable to write report: {}", e);
+		std::process::exit(1);
+	}
+
+	if let Some(scriptfile) = scriptfile {
+		let result = File::create(scriptfile).and_then(|file| {
+			let mut script = BufWriter::new(file);
+			write_script(&reconciliations, &mut script)?;
+			script.flush()
+		});
+		if let Err(e) = result {
+			eprintln!("I/O ERROR: Unable to write script '{}': {}", scriptfile, e);
+			std::process::exit(1);
+		}
+	}
+
+	let count = |status: Status| reconciliations.iter().filter(|reconciliation| reconciliation.status == status).count();
+	eprintln!("{} placeholders: {} matched, {} missing, {} size mismatches",
+		reconciliations.len(),
+		count(Status::Matched),
+		count(Status::Missing),
+		count(Status::SizeMismatch));
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, std::io::Error> {
+	let mut data = Vec::new();
+	File::open(path)?.read_to_end(&mut data)?;
+	Ok(data)
+}
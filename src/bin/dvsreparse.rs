@@ -0,0 +1,125 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+extern crate clap;
+extern crate evrecovery;
+
+use std::io::{Read, Error, stderr};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use clap::{Arg, App};
+use evrecovery::io::{Debug, decode_text_file};
+use evrecovery::restore::find_inputs;
+use evrecovery::reparse::{Placeholder, ReparsePoint, is_fsutil_output, match_dvs_files, parse_fsutil};
+
+fn main() {
+	let matches = App::new("dvsreparse")
+		.version("1.0")
+		.author("Steve Muller <steve.muller@outlook.com>")
+		.about("This utility parses the reparse points of Enterprise Vault placeholders, and finds the DVS files that they refer to.")
+		.arg(Arg::with_name("verbose")
+			.short("v")
+			.help("Increases the debug verbosity. This will print a lot of debug messages to standard error (STDERR). Can be used up to 4 times.")
+			.multiple(true)
+			.takes_value(false))
+		.arg(Arg::with_name("input")
+			.value_name("FILE")
+			.help("One or more files that contain either the output of 'fsutil reparsepoint query' (possibly for several placeholders, each preceded by a line with its path), or a raw reparse buffer.")
+			.multiple(true)
+			.required(true))
+		.arg(Arg::with_name("partition")
+			.value_name("PATH")
+			.help("A DVS file, or a directory (such as a vault partition) that is searched recursively for DVS files. The placeholders are matched to these DVS files by the ID of their saveset. Can be specified several times.")
+			.short("p")
+			.long("partition")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+	.get_matches();
+
+	let verbose = matches.occurrences_of("verbose") as i8 - 1;
+	let inputs = matches.values_of("input").unwrap().collect::<Vec<&str>>();
+	let partitions = matches.values_of("partition").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+
+	let mut debug = Debug::new(stderr(), verbose);
+	let mut num_failed = 0;
+
+	let mut placeholders: Vec<Placeholder> = Vec::new();
+	for input in inputs {
+		debug.logln(0, format!("Reading '{}' ...", input));
+		match read_placeholders(input) {
+			Ok(input_placeholders) => placeholders.extend(input_placeholders),
+			Err(e) => {
+				eprintln!("I/O ERROR: {}: {}", input, e);
+				num_failed += 1;
+			},
+		}
+	}
+
+	let mut dvs_files: Vec<PathBuf> = Vec::new();
+	for partition in partitions {
+		debug.logln(0, format!("Searching '{}' for DVS files ...", partition));
+		match find_inputs(Path::new(partition), &mut debug) {
+			Ok(files) => dvs_files.extend(files),
+			Err(e) => {
+				eprintln!("I/O ERROR: {}: {}", partition, e);
+				num_failed += 1;
+			},
+		}
+	}
+	let dvs_matches = match_dvs_files(&placeholders, &dvs_files);
+
+	for (placeholder, dvs_files_found) in placeholders.iter().zip(dvs_matches.iter()) {
+		let reparse_point = &placeholder.reparse_point;
+		let unknown = || "?".to_owned();
+		println!("{}", placeholder.path.clone().unwrap_or_else(unknown));
+		println!("  tag: 0x{:08X}, GUID: {}", reparse_point.tag, reparse_point.guid.clone().unwrap_or_else(unknown));
+		println!("  vault store: {}", reparse_point.vault_store.clone().unwrap_or_else(unknown));
+		println!("  file size: {}, allocation size: {}",
+			reparse_point.file_size.map(|size| size.to_string()).unwrap_or_else(unknown),
+			reparse_point.allocation_size.map(|size| size.to_string()).unwrap_or_else(unknown));
+		println!("  vault ID: {}", reparse_point.vault_id.clone().unwrap_or_else(unknown));
+		println!("  saveset ID: {}", reparse_point.saveset_id.clone().unwrap_or_else(unknown));
+		println!("  URL: {}", reparse_point.url.clone().unwrap_or_else(unknown));
+		if !dvs_files.is_empty() {
+			match dvs_files_found.len() {
+				0 => println!("  DVS: (not found)"),
+				_ => for dvs_file in dvs_files_found {
+					println!("  DVS: {}", dvs_file.to_string_lossy());
+				},
+			}
+		}
+	}
+
+	if num_failed > 0 {
+		std::process::exit(1);
+	}
+}
+
+/// Reads the placeholders from an input, which is either `fsutil` output or a raw reparse buffer.
+/// A raw reparse buffer is labelled with the name of the input file.
+fn read_placeholders(input: &str) -> Result<Vec<Placeholder>, Error> {
+	let mut data: Vec<u8> = Vec::new();
+	File::open(input)?.read_to_end(&mut data)?;
+
+	let text = decode_text_file(&data);
+	if is_fsutil_output(&text) {
+		return parse_fsutil(&text);
+	}
+	Ok(vec![Placeholder { path: Some(input.to_owned()), reparse_point: ReparsePoint::from_bytes(&data)? }])
+}
@@ -19,20 +19,27 @@ along with this program.  If not, see <http://www.gnu.org/licenses/>.
 extern crate clap;
 extern crate evrecovery;
 
-use std::io::{Read, Seek, Write, stdin, stdout, stderr};
+use std::io::{Read, Write, stdin, stdout, stderr};
 use std::io::{Error, ErrorKind};
-use std::io::copy;
-use std::fs::{File, create_dir_all};
-use std::path::{Component, PathBuf};
-use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use std::env::current_dir;
+use std::fs::{File, create_dir_all, metadata};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 use clap::{Arg, App};
-use evrecovery::cfbf::{Container, ObjectResult};
+use evrecovery::cfbf::Container;
 use evrecovery::io::SeekableRead;
-use evrecovery::dvs::File as DvsFile;
+use evrecovery::cab::{CabinetCache, split_member_path};
 use evrecovery::io::Debug;
-
-trait ReadSeek: Read + Seek { }
-impl<T> ReadSeek for T where T: Read + Seek { }
+use evrecovery::dvf::Index as DvfIndex;
+use evrecovery::journal::{Journal, Entry as JournalEntry, Outcome};
+use evrecovery::restore::{Content, Source, find_inputs, open_input, open_payload, read_original_path_or_title, locate_content, locate_sharable, read_vba_projects, sniff_content, detect_content_encryption, write_content};
+use evrecovery::metadata::Metadata;
+use evrecovery::metadata::indexed::IndexedItem;
+use evrecovery::catalogue::write_sidecar;
+use evrecovery::sis::{Index as SisIndex, MissingPart};
+use evrecovery::sniff::{check_metadata, fix_extension};
+use evrecovery::restore::plan::{Plan, PlannedSource, Step};
+use evrecovery::verify::{Expectation, Mismatch, Verifier};
 
 fn main() {
 	let matches = App::new("dvsrestore")
@@ -46,7 +53,8 @@ fn main() {
 			.takes_value(false))
 		.arg(Arg::with_name("input")
 			.value_name("FILE")
-			.help("A DVS file. If omitted, the file will be read from STDIN instead.")
+			.help("One or more DVS files, or directories that will be searched recursively for DVS files. If omitted, a single DVS file will be read from STDIN instead.")
+			.multiple(true)
 			.required(false))
 		.arg(Arg::with_name("path-only")
 			.long("path-only")
@@ -65,221 +73,742 @@ fn main() {
 			.long("ext")
 			.default_value("dvf")
 			.required(false))
+		.arg(Arg::with_name("verify")
+			.long("verify")
+			.help("If set, the size of the restored content is compared against the 'OriginalSize' (embedded files) or 'LargeFileSize' (outsourced files) properties. Mismatches are reported as verification errors.")
+			.takes_value(false)
+			.required(false))
+		.arg(Arg::with_name("verify-checksum")
+			.long("verify-checksum")
+			.help("Like --verify, but additionally compares the restored content against the 'Checksum' property, if its format can be decoded (CRC-32, MD5 or SHA-1).")
+			.takes_value(false)
+			.required(false))
+		.arg(Arg::with_name("check-types")
+			.long("check-types")
+			.help("If set, the format of the restored content is determined by inspecting it, and mismatches against the 'MIMEType' and 'File Extension' properties and the extension of the target file name are reported as type warnings.")
+			.takes_value(false)
+			.required(false))
+		.arg(Arg::with_name("fix-extensions")
+			.long("fix-extensions")
+			.help("Like --check-types, but additionally corrects the extension of the target file name if it does not match the content: a missing extension is appended, and the extension of another format is replaced.")
+			.takes_value(false)
+			.required(false))
+		.arg(Arg::with_name("extract-vba")
+			.long("extract-vba")
+			.help("If set, the VBA macros of every restored legacy or macro-enabled Office document are exported next to it, into the directory '<target file>.vba', so that they can be reviewed.")
+			.takes_value(false)
+			.required(false))
+		.arg(Arg::with_name("indexed-text")
+			.long("indexed-text")
+			.help("If set, the text that has been indexed for an archived file ('Indexable Item Stream') is written to '<target file>.txt' if neither an embedded, an outsourced nor a sharable file can be found, so that at least the text is recovered.")
+			.takes_value(false)
+			.required(false))
+		.arg(Arg::with_name("sidecar")
+			.long("sidecar")
+			.help("If set, the metadata of every restored file (including the decoded 'UserXMLStream' and 'AgentProperties' streams) is written next to it, into the JSON file '<target file>.metadata.json'.")
+			.takes_value(false)
+			.required(false))
+		.arg(Arg::with_name("dvf-root")
+			.value_name("DIR")
+			.help("An additional directory that is searched recursively for outsourced files, e.g. on another vault partition. Outsourced files are always looked for next to the DVS file and in the sub-folders of its directory. Can be specified several times.")
+			.long("dvf-root")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+		.arg(Arg::with_name("sis-root")
+			.value_name("DIR")
+			.help("An additional directory that is searched recursively for sharable parts ('.dvssp') and converted content ('.dvscc') of Single Instance Storage. They are always looked for next to the DVS file and in the sub-folders of its directory. Can be specified several times.")
+			.long("sis-root")
+			.multiple(true)
+			.number_of_values(1)
+			.required(false))
+		.arg(Arg::with_name("unclaimed")
+			.value_name("FILE")
+			.help("If specified, the paths of all indexed outsourced files that do not belong to any processed DVS file are written to this file, one per line.")
+			.long("unclaimed")
+			.required(false))
+		.arg(Arg::with_name("dry-run")
+			.long("dry-run")
+			.help("If set, every input is parsed and its target path and content source are resolved, but nothing is written. A report on conflicts, path rewrites, missing content and the total number of bytes needed is output to STDOUT.")
+			.takes_value(false)
+			.requires_all(&["target", "input"])
+			.conflicts_with_all(&["path-only", "journal", "execute-plan"])
+			.required(false))
+		.arg(Arg::with_name("plan")
+			.value_name("PLANFILE")
+			.help("The file where a dry run writes its plan. The plan lists, for every input, where its content comes from and where it would be written to. It can be reviewed and edited, and then be executed with --execute-plan.")
+			.long("plan")
+			.requires("dry-run")
+			.required(false))
+		.arg(Arg::with_name("execute-plan")
+			.value_name("PLANFILE")
+			.help("Restores the files exactly as listed in the given plan (produced by --dry-run --plan), instead of processing the inputs.")
+			.long("execute-plan")
+			.conflicts_with_all(&["input", "path-only"])
+			.required(false))
+		.arg(Arg::with_name("journal")
+			.value_name("JOURNALFILE")
+			.help("A file in which the outcome of every processed input is recorded. Inputs that the journal lists as successfully restored (and that have not been modified since) are skipped, so that an interrupted run can be resumed.")
+			.short("j")
+			.long("journal")
+			.required(false))
+		.arg(Arg::with_name("retry-failed")
+			.long("retry-failed")
+			.help("If set, only the inputs that are recorded as failed in the journal are processed. If no input is specified, all failed inputs from the journal are retried.")
+			.takes_value(false)
+			.requires("journal")
+			.required(false))
 	.get_matches();
 
 	let verbose = matches.occurrences_of("verbose") as i8 - 1;
-	let inputfile = matches.value_of("input").unwrap_or("");
-	let target_dir = matches.value_of("target").unwrap_or("");
-	let pathonly = matches.occurrences_of("path-only") > 0;
+	let inputfiles = matches.values_of("input").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+	let options = Options {
+		target_dir: matches.value_of("target").unwrap_or(""),
+		pathonly: matches.occurrences_of("path-only") > 0,
+		verify: matches.occurrences_of("verify") > 0 || matches.occurrences_of("verify-checksum") > 0,
+		verify_checksum: matches.occurrences_of("verify-checksum") > 0,
+		check_types: matches.occurrences_of("check-types") > 0 || matches.occurrences_of("fix-extensions") > 0,
+		fix_extensions: matches.occurrences_of("fix-extensions") > 0,
+		extract_vba: matches.occurrences_of("extract-vba") > 0,
+		sidecar: matches.occurrences_of("sidecar") > 0,
+		indexed_text: matches.occurrences_of("indexed-text") > 0,
+	};
 	let outsourced_extension = matches.value_of("ext").unwrap();
+	let retry_failed = matches.occurrences_of("retry-failed") > 0;
+	let dvf_roots = matches.values_of("dvf-root").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+	let sis_roots = matches.values_of("sis-root").map(|values| values.collect::<Vec<&str>>()).unwrap_or_default();
+	let unclaimed_file = matches.value_of("unclaimed");
+	let dry_run = matches.occurrences_of("dry-run") > 0;
+	let plan_file = matches.value_of("plan");
+	let execute_plan_file = matches.value_of("execute-plan");
 
 	let mut debug = Debug::new(stderr(), verbose);
-	let input: Box<Read> = match inputfile {
-		"" | "-" => Box::new(stdin()),
-		_ => Box::new(File::open(inputfile).unwrap())
+
+	// Without any input file, read a single DVS file from STDIN
+	if !retry_failed && execute_plan_file.is_none() && (inputfiles.is_empty() || inputfiles == ["-"]) {
+		if let Err(e) = process(stdin(), &options, Option::None, Option::None, &mut debug) {
+			if Mismatch::from_error(&e).is_some() {
+				eprintln!("VERIFICATION ERROR: {}", e);
+				std::process::exit(2);
+			}
+			if MissingPart::from_error(&e).is_some() {
+				eprintln!("MISSING PART ERROR: {}", e);
+				std::process::exit(3);
+			}
+			eprintln!("I/O ERROR: {}", e);
+			std::process::exit(1);
+		}
+		return;
+	}
+
+	let mut journal = match matches.value_of("journal") {
+		Some(journalfile) => match Journal::open(Path::new(journalfile), &mut debug) {
+			Ok(journal) => Option::Some(journal),
+			Err(e) => {
+				eprintln!("I/O ERROR: Unable to open journal: {}", e);
+				std::process::exit(1);
+			},
+		},
+		None => Option::None,
 	};
 
-	let inputfile_outsourced: Option<String> = match inputfile {
-		"" | "-" => Option::None,
-		_ => if inputfile.to_lowercase().ends_with(".dvs") { Option::Some(format!("{}.{}", &inputfile[..inputfile.len()-4], outsourced_extension)) } else { Option::None },
+	// Determine the list of inputs, each with its planned step if a plan is executed
+	let jobs: Vec<(String, Option<Step>)> = match execute_plan_file {
+		Some(execute_plan_file) => match File::open(execute_plan_file).and_then(Plan::read) {
+			Ok(plan) => plan.steps.into_iter().map(|step| (step.input.clone(), Option::Some(step))).collect(),
+			Err(e) => {
+				eprintln!("I/O ERROR: Unable to read plan: {}", e);
+				std::process::exit(1);
+			},
+		},
+		None => match journal {
+			Some(ref journal) if retry_failed && inputfiles.is_empty() => journal.failed_inputs(),
+			_ => match collect_inputs(&inputfiles, &mut debug) {
+				Ok(inputfiles) => inputfiles,
+				Err(e) => {
+					eprintln!("I/O ERROR: {}", e);
+					std::process::exit(1);
+				},
+			},
+		}.into_iter().map(|inputfile| (inputfile, Option::None)).collect(),
 	};
 
-	if let Err(e) = process(input, target_dir, pathonly, inputfile_outsourced, &mut debug) {
-		eprintln!("I/O ERROR: {}", e);
+	// Index the outsourced files in the additional search roots
+	let mut dvf_index = DvfIndex::new(outsourced_extension);
+	for dvf_root in dvf_roots {
+		if let Err(e) = dvf_index.add_root(Path::new(dvf_root), &mut debug) {
+			eprintln!("I/O ERROR: Unable to search '{}' for outsourced files: {}", dvf_root, e);
+			std::process::exit(1);
+		}
+	}
+
+	// Index the sharable parts and converted content in the additional search roots
+	let mut sis_index = SisIndex::new();
+	let mut cabinets = CabinetCache::new();
+	for sis_root in sis_roots {
+		if let Err(e) = sis_index.add_root(Path::new(sis_root), &mut debug) {
+			eprintln!("I/O ERROR: Unable to search '{}' for sharable parts: {}", sis_root, e);
+			std::process::exit(1);
+		}
+	}
+
+	// In a dry run, only plan what would be done
+	if dry_run {
+		let mut plan = Plan::default();
+		for (inputfile, _) in jobs {
+			plan.steps.push(plan_input(&inputfile, &options, &mut dvf_index, &mut sis_index, &mut cabinets, &mut debug));
+		}
+		let num_conflicts = plan.detect_conflicts();
+		print_plan_report(&plan, num_conflicts);
+		if let Some(plan_file) = plan_file {
+			if let Err(e) = File::create(plan_file).and_then(|mut output| plan.write(&mut output)) {
+				eprintln!("I/O ERROR: Unable to write plan: {}", e);
+				std::process::exit(1);
+			}
+		}
+		return;
+	}
+
+	let mut num_failed = 0;
+	let mut num_mismatches = 0;
+	let mut num_missing_parts = 0;
+	for (inputfile, step) in jobs {
+		// Consult the journal to decide whether this input needs to be processed
+		let (size, mtime) = file_size_and_mtime(&inputfile);
+		if let Some(ref journal) = journal {
+			if retry_failed && !journal.is_failed(&inputfile) {
+				debug.logln(0, format!("Skipping '{}' (not recorded as failed).", inputfile));
+				continue;
+			}
+			if journal.is_done(&inputfile, size, mtime) {
+				debug.logln(0, format!("Skipping '{}' (already restored).", inputfile));
+				// The outsourced file of a skipped input must not be reported as unclaimed
+				if unclaimed_file.is_some() {
+					locate_outsourced(&inputfile, &mut dvf_index, &mut debug);
+				}
+				continue;
+			}
+		}
+
+		let result = match step {
+			Some(ref step) => process_step(step, &options, &mut cabinets, &mut debug),
+			None => process_file(&inputfile, &options, &mut dvf_index, &mut sis_index, &mut cabinets, &mut debug),
+		};
+		let (outcome, output, encryption) = match result {
+			Ok(restored) => (Outcome::Ok, restored.path.map(|output| output.to_string_lossy().into_owned()), restored.encryption),
+			Err(ref e) if Mismatch::from_error(e).is_some() => {
+				eprintln!("VERIFICATION ERROR: {}: {}", inputfile, e);
+				num_mismatches += 1;
+				(Outcome::Mismatch, Option::None, Option::None)
+			},
+			Err(ref e) if MissingPart::from_error(e).is_some() => {
+				eprintln!("MISSING PART ERROR: {}: {}", inputfile, e);
+				num_missing_parts += 1;
+				(Outcome::Failed, Option::None, Option::None)
+			},
+			Err(ref e) => {
+				eprintln!("I/O ERROR: {}: {}", inputfile, e);
+				num_failed += 1;
+				(Outcome::Failed, Option::None, Option::None)
+			},
+		};
+
+		// Record the outcome
+		if let Some(ref mut journal) = journal {
+			if let Err(e) = journal.record(JournalEntry { input: inputfile, size, mtime, outcome, output, encryption }) {
+				eprintln!("I/O ERROR: Unable to write to journal: {}", e);
+				std::process::exit(1);
+			}
+		}
+	}
+
+	// Report the outsourced files that do not belong to any DVS file
+	if let Some(unclaimed_file) = unclaimed_file {
+		let unclaimed = dvf_index.unclaimed();
+		debug.logln(0, format!("Found {} unclaimed outsourced files.", unclaimed.len()));
+		let result = File::create(unclaimed_file).and_then(|mut output| {
+			unclaimed.iter().try_for_each(|path| writeln!(output, "{}", path.to_string_lossy()))
+		});
+		if let Err(e) = result {
+			eprintln!("I/O ERROR: Unable to write the list of unclaimed outsourced files: {}", e);
+			std::process::exit(1);
+		}
+	}
+
+	if num_missing_parts > 0 {
+		debug.logln(0, format!("{} inputs refer to sharable parts that cannot be found.", num_missing_parts));
+	}
+	if num_failed > 0 {
 		std::process::exit(1);
 	}
+	if num_missing_parts > 0 {
+		std::process::exit(3);
+	}
+	if num_mismatches > 0 {
+		std::process::exit(2);
+	}
+}
+
+/// The options that control how every input is processed.
+struct Options<'a> {
+	target_dir: &'a str,
+	pathonly: bool,
+	verify: bool,
+	verify_checksum: bool,
+	check_types: bool,
+	fix_extensions: bool,
+	extract_vba: bool,
+	sidecar: bool,
+	indexed_text: bool,
 }
 
-fn process(input: impl Read, target_dir: &str, pathonly: bool, inputfile_outsourced: Option<String>, debug: &mut Debug) -> Result<(), Error> {
-	match pathonly {
-		true => process_info(input, debug),
-		false => process_dump(input, target_dir, inputfile_outsourced, debug),
+/// Expands the given list of inputs into a list of DVS files.
+/// Directories are searched recursively for files with a `.dvs` extension, and cabinet files are expanded into their DVS members;
+/// the result is sorted for every directory.
+fn collect_inputs(inputs: &[&str], debug: &mut Debug) -> Result<Vec<String>, Error> {
+	let mut result = Vec::new();
+	for input in inputs {
+		result.extend(find_inputs(Path::new(input), debug)?.iter().map(|path| path.to_string_lossy().into_owned()));
 	}
+	Ok(result)
 }
 
-fn process_info(input: impl Read, debug: &mut Debug) -> Result<(), Error> {
-	// First extract the CFBF file
-	debug.logln(0, format!("Reading DVS file ..."));
-	let mut cfbfdata: Vec<u8> = Vec::new();
-	DvsFile::new(input, debug)?.decompress(&mut cfbfdata, debug)?;
-	debug.logln(0, format!("Read DVS file."));
+/// Retrieves the size and modification time (UNIX timestamp) of a file, or zeros if the file cannot be accessed.
+/// For members of a cabinet file, those of the cabinet file are used.
+fn file_size_and_mtime(inputfile: &str) -> (u64, u64) {
+	let inputfile = match split_member_path(Path::new(inputfile)) {
+		Some((cabinet_path, _)) if !Path::new(inputfile).exists() => cabinet_path,
+		_ => PathBuf::from(inputfile),
+	};
+	match metadata(inputfile) {
+		Ok(metadata) => (
+			metadata.len(),
+			metadata.modified().ok().and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok()).map(|mtime| mtime.as_secs()).unwrap_or(0)),
+		Err(_) => (0, 0),
+	}
+}
+
+/// The result of restoring a single DVS file.
+#[derive(Default)]
+struct Restored {
+	/// The path of the restored file, if it has been written to a file.
+	path: Option<PathBuf>,
+	/// The encryption of the restored document, if it is encrypted or IRM-protected.
+	encryption: Option<String>,
+}
+
+/// Restores a single DVS file given by its path.
+fn process_file(inputfile: &str, options: &Options, dvf_index: &mut DvfIndex, sis_index: &mut SisIndex, cabinets: &mut CabinetCache, debug: &mut Debug) -> Result<Restored, Error> {
+	debug.logln(0, format!("Processing '{}' ...", inputfile));
+	let input = open_input(Path::new(inputfile), cabinets, debug)?;
+	let inputfile_outsourced = locate_outsourced(inputfile, dvf_index, debug);
+	process(input, options, inputfile_outsourced, Option::Some((sis_index, Path::new(inputfile))), debug)
+}
 
-	// Parse CFBF file
-	debug.logln(0, format!("Reading CFBF file ..."));
-	let mut container = Container::new(SeekableRead::new(&cfbfdata[..])?, debug)?;
-	debug.logln(0, format!("Read CFBF file."));
+/// Looks for the outsourced file that belongs to the given DVS file,
+/// next to the DVS file, in the sub-folders of its directory, and in all additional search roots.
+fn locate_outsourced(inputfile: &str, dvf_index: &mut DvfIndex, debug: &mut Debug) -> Option<PathBuf> {
+	dvf_index.locate(Path::new(inputfile), debug)
+}
 
-	// Get information
-	let original_path_dir = read_path_from_embedded_file(&mut container, &["Root Entry".to_owned(), "User Information".to_owned(), "Location".to_owned(), "ExchangeLocation".to_owned(), "FolderPath".to_owned()], false, false, debug)?;
-	debug.logln(0, format!("Original directory: {:?}", original_path_dir));
-	let original_path_file = read_path_from_embedded_file(&mut container, &["Root Entry".to_owned(), "User Information".to_owned(), "User Archivable Item".to_owned(), "Title".to_owned()], true, false, debug)?;
-	debug.logln(0, format!("Original file name: {:?}", original_path_file));
+/// Looks for the content of the archived file: an outsourced file, an embedded file, or a sharable part.
+/// Sharable parts are only looked for if the path of the DVS file is known (`sis`).
+fn locate_source(container: &mut Container<SeekableRead>, inputfile_outsourced: Option<&Path>, sis: Option<(&mut SisIndex, &Path)>, debug: &mut Debug) -> Result<Source, Error> {
+	let source = locate_content(container, inputfile_outsourced, debug)?;
+	match (source, sis) {
+		(Source::Missing, Some((sis_index, inputfile))) => locate_sharable(container, sis_index, inputfile, debug),
+		(source, _) => Ok(source),
+	}
+}
+
+fn process(input: impl Read, options: &Options, inputfile_outsourced: Option<PathBuf>, sis: Option<(&mut SisIndex, &Path)>, debug: &mut Debug) -> Result<Restored, Error> {
+	match options.pathonly {
+		true => process_info(input, inputfile_outsourced, sis, debug).map(|_| Restored::default()),
+		false => process_dump(input, options, inputfile_outsourced, sis, debug),
+	}
+}
+
+fn process_info(input: impl Read, inputfile_outsourced: Option<PathBuf>, sis: Option<(&mut SisIndex, &Path)>, debug: &mut Debug) -> Result<(), Error> {
+	// First extract and parse the CFBF file
+	debug.logln(0, "Reading DVS file ...");
+	let mut container = open_payload(input, debug)?;
+	debug.logln(0, "Read DVS file.");
+
+	// Get information, named after the document title like the restored file if the original path is missing
+	let source = locate_source(&mut container, inputfile_outsourced.as_deref(), sis, debug)?;
+	let original_path = read_original_path_or_title(&mut container, &mut Content::new(&source), false, debug)?;
+	debug.logln(0, format!("Original directory: {:?}", original_path.dir));
+	debug.logln(0, format!("Original file name: {:?}", original_path.file));
 
 	// Build original path
-	let mut original_path = PathBuf::from(original_path_dir);
-	original_path.push(&original_path_file);
-	println!("{}", original_path.to_str().unwrap());
+	println!("{}", original_path.path().to_str().unwrap());
 	Ok(())
 }
 
-fn process_dump(input: impl Read, target_dir: &str, inputfile_outsourced: Option<String>, debug: &mut Debug) -> Result<(), Error> {
-	// First extract the CFBF file
+fn process_dump(input: impl Read, options: &Options, inputfile_outsourced: Option<PathBuf>, sis: Option<(&mut SisIndex, &Path)>, debug: &mut Debug) -> Result<Restored, Error> {
+	// First extract and parse the CFBF file
 	debug.logln(0, format!("Reading DVS file ..."));
-	let mut cfbfdata: Vec<u8> = Vec::new();
-	DvsFile::new(input, debug)?.decompress(&mut cfbfdata, debug)?;
+	let mut container = open_payload(input, debug)?;
 	debug.logln(0, format!("Read DVS file."));
 
-	// Parse CFBF file
-	debug.logln(0, format!("Reading CFBF file ..."));
-	let mut container = Container::new(SeekableRead::new(&cfbfdata[..])?, debug)?;
-	debug.logln(0, format!("Read CFBF file."));
+	// First look for an outsourced file, then for an embedded file, and finally for a sharable part
+	debug.logln(0, "Locating archived data ...");
+	let source = locate_source(&mut container, inputfile_outsourced.as_deref(), sis, debug)?;
+	let mut content = Content::new(&source);
 
-	// Open the target file for writing
-	let mut target_file: Box<Write> = match target_dir {
-		"" => Box::new(stdout()),
+	// Deduce the path of the target file, unless the file shall be written to STDOUT
+	let mut target_path = match options.target_dir {
+		"" => Option::None,
 		_ => {
-			// In the CFBF file, there are two embedded files that hold the original file path:
-			// (1) '/User Information/Location/ExchangeLocation/FolderPath' for the directory name
-			// (2) '/User Information/User Archivable Item/Title' for the file name
-			let original_path_dir = read_path_from_embedded_file(&mut container, &["Root Entry".to_owned(), "User Information".to_owned(), "Location".to_owned(), "ExchangeLocation".to_owned(), "FolderPath".to_owned()], false, true, debug)?;
-			debug.logln(0, format!("Original directory: {:?}", original_path_dir));
-			let original_path_file = read_path_from_embedded_file(&mut container, &["Root Entry".to_owned(), "User Information".to_owned(), "User Archivable Item".to_owned(), "Title".to_owned()], true, true, debug)?;
-			debug.logln(0, format!("Original file name: {:?}", original_path_file));
-
-			// Make sure that the destination directory exists, and also use the canonicalized version of the path
-			// Under Windows, this uses UNC paths that support long file names
-			let mut target_path = PathBuf::from(target_dir);
-			create_dir_all(&target_path)?;
-			target_path = target_path.canonicalize()?;
-
-			// Create the sub-folder that will contain the to-be-created file in the target directory,
-			// with the relative path specified by 'original_path_dir/original_path_file'
-			target_path.push(&original_path_dir);
-			debug.logln(0, format!("Create directory (if it does not exist): {:?}", target_path));
-			create_dir_all(&target_path)?;
-
-			// Deduce the path of the target file
-			target_path.push(&original_path_file);
-
-			// Open the file for writing
-			debug.log(0, format!("Creating file {:?} ... ", target_path));
-			let target_file = Box::new(File::create(target_path)?);
-			debug.logln(0, format!("OK."));
-			target_file
+			let original_path = read_original_path_or_title(&mut container, &mut content, true, debug)?;
+			debug.logln(0, format!("Original directory: {:?}", original_path.dir));
+			debug.logln(0, format!("Original file name: {:?}", original_path.file));
+			Option::Some(target_root(options.target_dir, true)?.join(original_path.path()))
 		},
 	};
 
-	// First look for an outsourced file
-	match process_dump_outsourced(inputfile_outsourced, &mut target_file, debug) {
-		Ok(true) => Ok(()),
-		Ok(false) => {
-			// If there is no outsourced file, look for an embedded file
-			match process_dump_object(&mut container, &mut target_file, debug) {
-				Ok(true) => Ok(()),
-				Ok(false) => {
-					Err(Error::new(ErrorKind::InvalidData, "Unable to find embedded file '/**/FileContentStream', and no outsourced file can be found!"))
-				},
-				Err(e) => Err(e),
+	// Compare the format of the content against the metadata and the file name
+	let context = target_path.as_ref().map(|path| path.to_string_lossy().into_owned()).unwrap_or_else(|| "STDOUT".to_owned());
+	if options.check_types {
+		// A content that cannot be inspected is still restored
+		match check_type(&mut container, &mut content, target_path.as_mut(), options, debug) {
+			Ok(notes) => for note in notes {
+				eprintln!("TYPE WARNING: {}: {}", context, note);
+			},
+			Err(e) => eprintln!("TYPE WARNING: {}: unable to determine the format of the content: {}", context, e),
+		}
+	}
+	let encryption = detect_encryption(&mut container, &mut content, &context, debug);
+	if let (true, &Source::Missing, Some(target_path)) = (options.indexed_text, &source, target_path.as_ref()) {
+		write_indexed_text(&mut container, target_path, debug)?;
+	}
+	write_restored(&mut container, &source, target_path.as_deref(), options, debug)?;
+	if let (true, Some(target_path)) = (options.extract_vba, target_path.as_ref()) {
+		// The file has been restored already, so macros that cannot be read are only reported
+		if let Err(e) = extract_vba(&mut container, &mut content, target_path, debug) {
+			eprintln!("VBA WARNING: {}: unable to export the VBA macros: {}", target_path.to_string_lossy(), e);
+		}
+	}
+	if let (true, Some(target_path)) = (options.sidecar, target_path.as_ref()) {
+		// The file has been restored already, so metadata that cannot be written is only reported
+		if let Err(e) = write_sidecar_file(&mut container, target_path, debug) {
+			eprintln!("SIDECAR WARNING: {}: unable to write the metadata: {}", target_path.to_string_lossy(), e);
+		}
+	}
+	Ok(Restored { path: target_path, encryption })
+}
+
+/// Reports an encrypted or IRM-protected document as `ENCRYPTED`, and returns its encryption.
+/// Encrypted documents are intact, although they cannot be opened without the password, so this never fails the restore.
+fn detect_encryption(container: &mut Container<SeekableRead>, content: &mut Content, context: &str, debug: &mut Debug) -> Option<String> {
+	match detect_content_encryption(container, content, debug) {
+		Ok(Some(encryption)) => {
+			eprintln!("ENCRYPTED: {}: {}", context, encryption);
+			Some(encryption.to_string())
+		},
+		Ok(None) => None,
+		Err(e) => {
+			debug.logln(0, format!("Unable to detect the encryption: {}", e));
+			None
+		},
+	}
+}
+
+/// Determines the directory beneath which the original paths are recreated.
+/// If `create` is set, the directory is created and its canonicalized version is used.
+/// Under Windows, this uses UNC paths that support long file names.
+fn target_root(target_dir: &str, create: bool) -> Result<PathBuf, Error> {
+	let target_path = PathBuf::from(target_dir);
+	if create {
+		create_dir_all(&target_path)?;
+	}
+	match target_path.canonicalize() {
+		Ok(target_path) => Ok(target_path),
+		Err(_) => Ok(current_dir()?.join(target_path)),
+	}
+}
+
+/// Writes the content of the archived file to the target file (or STDOUT), and verifies it if requested.
+fn write_restored(container: &mut Container<SeekableRead>, source: &Source, target_path: Option<&Path>, options: &Options, debug: &mut Debug) -> Result<(), Error> {
+	if let Source::Missing = *source {
+		return Err(Error::new(ErrorKind::InvalidData, "Unable to find embedded file '/**/FileContentStream', and no outsourced file can be found!"));
+	}
+
+	// Read the properties that the restored content shall be verified against
+	let expectation = match options.verify {
+		true => {
+			debug.logln(0, "Reading size and checksum properties ...");
+			Expectation::read(container, debug)?
+		},
+		false => Expectation::default(),
+	};
+
+	// Open the target file for writing
+	let target_file: Box<dyn Write> = match target_path {
+		Option::None => Box::new(stdout()),
+		Option::Some(target_path) => {
+			// Create the sub-folder that will contain the to-be-created file in the target directory
+			if let Some(target_dir) = target_path.parent() {
+				debug.logln(0, format!("Create directory (if it does not exist): {:?}", target_dir));
+				create_dir_all(target_dir)?;
 			}
+			debug.log(0, format!("Creating file {:?} ... ", target_path));
+			let target_file = File::create(target_path)?;
+			debug.logln(0, "OK.");
+			Box::new(target_file)
 		},
-		Err(e) => Err(e),
+	};
+
+	let mut target_file = Verifier::new(target_file, expectation, options.verify_checksum);
+	match *source {
+		Source::Outsourced(ref path) => debug.logln(0, format!("Copying outsourced file {:?} ...", path)),
+		Source::Sharable(ref path) => debug.logln(0, format!("Dumping sharable part {:?} ...", path)),
+		_ => debug.logln(0, "Dumping archived data ..."),
+	}
+	write_content(container, source, &mut target_file, debug)?;
+	debug.logln(0, "Done.");
+
+	// Verify the restored content
+	if options.verify {
+		debug.logln(0, "Verifying restored content ...");
+		let is_outsourced = matches!(*source, Source::Outsourced(_));
+		let num_bytes = target_file.finish(is_outsourced, debug)?;
+		debug.logln(0, format!("Verified {} bytes.", num_bytes));
+	}
+	Ok(())
+}
+
+/// Returns the directory into which the VBA macros of a restored file are exported (`<target file>.vba`).
+fn vba_dir(target_path: &Path) -> PathBuf {
+	let mut vba_dir = target_path.as_os_str().to_owned();
+	vba_dir.push(".vba");
+	PathBuf::from(vba_dir)
+}
+
+/// Exports the VBA macros of the archived file (if it is an Office document that holds any) next to the restored file, and reports them.
+fn extract_vba(container: &mut Container<SeekableRead>, content: &mut Content, target_path: &Path, debug: &mut Debug) -> Result<(), Error> {
+	debug.logln(0, "Looking for VBA macros ...");
+	let projects = read_vba_projects(container, content, debug)?;
+	let vba_dir = vba_dir(target_path);
+	for project in &projects {
+		// Several projects are written into sub-directories named after their storages
+		let project_dir = match projects.len() {
+			1 => vba_dir.clone(),
+			_ => vba_dir.join(project.path.trim_start_matches('/').replace('/', "_")),
+		};
+		project.write_modules(&project_dir)?;
+		eprintln!("VBA MACROS: {}: {} modules of project '{}' exported to {}", target_path.to_string_lossy(), project.modules.len(), project.name, project_dir.to_string_lossy());
 	}
+	Ok(())
+}
+
+/// Returns the path of the file into which the indexed text of an archived file is written (`<target file>.txt`).
+fn indexed_text_path(target_path: &Path) -> PathBuf {
+	let mut text_path = target_path.as_os_str().to_owned();
+	text_path.push(".txt");
+	PathBuf::from(text_path)
 }
 
-fn process_dump_object(container: &mut Container<SeekableRead>, target_file: &mut Box<Write>, debug: &mut Debug) -> Result<bool, Error> {
-	// Find the object that contains the archived data
-	// In the CFBF file, this is the embedded file '/Sharable Content/Archivable Item/FileContentStream'
-	// But let's be more generous and search for any 'FileContentStream' in the entire file
-	debug.logln(0, format!("Locating archived data in the CFBF file ..."));
-	let archived_data_object = container.find_child_by_name("FileContentStream", debug)?;
-
-	match archived_data_object {
-		// If there is an embedded file 'FileContentStream', just copy it to the output
-		ObjectResult::Ok(object) => {
-			debug.logln(0, format!("Found '/**/FileContentStream'."));
-			debug.logln(0, format!("Dumping archived data ..."));
-			container.dump_stream(&object, target_file, debug)?;
-			debug.logln(0, format!("Done."));
-			Ok(true)
+/// Writes the indexed text of an archived file whose content is missing next to where the restored file would be, and reports it.
+fn write_indexed_text(container: &mut Container<SeekableRead>, target_path: &Path, debug: &mut Debug) -> Result<(), Error> {
+	debug.logln(0, "Reading indexed text ...");
+	let text = match IndexedItem::read(container, debug)?.text {
+		Some(text) => text,
+		None => {
+			debug.logln(0, "No indexed text can be decoded.");
+			return Ok(());
 		},
-		// If there is no embedded file 'FileContentStream',
-		// return 'false' to indicate that no object has been found
-		ObjectResult::None => {
-			debug.logln(0, format!("The embedded file '/**/FileContentStream' does not exist in this file!"));
-			Ok(false)
-		}
+	};
+	let text_path = indexed_text_path(target_path);
+	if let Some(target_dir) = text_path.parent() {
+		create_dir_all(target_dir)?;
 	}
+	File::create(&text_path)?.write_all(text.as_bytes())?;
+	eprintln!("INDEXED TEXT: {}: {} characters written to {}", target_path.to_string_lossy(), text.chars().count(), text_path.to_string_lossy());
+	Ok(())
+}
+
+/// Returns the path of the sidecar file that holds the metadata of a restored file (`<target file>.metadata.json`).
+fn sidecar_path(target_path: &Path) -> PathBuf {
+	let mut sidecar_path = target_path.as_os_str().to_owned();
+	sidecar_path.push(".metadata.json");
+	PathBuf::from(sidecar_path)
+}
+
+/// Writes the metadata of the archived file into a sidecar file next to the restored file.
+fn write_sidecar_file(container: &mut Container<SeekableRead>, target_path: &Path, debug: &mut Debug) -> Result<(), Error> {
+	let sidecar_path = sidecar_path(target_path);
+	debug.logln(0, format!("Writing metadata to {:?} ...", sidecar_path));
+	let metadata = Metadata::read(container, debug)?;
+	write_sidecar(&mut File::create(&sidecar_path)?, &metadata)
 }
 
-fn process_dump_outsourced(inputfile_outsourced: Option<String>, target_file: &mut Write, debug: &mut Debug) -> Result<bool, Error> {
-	match inputfile_outsourced {
-		Option::None => {
-			debug.logln(0, format!("No outsourced file can be deduced (reading from STDIN)! Skipping."));
-			Ok(false)
+/// Determines the format of the archived file by inspecting its content, and compares it against the `MIMEType` and `File Extension` properties
+/// and the extension of the target file name. If extensions shall be fixed, the target file name is corrected.
+/// Returns a description of every mismatch.
+fn check_type(container: &mut Container<SeekableRead>, content: &mut Content, target_path: Option<&mut PathBuf>, options: &Options, debug: &mut Debug) -> Result<Vec<String>, Error> {
+	debug.logln(0, "Determining the format of the content ...");
+	let file_type = match sniff_content(container, content, debug)? {
+		Some(file_type) => file_type,
+		None => {
+			debug.logln(0, "The format of the content is not recognized.");
+			return Ok(Vec::new());
 		},
-		Option::Some(inputfile_outsourced) => {
-			debug.log(0, format!("Looking for outsourced file '{}' ... ", inputfile_outsourced));
-			match PathBuf::from(inputfile_outsourced).canonicalize() {
-				// If the outsourced file exists, copy its contents to the target file
-				Ok(inputfile_outsourced) => {
-					debug.logln(0, format!("OK."));
-
-					debug.log(0, format!("Opening '{:?}' for reading ...", inputfile_outsourced));
-					let mut inputfile_outsourced = File::open(inputfile_outsourced)?;
-					debug.logln(0, format!("Done."));
-
-					debug.log(0, format!("Copying outsourced file ..."));
-					copy(&mut inputfile_outsourced, target_file)?;
-					debug.logln(0, format!("Done."));
-					Ok(true)
-				}
-				// If the outsourced file does not exist,
-				// return 'false' to indicate that the outsourced file has not been found
-				Err(_) => {
-					debug.logln(0, format!("not found!"));
-					Ok(false)
-				}
+	};
+	debug.logln(0, format!("Content format: {}", file_type));
+
+	let mut notes = check_metadata(file_type, &Metadata::read(container, debug)?);
+	if let Some(target_path) = target_path {
+		if let Some((fixed_path, description)) = fix_extension(file_type, target_path) {
+			match options.fix_extensions {
+				true => {
+					let file_name = fixed_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+					notes.push(format!("{}; renamed to '{}'", description, file_name));
+					*target_path = fixed_path;
+				},
+				false => notes.push(description),
 			}
 		}
 	}
+	Ok(notes)
 }
 
-/// Reads an embedded file and interpretes it as a UTF-16 string (prefixed by a byte length) encoding a path.
-/// The `as_single_component` argument specifies whether all directory separators shall be escaped (`true`) or not (`false`).
-fn read_path_from_embedded_file<TFile>(container: &mut Container<TFile>, path: &[String], as_single_component: bool, strip_root: bool, debug: &mut Debug) -> Result<PathBuf, Error> where TFile: Read + Seek {
-	debug.logln(0, format!("Reading content of {} ...", path.join("/")));
-	//match container.find_child_by_path(path, debug)? {
-	match container.find_child_by_name(&path.last().unwrap(), debug)? {
-		ObjectResult::None => {
-			debug.logln(0, format!("Embedded file {} could not be found!", path.join("/")));
-			return Err(Error::new(ErrorKind::InvalidData, "Unable to find an embedded file!"));
+/// Determines what restoring a single DVS file would do, without writing anything.
+/// Errors are not returned, but recorded in the step.
+fn plan_input(inputfile: &str, options: &Options, dvf_index: &mut DvfIndex, sis_index: &mut SisIndex, cabinets: &mut CabinetCache, debug: &mut Debug) -> Step {
+	debug.logln(0, format!("Planning '{}' ...", inputfile));
+	match plan_input_inner(inputfile, options, dvf_index, sis_index, cabinets, debug) {
+		Ok(step) => step,
+		Err(ref e) if MissingPart::from_error(e).is_some() => Step {
+			input: inputfile.to_owned(),
+			source: PlannedSource::Missing,
+			size: Option::None,
+			target: Option::None,
+			notes: vec![format!("missing: {}", e)],
+		},
+		Err(e) => Step {
+			input: inputfile.to_owned(),
+			source: PlannedSource::Error,
+			size: Option::None,
+			target: Option::None,
+			notes: vec![format!("error: {}", e)],
 		},
-		ObjectResult::Ok(object) => {
-			// Read the content from that embedded file
-			debug.logln(0, format!("Reading embedded file {} ...", path.join("/")));
-			let mut buffer: Vec<u8> = Vec::new();
-			container.dump_stream(&object, &mut buffer, debug)?;
-			debug.logln(0, format!("Read {} bytes.", buffer.len()));
-
-			// The file content is a 32bit integer denoting the byte length, followed by a NUL-terminated UTF16-encoded string
-			// Extract the Unicode code points (as u16's); also remove the trailing NUL character
-			let mut buffer16 = vec![0u16; (buffer.len() - 4) / 2 - 1];
-			for i in 0..buffer16.len() {
-				buffer16[i] = (buffer[i * 2 + 4] as u16) | (buffer[i * 2 + 5] as u16) << 8;
+	}
+}
+
+fn plan_input_inner(inputfile: &str, options: &Options, dvf_index: &mut DvfIndex, sis_index: &mut SisIndex, cabinets: &mut CabinetCache, debug: &mut Debug) -> Result<Step, Error> {
+	let input = open_input(Path::new(inputfile), cabinets, debug)?;
+	let inputfile_outsourced = locate_outsourced(inputfile, dvf_index, debug);
+	let mut container = open_payload(input, debug)?;
+
+	let source = locate_source(&mut container, inputfile_outsourced.as_deref(), Option::Some((sis_index, Path::new(inputfile))), debug)?;
+	let mut content = Content::new(&source);
+
+	let original_path = read_original_path_or_title(&mut container, &mut content, true, debug)?;
+	let mut target = target_root(options.target_dir, false)?.join(original_path.path());
+	let mut notes = original_path.rewrites.iter().map(|rewrite| format!("rewrite: {}", rewrite)).collect::<Vec<String>>();
+	if options.check_types {
+		match check_type(&mut container, &mut content, Option::Some(&mut target), options, debug) {
+			Ok(type_notes) => notes.extend(type_notes.iter().map(|note| format!("type: {}", note))),
+			Err(e) => notes.push(format!("type: unable to determine the format of the content: {}", e)),
+		}
+	}
+	match detect_content_encryption(&mut container, &mut content, debug) {
+		Ok(Some(encryption)) => notes.push(format!("encrypted: {}", encryption)),
+		Ok(None) => {},
+		Err(e) => debug.logln(0, format!("Unable to detect the encryption: {}", e)),
+	}
+	if options.extract_vba {
+		match read_vba_projects(&mut container, &mut content, debug) {
+			Ok(projects) => for project in projects {
+				notes.push(format!("vba: {} modules of project '{}' will be exported to {}", project.modules.len(), project.name, vba_dir(&target).to_string_lossy()));
+			},
+			Err(e) => notes.push(format!("vba: unable to read the VBA macros: {}", e)),
+		}
+	}
+	let size = source.size();
+	let source = match source {
+		Source::Outsourced(path) => PlannedSource::Outsourced(path),
+		Source::Embedded(_) => PlannedSource::Embedded,
+		Source::Sharable(path) => PlannedSource::Sharable(path),
+		Source::Missing => {
+			notes.push("missing: neither an embedded nor an outsourced file can be found".to_owned());
+			if options.indexed_text {
+				notes.push(match IndexedItem::read(&mut container, debug)?.text {
+					Some(text) => format!("indexed text: {} characters will be written to {}", text.chars().count(), indexed_text_path(&target).to_string_lossy()),
+					None => "indexed text: none can be decoded".to_owned(),
+				});
 			}
+			PlannedSource::Missing
+		},
+	};
 
-			// Convert it into a string and then into a (possibly absolute) path
-			debug.logln(0, format!("Interpreting file content as string ..."));
-			let result = PathBuf::from(decode_utf16(buffer16.iter().cloned())
-				.map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
-				.map(|c| if c == '\x7F' || c == '\\' || c == '/' { if as_single_component { '_' } else { '/' } } else { c })
-				.collect::<String>());
-
-			// Make sure that the path is not absolute by removing prefixes and root directories
-			Ok(result
-				.components()
-				.filter(|c| match c { Component::Prefix(_) | Component::RootDir => !strip_root, _ => true })
-				.collect::<PathBuf>())
+	Ok(Step { input: inputfile.to_owned(), source, size, target: Option::Some(target), notes })
+}
+
+/// Executes a single step of a reviewed plan, writing exactly the planned content to the planned target.
+fn process_step(step: &Step, options: &Options, cabinets: &mut CabinetCache, debug: &mut Debug) -> Result<Restored, Error> {
+	debug.logln(0, format!("Processing '{}' ...", step.input));
+	let target_path = match step.target {
+		Some(ref target_path) => target_path,
+		None => return Err(Error::new(ErrorKind::InvalidData, "The plan specifies no target for this input")),
+	};
+
+	debug.logln(0, "Reading DVS file ...");
+	let mut container = open_payload(open_input(Path::new(&step.input), cabinets, debug)?, debug)?;
+	debug.logln(0, "Read DVS file.");
+
+	let source = match step.source {
+		PlannedSource::Embedded => locate_content(&mut container, Option::None, debug)?,
+		PlannedSource::Outsourced(ref path) => Source::Outsourced(path.clone()),
+		PlannedSource::Sharable(ref path) => Source::Sharable(path.clone()),
+		PlannedSource::Missing | PlannedSource::Error => {
+			// At least the indexed text can be recovered for missing content
+			if options.indexed_text && step.source == PlannedSource::Missing {
+				write_indexed_text(&mut container, target_path, debug)?;
+			}
+			return Err(Error::new(ErrorKind::InvalidData, "The plan specifies no content for this input"));
 		},
+	};
+	let mut content = Content::new(&source);
+	let encryption = detect_encryption(&mut container, &mut content, &target_path.to_string_lossy(), debug);
+	write_restored(&mut container, &source, Some(target_path), options, debug)?;
+	if options.extract_vba {
+		if let Err(e) = extract_vba(&mut container, &mut content, target_path, debug) {
+			eprintln!("VBA WARNING: {}: unable to export the VBA macros: {}", target_path.to_string_lossy(), e);
+		}
+	}
+	if options.sidecar {
+		if let Err(e) = write_sidecar_file(&mut container, target_path, debug) {
+			eprintln!("SIDECAR WARNING: {}: unable to write the metadata: {}", target_path.to_string_lossy(), e);
+		}
+	}
+	Ok(Restored { path: Some(target_path.clone()), encryption })
+}
+
+/// Prints a summary of a plan, including all notes, to STDOUT.
+fn print_plan_report(plan: &Plan, num_conflicts: usize) {
+	for step in plan.steps.iter().filter(|step| !step.notes.is_empty()) {
+		println!("{}", step.input);
+		for note in &step.notes {
+			println!("  {}", note);
+		}
 	}
-}
\ No newline at end of file
+
+	let count = |source: &dyn Fn(&PlannedSource) -> bool| plan.steps.iter().filter(|step| source(&step.source)).count();
+	println!("{} inputs: {} embedded, {} outsourced, {} sharable, {} without content, {} unreadable",
+		plan.steps.len(),
+		count(&|source| *source == PlannedSource::Embedded),
+		count(&|source| matches!(*source, PlannedSource::Outsourced(_))),
+		count(&|source| matches!(*source, PlannedSource::Sharable(_))),
+		count(&|source| *source == PlannedSource::Missing),
+		count(&|source| *source == PlannedSource::Error));
+	let count_notes = |prefix: &str| plan.steps.iter().filter(|step| step.notes.iter().any(|note| note.starts_with(prefix))).count();
+	println!("{} conflicting targets, {} rewritten paths, {} type mismatches, {} encrypted documents, {} documents with VBA macros",
+		num_conflicts,
+		count_notes("rewrite:"),
+		count_notes("type:"),
+		count_notes("encrypted:"),
+		count_notes("vba:"));
+	println!("{} bytes needed", plan.total_size());
+}
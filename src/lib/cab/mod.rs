@@ -0,0 +1,523 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+extern crate libflate;
+
+use std::io::{Read, Seek, SeekFrom, Error, ErrorKind};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use self::libflate::deflate::Decoder;
+use super::io::Debug;
+
+/// The size of the MSZIP history window, which is carried over from one data block to the next.
+const MSZIP_WINDOW_SIZE: usize = 32768;
+
+/// A Microsoft Cabinet (`.cab`) file.
+/// Members can be read from folders that are stored uncompressed or compressed with MSZIP; Quantum and LZX are not supported.
+/// Cabinet sets (folders that span several cabinet files) are not supported either.
+pub struct Cabinet<TFile> where TFile: Read + Seek {
+	input: TFile,
+	folders: Vec<Folder>,
+	members: Vec<Member>,
+	/// The number of reserved bytes in every data block header.
+	data_reserve: usize,
+	/// The state of decompressing the current folder, so that members can be read in sequence without starting over.
+	decoder: Option<FolderDecoder>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Compression {
+	None,
+	MsZip,
+	Quantum,
+	Lzx,
+	Unknown(u16),
+}
+
+/// A folder, i.e. a stream of data blocks that are compressed together and contain one or more members.
+pub struct Folder {
+	/// The offset of the first data block in the cabinet file.
+	pub data_offset: u32,
+	/// The number of data blocks.
+	pub num_blocks: u16,
+	pub compression: Compression,
+}
+
+/// A file stored in the cabinet.
+#[derive(Clone)]
+pub struct Member {
+	/// The name of the file, which may include a relative path (separated by backslashes).
+	pub name: String,
+	/// The uncompressed size of the file.
+	pub size: u32,
+	/// The index of the folder that contains the file (`0xFFFD` to `0xFFFF` for files that continue in another cabinet).
+	pub folder: u16,
+	/// The uncompressed offset of the file within its folder.
+	pub folder_offset: u32,
+	/// The modification date and time (MS-DOS format).
+	pub date: u16,
+	pub time: u16,
+	pub attributes: u16,
+}
+
+struct FolderDecoder {
+	folder: usize,
+	/// The index of the next data block to be read.
+	next_block: u16,
+	/// The offset of the next data block in the cabinet file.
+	next_offset: u64,
+	/// The decompressed data of the current data block.
+	block: Vec<u8>,
+	/// The read position within the current data block.
+	block_pos: usize,
+	/// The uncompressed offset (within the folder) of the read position.
+	position: u64,
+	/// The last bytes of decompressed data, which MSZIP blocks may refer back to.
+	window: Vec<u8>,
+}
+
+impl<TFile> Cabinet<TFile> where TFile: Read + Seek {
+	pub fn new(mut input: TFile, debug: &mut Debug) -> Result<Cabinet<TFile>, Error> {
+		// Read header
+		debug.log(1, "[new] Reading cabinet header (36 bytes) ... ");
+		let mut header = [0; 36];
+		input.seek(SeekFrom::Start(0))?;
+		input.read_exact(&mut header)?;
+		debug.logln(1, "OK.");
+
+		if &header[0..4] != b"MSCF" {
+			debug.logln(1, "Bad signature, expected 'MSCF'!");
+			return Err(Error::new(ErrorKind::InvalidData, "Bad cabinet signature"));
+		}
+		let files_offset = read_u32(&header, 16);
+		let version = (header[25], header[24]);
+		let num_folders = read_u16(&header, 26);
+		let num_members = read_u16(&header, 28);
+		let flags = read_u16(&header, 30);
+		debug.logln(1, format!("[new] Cabinet version {}.{}, {} folders, {} files, flags 0x{:04X}.", version.0, version.1, num_folders, num_members, flags));
+
+		// Optional reserved areas, and the names of the previous and next cabinets in a set
+		let (mut folder_reserve, mut data_reserve) = (0, 0);
+		if flags & 0x0004 != 0 {
+			let mut reserve = [0; 4];
+			input.read_exact(&mut reserve)?;
+			let header_reserve = read_u16(&reserve, 0);
+			folder_reserve = reserve[2] as usize;
+			data_reserve = reserve[3] as usize;
+			input.seek(SeekFrom::Current(header_reserve as i64))?;
+		}
+		if flags & 0x0001 != 0 {
+			read_string(&mut input)?;
+			read_string(&mut input)?;
+		}
+		if flags & 0x0002 != 0 {
+			read_string(&mut input)?;
+			read_string(&mut input)?;
+		}
+
+		// Folders
+		let mut folders = Vec::with_capacity(num_folders as usize);
+		for _ in 0..num_folders {
+			let mut buffer = [0; 8];
+			input.read_exact(&mut buffer)?;
+			input.seek(SeekFrom::Current(folder_reserve as i64))?;
+			let compression = match read_u16(&buffer, 6) & 0x000F {
+				0 => Compression::None,
+				1 => Compression::MsZip,
+				2 => Compression::Quantum,
+				3 => Compression::Lzx,
+				other => Compression::Unknown(other),
+			};
+			let folder = Folder { data_offset: read_u32(&buffer, 0), num_blocks: read_u16(&buffer, 4), compression };
+			debug.logln(2, format!("[new] Folder at offset {} with {} data blocks ({:?}).", folder.data_offset, folder.num_blocks, folder.compression));
+			folders.push(folder);
+		}
+
+		// Members
+		input.seek(SeekFrom::Start(files_offset as u64))?;
+		let mut members = Vec::with_capacity(num_members as usize);
+		for _ in 0..num_members {
+			let mut buffer = [0; 16];
+			input.read_exact(&mut buffer)?;
+			let attributes = read_u16(&buffer, 14);
+			let name = read_string(&mut input)?;
+			// The name is UTF-8 encoded if the corresponding attribute is set, otherwise it uses some code page (assumed to be Latin-1)
+			let name = match attributes & 0x0080 {
+				0 => name.iter().map(|&byte| byte as char).collect::<String>(),
+				_ => String::from_utf8_lossy(&name).into_owned(),
+			};
+			let member = Member {
+				name,
+				size: read_u32(&buffer, 0),
+				folder_offset: read_u32(&buffer, 4),
+				folder: read_u16(&buffer, 8),
+				date: read_u16(&buffer, 10),
+				time: read_u16(&buffer, 12),
+				attributes,
+			};
+			debug.logln(2, format!("[new] Member '{}' ({} bytes) in folder {} at offset {}.", member.name, member.size, member.folder, member.folder_offset));
+			members.push(member);
+		}
+
+		Ok(Cabinet { input, folders, members, data_reserve, decoder: None })
+	}
+
+	pub fn folders(&self) -> &[Folder] {
+		&self.folders
+	}
+
+	pub fn members(&self) -> &[Member] {
+		&self.members
+	}
+
+	/// Finds a member by its name (case-insensitive, `/` and `\` are equivalent).
+	pub fn find(&self, name: &str) -> Option<usize> {
+		let normalize = |name: &str| name.replace('/', "\\").to_lowercase();
+		let name = normalize(name);
+		self.members.iter().position(|member| normalize(&member.name) == name)
+	}
+
+	/// Opens a member for reading.
+	/// Reading the members in the order in which they are stored is efficient; other orders require decompressing their folders again.
+	pub fn open_member(&mut self, index: usize) -> Result<MemberReader<'_, TFile>, Error> {
+		let member = self.members.get(index).cloned()
+			.ok_or_else(|| Error::new(ErrorKind::NotFound, "No such cabinet member"))?;
+		let folder = member.folder as usize;
+		if folder >= self.folders.len() {
+			return Err(Error::new(ErrorKind::InvalidData, format!("Cabinet member '{}' continues in another cabinet, which is not supported", member.name)));
+		}
+		match self.folders[folder].compression {
+			Compression::None | Compression::MsZip => {},
+			compression => return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported cabinet compression: {:?}", compression))),
+		}
+
+		// Start over if the member is located in another folder, or before the current position
+		let restart = match self.decoder {
+			Some(ref decoder) => decoder.folder != folder || decoder.position > member.folder_offset as u64,
+			None => true,
+		};
+		if restart {
+			self.decoder = Some(FolderDecoder {
+				folder,
+				next_block: 0,
+				next_offset: self.folders[folder].data_offset as u64,
+				block: Vec::new(),
+				block_pos: 0,
+				position: 0,
+				window: Vec::new(),
+			});
+		}
+
+		// Skip the data before the member
+		let mut skip = member.folder_offset as u64 - self.decoder.as_ref().unwrap().position;
+		while skip > 0 {
+			let available = self.fill_block()?;
+			if available == 0 {
+				return Err(Error::new(ErrorKind::UnexpectedEof, "Cabinet folder ends before its member"));
+			}
+			let num_bytes = (available as u64).min(skip) as usize;
+			let decoder = self.decoder.as_mut().unwrap();
+			decoder.block_pos += num_bytes;
+			decoder.position += num_bytes as u64;
+			skip -= num_bytes as u64;
+		}
+
+		Ok(MemberReader { cabinet: self, remaining: member.size as u64 })
+	}
+
+	/// Makes sure that the current data block has unread data, reading the next one if necessary.
+	/// Returns the number of unread bytes, which is zero at the end of the folder.
+	fn fill_block(&mut self) -> Result<usize, Error> {
+		let decoder = self.decoder.as_mut().unwrap();
+		while decoder.block_pos == decoder.block.len() {
+			if decoder.next_block >= self.folders[decoder.folder].num_blocks {
+				return Ok(0);
+			}
+
+			// Data block header: checksum (u32), compressed size (u16), uncompressed size (u16), reserved area
+			self.input.seek(SeekFrom::Start(decoder.next_offset))?;
+			let mut header = [0; 8];
+			self.input.read_exact(&mut header)?;
+			let compressed_size = read_u16(&header, 4) as usize;
+			let uncompressed_size = read_u16(&header, 6) as usize;
+			self.input.seek(SeekFrom::Current(self.data_reserve as i64))?;
+			let mut data = vec![0; compressed_size];
+			self.input.read_exact(&mut data)?;
+			decoder.next_offset += 8 + self.data_reserve as u64 + compressed_size as u64;
+			decoder.next_block += 1;
+
+			decoder.block = match self.folders[decoder.folder].compression {
+				Compression::MsZip => decompress_mszip(&data, &decoder.window, uncompressed_size)?,
+				_ => data,
+			};
+			decoder.block_pos = 0;
+
+			// Keep the last 32 KiB of decompressed data as the history for the next block
+			decoder.window.extend_from_slice(&decoder.block);
+			if decoder.window.len() > MSZIP_WINDOW_SIZE {
+				let excess = decoder.window.len() - MSZIP_WINDOW_SIZE;
+				decoder.window.drain(..excess);
+			}
+		}
+		Ok(decoder.block.len() - decoder.block_pos)
+	}
+}
+
+/// Decompresses an MSZIP data block: the signature `CK`, followed by a deflate stream that may refer back to the previous 32 KiB of data.
+/// The history is provided to the deflate decoder by prepending it as an uncompressed deflate block, whose output is discarded.
+fn decompress_mszip(data: &[u8], window: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, Error> {
+	if data.len() < 2 || &data[0..2] != b"CK" {
+		return Err(Error::new(ErrorKind::InvalidData, "Bad MSZIP block signature"));
+	}
+	let length = window.len() as u16;
+	let mut stream = Vec::with_capacity(5 + window.len() + data.len() - 2);
+	stream.extend_from_slice(&[0x00, length as u8, (length >> 8) as u8, !length as u8, (!length >> 8) as u8]);
+	stream.extend_from_slice(window);
+	stream.extend_from_slice(&data[2..]);
+
+	let mut output = Vec::with_capacity(window.len() + uncompressed_size);
+	Decoder::new(&stream[..]).read_to_end(&mut output)?;
+	let output = output.split_off(window.len());
+	if output.len() != uncompressed_size {
+		return Err(Error::new(ErrorKind::InvalidData, format!("MSZIP block decompressed to {} bytes, expected {}", output.len(), uncompressed_size)));
+	}
+	Ok(output)
+}
+
+/// A member of a cabinet, opened for reading.
+pub struct MemberReader<'a, TFile> where TFile: Read + Seek + 'a {
+	cabinet: &'a mut Cabinet<TFile>,
+	remaining: u64,
+}
+
+impl<'a, TFile> Read for MemberReader<'a, TFile> where TFile: Read + Seek {
+	fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+		if self.remaining == 0 || buf.is_empty() {
+			return Ok(0);
+		}
+		let available = self.cabinet.fill_block()?;
+		if available == 0 {
+			return Err(Error::new(ErrorKind::UnexpectedEof, "Cabinet folder ends before the end of its member"));
+		}
+		let num_bytes = (available as u64).min(self.remaining).min(buf.len() as u64) as usize;
+		let decoder = self.cabinet.decoder.as_mut().unwrap();
+		buf[..num_bytes].copy_from_slice(&decoder.block[decoder.block_pos..decoder.block_pos + num_bytes]);
+		decoder.block_pos += num_bytes;
+		decoder.position += num_bytes as u64;
+		self.remaining -= num_bytes as u64;
+		Ok(num_bytes)
+	}
+}
+
+/// Whether the path has a `.cab` extension (case-insensitive).
+pub fn is_cabinet(path: &Path) -> bool {
+	path.extension().map(|ext| ext.to_string_lossy().eq_ignore_ascii_case("cab")).unwrap_or(false)
+}
+
+/// Lists the members of a cabinet file whose extension matches the given one (case-insensitive),
+/// as paths beneath the cabinet file (as if it were a directory).
+pub fn member_paths(cabinet_path: &Path, extension: &str, debug: &mut Debug) -> Result<Vec<PathBuf>, Error> {
+	let cabinet = Cabinet::new(File::open(cabinet_path)?, debug)?;
+	let extension = format!(".{}", extension.to_lowercase());
+	Ok(cabinet.members().iter()
+		.filter(|member| member.name.to_lowercase().ends_with(&extension))
+		.map(|member| member.name.split('\\').fold(cabinet_path.to_path_buf(), |path, component| path.join(component)))
+		.collect())
+}
+
+/// Splits a path beneath a cabinet file (as returned by `member_paths`) into the path of the cabinet file and the name of the member.
+/// Returns `None` if no ancestor of the path is a cabinet file.
+pub fn split_member_path(path: &Path) -> Option<(PathBuf, String)> {
+	for cabinet_path in path.ancestors().skip(1) {
+		if is_cabinet(cabinet_path) && cabinet_path.is_file() {
+			let name = path.strip_prefix(cabinet_path).ok()?
+				.components()
+				.map(|component| component.as_os_str().to_string_lossy().into_owned())
+				.collect::<Vec<String>>()
+				.join("\\");
+			return Some((cabinet_path.to_path_buf(), name));
+		}
+	}
+	None
+}
+
+/// Keeps the most recently used cabinet file open, so that reading its members one after the other
+/// does not decompress its folders over and over again.
+#[derive(Default)]
+pub struct CabinetCache {
+	current: Option<(PathBuf, Cabinet<File>)>,
+}
+
+impl CabinetCache {
+	pub fn new() -> CabinetCache {
+		CabinetCache { current: None }
+	}
+
+	/// Reads a member of a cabinet file completely, given a path as returned by `member_paths`.
+	pub fn read_member_path(&mut self, path: &Path, debug: &mut Debug) -> Result<Vec<u8>, Error> {
+		let (cabinet_path, name) = split_member_path(path)
+			.ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{:?} is not located in a cabinet file", path)))?;
+		let is_open = match self.current {
+			Some((ref current_path, _)) => *current_path == cabinet_path,
+			None => false,
+		};
+		if !is_open {
+			debug.logln(1, format!("[read_member_path] Opening cabinet {:?} ...", cabinet_path));
+			self.current = None;
+			self.current = Some((cabinet_path.clone(), Cabinet::new(File::open(&cabinet_path)?, debug)?));
+		}
+		let cabinet = &mut self.current.as_mut().unwrap().1;
+
+		debug.logln(1, format!("[read_member_path] Reading '{}' from cabinet {:?} ...", name, cabinet_path));
+		let index = cabinet.find(&name)
+			.ok_or_else(|| Error::new(ErrorKind::NotFound, format!("The cabinet {:?} has no member '{}'", cabinet_path, name)))?;
+		let mut data = Vec::with_capacity(cabinet.members()[index].size as usize);
+		cabinet.open_member(index)?.read_to_end(&mut data)?;
+		Ok(data)
+	}
+}
+
+/// Reads a NUL-terminated string.
+fn read_string(input: &mut impl Read) -> Result<Vec<u8>, Error> {
+	let mut result = Vec::new();
+	let mut byte = [0; 1];
+	loop {
+		input.read_exact(&mut byte)?;
+		if byte[0] == 0 {
+			return Ok(result);
+		}
+		result.push(byte[0]);
+	}
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+	data[offset] as u16 | (data[offset + 1] as u16) << 8
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+	data[offset] as u32 | (data[offset + 1] as u32) << 8 | (data[offset + 2] as u32) << 16 | (data[offset + 3] as u32) << 24
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Cursor, stderr};
+	use std::fs::{create_dir_all, remove_dir_all, write};
+	use std::env::temp_dir;
+	use super::*;
+
+	const FIRST: &[u8] = b"The quick brown fox jumps over the lazy dog. ";
+	const SECOND: &[u8] = b"The quick brown fox jumps over the lazy cat. ";
+
+	/// `FIRST`, compressed as a raw deflate stream.
+	const FIRST_DEFLATED: &str = "0bc94855282ccd4cce56482aca2fcf5348cbaf50c82acd2d2856c82f4b2d5228014ae72456552aa4e4a7eb290000";
+	/// `SECOND`, compressed as a raw deflate stream that refers back to `FIRST`.
+	const SECOND_DEFLATED: &str = "0b2152717262899e0200";
+
+	fn mszip_block(hex: &str) -> Vec<u8> {
+		let mut block = b"CK".to_vec();
+		block.extend((0..hex.len()).step_by(2).map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap()));
+		block
+	}
+
+	/// Builds a cabinet with one MSZIP folder of two data blocks, holding the members `dir\a.dvs` (`FIRST`) and `b.txt` (`SECOND`).
+	fn build_cabinet() -> Vec<u8> {
+		let members: [(&str, u32, u32); 2] = [("dir\\a.dvs", FIRST.len() as u32, 0), ("b.txt", SECOND.len() as u32, FIRST.len() as u32)];
+		let files_offset = 36 + 8;
+		let data_offset = files_offset + members.iter().map(|&(name, _, _)| 17 + name.len()).sum::<usize>();
+
+		let mut cabinet = b"MSCF".to_vec();
+		cabinet.extend(&[0; 12]);
+		cabinet.extend(&(files_offset as u32).to_le_bytes());
+		cabinet.extend(&[0, 0, 0, 0, 3, 1]);
+		cabinet.extend(&1u16.to_le_bytes());
+		cabinet.extend(&(members.len() as u16).to_le_bytes());
+		cabinet.extend(&[0; 6]);
+
+		cabinet.extend(&(data_offset as u32).to_le_bytes());
+		cabinet.extend(&2u16.to_le_bytes());
+		cabinet.extend(&1u16.to_le_bytes());
+
+		for &(name, size, offset) in &members {
+			cabinet.extend(&size.to_le_bytes());
+			cabinet.extend(&offset.to_le_bytes());
+			cabinet.extend(&[0; 8]);
+			cabinet.extend(name.as_bytes());
+			cabinet.push(0);
+		}
+		assert_eq!(cabinet.len(), data_offset);
+
+		for &(hex, size) in &[(FIRST_DEFLATED, FIRST.len()), (SECOND_DEFLATED, SECOND.len())] {
+			let block = mszip_block(hex);
+			cabinet.extend(&[0; 4]);
+			cabinet.extend(&(block.len() as u16).to_le_bytes());
+			cabinet.extend(&(size as u16).to_le_bytes());
+			cabinet.extend(block);
+		}
+		cabinet
+	}
+
+	#[test]
+	fn decompress_mszip_blocks() {
+		assert_eq!(decompress_mszip(&mszip_block(FIRST_DEFLATED), &[], FIRST.len()).unwrap(), FIRST);
+		// The second block can only be decompressed with the history of the first one
+		assert_eq!(decompress_mszip(&mszip_block(SECOND_DEFLATED), FIRST, SECOND.len()).unwrap(), SECOND);
+		assert!(decompress_mszip(&mszip_block(SECOND_DEFLATED), &[], SECOND.len()).is_err());
+		// Bad signature, and a size that does not match
+		assert!(decompress_mszip(&mszip_block(FIRST_DEFLATED)[1..], &[], FIRST.len()).is_err());
+		assert!(decompress_mszip(&mszip_block(FIRST_DEFLATED), &[], FIRST.len() + 1).is_err());
+	}
+
+	#[test]
+	fn read_members() {
+		let mut debug = Debug::new(stderr(), -1);
+		let mut cabinet = Cabinet::new(Cursor::new(build_cabinet()), &mut debug).unwrap();
+		assert_eq!(cabinet.folders()[0].compression, Compression::MsZip);
+		assert_eq!(cabinet.members().iter().map(|member| member.name.as_str()).collect::<Vec<&str>>(), ["dir\\a.dvs", "b.txt"]);
+		assert_eq!(cabinet.find("DIR/A.DVS"), Some(0));
+		assert_eq!(cabinet.find("a.dvs"), None);
+
+		// In reverse order, the folder has to be decompressed again
+		for &(index, expected) in &[(1, SECOND), (0, FIRST), (1, SECOND)] {
+			let mut data = Vec::new();
+			cabinet.open_member(index).unwrap().read_to_end(&mut data).unwrap();
+			assert_eq!(data, expected);
+		}
+		assert!(cabinet.open_member(2).is_err());
+		assert!(Cabinet::new(Cursor::new(b"MSCX".to_vec()), &mut debug).is_err());
+	}
+
+	#[test]
+	fn read_member_paths() {
+		let dir = temp_dir().join(format!("evrecovery-cab-{}", std::process::id()));
+		create_dir_all(&dir).unwrap();
+		let cabinet_path = dir.join("test.CAB");
+		write(&cabinet_path, build_cabinet()).unwrap();
+
+		let mut debug = Debug::new(stderr(), -1);
+		let paths = member_paths(&cabinet_path, "DVS", &mut debug).unwrap();
+		assert_eq!(paths, vec![cabinet_path.join("dir").join("a.dvs")]);
+		assert_eq!(split_member_path(&paths[0]), Some((cabinet_path.clone(), "dir\\a.dvs".to_owned())));
+		assert_eq!(split_member_path(&dir.join("a.dvs")), None);
+
+		let mut cabinets = CabinetCache::new();
+		assert_eq!(cabinets.read_member_path(&paths[0], &mut debug).unwrap(), FIRST);
+		assert_eq!(cabinets.read_member_path(&cabinet_path.join("b.txt"), &mut debug).unwrap(), SECOND);
+		assert!(cabinets.read_member_path(&cabinet_path.join("c.txt"), &mut debug).is_err());
+		remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,648 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use std::io::{Read, Write, Error, ErrorKind};
+#[cfg(feature = "sqlite")]
+use std::io::{Seek, SeekFrom};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use super::metadata::{ContentLocation, Metadata, format_timestamp, parse_timestamp};
+use super::metadata::properties::PropertyMap;
+use super::propset::Summary;
+
+/// The output formats of a catalogue.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Format {
+	/// Comma-separated values, with a header line (RFC 4180).
+	Csv,
+	/// Newline-delimited JSON: one JSON object per line.
+	Ndjson,
+}
+
+impl Format {
+	pub fn from_name(name: &str) -> Option<Format> {
+		match name.to_lowercase().as_str() {
+			"csv" => Some(Format::Csv),
+			"ndjson" | "jsonl" => Some(Format::Ndjson),
+			_ => None,
+		}
+	}
+}
+
+/// A catalogue entry, describing a single archived item.
+pub struct Entry {
+	/// The path of the DVS file.
+	pub dvs_path: String,
+	/// The size of the DVS file.
+	pub dvs_size: u64,
+	/// The path of the out-sourced file, if one has been found.
+	pub dvf_path: Option<String>,
+	pub metadata: Metadata,
+	/// The summary information recorded in the archived file itself (for legacy Office documents), if it has been read.
+	pub document: Summary,
+	/// The encryption of the archived file (for encrypted or IRM-protected Office documents), if it has been detected.
+	pub encryption: Option<String>,
+	/// The reason why the DVS file could not be read, if it could not.
+	pub error: Option<String>,
+	/// Problems that did not prevent reading the DVS file, such as unreadable summary information.
+	pub notes: Option<String>,
+}
+
+/// The names of the columns of a catalogue, in order.
+pub const COLUMNS: [&str; 31] = [
+	"dvs_path",
+	"dvs_size",
+	"original_path",
+	"folder_path",
+	"title",
+	"original_size",
+	"large_file_size",
+	"created_time",
+	"modified_time",
+	"archived_date",
+	"mime_type",
+	"author",
+	"vault_id",
+	"retention_category",
+	"file_extension",
+	"content",
+	"user_properties",
+	"agent_properties",
+	"doc_title",
+	"doc_subject",
+	"doc_author",
+	"doc_last_author",
+	"doc_created",
+	"doc_modified",
+	"doc_application",
+	"doc_company",
+	"doc_page_count",
+	"encryption",
+	"dvf_path",
+	"error",
+	"notes",
+];
+
+enum Value {
+	Text(String),
+	Number(u64),
+	Null,
+}
+
+impl Entry {
+	/// The values of this entry, in the same order as `COLUMNS`.
+	fn values(&self) -> Vec<Value> {
+		let text = |value: &Option<String>| match *value { Some(ref value) => Value::Text(value.clone()), None => Value::Null };
+		let number = |value: Option<u64>| match value { Some(value) => Value::Number(value), None => Value::Null };
+		let timestamp = |value: Option<u64>| match value { Some(value) => Value::Text(format_timestamp(value)), None => Value::Null };
+		let properties = |value: &Option<PropertyMap>| match *value { Some(ref value) => Value::Text(format_properties(value)), None => Value::Null };
+		let metadata = &self.metadata;
+		vec![
+			Value::Text(self.dvs_path.clone()),
+			Value::Number(self.dvs_size),
+			text(&metadata.original_path()),
+			text(&metadata.folder_path),
+			text(&metadata.title),
+			number(metadata.original_size),
+			number(metadata.large_file_size),
+			timestamp(metadata.created_time),
+			timestamp(metadata.modified_time),
+			timestamp(metadata.archived_date),
+			text(&metadata.mime_type),
+			text(&metadata.author),
+			text(&metadata.vault_id),
+			text(&metadata.retention_category),
+			text(&metadata.file_extension),
+			match self.error { Some(_) => Value::Null, None => Value::Text(metadata.content.name().to_owned()) },
+			properties(&metadata.user_properties),
+			properties(&metadata.agent_properties),
+			text(&self.document.title),
+			text(&self.document.subject),
+			text(&self.document.author),
+			text(&self.document.last_author),
+			timestamp(self.document.created),
+			timestamp(self.document.modified),
+			text(&self.document.application),
+			text(&self.document.company),
+			number(self.document.page_count),
+			text(&self.encryption),
+			text(&self.dvf_path),
+			text(&self.error),
+			text(&self.notes),
+		]
+	}
+}
+
+impl Entry {
+	/// Builds an entry from the (textual) values of its fields, as found in a catalogue.
+	/// Unknown columns are ignored; missing columns and empty values are treated as missing properties.
+	fn from_fields(fields: &HashMap<String, String>) -> Result<Entry, Error> {
+		let text = |column: &str| fields.get(column).filter(|value| !value.is_empty()).cloned();
+		let number = |column: &str| match text(column) {
+			Some(value) => value.parse::<u64>().map(Some).map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid number in column '{}': {}", column, value))),
+			None => Ok(None),
+		};
+		let timestamp = |column: &str| match text(column) {
+			Some(value) => parse_timestamp(&value).map(Some).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Invalid timestamp in column '{}': {}", column, value))),
+			None => Ok(None),
+		};
+		let properties = |column: &str| match text(column) {
+			Some(value) => parse_properties(&value).map(Some).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Invalid properties in column '{}': {}", column, value))),
+			None => Ok(None),
+		};
+
+		let dvs_path = text("dvs_path").ok_or_else(|| Error::new(ErrorKind::InvalidData, "Catalogue entry without a 'dvs_path'"))?;
+		Ok(Entry {
+			dvs_path,
+			dvs_size: number("dvs_size")?.unwrap_or(0),
+			dvf_path: text("dvf_path"),
+			metadata: Metadata {
+				folder_path: text("folder_path"),
+				title: text("title"),
+				original_size: number("original_size")?,
+				large_file_size: number("large_file_size")?,
+				created_time: timestamp("created_time")?,
+				modified_time: timestamp("modified_time")?,
+				archived_date: timestamp("archived_date")?,
+				mime_type: text("mime_type"),
+				author: text("author"),
+				vault_id: text("vault_id"),
+				retention_category: text("retention_category"),
+				file_extension: text("file_extension"),
+				user_properties: properties("user_properties")?,
+				agent_properties: properties("agent_properties")?,
+				content: text("content").and_then(|content| ContentLocation::from_name(&content)).unwrap_or_default(),
+			},
+			document: Summary {
+				title: text("doc_title"),
+				subject: text("doc_subject"),
+				author: text("doc_author"),
+				last_author: text("doc_last_author"),
+				created: timestamp("doc_created")?,
+				modified: timestamp("doc_modified")?,
+				application: text("doc_application"),
+				company: text("doc_company"),
+				page_count: number("doc_page_count")?,
+				..Summary::default()
+			},
+			encryption: text("encryption"),
+			error: text("error"),
+			notes: text("notes"),
+		})
+	}
+}
+
+/// Reads all entries of a catalogue that has been written by a `Writer`.
+/// The format is detected automatically: NDJSON if the first line starts with `{`, CSV otherwise.
+pub fn read(mut input: impl Read) -> Result<Vec<Entry>, Error> {
+	let mut text = String::new();
+	input.read_to_string(&mut text)?;
+	match text.trim_start().starts_with('{') {
+		true => read_ndjson(&text),
+		false => read_csv(&text),
+	}
+}
+
+/// Reads all entries of a catalogue file, which is either a text catalogue (see `read`) or, if built with the `sqlite` feature, a SQLite database.
+pub fn read_file(path: &Path) -> Result<Vec<Entry>, Error> {
+	let mut file = File::open(path)?;
+	#[cfg(feature = "sqlite")]
+	{
+		let mut header = [0u8; 16];
+		let num_bytes = file.read(&mut header)?;
+		if sqlite::is_sqlite(&header[..num_bytes]) {
+			return sqlite::read(path);
+		}
+		file.seek(SeekFrom::Start(0))?;
+	}
+	read(&mut file)
+}
+
+fn read_csv(text: &str) -> Result<Vec<Entry>, Error> {
+	let mut records = parse_csv(text)?.into_iter();
+	let header = match records.next() {
+		Some(header) => header,
+		None => return Ok(Vec::new()),
+	};
+
+	let mut entries = Vec::new();
+	for record in records {
+		if record.len() == 1 && record[0].is_empty() {
+			continue;
+		}
+		let fields = header.iter().cloned().zip(record).collect::<HashMap<String, String>>();
+		entries.push(Entry::from_fields(&fields)?);
+	}
+	Ok(entries)
+}
+
+/// Splits CSV text into records of fields (RFC 4180), honouring quoted fields that contain separators or line breaks.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, Error> {
+	let mut records = Vec::new();
+	let mut record = Vec::new();
+	let mut field = String::new();
+	let mut chars = text.chars().peekable();
+	let mut quoted = false;
+	while let Some(c) = chars.next() {
+		match (quoted, c) {
+			(true, '"') if chars.peek() == Some(&'"') => { chars.next(); field.push('"'); },
+			(true, '"') => quoted = false,
+			(true, c) => field.push(c),
+			(false, '"') => quoted = true,
+			(false, ',') => record.push(std::mem::take(&mut field)),
+			(false, '\r') => {},
+			(false, '\n') => {
+				record.push(std::mem::take(&mut field));
+				records.push(std::mem::take(&mut record));
+			},
+			(false, c) => field.push(c),
+		}
+	}
+	if quoted {
+		return Err(Error::new(ErrorKind::InvalidData, "Unterminated quoted field in catalogue"));
+	}
+	if !field.is_empty() || !record.is_empty() {
+		record.push(field);
+		records.push(record);
+	}
+	Ok(records)
+}
+
+fn read_ndjson(text: &str) -> Result<Vec<Entry>, Error> {
+	let mut entries = Vec::new();
+	for (index, line) in text.lines().enumerate() {
+		if line.trim().is_empty() {
+			continue;
+		}
+		match parse_json_object(line) {
+			Some(fields) => entries.push(Entry::from_fields(&fields)?),
+			None => return Err(Error::new(ErrorKind::InvalidData, format!("Malformed JSON object in line {} of the catalogue", index + 1))),
+		}
+	}
+	Ok(entries)
+}
+
+/// Parses a flat JSON object, whose values are strings, numbers or `null`, into a map of textual values.
+/// `null` values are mapped to empty strings, in line with empty CSV fields.
+fn parse_json_object(line: &str) -> Option<HashMap<String, String>> {
+	parse_json_pairs(line).map(|pairs| pairs.into_iter().collect())
+}
+
+/// Parses a flat JSON object like `parse_json_object`, but keeps the members in order (including repeated keys).
+fn parse_json_pairs(line: &str) -> Option<Vec<(String, String)>> {
+	let mut fields = Vec::new();
+	let mut chars = line.trim().chars().peekable();
+	let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| while chars.peek().is_some_and(|c| c.is_whitespace()) { chars.next(); };
+
+	if chars.next() != Some('{') {
+		return None;
+	}
+	skip_whitespace(&mut chars);
+	if chars.peek() == Some(&'}') {
+		return Some(fields);
+	}
+	loop {
+		skip_whitespace(&mut chars);
+		let key = parse_json_string(&mut chars)?;
+		skip_whitespace(&mut chars);
+		if chars.next() != Some(':') {
+			return None;
+		}
+		skip_whitespace(&mut chars);
+		let value = match *chars.peek()? {
+			'"' => parse_json_string(&mut chars)?,
+			'n' => {
+				if chars.by_ref().take(4).collect::<String>() != "null" {
+					return None;
+				}
+				String::new()
+			},
+			_ => {
+				let mut number = String::new();
+				while let Some(&c) = chars.peek() {
+					if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+						break;
+					}
+					number.push(c);
+					chars.next();
+				}
+				if number.is_empty() {
+					return None;
+				}
+				number
+			},
+		};
+		fields.push((key, value));
+		skip_whitespace(&mut chars);
+		match chars.next()? {
+			',' => continue,
+			'}' => return Some(fields),
+			_ => return None,
+		}
+	}
+}
+
+/// Parses a JSON string literal, including its quotes.
+fn parse_json_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+	if chars.next() != Some('"') {
+		return None;
+	}
+	let mut result = String::new();
+	let mut pending_surrogate: Option<u32> = None;
+	loop {
+		let c = chars.next()?;
+		let code = match c {
+			'"' => return Some(result),
+			'\\' => match chars.next()? {
+				'"' => '"' as u32,
+				'\\' => '\\' as u32,
+				'/' => '/' as u32,
+				'b' => 0x08,
+				'f' => 0x0C,
+				'n' => '\n' as u32,
+				'r' => '\r' as u32,
+				't' => '\t' as u32,
+				'u' => u32::from_str_radix(&chars.by_ref().take(4).collect::<String>(), 16).ok()?,
+				_ => return None,
+			},
+			c => c as u32,
+		};
+		// Combine UTF-16 surrogate pairs
+		match (pending_surrogate.take(), code) {
+			(None, 0xD800..=0xDBFF) => pending_surrogate = Some(code),
+			(Some(high), 0xDC00..=0xDFFF) => result.push(std::char::from_u32(0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00))?),
+			(Some(_), code) => {
+				result.push(std::char::REPLACEMENT_CHARACTER);
+				result.push(std::char::from_u32(code).unwrap_or(std::char::REPLACEMENT_CHARACTER));
+			},
+			(None, code) => result.push(std::char::from_u32(code).unwrap_or(std::char::REPLACEMENT_CHARACTER)),
+		}
+	}
+}
+
+/// Formats a property map for a catalogue field: key/value pairs as a JSON object, and raw content as hexadecimal digits.
+/// Empty raw content is formatted as an empty object, since an empty field denotes an absent property (it holds no properties either way).
+pub fn format_properties(properties: &PropertyMap) -> String {
+	match *properties {
+		PropertyMap::Raw(ref data) if data.is_empty() => "{}".to_owned(),
+		PropertyMap::Map(ref pairs) => format!("{{{}}}", pairs.iter().map(|(key, value)| format!("{}:{}", json_escape(key), json_escape(value))).collect::<Vec<String>>().join(",")),
+		PropertyMap::Raw(ref data) => data.iter().map(|byte| format!("{:02X}", byte)).collect(),
+	}
+}
+
+/// Parses a property map that has been formatted by `format_properties`.
+pub fn parse_properties(text: &str) -> Option<PropertyMap> {
+	if text.starts_with('{') {
+		return parse_json_pairs(text).map(PropertyMap::Map);
+	}
+	if !text.len().is_multiple_of(2) || !text.is_ascii() {
+		return None;
+	}
+	(0..text.len()).step_by(2).map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok()).collect::<Option<Vec<u8>>>().map(PropertyMap::Raw)
+}
+
+/// Writes the metadata of a single archived item as a JSON object (one member per line), as kept in a sidecar file next to the restored file.
+/// The members are named like the columns of a catalogue; property maps are written as nested objects, and raw properties as hexadecimal digits.
+pub fn write_sidecar(output: &mut impl Write, metadata: &Metadata) -> Result<(), Error> {
+	let text = |value: &Option<String>| value.as_ref().map(|value| json_escape(value)).unwrap_or_else(|| "null".to_owned());
+	let number = |value: Option<u64>| value.map(|value| value.to_string()).unwrap_or_else(|| "null".to_owned());
+	let timestamp = |value: Option<u64>| value.map(|value| json_escape(&format_timestamp(value))).unwrap_or_else(|| "null".to_owned());
+	let properties = |value: &Option<PropertyMap>| match *value {
+		Some(PropertyMap::Map(ref pairs)) if pairs.is_empty() => "{}".to_owned(),
+		Some(PropertyMap::Raw(ref data)) if data.is_empty() => "{}".to_owned(),
+		Some(PropertyMap::Map(ref pairs)) => format!("{{\n{}\n\t}}", pairs.iter().map(|(key, value)| format!("\t\t{}: {}", json_escape(key), json_escape(value))).collect::<Vec<String>>().join(",\n")),
+		Some(ref raw) => json_escape(&format_properties(raw)),
+		None => "null".to_owned(),
+	};
+	let members = [
+		("original_path", text(&metadata.original_path())),
+		("folder_path", text(&metadata.folder_path)),
+		("title", text(&metadata.title)),
+		("original_size", number(metadata.original_size)),
+		("large_file_size", number(metadata.large_file_size)),
+		("created_time", timestamp(metadata.created_time)),
+		("modified_time", timestamp(metadata.modified_time)),
+		("archived_date", timestamp(metadata.archived_date)),
+		("mime_type", text(&metadata.mime_type)),
+		("author", text(&metadata.author)),
+		("vault_id", text(&metadata.vault_id)),
+		("retention_category", text(&metadata.retention_category)),
+		("file_extension", text(&metadata.file_extension)),
+		("content", json_escape(metadata.content.name())),
+		("user_properties", properties(&metadata.user_properties)),
+		("agent_properties", properties(&metadata.agent_properties)),
+	];
+	let members = members.iter().map(|(name, value)| format!("\t{}: {}", json_escape(name), value)).collect::<Vec<String>>();
+	writeln!(output, "{{\n{}\n}}", members.join(",\n"))
+}
+
+/// Writes catalogue entries to an output, in the given format.
+pub struct Writer<TWrite: Write> {
+	output: TWrite,
+	format: Format,
+	header_written: bool,
+}
+
+impl<TWrite> Writer<TWrite> where TWrite: Write {
+	pub fn new(output: TWrite, format: Format) -> Writer<TWrite> {
+		Writer { output, format, header_written: false }
+	}
+
+	pub fn write(&mut self, entry: &Entry) -> Result<(), Error> {
+		let values = entry.values();
+		match self.format {
+			Format::Csv => {
+				if !self.header_written {
+					writeln!(self.output, "{}", COLUMNS.join(","))?;
+					self.header_written = true;
+				}
+				let fields = values.iter().map(|value| match *value {
+					Value::Text(ref text) => csv_escape(text),
+					Value::Number(number) => number.to_string(),
+					Value::Null => String::new(),
+				}).collect::<Vec<String>>();
+				writeln!(self.output, "{}", fields.join(","))
+			},
+			Format::Ndjson => {
+				let fields = COLUMNS.iter().zip(values.iter()).map(|(column, value)| format!("{}:{}", json_escape(column), match *value {
+					Value::Text(ref text) => json_escape(text),
+					Value::Number(number) => number.to_string(),
+					Value::Null => "null".to_owned(),
+				})).collect::<Vec<String>>();
+				writeln!(self.output, "{{{}}}", fields.join(","))
+			},
+		}
+	}
+
+	pub fn flush(&mut self) -> Result<(), Error> {
+		self.output.flush()
+	}
+}
+
+/// Quotes a CSV field if necessary.
+pub fn csv_escape(text: &str) -> String {
+	if text.contains([',', '"', '\r', '\n']) {
+		format!("\"{}\"", text.replace('"', "\"\""))
+	}
+	else {
+		text.to_owned()
+	}
+}
+
+/// Encodes a string as a JSON string literal.
+pub fn json_escape(text: &str) -> String {
+	let mut result = String::with_capacity(text.len() + 2);
+	result.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => result.push_str("\\\""),
+			'\\' => result.push_str("\\\\"),
+			'\n' => result.push_str("\\n"),
+			'\r' => result.push_str("\\r"),
+			'\t' => result.push_str("\\t"),
+			c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+			c => result.push(c),
+		}
+	}
+	result.push('"');
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry() -> Entry {
+		Entry {
+			dvs_path: "V:\\2018\\a,\"b\".dvs".to_owned(),
+			dvs_size: 1234,
+			dvf_path: None,
+			metadata: Metadata {
+				folder_path: Some("F:\\Data\nProjects".to_owned()),
+				title: Some("Übersicht.doc".to_owned()),
+				original_size: Some(56789),
+				created_time: Some(1520000000),
+				content: ContentLocation::Embedded,
+				..Metadata::default()
+			},
+			document: Summary::default(),
+			encryption: None,
+			error: None,
+			notes: None,
+		}
+	}
+
+	fn round_trip(format: Format) -> Vec<Entry> {
+		let mut output = Vec::new();
+		{
+			let mut writer = Writer::new(&mut output, format);
+			writer.write(&entry()).unwrap();
+			writer.write(&Entry { error: Some("Unreadable".to_owned()), ..entry() }).unwrap();
+		}
+		read(&output[..]).unwrap()
+	}
+
+	#[test]
+	fn csv_and_ndjson_round_trip() {
+		for &format in &[Format::Csv, Format::Ndjson] {
+			let entries = round_trip(format);
+			assert_eq!(entries.len(), 2);
+			assert_eq!(entries[0].dvs_path, entry().dvs_path);
+			assert_eq!(entries[0].dvs_size, 1234);
+			assert_eq!(entries[0].metadata.folder_path, entry().metadata.folder_path);
+			assert_eq!(entries[0].metadata.title, entry().metadata.title);
+			assert_eq!(entries[0].metadata.original_size, Some(56789));
+			assert_eq!(entries[0].metadata.large_file_size, None);
+			assert_eq!(entries[0].metadata.created_time, Some(1520000000));
+			assert!(entries[0].metadata.content == ContentLocation::Embedded);
+			assert_eq!(entries[1].error, Some("Unreadable".to_owned()));
+		}
+	}
+
+	#[test]
+	fn properties_round_trip() {
+		let map = PropertyMap::Map(vec![
+			("Owner".to_owned(), "DOMAIN\\jdoe".to_owned()),
+			("Note".to_owned(), "\"quoted\", tab\t".to_owned()),
+			("Owner".to_owned(), String::new()),
+		]);
+		let raw = PropertyMap::Raw(vec![0x00, 0x7F, 0xFF]);
+		for properties in &[map, raw, PropertyMap::Map(Vec::new())] {
+			assert_eq!(parse_properties(&format_properties(properties)).as_ref(), Some(properties));
+		}
+		assert_eq!(format_properties(&PropertyMap::Raw(vec![0x00, 0x7F, 0xFF])), "007FFF");
+		// Empty raw content reads back as an empty map, rather than as an absent property
+		assert_eq!(format_properties(&PropertyMap::Raw(Vec::new())), "{}");
+		assert_eq!(parse_properties("{}"), Some(PropertyMap::Map(Vec::new())));
+		// Invalid fields
+		assert_eq!(parse_properties("7F0"), None);
+		assert_eq!(parse_properties("GG"), None);
+		assert_eq!(parse_properties("{\"a\":"), None);
+	}
+
+	#[test]
+	fn catalogue_properties_round_trip() {
+		let mut entry = entry();
+		entry.metadata.user_properties = Some(PropertyMap::Map(vec![("Share/Name".to_owned(), "Projects, 2018".to_owned())]));
+		entry.metadata.agent_properties = Some(PropertyMap::Raw(Vec::new()));
+		for &format in &[Format::Csv, Format::Ndjson] {
+			let mut output = Vec::new();
+			Writer::new(&mut output, format).write(&entry).unwrap();
+			let entries = read(&output[..]).unwrap();
+			assert_eq!(entries[0].metadata.user_properties, entry.metadata.user_properties);
+			assert_eq!(entries[0].metadata.agent_properties, Some(PropertyMap::Map(Vec::new())));
+		}
+	}
+
+	#[test]
+	fn reject_malformed_catalogues() {
+		assert!(read("dvs_path,dvs_size\n\"a.dvs,12\n".as_bytes()).is_err());
+		assert!(read("dvs_path,dvs_size\na.dvs,twelve\n".as_bytes()).is_err());
+		assert!(read("{\"dvs_path\":\"a.dvs\",\n".as_bytes()).is_err());
+		assert_eq!(read("dvs_path,unknown\na.dvs,x\n".as_bytes()).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn json_strings() {
+		let text = "quote \" backslash \\ tab \t bell \u{7} snowman \u{2603}";
+		assert_eq!(parse_json_string(&mut json_escape(text).chars().peekable()), Some(text.to_owned()));
+		// Surrogate pairs are combined
+		assert_eq!(parse_json_string(&mut "\"\\ud83d\\ude00\"".chars().peekable()), Some("\u{1F600}".to_owned()));
+	}
+
+	#[cfg(feature = "sqlite")]
+	#[test]
+	fn sqlite_round_trip() {
+		let path = std::env::temp_dir().join(format!("evrecovery-catalogue-{}.sqlite", std::process::id()));
+		{
+			let mut writer = sqlite::SqliteWriter::create(&path).unwrap();
+			writer.write(&entry()).unwrap();
+			writer.finish().unwrap();
+		}
+		let entries = read_file(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].dvs_path, entry().dvs_path);
+		assert_eq!(entries[0].metadata.original_size, Some(56789));
+		assert_eq!(entries[0].metadata.created_time, Some(1520000000));
+	}
+}
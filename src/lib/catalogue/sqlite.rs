@@ -0,0 +1,99 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+extern crate rusqlite;
+
+use std::collections::HashMap;
+use std::io::Error;
+use std::path::Path;
+use self::rusqlite::{Connection, params_from_iter};
+use self::rusqlite::types::{ToSqlOutput, Value as SqlValue, ValueRef};
+use super::{COLUMNS, Entry, Value};
+
+/// The name of the table that holds the catalogue entries.
+const TABLE: &str = "catalogue";
+
+/// The columns that hold numbers; all others hold text.
+const NUMBER_COLUMNS: [&str; 4] = ["dvs_size", "original_size", "large_file_size", "doc_page_count"];
+
+/// Writes catalogue entries into a table of a SQLite database, with one column per entry of `COLUMNS`.
+/// All entries are written in a single transaction, which is committed by `finish`.
+pub struct SqliteWriter {
+	connection: Connection,
+}
+
+impl SqliteWriter {
+	/// Creates the database file (replacing the catalogue table, if it exists already).
+	pub fn create(path: &Path) -> Result<SqliteWriter, Error> {
+		let connection = Connection::open(path).map_err(into_error)?;
+		let columns = COLUMNS.iter()
+			.map(|column| format!("{} {}", column, if NUMBER_COLUMNS.contains(column) { "INTEGER" } else { "TEXT" }))
+			.collect::<Vec<String>>();
+		connection.execute_batch(&format!("DROP TABLE IF EXISTS {table}; CREATE TABLE {table} ({columns}); BEGIN;", table = TABLE, columns = columns.join(", ")))
+			.map_err(into_error)?;
+		Ok(SqliteWriter { connection })
+	}
+
+	pub fn write(&mut self, entry: &Entry) -> Result<(), Error> {
+		let placeholders = vec!["?"; COLUMNS.len()].join(", ");
+		let mut statement = self.connection.prepare_cached(&format!("INSERT INTO {} VALUES ({})", TABLE, placeholders)).map_err(into_error)?;
+		statement.execute(params_from_iter(entry.values().into_iter().map(|value| match value {
+			Value::Text(text) => ToSqlOutput::Owned(SqlValue::Text(text)),
+			Value::Number(number) => ToSqlOutput::Owned(SqlValue::Integer(number as i64)),
+			Value::Null => ToSqlOutput::Owned(SqlValue::Null),
+		}))).map_err(into_error)?;
+		Ok(())
+	}
+
+	/// Commits all written entries.
+	pub fn finish(self) -> Result<(), Error> {
+		self.connection.execute_batch("COMMIT;").map_err(into_error)
+	}
+}
+
+/// Reads all entries of a catalogue that has been written by a `SqliteWriter`.
+pub fn read(path: &Path) -> Result<Vec<Entry>, Error> {
+	let connection = Connection::open(path).map_err(into_error)?;
+	let mut statement = connection.prepare(&format!("SELECT * FROM {}", TABLE)).map_err(into_error)?;
+	let columns = statement.column_names().iter().map(|name| name.to_string()).collect::<Vec<String>>();
+	let mut rows = statement.query([]).map_err(into_error)?;
+	let mut entries = Vec::new();
+	while let Some(row) = rows.next().map_err(into_error)? {
+		let mut fields = HashMap::new();
+		for (index, column) in columns.iter().enumerate() {
+			let value = match row.get_ref(index).map_err(into_error)? {
+				ValueRef::Null => String::new(),
+				ValueRef::Integer(number) => number.to_string(),
+				ValueRef::Real(number) => number.to_string(),
+				ValueRef::Text(text) | ValueRef::Blob(text) => String::from_utf8_lossy(text).into_owned(),
+			};
+			fields.insert(column.clone(), value);
+		}
+		entries.push(Entry::from_fields(&fields)?);
+	}
+	Ok(entries)
+}
+
+/// Whether the given data is the start of a SQLite database file.
+pub fn is_sqlite(header: &[u8]) -> bool {
+	header.starts_with(b"SQLite format 3\0")
+}
+
+fn into_error(error: rusqlite::Error) -> Error {
+	Error::other(format!("SQLite: {}", error))
+}
@@ -16,9 +16,11 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-mod structures;
+pub mod structures;
+#[cfg(test)]
+pub mod testing;
 
-use self::structures::{Header, SectorLocation};
+use self::structures::{Header, SectorLocation, MAXREGSECT, ENDOFCHAIN, FREESECT};
 use std::io::{Read, Seek, SeekFrom, Write, Error, ErrorKind};
 use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
 use std::cmp::min;
@@ -41,12 +43,15 @@ pub struct Object {
 	right_sibling_id: u32,
 	/// If this object is a folder: the ID of the first child of this folder. Otherwise undefined.
 	child_id: u32,
+	/// If this object is a folder: the class identifier of the object that it holds (all zeros if none is set).
+	pub clsid: [u8; 16],
 	pub creation_time: u64,
 	pub modified_time: u64,
-	/// If this object is a file: the location of the first sector that holds the file content.
-	starting_sector_location: u32,
+	/// If this object is a file: the location of the first sector (or mini sector) that holds the file content.
+	/// For the root storage: the location of the first sector of the ministream.
+	pub starting_sector_location: u32,
 	/// If this object is a file: the length of the file content.
-	stream_size: u64,
+	pub stream_size: u64,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -60,6 +65,23 @@ pub enum ObjectType {
 	Unknown,
 }
 
+/// A chain of sectors (or mini-sectors), as recorded in the FAT (or miniFAT).
+pub struct Chain {
+	pub sectors: Vec<u32>,
+	pub end: ChainEnd,
+}
+
+/// How a chain of sectors ends.
+#[derive(Copy, Clone, PartialEq)]
+pub enum ChainEnd {
+	/// The chain is terminated properly, by `ENDOFCHAIN`.
+	EndOfChain,
+	/// The chain leads back to a sector that it already contains.
+	Loop(u32),
+	/// The chain leads to a special value other than `ENDOFCHAIN` (such as `FREESECT`), or to a sector beyond the end of the table.
+	Invalid(u32),
+}
+
 #[derive(Clone)]
 pub enum ObjectResult {
 	Ok(Object),
@@ -84,7 +106,8 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 			(buffer[5] as u64) << 40 |
 			(buffer[6] as u64) << 48 |
 			(buffer[7] as u64) << 56;
-		// skip CLSID (16 bytes)
+		let mut clsid = [0; 16];
+		clsid.copy_from_slice(&buffer[8..24]);
 		let minor_version = (buffer[24] as u16) | (buffer[25] as u16) << 8;
 		let major_version = (buffer[26] as u16) | (buffer[27] as u16) << 8;
 		let byte_order = (buffer[28] as u16) | (buffer[29] as u16) << 8;
@@ -94,7 +117,7 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 		let number_of_directory_sectors = (buffer[40] as u32) | (buffer[41] as u32) << 8 | (buffer[42] as u32) << 16 | (buffer[43] as u32) << 24;
 		let number_of_fat_sectors = (buffer[44] as u32) | (buffer[45] as u32) << 8 | (buffer[46] as u32) << 16 | (buffer[47] as u32) << 24;
 		let first_directory_sector_location = SectorLocation((buffer[48] as u32) | (buffer[49] as u32) << 8 | (buffer[50] as u32) << 16 | (buffer[51] as u32) << 24);
-		// skip transaction signature number (4 bytes)
+		let transaction_signature_number = (buffer[52] as u32) | (buffer[53] as u32) << 8 | (buffer[54] as u32) << 16 | (buffer[55] as u32) << 24;
 		let mini_stream_cutoff_size = (buffer[56] as u32) | (buffer[57] as u32) << 8 | (buffer[58] as u32) << 16 | (buffer[59] as u32) << 24;
 		let first_mini_fat_sector_location = SectorLocation((buffer[60] as u32) | (buffer[61] as u32) << 8 | (buffer[62] as u32) << 16 | (buffer[63] as u32) << 24);
 		let number_of_mini_fat_sectors = (buffer[64] as u32) | (buffer[65] as u32) << 8 | (buffer[66] as u32) << 16 | (buffer[67] as u32) << 24;
@@ -105,6 +128,7 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 			file,
 			header: Header {
 				signature,
+				clsid,
 				minor_version,
 				major_version,
 				byte_order,
@@ -115,6 +139,7 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 				number_of_directory_sectors,
 				number_of_fat_sectors,
 				first_directory_sector_location,
+				transaction_signature_number,
 				mini_stream_cutoff_size,
 				first_mini_fat_sector_location,
 				number_of_mini_fat_sectors,
@@ -124,6 +149,122 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 		})
 	}
 
+	pub fn header(&self) -> &Header {
+		&self.header
+	}
+
+	/// Reads the 109 DIFAT entries that follow the header, i.e. the locations of the first 109 FAT sectors.
+	pub fn get_header_difat(&mut self) -> Result<Vec<u32>, Error> {
+		self.file.seek(SeekFrom::Start(0x4C))?;
+		(0..109).map(|_| self.read_u32()).collect()
+	}
+
+	/// Determines the locations of all DIFAT sectors, by following the chain of DIFAT sectors from the header.
+	pub fn get_difat_sectors(&mut self, debug: &mut Debug) -> Result<Vec<u32>, Error> {
+		let mut result = Vec::new();
+		let mut location = self.header.first_difat_sector_location.0;
+		let difat_entries_per_sector = self.header.sector_size / 4 - 1;
+		while location <= MAXREGSECT && result.len() < self.header.number_of_difat_sectors as usize {
+			if result.contains(&location) {
+				debug.logln(0, format!("[get_difat_sectors] The chain of DIFAT sectors loops back to sector #{}", location));
+				break;
+			}
+			result.push(location);
+			// The last entry of every DIFAT sector holds the location of the next DIFAT sector
+			match self.read_table_sector(location, debug)? {
+				Some(entries) => location = entries[difat_entries_per_sector as usize],
+				None => {
+					debug.logln(0, format!("[get_difat_sectors] DIFAT sector #{} cannot be read; the chain of DIFAT sectors ends there", location));
+					break;
+				},
+			}
+		}
+		Ok(result)
+	}
+
+	/// Determines the locations of all FAT sectors, from the header and the DIFAT sectors.
+	pub fn get_fat_sectors(&mut self, debug: &mut Debug) -> Result<Vec<u32>, Error> {
+		let count = self.header.number_of_fat_sectors as usize;
+		let mut result = self.get_header_difat()?;
+		result.truncate(count);
+		let difat_entries_per_sector = self.header.sector_size / 4 - 1;
+		for difat_sector in self.get_difat_sectors(debug)? {
+			// The locations held by an unreadable DIFAT sector are unknown
+			let entries = self.read_table_sector(difat_sector, debug)?.unwrap_or_else(|| {
+				debug.logln(0, format!("[get_fat_sectors] DIFAT sector #{} cannot be read; its FAT sectors are recorded as FREESECT", difat_sector));
+				vec![FREESECT; difat_entries_per_sector as usize]
+			});
+			for &entry in entries.iter().take(difat_entries_per_sector as usize) {
+				if result.len() == count {
+					return Ok(result);
+				}
+				result.push(entry);
+			}
+		}
+		Ok(result)
+	}
+
+	/// Reads the whole FAT, i.e. the number of the next sector in the chain for every sector of the file.
+	pub fn get_fat(&mut self, debug: &mut Debug) -> Result<Vec<u32>, Error> {
+		let fat_entries_per_sector = self.header.sector_size / 4;
+		let fat_sectors = self.get_fat_sectors(debug)?;
+		let mut result = Vec::new();
+		let mut unreadable = 0;
+		for &fat_sector in &fat_sectors {
+			debug.logln(2, format!("[get_fat] Reading FAT sector #{} ...", fat_sector));
+			match self.read_table_sector(fat_sector, debug)? {
+				Some(entries) => result.extend(entries),
+				None => {
+					unreadable += 1;
+					result.extend(vec![FREESECT; fat_entries_per_sector as usize]);
+				},
+			}
+		}
+		if unreadable > 0 {
+			debug.logln(0, format!("[get_fat] {} of {} FAT sectors cannot be read; their entries are recorded as FREESECT", unreadable, fat_sectors.len()));
+		}
+		Ok(result)
+	}
+
+	/// Reads the whole miniFAT, i.e. the number of the next mini-sector in the chain for every mini-sector of the ministream.
+	/// The miniFAT sectors are located by the given FAT.
+	pub fn get_mini_fat(&mut self, fat: &[u32], debug: &mut Debug) -> Result<Vec<u32>, Error> {
+		let minifat_entries_per_sector = self.header.sector_size / 4;
+		let chain = follow_chain(fat, self.header.first_mini_fat_sector_location.0);
+		let mut result = Vec::new();
+		let mut unreadable = 0;
+		for &minifat_sector in chain.sectors.iter().take(self.header.number_of_mini_fat_sectors as usize) {
+			debug.logln(2, format!("[get_mini_fat] Reading miniFAT sector #{} ...", minifat_sector));
+			match self.read_table_sector(minifat_sector, debug)? {
+				Some(entries) => result.extend(entries),
+				None => {
+					unreadable += 1;
+					result.extend(vec![FREESECT; minifat_entries_per_sector as usize]);
+				},
+			}
+		}
+		if unreadable > 0 {
+			debug.logln(0, format!("[get_mini_fat] {} miniFAT sectors cannot be read; their entries are recorded as FREESECT", unreadable));
+		}
+		Ok(result)
+	}
+
+	/// Reads all entries of a sector of the DIFAT, FAT or miniFAT.
+	/// A sector that is not a regular sector, or that lies (partly) beyond the end of the file, cannot be read: `None` is returned,
+	/// so that damaged files can still be examined as far as possible.
+	fn read_table_sector(&mut self, sector: u32, debug: &mut Debug) -> Result<Option<Vec<u32>>, Error> {
+		if sector > MAXREGSECT {
+			return Ok(None);
+		}
+		self.seek_sector(sector, debug)?;
+		let mut buffer = vec![0; self.header.sector_size as usize];
+		match self.file.read_exact(&mut buffer) {
+			Ok(()) => Ok(Some(buffer.chunks(4).map(|entry| u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]])).collect())),
+			Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
 	pub fn get_root_object(&mut self, debug: &mut Debug) -> Result<Object, Error> {
 		self.get_object(0, debug)
 	}
@@ -178,6 +319,32 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 		}
 	}
 
+	/// Lists all direct children of the given storage object, in the order of the binary tree (i.e. sorted as the CFBF specification demands).
+	pub fn get_children(&mut self, object: &Object, debug: &mut Debug) -> Result<Vec<Object>, Error> {
+		let mut children = Vec::new();
+		if let ObjectResult::Ok(child) = self.get_first_child(object, debug)? {
+			self.collect_siblings(child, &mut children, 0, debug)?;
+		}
+		Ok(children)
+	}
+
+	/// Collects an object and all of its siblings in the binary tree (in-order).
+	/// The depth is limited, so that corrupt (cyclic) trees cannot cause an endless recursion.
+	fn collect_siblings(&mut self, object: Object, result: &mut Vec<Object>, depth: usize, debug: &mut Debug) -> Result<(), Error> {
+		if depth > 1024 {
+			return Err(Error::new(ErrorKind::InvalidData, "The directory tree is too deep (possibly cyclic)"));
+		}
+		if let ObjectResult::Ok(left_sibling) = self.get_left_sibling(&object, debug)? {
+			self.collect_siblings(left_sibling, result, depth + 1, debug)?;
+		}
+		let right_sibling = self.get_right_sibling(&object, debug)?;
+		result.push(object);
+		if let ObjectResult::Ok(right_sibling) = right_sibling {
+			self.collect_siblings(right_sibling, result, depth + 1, debug)?;
+		}
+		Ok(())
+	}
+
 	/// Finds an object that is subordinated to the given object, by its path.
 	/// The path is a collection of names for the root storage object, all intermediate storage objects (directories), and the final object (directory or file).
 	/// To find an object, call this method as:
@@ -266,6 +433,11 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 		}
 	}
 
+	/// Whether the content of the given object is stored in the ministream (rather than in sectors of its own), which is the case for small streams.
+	pub fn is_in_ministream(&self, object: &Object) -> bool {
+		object.object_type == ObjectType::Stream && object.stream_size < self.header.mini_stream_cutoff_size as u64
+	}
+
 	pub fn dump_stream(&mut self, object: &Object, output: &mut Write, debug: &mut Debug) -> Result<(), Error> {
 		debug.logln(2, format!("[dump_stream] Dumping data for stream #{} ({} bytes) ...", object.id, object.stream_size));
 		// This method only makes sense for stream objects (i.e. files) and the root storage (which contains the ministream)
@@ -273,7 +445,7 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 			ObjectType::RootStorage => self.dump_stream_normal(object, output, debug),
 			ObjectType::Stream =>
 				// Two cases: if the file is small, look for it in the ministream; otherwise read it from a sector
-				if object.stream_size < self.header.mini_stream_cutoff_size as u64 {
+				if self.is_in_ministream(object) {
 					self.dump_stream_mini(object, output, debug)
 				}
 				else {
@@ -500,7 +672,8 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 		let left_sibling_id = (buffer[68] as u32) | (buffer[69] as u32) << 8 | (buffer[70] as u32) << 16 | (buffer[71] as u32) << 24;
 		let right_sibling_id = (buffer[72] as u32) | (buffer[73] as u32) << 8 | (buffer[74] as u32) << 16 | (buffer[75] as u32) << 24;
 		let child_id = (buffer[76] as u32) | (buffer[77] as u32) << 8 | (buffer[78] as u32) << 16 | (buffer[79] as u32) << 24;
-		// skip CLSID (16 bytes)
+		let mut clsid = [0u8; 16];
+		clsid.copy_from_slice(&buffer[80..96]);
 		// skip state bits (4 bytes)
 		let creation_time = (buffer[100] as u64) | (buffer[101] as u64) << 8 | (buffer[102] as u64) << 16 | (buffer[103] as u64) << 24 | (buffer[104] as u64) << 32 | (buffer[105] as u64) << 40 | (buffer[106] as u64) << 48 | (buffer[107] as u64) << 56;
 		let modified_time = (buffer[108] as u64) | (buffer[109] as u64) << 8 | (buffer[110] as u64) << 16 | (buffer[111] as u64) << 24 | (buffer[112] as u64) << 32 | (buffer[113] as u64) << 40 | (buffer[114] as u64) << 48 | (buffer[115] as u64) << 56;
@@ -529,6 +702,7 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 			left_sibling_id,
 			right_sibling_id,
 			child_id,
+			clsid,
 			creation_time,
 			modified_time,
 			starting_sector_location,
@@ -554,3 +728,120 @@ impl<TFile> Container<TFile> where TFile: Read + Seek {
 		Ok(())
 	}
 }
+
+/// Follows a chain of sectors through the given FAT (or mini-sectors through the miniFAT), from its first sector.
+pub fn follow_chain(table: &[u32], start: u32) -> Chain {
+	let mut sectors = Vec::new();
+	let mut visited = vec![false; table.len()];
+	let mut current = start;
+	let end = loop {
+		if current == ENDOFCHAIN {
+			break ChainEnd::EndOfChain;
+		}
+		if current > MAXREGSECT || current as usize >= table.len() {
+			break ChainEnd::Invalid(current);
+		}
+		if visited[current as usize] {
+			break ChainEnd::Loop(current);
+		}
+		visited[current as usize] = true;
+		sectors.push(current);
+		current = table[current as usize];
+	};
+	Chain { sectors, end }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Cursor, stderr};
+	use super::structures::FATSECT;
+	use super::testing::build_cfbf;
+	use super::*;
+
+	/// Overwrites a little-endian `u32` within a CFBF file.
+	fn patch(data: &mut [u8], offset: usize, value: u32) {
+		data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+	}
+
+	#[test]
+	fn follow_chains() {
+		let table = [1, 2, ENDOFCHAIN, 4, 3, FREESECT, 9];
+		let chain = follow_chain(&table, 0);
+		assert!(chain.sectors == [0, 1, 2] && chain.end == ChainEnd::EndOfChain);
+		let chain = follow_chain(&table, 3);
+		assert!(chain.sectors == [3, 4] && chain.end == ChainEnd::Loop(3));
+		let chain = follow_chain(&table, 5);
+		assert!(chain.sectors == [5] && chain.end == ChainEnd::Invalid(FREESECT));
+		// Beyond the end of the table
+		let chain = follow_chain(&table, 6);
+		assert!(chain.sectors == [6] && chain.end == ChainEnd::Invalid(9));
+		let chain = follow_chain(&table, 7);
+		assert!(chain.sectors.is_empty() && chain.end == ChainEnd::Invalid(7));
+		let chain = follow_chain(&table, ENDOFCHAIN);
+		assert!(chain.sectors.is_empty() && chain.end == ChainEnd::EndOfChain);
+	}
+
+	#[test]
+	fn read_allocation_tables() {
+		let mut debug = Debug::new(stderr(), -1);
+		let data = build_cfbf(&[("Data", &[0; 600])]);
+		let mut container = Container::new(Cursor::new(&data), &mut debug).unwrap();
+		assert_eq!(container.get_difat_sectors(&mut debug).unwrap(), []);
+		assert_eq!(container.get_fat_sectors(&mut debug).unwrap(), [0]);
+		let fat = container.get_fat(&mut debug).unwrap();
+		assert_eq!(fat.len(), 128);
+		assert_eq!(fat[..5], [FATSECT, ENDOFCHAIN, 3, ENDOFCHAIN, FREESECT]);
+		assert!(container.get_mini_fat(&fat, &mut debug).unwrap().is_empty());
+	}
+
+	#[test]
+	fn read_damaged_allocation_tables() {
+		let mut debug = Debug::new(stderr(), -1);
+		// A second FAT sector, whose location is held by a DIFAT sector beyond the end of the file
+		let mut data = build_cfbf(&[("Data", b"content")]);
+		patch(&mut data, 0x2C, 110);
+		patch(&mut data, 0x44, 1000);
+		patch(&mut data, 0x48, 1);
+		let mut container = Container::new(Cursor::new(&data), &mut debug).unwrap();
+		assert_eq!(container.get_difat_sectors(&mut debug).unwrap(), [1000]);
+		let fat_sectors = container.get_fat_sectors(&mut debug).unwrap();
+		assert_eq!(fat_sectors.len(), 110);
+		assert!(fat_sectors[0] == 0 && fat_sectors[1..].iter().all(|&sector| sector == FREESECT));
+		// The entries of the unreadable FAT sectors are recorded as free, so that the readable ones can still be followed
+		let fat = container.get_fat(&mut debug).unwrap();
+		assert_eq!(fat.len(), 110 * 128);
+		assert!(fat[128..].iter().all(|&next| next == FREESECT));
+		assert!(follow_chain(&fat, 2).end == ChainEnd::EndOfChain);
+		// A miniFAT sector beyond the end of the file
+		patch(&mut data, 0x3C, 2);
+		patch(&mut data, 0x40, 1);
+		let mut container = Container::new(Cursor::new(&data[..3 * 512]), &mut debug).unwrap();
+		assert_eq!(container.get_mini_fat(&fat, &mut debug).unwrap(), vec![FREESECT; 128]);
+
+		// A chain of DIFAT sectors that loops back to its first sector
+		let mut difat_sector = vec![0xFF; 508];
+		difat_sector.extend(&2u32.to_le_bytes());
+		let mut data = build_cfbf(&[("Data", &difat_sector)]);
+		patch(&mut data, 0x44, 2);
+		patch(&mut data, 0x48, 3);
+		let mut container = Container::new(Cursor::new(&data), &mut debug).unwrap();
+		assert_eq!(container.get_difat_sectors(&mut debug).unwrap(), [2]);
+		assert_eq!(container.get_fat_sectors(&mut debug).unwrap(), [0]);
+	}
+
+	#[test]
+	fn list_children() {
+		let mut debug = Debug::new(stderr(), -1);
+		let mut data = build_cfbf(&[("B", b"b"), ("A", b"a"), ("Dir/C", b"c")]);
+		let mut container = Container::new(Cursor::new(&data), &mut debug).unwrap();
+		let root = container.get_root_object(&mut debug).unwrap();
+		let names = container.get_children(&root, &mut debug).unwrap().into_iter().map(|child| child.name).collect::<Vec<String>>();
+		assert_eq!(names, ["A", "B", "Dir"]);
+
+		// The right sibling of 'Dir' (the third entry) leads back to 'B' (the first entry)
+		patch(&mut data, 2 * 512 + 3 * 128 + 72, 1);
+		let mut container = Container::new(Cursor::new(&data), &mut debug).unwrap();
+		let error = container.get_children(&root, &mut debug).err().unwrap();
+		assert_eq!(error.kind(), ErrorKind::InvalidData);
+	}
+}
@@ -18,9 +18,21 @@ along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 // Also see: [MS-CFB]: Compound File Binary File Format specifications, https://msdn.microsoft.com/en-us/library/dd942138.aspx
 
+/// The largest regular sector number; all larger numbers have a special meaning.
+pub const MAXREGSECT: u32 = 0xFFFFFFFA;
+/// Marks a sector that holds DIFAT entries (in the FAT).
+pub const DIFSECT: u32 = 0xFFFFFFFC;
+/// Marks a sector that holds FAT entries (in the FAT).
+pub const FATSECT: u32 = 0xFFFFFFFD;
+/// Marks the last sector of a chain.
+pub const ENDOFCHAIN: u32 = 0xFFFFFFFE;
+/// Marks an unallocated sector.
+pub const FREESECT: u32 = 0xFFFFFFFF;
+
 /// The header of a CFBF file, excluding the trailing DIFAT entries.
 pub struct Header {
 	pub signature: u64,
+	pub clsid: [u8; 16],
 	pub minor_version: u16,
 	pub major_version: u16,
 	pub byte_order: u16,
@@ -31,6 +43,7 @@ pub struct Header {
 	pub number_of_directory_sectors: u32,
 	pub number_of_fat_sectors: u32,
 	pub first_directory_sector_location: SectorLocation,
+	pub transaction_signature_number: u32,
 	pub mini_stream_cutoff_size: u32,
 	pub first_mini_fat_sector_location: SectorLocation,
 	pub number_of_mini_fat_sectors: u32,
@@ -0,0 +1,137 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Builds small CFBF files for the tests of the parsers that read from CFBF containers.
+
+const FREESECT: u32 = 0xFFFFFFFF;
+const ENDOFCHAIN: u32 = 0xFFFFFFFE;
+const FATSECT: u32 = 0xFFFFFFFD;
+const NOSTREAM: u32 = 0xFFFFFFFF;
+const SECTOR_SIZE: usize = 512;
+
+struct Entry {
+	name: String,
+	/// 1 for storages, 2 for streams, 5 for the root storage.
+	object_type: u8,
+	children: Vec<usize>,
+	data: Vec<u8>,
+	start: u32,
+}
+
+/// Builds a CFBF file (version 3) that holds the given streams, given by their paths (separated by `/`).
+/// Storages are created as needed. All streams are stored in regular sectors (the mini stream cutoff size is 0),
+/// and the file must fit into the 128 sectors that a single FAT sector can describe.
+pub fn build_cfbf(streams: &[(&str, &[u8])]) -> Vec<u8> {
+	let mut entries = vec![Entry { name: "Root Entry".to_owned(), object_type: 5, children: Vec::new(), data: Vec::new(), start: ENDOFCHAIN }];
+	for &(path, data) in streams {
+		let components = path.split('/').collect::<Vec<&str>>();
+		let mut parent = 0;
+		for (index, &name) in components.iter().enumerate() {
+			let is_stream = index + 1 == components.len();
+			let existing = entries[parent].children.iter().cloned().find(|&child| entries[child].name == name);
+			parent = match existing {
+				Some(child) => child,
+				None => {
+					let object_type = if is_stream { 2 } else { 1 };
+					let data = if is_stream { data.to_vec() } else { Vec::new() };
+					entries.push(Entry { name: name.to_owned(), object_type, children: Vec::new(), data, start: ENDOFCHAIN });
+					let child = entries.len() - 1;
+					entries[parent].children.push(child);
+					child
+				},
+			};
+		}
+	}
+
+	// Sector 0 holds the FAT, followed by the directory and the streams
+	let num_dir_sectors = (entries.len() * 128).div_ceil(SECTOR_SIZE);
+	let mut fat = vec![FATSECT];
+	let chain = |fat: &mut Vec<u32>, num_sectors: usize| -> u32 {
+		if num_sectors == 0 {
+			return ENDOFCHAIN;
+		}
+		let start = fat.len();
+		fat.extend((start + 1..start + num_sectors).map(|next| next as u32));
+		fat.push(ENDOFCHAIN);
+		start as u32
+	};
+	let dir_start = chain(&mut fat, num_dir_sectors);
+	for entry in entries.iter_mut().filter(|entry| entry.object_type == 2) {
+		entry.start = chain(&mut fat, entry.data.len().div_ceil(SECTOR_SIZE));
+	}
+	assert!(fat.len() <= SECTOR_SIZE / 4, "too many sectors for a single FAT sector");
+	fat.resize(SECTOR_SIZE / 4, FREESECT);
+
+	// The children of every storage are chained as right siblings, in the order of CFBF names (by length, then case-insensitively)
+	let mut siblings = vec![(NOSTREAM, NOSTREAM); entries.len()];
+	let mut first_child = vec![NOSTREAM; entries.len()];
+	for (index, entry) in entries.iter().enumerate() {
+		let mut children = entry.children.clone();
+		children.sort_by_key(|&child| (entries[child].name.encode_utf16().count(), entries[child].name.to_uppercase()));
+		if let Some(&first) = children.first() {
+			first_child[index] = first as u32;
+		}
+		for pair in children.windows(2) {
+			siblings[pair[0]].1 = pair[1] as u32;
+		}
+	}
+
+	let mut result = Vec::with_capacity(SECTOR_SIZE * (fat.len() + 1));
+	result.extend(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]);
+	result.extend(&[0; 16]);
+	result.extend(&[0x3E, 0x00, 0x03, 0x00, 0xFE, 0xFF, 0x09, 0x00, 0x06, 0x00]);
+	result.extend(&[0; 6]);
+	// Directory and FAT sectors, mini stream cutoff size, MiniFAT, DIFAT, and the first DIFAT entry (the FAT sector)
+	for value in &[0, 1, dir_start, 0, 0, ENDOFCHAIN, 0, ENDOFCHAIN, 0, 0] {
+		result.extend(&value.to_le_bytes());
+	}
+	result.resize(SECTOR_SIZE, 0xFF);
+
+	for value in &fat {
+		result.extend(&value.to_le_bytes());
+	}
+
+	let mut directory = Vec::with_capacity(num_dir_sectors * SECTOR_SIZE);
+	for (index, entry) in entries.iter().enumerate() {
+		let name = entry.name.encode_utf16().collect::<Vec<u16>>();
+		let mut name_bytes = name.iter().flat_map(|c| vec![*c as u8, (*c >> 8) as u8]).collect::<Vec<u8>>();
+		name_bytes.resize(64, 0);
+		directory.extend(&name_bytes);
+		directory.extend(&(((name.len() + 1) * 2) as u16).to_le_bytes());
+		directory.extend(&[entry.object_type, 1]);
+		directory.extend(&siblings[index].0.to_le_bytes());
+		directory.extend(&siblings[index].1.to_le_bytes());
+		directory.extend(&first_child[index].to_le_bytes());
+		directory.extend(&[0; 36]);
+		directory.extend(&entry.start.to_le_bytes());
+		directory.extend(&(entry.data.len() as u64).to_le_bytes());
+	}
+	while directory.len() < num_dir_sectors * SECTOR_SIZE {
+		let mut unused = vec![0; 128];
+		unused[68..80].copy_from_slice(&[0xFF; 12]);
+		directory.extend(unused);
+	}
+	result.extend(directory);
+
+	for entry in entries.iter().filter(|entry| entry.object_type == 2) {
+		let mut data = entry.data.clone();
+		data.resize(data.len().div_ceil(SECTOR_SIZE) * SECTOR_SIZE, 0);
+		result.extend(data);
+	}
+	result
+}
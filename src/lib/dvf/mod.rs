@@ -0,0 +1,201 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::fs::read_dir;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use super::io::Debug;
+
+/// An index of out-sourced files (`.dvf` files), by file stem.
+/// Enterprise Vault stores large archived files next to the `.dvs` file, or in one of the sub-folders of the directory where the `.dvs` file is located.
+/// The index collects all such files beneath a set of search roots, so that every DVS file can be resolved to its out-sourced content,
+/// and so that out-sourced files which are never claimed by a DVS file can be reported.
+pub struct Index {
+	/// The (lower-case) file extension of out-sourced files, without leading dot.
+	extension: String,
+	/// The out-sourced files, grouped by their lower-case file stem.
+	files: HashMap<String, Vec<PathBuf>>,
+	/// The directories that have already been searched, so that overlapping search roots are only walked once.
+	visited_dirs: HashSet<PathBuf>,
+	/// The out-sourced files that have been resolved for some DVS file.
+	claimed: HashSet<PathBuf>,
+}
+
+impl Index {
+	pub fn new(extension: &str) -> Index {
+		Index {
+			extension: extension.trim_start_matches('.').to_lowercase(),
+			files: HashMap::new(),
+			visited_dirs: HashSet::new(),
+			claimed: HashSet::new(),
+		}
+	}
+
+	/// Recursively searches the given directory for out-sourced files, and adds them to the index.
+	/// Directories that have been searched before are skipped.
+	pub fn add_root(&mut self, root: &Path, debug: &mut Debug) -> Result<(), Error> {
+		let root = root.canonicalize()?;
+		if self.visited_dirs.contains(&root) {
+			return Ok(());
+		}
+		debug.logln(1, format!("[add_root] Indexing out-sourced files in {:?} ...", root));
+		self.add_root_recursive(root, debug)?;
+		debug.logln(1, format!("[add_root] Index contains {} out-sourced files.", self.files.values().map(|paths| paths.len()).sum::<usize>()));
+		Ok(())
+	}
+
+	fn add_root_recursive(&mut self, dir: PathBuf, debug: &mut Debug) -> Result<(), Error> {
+		if !self.visited_dirs.insert(dir.clone()) {
+			return Ok(());
+		}
+		for entry in read_dir(&dir)? {
+			let path = entry?.path();
+			if path.is_dir() {
+				self.add_root_recursive(path, debug)?;
+			}
+			else if self.has_extension(&path) {
+				if let Some(stem) = path.file_stem().map(|stem| stem.to_string_lossy().to_lowercase()) {
+					debug.logln(2, format!("[add_root_recursive] Found out-sourced file {:?}.", path));
+					self.files.entry(stem).or_default().push(path);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn has_extension(&self, path: &Path) -> bool {
+		path.extension().map(|ext| ext.to_string_lossy().to_lowercase() == self.extension).unwrap_or(false)
+	}
+
+	/// Resolves the out-sourced file that belongs to the given DVS file, and marks it as claimed.
+	/// A file with the same path (but the out-sourced file extension) is preferred.
+	/// Otherwise the indexed file with the same stem that is closest to the directory of the DVS file is chosen.
+	pub fn resolve(&mut self, dvs_path: &Path, debug: &mut Debug) -> Option<PathBuf> {
+		// Look right next to the DVS file first
+		let sibling = dvs_path.with_extension(&self.extension);
+		if let Ok(sibling) = sibling.canonicalize() {
+			debug.logln(1, format!("[resolve] Found out-sourced file {:?} next to the DVS file.", sibling));
+			self.claimed.insert(sibling.clone());
+			return Some(sibling);
+		}
+
+		let stem = dvs_path.file_stem()?.to_string_lossy();
+		self.resolve_name(&stem, dvs_path, debug)
+	}
+
+	/// Resolves an indexed file by its name (without extension, case-insensitive), and marks it as claimed.
+	/// If several files have this name, the one closest to the directory of the given DVS file is chosen.
+	pub fn resolve_name(&mut self, name: &str, dvs_path: &Path, debug: &mut Debug) -> Option<PathBuf> {
+		let stem = name.to_lowercase();
+		let dvs_dir = containing_dir(dvs_path).canonicalize().unwrap_or_default();
+		let candidates = self.files.get(&stem)?;
+		if candidates.len() > 1 {
+			debug.logln(1, format!("[resolve_name] {} out-sourced files are named '{}', choosing the closest one.", candidates.len(), stem));
+		}
+
+		// Prefer files beneath the directory of the DVS file, the less deep the better; otherwise take the first one found
+		let result = candidates.iter()
+			.min_by_key(|candidate| match candidate.strip_prefix(&dvs_dir) {
+				Ok(relative) => relative.components().count(),
+				Err(_) => usize::MAX,
+			})
+			.cloned()?;
+		debug.logln(1, format!("[resolve_name] Found out-sourced file {:?}.", result));
+		self.claimed.insert(result.clone());
+		Some(result)
+	}
+
+	/// Adds the directory of the given DVS file (including all sub-folders) to the search roots.
+	/// Errors while indexing are logged, but are not fatal.
+	pub fn add_dvs_dir(&mut self, dvs_path: &Path, debug: &mut Debug) {
+		let dvs_dir = containing_dir(dvs_path);
+		if let Err(e) = self.add_root(dvs_dir, debug) {
+			debug.logln(1, format!("[add_dvs_dir] Unable to search {:?} for out-sourced files: {}", dvs_dir, e));
+		}
+	}
+
+	/// Indexes the directory of the given DVS file (including all sub-folders), and then resolves its out-sourced file.
+	/// Errors while indexing are logged, but do not prevent resolving from the existing index.
+	pub fn locate(&mut self, dvs_path: &Path, debug: &mut Debug) -> Option<PathBuf> {
+		self.add_dvs_dir(dvs_path, debug);
+		self.resolve(dvs_path, debug)
+	}
+
+	/// Lists all indexed out-sourced files that have not been claimed by any DVS file, sorted by path.
+	pub fn unclaimed(&self) -> Vec<PathBuf> {
+		let mut result = self.files.values()
+			.flat_map(|paths| paths.iter())
+			.filter(|path| !self.claimed.contains(*path))
+			.cloned()
+			.collect::<Vec<PathBuf>>();
+		result.sort();
+		result
+	}
+}
+
+/// The directory in which a DVS file is located.
+/// For members of a cabinet file (see `cab::member_paths`), this is the directory of the cabinet file,
+/// so that out-sourced files are looked for relative to the cabinet file.
+fn containing_dir(dvs_path: &Path) -> &Path {
+	let mut dir = dvs_path.parent();
+	while let Some(parent) = dir {
+		if !parent.is_file() {
+			break;
+		}
+		dir = parent.parent();
+	}
+	match dir {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => Path::new("."),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env::temp_dir;
+	use std::fs::{File, create_dir_all, remove_dir_all};
+	use std::io::stderr;
+	use super::*;
+
+	#[test]
+	fn locate_outsourced_files() {
+		let root = temp_dir().join(format!("evrecovery-dvf-{}", std::process::id()));
+		let _ = remove_dir_all(&root);
+		for dir in &["a/deep/deeper", "a/near", "b"] {
+			create_dir_all(root.join(dir)).unwrap();
+		}
+		for file in &["a/x.dvs", "a/deep/deeper/X.DVF", "a/near/x.dvf", "a/y.dvs", "a/y.dvf", "b/z.dvf"] {
+			File::create(root.join(file)).unwrap();
+		}
+		let root = root.canonicalize().unwrap();
+		let mut debug = Debug::new(stderr(), -1);
+		let mut index = Index::new(".DVF");
+
+		// The file next to the DVS file is preferred, then the least deep file with the same name (case-insensitive)
+		assert_eq!(index.locate(&root.join("a/y.dvs"), &mut debug), Some(root.join("a/y.dvf")));
+		assert_eq!(index.locate(&root.join("a/x.dvs"), &mut debug), Some(root.join("a/near/x.dvf")));
+		assert_eq!(index.locate(&root.join("a/w.dvs"), &mut debug), None);
+		// Files outside the directory of the DVS file are only found once their root has been added
+		assert_eq!(index.locate(&root.join("a/z.dvs"), &mut debug), None);
+		index.add_root(&root.join("b"), &mut debug).unwrap();
+		assert_eq!(index.unclaimed(), [root.join("a/deep/deeper/X.DVF"), root.join("b/z.dvf")]);
+		assert_eq!(index.locate(&root.join("a/z.dvs"), &mut debug), Some(root.join("b/z.dvf")));
+		remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,373 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use std::fmt;
+use std::mem::replace;
+use std::io::{Read, Seek, Cursor, Error, ErrorKind};
+use super::cfbf::{Container, Object, ObjectType};
+use super::io::Debug;
+use super::propset::decode_code_page;
+
+/// The default depth up to which nested compound files are searched for embedded objects.
+pub const MAX_DEPTH: usize = 8;
+
+/// The maximum nesting of storages within a compound file; deeper storages are not searched.
+const MAX_STORAGE_DEPTH: usize = 32;
+
+const CFBF_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// The smallest possible compound file: a header, a FAT sector and a directory sector.
+const MIN_CFBF_SIZE: u64 = 3 * 512;
+
+/// The stream that holds an object packaged by the Object Packager (the original file name and content).
+const OLE10_NATIVE: &str = "\u{1}Ole10Native";
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ObjectKind {
+	/// A file packaged by the Object Packager (`\x01Ole10Native` stream).
+	Ole10Native,
+	/// An Office Open XML document (`Package` stream).
+	Package,
+	/// The native data of an object, such as a PDF document (`CONTENTS` stream).
+	Contents,
+	/// A stream that holds a whole compound file.
+	CompoundFile,
+	/// A storage that holds an embedded document (such as a legacy Office document, whose streams are stored directly in the storage).
+	Storage,
+}
+
+impl fmt::Display for ObjectKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			ObjectKind::Ole10Native => "packaged file",
+			ObjectKind::Package => "OOXML package",
+			ObjectKind::Contents => "native contents",
+			ObjectKind::CompoundFile => "compound file",
+			ObjectKind::Storage => "embedded storage",
+		})
+	}
+}
+
+/// An object that is embedded in a compound file.
+pub struct EmbeddedObject {
+	/// The path of the stream or storage that holds the object, e.g. `/ObjectPool/_1234567890/\001Ole10Native`.
+	/// Control characters are escaped (see `escape_name`). The paths of objects found in nested compound files
+	/// are prefixed with the path of the nested compound file and a `!`.
+	pub path: String,
+	/// The number of nested compound files that enclose the object (0 for objects in the outermost file).
+	pub depth: usize,
+	pub kind: ObjectKind,
+	/// The original file name of a packaged file.
+	pub file_name: Option<String>,
+	/// The original path of a packaged file, as recorded by the Object Packager.
+	pub source_path: Option<String>,
+	/// The content of the object (empty for embedded storages).
+	pub data: Vec<u8>,
+	/// The streams of an embedded storage, with their paths relative to the storage.
+	pub streams: Vec<(String, Vec<u8>)>,
+}
+
+/// A file packaged by the Object Packager, as stored in a `\x01Ole10Native` stream.
+pub struct NativePackage {
+	pub label: String,
+	pub source_path: String,
+	pub temp_path: String,
+	pub data: Vec<u8>,
+}
+
+impl NativePackage {
+	/// Parses the content of a `\x01Ole10Native` stream.
+	///
+	/// The stream starts with its size, followed by a version, the label and the original path (as NUL-terminated ANSI strings),
+	/// two 16-bit words (the second one being 3 for embedded files), the path of the temporary file (prefixed by its length),
+	/// and finally the size and content of the file.
+	pub fn parse(data: &[u8]) -> Result<NativePackage, Error> {
+		let invalid = || Error::new(ErrorKind::InvalidData, "The Ole10Native stream is truncated");
+		let u32_at = |index: usize| -> Result<usize, Error> {
+			let bytes = data.get(index..index + 4).ok_or_else(invalid)?;
+			Ok(bytes[0] as usize | (bytes[1] as usize) << 8 | (bytes[2] as usize) << 16 | (bytes[3] as usize) << 24)
+		};
+		let string_at = |index: usize| -> Result<(String, usize), Error> {
+			let length = data.get(index..).ok_or_else(invalid)?.iter().position(|&byte| byte == 0).ok_or_else(invalid)?;
+			Ok((decode_code_page(&data[index..index + length], 1252), index + length + 1))
+		};
+
+		let (label, index) = string_at(6)?;
+		let (source_path, index) = string_at(index)?;
+		let kind = data.get(index + 2..index + 4).map(|bytes| bytes[0] as u16 | (bytes[1] as u16) << 8).ok_or_else(invalid)?;
+		if kind != 3 {
+			return Err(Error::new(ErrorKind::InvalidData, format!("The Ole10Native stream holds a link rather than a file (type {})", kind)));
+		}
+		let temp_path_length = u32_at(index + 4)?;
+		let temp_path = decode_code_page(data.get(index + 8..index + 8 + temp_path_length).ok_or_else(invalid)?, 1252);
+		let index = index + 8 + temp_path_length;
+		let size = u32_at(index)?;
+		let content = data.get(index + 4..index + 4 + size).ok_or_else(invalid)?;
+		Ok(NativePackage { label, source_path, temp_path, data: content.to_vec() })
+	}
+
+	/// Determines the original file name: the last component of the original path, or else the label.
+	pub fn file_name(&self) -> String {
+		[&self.source_path, &self.temp_path, &self.label].iter()
+			.map(|path| path.rsplit(['\\', '/']).next().unwrap_or("").trim())
+			.find(|name| !name.is_empty())
+			.unwrap_or("")
+			.to_owned()
+	}
+}
+
+/// Finds all objects that are embedded in a compound file: packaged files, OOXML packages, native contents, embedded storages
+/// (in `ObjectPool` storages and `MBD*` storages, or wherever a storage holds an OLE object), and streams that hold whole compound files.
+///
+/// Compound files that are found (as streams, packaged files or packages) are searched as well, up to the given depth
+/// (0 means that only the outermost file is searched).
+pub fn find_objects<TFile>(container: &mut Container<TFile>, max_depth: usize, debug: &mut Debug) -> Result<Vec<EmbeddedObject>, Error> where TFile: Read + Seek {
+	let mut search = Search { max_depth, level: 0, result: Vec::new() };
+	let root = container.get_root_object(debug)?;
+	search.storage(container, &root, false, "", 0, debug)?;
+	Ok(search.result)
+}
+
+/// The state of a search for embedded objects.
+struct Search {
+	max_depth: usize,
+	/// The nesting of the current storage within its compound file.
+	level: usize,
+	result: Vec<EmbeddedObject>,
+}
+
+impl Search {
+	/// Searches a storage (and its sub-storages, up to `MAX_STORAGE_DEPTH`) for embedded objects.
+	fn storage<TFile>(&mut self, container: &mut Container<TFile>, storage: &Object, is_object: bool, prefix: &str, depth: usize, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+		if self.level > MAX_STORAGE_DEPTH {
+			debug.logln(1, format!("[storage] Not searching '{}', which is nested too deeply.", prefix));
+			return Ok(());
+		}
+		for child in container.get_children(storage, debug)? {
+			let path = format!("{}/{}", prefix, escape_name(&child.name));
+			match child.object_type {
+				ObjectType::Storage => {
+					let child_is_object = self.object_storage(container, storage, &child, &path, depth, debug)?;
+					self.level += 1;
+					let searched = self.storage(container, &child, child_is_object, &path, depth, debug);
+					self.level -= 1;
+					searched?;
+				},
+				ObjectType::Stream => {
+					// The streams that hold the content of an object have been handled with the object storage
+					if is_object && (child.name == OLE10_NATIVE || child.name == "Package" || child.name == "CONTENTS") {
+						continue;
+					}
+					if child.stream_size < MIN_CFBF_SIZE {
+						continue;
+					}
+					let mut data: Vec<u8> = Vec::new();
+					container.dump_stream(&child, &mut data, debug)?;
+					if data.starts_with(&CFBF_SIGNATURE) {
+						debug.logln(1, format!("[storage] Found a nested compound file in '{}'.", path));
+						self.add(EmbeddedObject { path, depth, kind: ObjectKind::CompoundFile, file_name: None, source_path: None, data, streams: Vec::new() }, debug);
+					}
+				},
+				_ => {},
+			}
+		}
+		Ok(())
+	}
+
+	/// Determines whether a storage holds an OLE object, and if so, adds the object to the result.
+	fn object_storage<TFile>(&mut self, container: &mut Container<TFile>, parent: &Object, storage: &Object, path: &str, depth: usize, debug: &mut Debug) -> Result<bool, Error> where TFile: Read + Seek {
+		let children = container.get_children(storage, debug)?;
+		let find = |name: &str| children.iter().find(|child| child.object_type == ObjectType::Stream && child.name == name).cloned();
+		let has_ole_streams = children.iter().any(|child| child.name == "\u{1}Ole" || child.name == "\u{1}CompObj");
+		if !(has_ole_streams || parent.name == "ObjectPool" || storage.name.starts_with("MBD")) {
+			return Ok(false);
+		}
+
+		if let Some(stream) = find(OLE10_NATIVE) {
+			let mut data: Vec<u8> = Vec::new();
+			container.dump_stream(&stream, &mut data, debug)?;
+			let path = format!("{}/{}", path, escape_name(OLE10_NATIVE));
+			match NativePackage::parse(&data) {
+				Ok(package) => {
+					debug.logln(1, format!("[object_storage] Found packaged file '{}' in '{}'.", package.file_name(), path));
+					let file_name = Some(package.file_name()).filter(|name| !name.is_empty());
+					let source_path = Some(package.source_path).filter(|source_path| !source_path.is_empty());
+					self.add(EmbeddedObject { path, depth, kind: ObjectKind::Ole10Native, file_name, source_path, data: package.data, streams: Vec::new() }, debug);
+				},
+				Err(e) => debug.logln(1, format!("[object_storage] Unable to parse '{}': {}", path, e)),
+			}
+			return Ok(true);
+		}
+		for &(name, kind) in [("Package", ObjectKind::Package), ("CONTENTS", ObjectKind::Contents)].iter() {
+			if let Some(stream) = find(name) {
+				let mut data: Vec<u8> = Vec::new();
+				container.dump_stream(&stream, &mut data, debug)?;
+				debug.logln(1, format!("[object_storage] Found {} in '{}'.", kind, path));
+				self.add(EmbeddedObject { path: format!("{}/{}", path, name), depth, kind, file_name: None, source_path: None, data, streams: Vec::new() }, debug);
+				return Ok(true);
+			}
+		}
+
+		debug.logln(1, format!("[object_storage] Found an embedded storage in '{}'.", path));
+		let mut streams = Vec::new();
+		collect_streams(container, storage, "", 0, &mut streams, debug)?;
+		self.result.push(EmbeddedObject { path: path.to_owned(), depth, kind: ObjectKind::Storage, file_name: None, source_path: None, data: Vec::new(), streams });
+		Ok(true)
+	}
+
+	/// Adds an object to the result, followed by the objects embedded in it if it is a compound file itself (up to the maximum depth).
+	fn add(&mut self, object: EmbeddedObject, debug: &mut Debug) {
+		let nested = object.depth < self.max_depth && object.data.starts_with(&CFBF_SIGNATURE);
+		let (prefix, depth) = (format!("{}!", object.path), object.depth + 1);
+		let data = match nested {
+			true => Some(object.data.clone()),
+			false => None,
+		};
+		self.result.push(object);
+		let data = match data {
+			Some(data) => data,
+			None => return,
+		};
+
+		// A nested compound file that cannot be parsed is reported as an object nevertheless
+		let level = replace(&mut self.level, 0);
+		let searched = Container::new(Cursor::new(data), debug).and_then(|mut container| {
+			let root = container.get_root_object(debug)?;
+			self.storage(&mut container, &root, false, &prefix, depth, debug)
+		});
+		self.level = level;
+		if let Err(e) = searched {
+			debug.logln(1, format!("[add] Unable to search the nested compound file '{}': {}", prefix.trim_end_matches('!'), e));
+		}
+	}
+}
+
+/// Reads all streams beneath a storage (up to `MAX_STORAGE_DEPTH`), with their paths relative to the storage.
+fn collect_streams<TFile>(container: &mut Container<TFile>, storage: &Object, prefix: &str, level: usize, result: &mut Vec<(String, Vec<u8>)>, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+	if level > MAX_STORAGE_DEPTH {
+		debug.logln(1, format!("[collect_streams] Not reading '{}', which is nested too deeply.", prefix));
+		return Ok(());
+	}
+	for child in container.get_children(storage, debug)? {
+		let path = format!("{}/{}", prefix, escape_name(&child.name));
+		match child.object_type {
+			ObjectType::Storage => collect_streams(container, &child, &path, level + 1, result, debug)?,
+			ObjectType::Stream => {
+				let mut data: Vec<u8> = Vec::new();
+				container.dump_stream(&child, &mut data, debug)?;
+				result.push((path, data));
+			},
+			_ => {},
+		}
+	}
+	Ok(())
+}
+
+/// Escapes the control characters in the name of a stream or storage (such as '\x01' in `\x01Ole10Native`) as octal escape sequences (`\001`).
+pub fn escape_name(name: &str) -> String {
+	name.chars().map(|c| match c {
+		'\u{0}'..='\u{1F}' => format!("\\{:03o}", c as u32),
+		_ => c.to_string(),
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::stderr;
+	use super::super::cfbf::testing::build_cfbf;
+	use super::*;
+
+	fn native_package(label: &str, source_path: &str, temp_path: &str, data: &[u8]) -> Vec<u8> {
+		let mut package = vec![0, 0, 0, 0, 2, 0];
+		package.extend_from_slice(label.as_bytes());
+		package.push(0);
+		package.extend_from_slice(source_path.as_bytes());
+		package.extend_from_slice(&[0, 0, 0, 3, 0]);
+		package.extend_from_slice(&(temp_path.len() as u32 + 1).to_le_bytes());
+		package.extend_from_slice(temp_path.as_bytes());
+		package.push(0);
+		package.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		package.extend_from_slice(data);
+		package
+	}
+
+	#[test]
+	fn parse_native_packages() {
+		let package = NativePackage::parse(&native_package("Label", "C:\\Data\\report.pdf", "C:\\Temp\\report.pdf", b"%PDF-")).unwrap();
+		assert_eq!(package.label, "Label");
+		assert_eq!(package.file_name(), "report.pdf");
+		assert_eq!(package.data, b"%PDF-");
+		assert_eq!(NativePackage::parse(&native_package("notes.txt", "", "", b"")).unwrap().file_name(), "notes.txt");
+
+		let data = native_package("Label", "C:\\report.pdf", "", b"%PDF-");
+		assert!(NativePackage::parse(&data[..data.len() - 1]).is_err());
+		// The type follows the label and the original path
+		let mut link = data.clone();
+		link[6 + "Label\0C:\\report.pdf\0".len() + 2] = 1;
+		assert!(NativePackage::parse(&link).is_err());
+	}
+
+	#[test]
+	fn escape_names() {
+		assert_eq!(escape_name("\u{1}Ole10Native"), "\\001Ole10Native");
+	}
+
+	#[test]
+	fn find_embedded_objects() {
+		let nested = build_cfbf(&[("ObjectPool/_2/\u{1}Ole10Native", &native_package("inner.txt", "", "", b"inner"))]);
+		let outer = build_cfbf(&[
+			("WordDocument", b""),
+			("ObjectPool/_1/\u{1}Ole10Native", &native_package("Label", "C:\\report.pdf", "", b"%PDF-")),
+			("ObjectPool/_1/\u{1}Ole", b""),
+			("MBD0001/Package", b"PK\x03\x04"),
+			("MBD0002/\u{1}CompObj", b""),
+			("MBD0002/WordDocument", b"doc"),
+			("Nested", &nested),
+		]);
+		let mut debug = Debug::new(stderr(), -1);
+		let mut container = Container::new(Cursor::new(outer), &mut debug).unwrap();
+		let objects = find_objects(&mut container, MAX_DEPTH, &mut debug).unwrap();
+		let found = objects.iter().map(|object| (object.path.as_str(), object.depth, object.kind)).collect::<Vec<(&str, usize, ObjectKind)>>();
+		assert_eq!(found.len(), 5);
+		assert!(found.contains(&("/ObjectPool/_1/\\001Ole10Native", 0, ObjectKind::Ole10Native)));
+		assert!(found.contains(&("/MBD0001/Package", 0, ObjectKind::Package)));
+		assert!(found.contains(&("/MBD0002", 0, ObjectKind::Storage)));
+		assert!(found.contains(&("/Nested", 0, ObjectKind::CompoundFile)));
+		assert!(found.contains(&("/Nested!/ObjectPool/_2/\\001Ole10Native", 1, ObjectKind::Ole10Native)));
+
+		let storage = objects.iter().find(|object| object.kind == ObjectKind::Storage).unwrap();
+		assert_eq!(storage.streams.len(), 2);
+		assert!(storage.streams.contains(&("/WordDocument".to_owned(), b"doc".to_vec())));
+		assert_eq!(objects.iter().find(|object| object.kind == ObjectKind::Ole10Native && object.depth == 0).unwrap().file_name.as_deref(), Some("report.pdf"));
+
+		// Nested compound files are not searched beyond the maximum depth
+		let mut container = Container::new(Cursor::new(build_cfbf(&[("Nested", &nested)])), &mut debug).unwrap();
+		assert_eq!(find_objects(&mut container, 0, &mut debug).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn limit_storage_depth() {
+		let shallow = format!("{}MBD0001/Package", "S/".repeat(MAX_STORAGE_DEPTH - 1));
+		let deep = format!("{}MBD0002/Package", "D/".repeat(MAX_STORAGE_DEPTH + 1));
+		let data = build_cfbf(&[(shallow.as_str(), b"PK"), (deep.as_str(), b"PK")]);
+		let mut debug = Debug::new(stderr(), -1);
+		let mut container = Container::new(Cursor::new(data), &mut debug).unwrap();
+		let objects = find_objects(&mut container, MAX_DEPTH, &mut debug).unwrap();
+		assert_eq!(objects.len(), 1);
+		assert!(objects[0].path.ends_with("/MBD0001/Package"));
+	}
+}
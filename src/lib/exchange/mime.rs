@@ -0,0 +1,397 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Write, Error};
+
+/// A message in the Internet Message Format (RFC 5322), with MIME bodies and attachments (RFC 2045 ff.).
+/// Header values are encoded (RFC 2047) where necessary; text bodies are written as quoted-printable, attachments as base64.
+#[derive(Default)]
+pub struct MessageBuilder {
+	headers: Vec<(String, String)>,
+	text: Option<String>,
+	html: Option<String>,
+	attachments: Vec<Attachment>,
+}
+
+pub struct Attachment {
+	pub filename: Option<String>,
+	pub content_type: Option<String>,
+	/// The `Content-ID` of an inline attachment (referred to by `cid:` URLs in the HTML body).
+	pub content_id: Option<String>,
+	pub data: Vec<u8>,
+}
+
+impl MessageBuilder {
+	pub fn new() -> MessageBuilder {
+		MessageBuilder::default()
+	}
+
+	/// Adds a header; values that are empty are skipped.
+	pub fn header(&mut self, name: &str, value: &str) -> &mut MessageBuilder {
+		if !value.trim().is_empty() {
+			self.headers.push((name.to_owned(), value.to_owned()));
+		}
+		self
+	}
+
+	pub fn has_header(&self, name: &str) -> bool {
+		self.headers.iter().any(|(header, _)| header.eq_ignore_ascii_case(name))
+	}
+
+	pub fn text(&mut self, text: &str) -> &mut MessageBuilder {
+		self.text = Some(text.to_owned());
+		self
+	}
+
+	pub fn html(&mut self, html: &str) -> &mut MessageBuilder {
+		self.html = Some(html.to_owned());
+		self
+	}
+
+	pub fn attachment(&mut self, attachment: Attachment) -> &mut MessageBuilder {
+		self.attachments.push(attachment);
+		self
+	}
+
+	pub fn write(&self, output: &mut dyn Write) -> Result<(), Error> {
+		for (name, value) in &self.headers {
+			match ADDRESS_HEADERS.iter().any(|header| header.eq_ignore_ascii_case(name)) {
+				true => write!(output, "{}: {}\r\n", name, encode_address_list(value))?,
+				false => write!(output, "{}: {}\r\n", name, encode_header_value(value))?,
+			}
+		}
+		write!(output, "MIME-Version: 1.0\r\n")?;
+
+		let tag = self.boundary_tag();
+		match self.attachments.is_empty() {
+			true => self.write_body(output, tag, 0),
+			false => {
+				let boundary = boundary(tag, 0);
+				write!(output, "Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n", boundary)?;
+				write!(output, "This is a multi-part message in MIME format.\r\n")?;
+				write!(output, "\r\n--{}\r\n", boundary)?;
+				self.write_body(output, tag, 1)?;
+				for attachment in &self.attachments {
+					write!(output, "\r\n--{}\r\n", boundary)?;
+					write_attachment(output, attachment)?;
+				}
+				write!(output, "\r\n--{}--\r\n", boundary)
+			},
+		}
+	}
+
+	/// Writes the headers and content of the body (plain text, HTML, or both as alternatives).
+	fn write_body(&self, output: &mut dyn Write, tag: u64, level: usize) -> Result<(), Error> {
+		match (&self.text, &self.html) {
+			(Some(text), Some(html)) => {
+				let boundary = boundary(tag, level);
+				write!(output, "Content-Type: multipart/alternative; boundary=\"{}\"\r\n\r\n", boundary)?;
+				write!(output, "--{}\r\n", boundary)?;
+				write_text(output, "text/plain", text)?;
+				write!(output, "\r\n--{}\r\n", boundary)?;
+				write_text(output, "text/html", html)?;
+				write!(output, "\r\n--{}--\r\n", boundary)
+			},
+			(None, Some(html)) => write_text(output, "text/html", html),
+			(Some(text), None) => write_text(output, "text/plain", text),
+			(None, None) => write_text(output, "text/plain", ""),
+		}
+	}
+
+	/// The tag that makes the boundaries of the message unique: a hash of its content.
+	/// Attached messages are included unencoded, with boundaries of their own; should one of them contain the tag anyway, the next one is taken.
+	fn boundary_tag(&self) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		self.headers.hash(&mut hasher);
+		self.text.hash(&mut hasher);
+		self.html.hash(&mut hasher);
+		for attachment in &self.attachments {
+			attachment.data.hash(&mut hasher);
+		}
+		let mut tag = hasher.finish();
+		while self.attachments.iter().any(|attachment| contains(&attachment.data, format_tag(tag).as_bytes())) {
+			tag = tag.wrapping_add(1);
+		}
+		tag
+	}
+}
+
+/// The boundary of a multipart body at the given nesting level.
+/// Since quoted-printable and base64 content never contains `=_`, it can only collide with attached messages, which have boundaries
+/// of their own; these differ by the tag (see `MessageBuilder::boundary_tag`).
+fn boundary(tag: u64, level: usize) -> String {
+	format!("----=_Part_{}_{}", level, format_tag(tag))
+}
+
+fn format_tag(tag: u64) -> String {
+	format!("{:016X}", tag)
+}
+
+fn contains(data: &[u8], pattern: &[u8]) -> bool {
+	data.windows(pattern.len()).any(|window| window == pattern)
+}
+
+fn write_text(output: &mut dyn Write, content_type: &str, text: &str) -> Result<(), Error> {
+	write!(output, "Content-Type: {}; charset=\"utf-8\"\r\n", content_type)?;
+	write!(output, "Content-Transfer-Encoding: quoted-printable\r\n\r\n")?;
+	write!(output, "{}\r\n", encode_quoted_printable(text.as_bytes()))
+}
+
+fn write_attachment(output: &mut dyn Write, attachment: &Attachment) -> Result<(), Error> {
+	let content_type = attachment.content_type.as_deref().unwrap_or("application/octet-stream");
+	match attachment.filename {
+		Some(ref filename) => {
+			write!(output, "Content-Type: {}; name=\"{}\"\r\n", content_type, encode_parameter(filename))?;
+			let disposition = if attachment.content_id.is_some() { "inline" } else { "attachment" };
+			write!(output, "Content-Disposition: {}; filename=\"{}\"\r\n", disposition, encode_parameter(filename))?;
+		},
+		None => write!(output, "Content-Type: {}\r\n", content_type)?,
+	}
+	if let Some(ref content_id) = attachment.content_id {
+		write!(output, "Content-ID: <{}>\r\n", content_id.trim_start_matches('<').trim_end_matches('>'))?;
+	}
+	// Attached messages must not be encoded (RFC 2046, section 5.2.1)
+	if content_type.eq_ignore_ascii_case("message/rfc822") {
+		write!(output, "Content-Transfer-Encoding: 8bit\r\n\r\n")?;
+		output.write_all(&attachment.data)?;
+		return write!(output, "\r\n");
+	}
+	write!(output, "Content-Transfer-Encoding: base64\r\n\r\n")?;
+	write!(output, "{}", encode_base64(&attachment.data))
+}
+
+/// Encodes data as base64 (RFC 2045), in lines of 76 characters (each terminated by CRLF).
+pub fn encode_base64(data: &[u8]) -> String {
+	const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut result = String::with_capacity(data.len() * 4 / 3 + data.len() / 57 * 2 + 4);
+	for line in data.chunks(57) {
+		for chunk in line.chunks(3) {
+			let value = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+			for index in 0..4 {
+				match index <= chunk.len() {
+					true => result.push(ALPHABET[(value >> (18 - index * 6) & 0x3F) as usize] as char),
+					false => result.push('='),
+				}
+			}
+		}
+		result.push_str("\r\n");
+	}
+	result
+}
+
+/// Encodes data as quoted-printable (RFC 2045), with soft line breaks after at most 76 characters.
+/// Line breaks in the data are normalised to CRLF.
+pub fn encode_quoted_printable(data: &[u8]) -> String {
+	let mut result = String::with_capacity(data.len() + data.len() / 8);
+	let mut line_length = 0;
+	for (index, &byte) in data.iter().enumerate() {
+		if byte == b'\n' {
+			result.push_str("\r\n");
+			line_length = 0;
+			continue;
+		}
+		if byte == b'\r' && data.get(index + 1) == Some(&b'\n') {
+			continue;
+		}
+		// Whitespace at the end of a line must be encoded
+		let at_line_end = matches!(data.get(index + 1), None | Some(&b'\r') | Some(&b'\n'));
+		let encoded = match byte {
+			b' ' | b'\t' if !at_line_end => (byte as char).to_string(),
+			33..=60 | 62..=126 => (byte as char).to_string(),
+			_ => format!("={:02X}", byte),
+		};
+		if line_length + encoded.len() > 75 {
+			result.push_str("=\r\n");
+			line_length = 0;
+		}
+		line_length += encoded.len();
+		result.push_str(&encoded);
+	}
+	result
+}
+
+/// Encodes a header value as encoded words (RFC 2047), unless it consists of printable ASCII characters only.
+pub fn encode_header_value(value: &str) -> String {
+	let value = value.replace(['\r', '\n'], " ");
+	if value.chars().all(|c| (' '..='~').contains(&c) || c == '\t') {
+		return value;
+	}
+
+	// Split into encoded words of at most 45 bytes each, without splitting characters
+	let mut words = Vec::new();
+	let mut word = String::new();
+	for c in value.chars() {
+		if word.len() + c.len_utf8() > 45 {
+			words.push(word);
+			word = String::new();
+		}
+		word.push(c);
+	}
+	words.push(word);
+	words.iter()
+		.map(|word| format!("=?UTF-8?B?{}?=", encode_base64(word.as_bytes()).trim_end()))
+		.collect::<Vec<String>>()
+		.join("\r\n ")
+}
+
+/// The headers whose values are lists of mailboxes (RFC 5322, section 3.6.2 f.).
+const ADDRESS_HEADERS: [&str; 6] = ["From", "Sender", "Reply-To", "To", "Cc", "Bcc"];
+
+/// Encodes the display names of a list of mailboxes as encoded words (RFC 2047) where necessary, e.g. `"Jürgen" <j@example.com>`
+/// as `=?UTF-8?B?SsO8cmdlbg==?= <j@example.com>`. Addresses are kept as they are, since encoded words must not appear within
+/// quoted strings or addresses (RFC 2047, section 5).
+pub fn encode_address_list(value: &str) -> String {
+	let value = value.replace(['\r', '\n'], " ");
+	if value.chars().all(|c| (' '..='~').contains(&c) || c == '\t') {
+		return value;
+	}
+	split_address_list(&value).iter()
+		.map(|mailbox| match mailbox.rfind('<') {
+			Some(index) if mailbox.ends_with('>') => {
+				let name = mailbox[..index].trim();
+				let unquoted = name.strip_prefix('"').and_then(|name| name.strip_suffix('"')).unwrap_or(name);
+				match (name.is_empty(), name.is_ascii()) {
+					(true, _) => mailbox[index..].to_owned(),
+					(false, true) => format!("{} {}", name, &mailbox[index..]),
+					(false, false) => format!("{} {}", encode_header_value(&unquoted.replace("\\\"", "\"")), &mailbox[index..]),
+				}
+			},
+			_ => mailbox.to_owned(),
+		})
+		.collect::<Vec<String>>()
+		.join(", ")
+}
+
+/// Splits a list of mailboxes at the commas that are neither quoted nor part of an address.
+fn split_address_list(value: &str) -> Vec<String> {
+	let mut mailboxes = Vec::new();
+	let mut mailbox = String::new();
+	let (mut quoted, mut escaped, mut in_address) = (false, false, false);
+	for c in value.chars() {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' if quoted => escaped = true,
+			'"' if !in_address => quoted = !quoted,
+			'<' if !quoted => in_address = true,
+			'>' if !quoted => in_address = false,
+			',' if !quoted && !in_address => {
+				mailboxes.push(mailbox.trim().to_owned());
+				mailbox.clear();
+				continue;
+			},
+			_ => {},
+		}
+		mailbox.push(c);
+	}
+	mailboxes.push(mailbox.trim().to_owned());
+	mailboxes.retain(|mailbox| !mailbox.is_empty());
+	mailboxes
+}
+
+/// Encodes the value of a quoted MIME parameter, such as a file name.
+fn encode_parameter(value: &str) -> String {
+	encode_header_value(&value.replace(['\\', '"'], "_"))
+}
+
+/// The address of a mailbox of which only the display name is known, since a mailbox must have an address (RFC 5322, section 3.4).
+/// The top-level domain `invalid` is reserved for that purpose (RFC 2606).
+pub const UNKNOWN_ADDRESS: &str = "unknown@invalid";
+
+/// Formats a display name and address as a mailbox (RFC 5322), e.g. `"Doe, John" <jdoe@example.com>`.
+/// Without an address, the display name is given the address `UNKNOWN_ADDRESS`.
+pub fn format_mailbox(name: Option<&str>, address: Option<&str>) -> Option<String> {
+	let name = name.map(|name| name.trim()).filter(|name| !name.is_empty());
+	let address = address.map(|address| address.trim()).filter(|address| !address.is_empty());
+	match (name, address) {
+		(Some(name), Some(address)) if name != address => Some(format!("\"{}\" <{}>", name.replace(['\\', '"'], ""), address)),
+		(_, Some(address)) => Some(format!("<{}>", address)),
+		(Some(name), None) => Some(format!("\"{}\" <{}>", name.replace(['\\', '"'], ""), UNKNOWN_ADDRESS)),
+		(None, None) => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn encode_base64_vectors() {
+		// RFC 4648, section 10
+		let vectors = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+		for &(data, encoded) in &vectors {
+			assert_eq!(encode_base64(data.as_bytes()).trim_end(), encoded);
+		}
+		// Lines of 76 characters
+		let encoded = encode_base64(&[0; 58]);
+		assert_eq!(encoded.lines().map(|line| line.len()).collect::<Vec<usize>>(), [76, 4]);
+	}
+
+	#[test]
+	fn encode_quoted_printable_text() {
+		assert_eq!(encode_quoted_printable("a=b \u{e9}\r\nc \nd\t".as_bytes()), "a=3Db =C3=A9\r\nc=20\r\nd=09");
+		let encoded = encode_quoted_printable(&[b'x'; 100]);
+		assert_eq!(encoded.split("=\r\n").map(|line| line.len()).collect::<Vec<usize>>(), [75, 25]);
+	}
+
+	#[test]
+	fn encode_headers() {
+		assert_eq!(encode_header_value("Hello\r\nworld"), "Hello  world");
+		assert_eq!(encode_header_value("Gr\u{fc}\u{df}e"), "=?UTF-8?B?R3LDvMOfZQ==?=");
+		assert_eq!(encode_header_value(&"\u{e9}".repeat(30)).matches("=?UTF-8?B?").count(), 2);
+		assert_eq!(format_mailbox(Some(" \"Doe, John\" "), Some("jdoe@example.com")), Some("\"Doe, John\" <jdoe@example.com>".to_owned()));
+		assert_eq!(format_mailbox(Some("jdoe@example.com"), Some("jdoe@example.com")), Some("<jdoe@example.com>".to_owned()));
+		// Only the display names of mailboxes are encoded
+		assert_eq!(encode_address_list("\"J\u{fc}rgen M\u{fc}ller\" <jm@example.com>, \"Doe, John\" <jdoe@example.com>,x@example.com"),
+			"=?UTF-8?B?SsO8cmdlbiBNw7xsbGVy?= <jm@example.com>, \"Doe, John\" <jdoe@example.com>, x@example.com");
+		assert_eq!(encode_address_list("\"a, \\\"b\\\" <c>\" <x@example.com>, \u{e9} <y@example.com>"),
+			"\"a, \\\"b\\\" <c>\" <x@example.com>, =?UTF-8?B?w6k=?= <y@example.com>");
+		let mut builder = MessageBuilder::new();
+		builder.header("From", "\"J\u{fc}rgen M\u{fc}ller\" <jm@example.com>").header("Subject", "Gr\u{fc}\u{df}e");
+		let mut output = Vec::new();
+		builder.write(&mut output).unwrap();
+		assert!(output.starts_with(b"From: =?UTF-8?B?SsO8cmdlbiBNw7xsbGVy?= <jm@example.com>\r\nSubject: =?UTF-8?B?R3LDvMOfZQ==?=\r\n"));
+		assert_eq!(format_mailbox(Some("John Doe"), None), Some("\"John Doe\" <unknown@invalid>".to_owned()));
+		assert_eq!(format_mailbox(Some(" "), None), None);
+	}
+
+	#[test]
+	fn build_multipart_messages() {
+		let mut builder = MessageBuilder::new();
+		builder.header("Subject", "Report").header("To", " ").text("See attached").html("<p>See attached</p>");
+		builder.attachment(Attachment { filename: Some("a\"b.txt".to_owned()), content_type: None, content_id: None, data: b"abc".to_vec() });
+		assert!(builder.has_header("subject") && !builder.has_header("To"));
+		let mut output = Vec::new();
+		builder.write(&mut output).unwrap();
+		let message = String::from_utf8(output).unwrap();
+		let tag = format_tag(builder.boundary_tag());
+		assert!(message.starts_with(&format!("Subject: Report\r\nMIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"----=_Part_0_{}\"\r\n", tag)));
+		assert!(message.contains(&format!("Content-Type: multipart/alternative; boundary=\"----=_Part_1_{}\"\r\n", tag)));
+		assert!(message.contains("Content-Disposition: attachment; filename=\"a_b.txt\"\r\nContent-Transfer-Encoding: base64\r\n\r\nYWJj\r\n"));
+		assert!(message.ends_with(&format!("\r\n------=_Part_0_{}--\r\n", tag)));
+
+		// The boundaries of an attached message differ from those of the message
+		let mut embedded = Vec::new();
+		builder.write(&mut embedded).unwrap();
+		let mut outer = MessageBuilder::new();
+		outer.text("Forwarded").attachment(Attachment { filename: None, content_type: Some("message/rfc822".to_owned()), content_id: None, data: embedded.clone() });
+		assert_ne!(outer.boundary_tag(), builder.boundary_tag());
+		assert!(!contains(&embedded, format_tag(outer.boundary_tag()).as_bytes()));
+	}
+}
@@ -0,0 +1,248 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+pub mod mime;
+
+use std::io::{Read, Seek, Write, Error};
+use std::path::PathBuf;
+use super::cfbf::{Container, ObjectResult, ObjectType};
+use super::io::{Debug, SeekableRead};
+use super::metadata::{Metadata, read_stream, decode_text, format_asctime, format_rfc5322_timestamp};
+use super::msg::{Message, is_msg};
+use self::mime::{Attachment, MessageBuilder, format_mailbox};
+
+/// Determines whether an archived item is a mail item (archived from Exchange), rather than a file (archived from a file system).
+///
+/// The `/User Information/AgentIdentifier` names the archiving agent. If it does not tell, an item is considered a mail item
+/// if its `ExchangeLocation` refers to a message store (`MsgStoreEntryId`), and it has no `FileSystemLocation`.
+pub fn is_mail_item<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<bool, Error> where TFile: Read + Seek {
+	if let Some(agent) = read_stream(container, "AgentIdentifier", debug)? {
+		let agent = decode_text(&agent).to_lowercase();
+		debug.logln(1, format!("[is_mail_item] Agent identifier: '{}'", agent));
+		if agent.contains("exch") || agent.contains("mail") {
+			return Ok(true);
+		}
+		if agent.contains("fsa") || agent.contains("file") {
+			return Ok(false);
+		}
+	}
+
+	let has_message_store = read_stream(container, "MsgStoreEntryId", debug)?.is_some_and(|data| data.iter().any(|&byte| byte != 0));
+	let has_file_system_location = match container.find_child_by_name("FileSystemLocation", debug)? {
+		ObjectResult::Ok(object) => object.object_type == ObjectType::Storage,
+		ObjectResult::None => false,
+	};
+	Ok(has_message_store && !has_file_system_location)
+}
+
+/// The header fields of which a message must have at least one to be recognised as such (see `is_rfc5322`).
+const MESSAGE_HEADERS: [&str; 6] = ["From", "Date", "Received", "Return-Path", "Message-ID", "Delivered-To"];
+
+/// Determines whether the data looks like a message in the Internet Message Format (RFC 5322):
+/// it starts with a header field, and its header section has at least one of the fields that every transmitted message has (see `MESSAGE_HEADERS`).
+pub fn is_rfc5322(data: &[u8]) -> bool {
+	let head = String::from_utf8_lossy(&data[..data.len().min(65536)]);
+	let first_line = head.lines().next().unwrap_or("");
+	let starts_with_header = match first_line.find(':') {
+		Some(index) if index > 0 => first_line[..index].bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-'),
+		_ => false,
+	};
+	starts_with_header && parse_headers(&head).iter().any(|(name, _)| MESSAGE_HEADERS.iter().any(|header| name.eq_ignore_ascii_case(header)))
+}
+
+/// Rebuilds the message of a mail item from its content, as a message in the Internet Message Format (RFC 5322).
+///
+/// Content that already is such a message is returned unchanged. Outlook messages (`.msg`) are converted, keeping their attachments.
+/// Any other content is wrapped into a message whose headers are taken from the metadata (`Title` as subject, `Author` as sender),
+/// either as its body (if it is text) or as an attachment.
+pub fn build_message(content: &[u8], metadata: &Metadata, debug: &mut Debug) -> Result<Vec<u8>, Error> {
+	let mut message: Vec<u8> = Vec::new();
+	if is_rfc5322(content) {
+		debug.logln(1, "[build_message] The content is an RFC 5322 message.");
+		message.extend_from_slice(content);
+	}
+	else if is_msg(content, debug) {
+		debug.logln(1, "[build_message] The content is an Outlook message.");
+		convert_msg(content, metadata, debug)?.write(&mut message)?;
+	}
+	else {
+		debug.logln(1, "[build_message] Wrapping the content into a message ...");
+		let mut builder = builder_from_metadata(metadata);
+		match String::from_utf8(content.to_vec()) {
+			Ok(ref text) if !text.contains('\0') => builder.text(text),
+			_ => builder.attachment(Attachment {
+				filename: metadata.title.clone(),
+				content_type: metadata.mime_type.clone().filter(|mime_type| mime_type.contains('/')),
+				content_id: None,
+				data: content.to_vec(),
+			}),
+		};
+		builder.write(&mut message)?;
+	}
+	Ok(message)
+}
+
+/// Creates a message with the headers that can be derived from the metadata.
+fn builder_from_metadata(metadata: &Metadata) -> MessageBuilder {
+	let mut builder = MessageBuilder::new();
+	if let Some(date) = metadata.created_time.or(metadata.archived_date) {
+		builder.header("Date", &format_rfc5322_timestamp(date));
+	}
+	if let Some(from) = format_mailbox(metadata.author.as_deref(), None) {
+		builder.header("From", &from);
+	}
+	builder.header("Subject", metadata.title.as_deref().unwrap_or(""));
+	builder
+}
+
+/// Converts an Outlook message (`.msg`) into a message in the Internet Message Format.
+/// Headers that the message does not record are taken from the metadata.
+fn convert_msg(content: &[u8], metadata: &Metadata, debug: &mut Debug) -> Result<MessageBuilder, Error> {
+	let mut container = Container::new(SeekableRead::new(content)?, debug)?;
+	let mut builder = Message::read(&mut container, debug)?.to_builder()?;
+	if !builder.has_header("Date") {
+		if let Some(date) = metadata.created_time.or(metadata.archived_date) {
+			builder.header("Date", &format_rfc5322_timestamp(date));
+		}
+	}
+	if !builder.has_header("From") {
+		if let Some(from) = format_mailbox(metadata.author.as_deref(), None) {
+			builder.header("From", &from);
+		}
+	}
+	if !builder.has_header("Subject") {
+		builder.header("Subject", metadata.title.as_deref().unwrap_or(""));
+	}
+	Ok(builder)
+}
+
+/// Parses a header section (RFC 5322) into pairs of names and unfolded values.
+pub fn parse_headers(text: &str) -> Vec<(String, String)> {
+	let mut headers: Vec<(String, String)> = Vec::new();
+	for line in text.lines() {
+		if line.is_empty() {
+			break;
+		}
+		if line.starts_with([' ', '\t']) {
+			if let Some((_, value)) = headers.last_mut() {
+				value.push(' ');
+				value.push_str(line.trim());
+			}
+			continue;
+		}
+		if let Some(index) = line.find(':') {
+			headers.push((line[..index].trim().to_owned(), line[index + 1..].trim().to_owned()));
+		}
+	}
+	headers
+}
+
+/// Extracts the address of the sender from the `From` header of a message, e.g. for the separator line of an mbox file.
+pub fn sender_address(message: &[u8]) -> Option<String> {
+	let head = String::from_utf8_lossy(&message[..message.len().min(65536)]).into_owned();
+	let from = parse_headers(&head).into_iter().find(|(name, _)| name.eq_ignore_ascii_case("From"))?.1;
+	let address = match (from.rfind('<'), from.rfind('>')) {
+		(Some(start), Some(end)) if start < end => &from[start + 1..end],
+		_ => from.as_str(),
+	};
+	match address.contains('@') && !address.contains(char::is_whitespace) {
+		true => Some(address.to_owned()),
+		false => None,
+	}
+}
+
+/// Appends a message to an mbox file (in the `mboxrd` format): the separator line `From <sender> <date>` is prepended,
+/// and all lines of the message that start with any number of `>` followed by `From ` are quoted by another `>`.
+pub fn write_mbox_entry(output: &mut dyn Write, message: &[u8], date: Option<u64>) -> Result<(), Error> {
+	let sender = sender_address(message).unwrap_or_else(|| "MAILER-DAEMON".to_owned());
+	writeln!(output, "From {} {}", sender, format_asctime(date.unwrap_or(0)))?;
+	let message = message.strip_suffix(b"\n").unwrap_or(message);
+	for line in message.split(|&byte| byte == b'\n') {
+		let line = line.strip_suffix(b"\r").unwrap_or(line);
+		let quotes = line.iter().take_while(|&&byte| byte == b'>').count();
+		if line[quotes..].starts_with(b"From ") {
+			output.write_all(b">")?;
+		}
+		output.write_all(line)?;
+		output.write_all(b"\n")?;
+	}
+	writeln!(output)
+}
+
+/// The file name of a message, derived from its subject (`Title`); characters that are invalid in file names are replaced by `_`.
+pub fn message_file_name(metadata: &Metadata) -> PathBuf {
+	let subject = metadata.title.as_ref().map(|title| title.trim()).filter(|title| !title.is_empty()).unwrap_or("(no subject)");
+	let subject = subject.chars()
+		.map(|c| if c.is_control() || "\\/:*?\"<>|".contains(c) { '_' } else { c })
+		.take(120)
+		.collect::<String>();
+	PathBuf::from(format!("{}.eml", subject.trim_end_matches(['.', ' '])))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::stderr;
+	use super::*;
+
+	#[test]
+	fn recognise_messages() {
+		assert!(is_rfc5322(b"Received: from mx.example.com\r\n\tby mail.example.com\r\nSubject: Hello\r\n\r\nBody"));
+		assert!(is_rfc5322(b"Subject: Hello\nfrom: <a@example.com>\n\nBody"));
+		// A header-like first line is not enough without any of the fields of transmitted messages
+		assert!(!is_rfc5322(b"Note: buy milk\nSubject: shopping\n\nFrom: me"));
+		assert!(!is_rfc5322(b"Dear John: From: the bank"));
+		assert!(!is_rfc5322(b": From\n"));
+		assert!(!is_rfc5322(b""));
+	}
+
+	#[test]
+	fn parse_header_sections() {
+		let headers = parse_headers("From: \"Doe, John\"\r\n <jdoe@example.com>\r\nSubject:  Hi \r\nbroken line\r\n\r\nTo: body@example.com");
+		assert_eq!(headers, vec![("From".to_owned(), "\"Doe, John\" <jdoe@example.com>".to_owned()), ("Subject".to_owned(), "Hi".to_owned())]);
+		assert_eq!(sender_address(b"FROM: \"Doe, John\"\r\n <jdoe@example.com>\r\n\r\n"), Some("jdoe@example.com".to_owned()));
+		assert_eq!(sender_address(b"From: jdoe@example.com\r\n\r\n"), Some("jdoe@example.com".to_owned()));
+		assert_eq!(sender_address(b"From: John Doe\r\n\r\n"), None);
+	}
+
+	#[test]
+	fn write_mbox_entries() {
+		let mut output = Vec::new();
+		write_mbox_entry(&mut output, b"From: <a@example.com>\r\n\r\nFrom here\r\n>From there\r\nFromage\r\n", Some(0)).unwrap();
+		assert_eq!(String::from_utf8(output).unwrap(),
+			"From a@example.com Thu Jan  1 00:00:00 1970\nFrom: <a@example.com>\n\n>From here\n>>From there\nFromage\n\n");
+		let mut output = Vec::new();
+		write_mbox_entry(&mut output, b"Subject: none", None).unwrap();
+		assert!(output.starts_with(b"From MAILER-DAEMON "));
+	}
+
+	#[test]
+	fn build_messages() {
+		let mut debug = Debug::new(stderr(), -1);
+		let metadata = Metadata { title: Some("Notes: 1/2".to_owned()), author: Some("John Doe".to_owned()), created_time: Some(86400), ..Default::default() };
+		let message = String::from_utf8(build_message(b"Hello\n", &metadata, &mut debug).unwrap()).unwrap();
+		assert!(message.starts_with("Date: Fri, 02 Jan 1970 00:00:00 +0000\r\nFrom: \"John Doe\" <unknown@invalid>\r\nSubject: Notes: 1/2\r\nMIME-Version: 1.0\r\n"));
+		assert!(message.ends_with("Content-Type: text/plain; charset=\"utf-8\"\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\nHello\r\n\r\n"));
+		// Messages are exported unchanged
+		let content = b"Date: Fri, 02 Jan 1970 00:00:00 +0000\n\nHello";
+		assert_eq!(build_message(content, &metadata, &mut debug).unwrap(), content);
+
+		assert_eq!(message_file_name(&metadata), PathBuf::from("Notes_ 1_2.eml"));
+		assert_eq!(message_file_name(&Metadata { title: Some(" Re: ... ".to_owned()), ..Default::default() }), PathBuf::from("Re_.eml"));
+		assert_eq!(message_file_name(&Metadata::default()), PathBuf::from("(no subject).eml"));
+	}
+}
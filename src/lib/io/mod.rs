@@ -15,10 +15,26 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
+#[cfg(test)]
+pub mod testing;
 
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::io::Error;
 use std::io::Stderr;
+use std::fs::{File, read_dir};
+#[cfg(windows)]
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
+
+/// The file name that replaces names consisting of dots or spaces only (see `sanitize_file_name`).
+const PLACEHOLDER_NAME: &str = "unnamed";
+
+/// A readable and seekable input, which can be used as a trait object.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T> ReadSeek for T where T: Read + Seek {}
 
 pub struct SeekableRead {
 	data: Vec<u8>,
@@ -60,16 +76,206 @@ impl Debug {
 		Debug { output, level }
 	}
 
-	pub fn log(&mut self, level: i8, string: String) {
+	pub fn log(&mut self, level: i8, string: impl AsRef<str>) {
 		if self.level >= level {
-			self.output.write_all(string.as_bytes()).unwrap();
+			self.output.write_all(string.as_ref().as_bytes()).unwrap();
 		}
 	}
 
-	pub fn logln(&mut self, level: i8, string: String) {
+	pub fn logln(&mut self, level: i8, string: impl AsRef<str>) {
 		if self.level >= level {
-			self.output.write_all(string.as_bytes()).unwrap();
+			self.output.write_all(string.as_ref().as_bytes()).unwrap();
 			self.output.write_all(&[0x0A]).unwrap();
 		}
 	}
 }
+
+/// Recursively lists all files beneath the given directory whose extension matches the given one (case-insensitive).
+/// The entries of every directory are visited in sorted order.
+pub fn find_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, Error> {
+	let mut result = Vec::new();
+	find_files_recursive(dir, &extension.to_lowercase(), &mut result)?;
+	Ok(result)
+}
+
+fn find_files_recursive(dir: &Path, extension: &str, result: &mut Vec<PathBuf>) -> Result<(), Error> {
+	let mut paths = read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<Vec<PathBuf>, Error>>()?;
+	paths.sort();
+	for path in paths {
+		if path.is_dir() {
+			find_files_recursive(&path, extension, result)?;
+		}
+		else if path.extension().map(|ext| ext.to_string_lossy().to_lowercase() == extension).unwrap_or(false) {
+			result.push(path);
+		}
+	}
+	Ok(())
+}
+
+/// Finds a path in the directory that does not exist yet, by appending ` (2)`, ` (3)` etc. to the file stem.
+pub fn unique_path(dir: &Path, file_name: impl AsRef<Path>) -> PathBuf {
+	let file_name = file_name.as_ref();
+	let path = dir.join(file_name);
+	if !path.exists() {
+		return path;
+	}
+	let stem = file_name.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+	let extension = file_name.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+	(2..).map(|number| dir.join(format!("{} ({}){}", stem, number, extension))).find(|path| !path.exists()).unwrap()
+}
+
+/// Sets the modification time of a directory.
+/// Under Windows, a directory can only be opened with write-attribute access and backup semantics,
+/// which `File::open` does not request.
+pub fn set_dir_modified(dir: &Path, modified: SystemTime) -> Result<(), Error> {
+	open_dir(dir)?.set_modified(modified)
+}
+
+#[cfg(windows)]
+fn open_dir(dir: &Path) -> Result<File, Error> {
+	use std::os::windows::fs::OpenOptionsExt;
+	const FILE_WRITE_ATTRIBUTES: u32 = 0x0100;
+	const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+	OpenOptions::new().access_mode(FILE_WRITE_ATTRIBUTES).custom_flags(FILE_FLAG_BACKUP_SEMANTICS).open(dir)
+}
+
+#[cfg(not(windows))]
+fn open_dir(dir: &Path) -> Result<File, Error> {
+	File::open(dir)
+}
+
+/// Escapes the characters that would break line-based, tab-separated files (such as journals and plans).
+/// Backslashes are left untouched, since they are ubiquitous in Windows paths.
+/// A single dash is escaped as well, since it denotes an absent value.
+pub fn escape_field(string: &str) -> String {
+	let mut result = String::with_capacity(string.len());
+	for c in string.chars() {
+		match c {
+			'%' => result.push_str("%25"),
+			'\t' => result.push_str("%09"),
+			'\n' => result.push_str("%0A"),
+			'\r' => result.push_str("%0D"),
+			_ => result.push(c),
+		}
+	}
+	if result == "-" { "%2D".to_owned() } else { result }
+}
+
+/// Reverts `escape_field`. Returns `None` if the string contains a malformed escape sequence.
+pub fn unescape_field(string: &str) -> Option<String> {
+	let mut result = String::with_capacity(string.len());
+	let mut chars = string.chars();
+	while let Some(c) = chars.next() {
+		if c == '%' {
+			let hex = chars.by_ref().take(2).collect::<String>();
+			let byte = u8::from_str_radix(&hex, 16).ok()?;
+			result.push(byte as char);
+		}
+		else {
+			result.push(c);
+		}
+	}
+	Some(result)
+}
+
+/// Escapes a name (such as the name of a CFBF stream or storage) so that it can be used as a file name under all operating systems:
+/// control characters, the characters that are not allowed under Windows (`\/:*?"<>|`) and the percent sign are replaced by `%XX`.
+/// Names that consist of dots only are escaped as well.
+pub fn escape_file_name(name: &str) -> String {
+	let mut result = String::with_capacity(name.len());
+	for c in name.chars() {
+		match c {
+			'\u{0}'..='\u{1F}' | '\u{7F}' | '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '%' => result.push_str(&format!("%{:02X}", c as u32)),
+			_ => result.push(c),
+		}
+	}
+	if !result.is_empty() && result.chars().all(|c| c == '.') {
+		return result.replace('.', "%2E");
+	}
+	result
+}
+
+/// Replaces the characters that are not allowed in file names (under Windows) by underscores.
+/// Trailing dots and spaces are removed; names that consist of nothing else (such as `..`) are replaced by a placeholder.
+pub fn sanitize_file_name(name: &str) -> String {
+	let name = name.chars()
+		.map(|c| if c.is_control() || "\\/:*?\"<>|".contains(c) { '_' } else { c })
+		.collect::<String>();
+	match name.trim_end_matches(['.', ' ']) {
+		"" => PLACEHOLDER_NAME.to_owned(),
+		name => name.to_owned(),
+	}
+}
+
+/// Formats a GUID (such as a CLSID or FMTID) that is stored with little-endian fields, e.g. `{F29F85E0-4FF9-1068-AB91-08002B27B3D9}`.
+pub fn format_guid(guid: &[u8; 16]) -> String {
+	let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<String>();
+	let reversed = |bytes: &[u8]| hex(&bytes.iter().rev().cloned().collect::<Vec<u8>>());
+	format!("{{{}-{}-{}-{}-{}}}", reversed(&guid[0..4]), reversed(&guid[4..6]), reversed(&guid[6..8]), hex(&guid[8..10]), hex(&guid[10..16]))
+}
+
+/// Decodes a UTF-16LE string, up to the first NUL character; invalid code units are replaced.
+pub fn decode_utf16le(data: &[u8]) -> String {
+	let buffer16 = data.chunks(2)
+		.filter(|c| c.len() == 2)
+		.map(|c| (c[0] as u16) | (c[1] as u16) << 8)
+		.take_while(|&c| c != 0)
+		.collect::<Vec<u16>>();
+	String::from_utf16_lossy(&buffer16)
+}
+
+/// Decodes the content of a text file, honouring a UTF-8 or UTF-16 byte order mark.
+/// Files without a byte order mark are decoded as UTF-8 (invalid sequences are replaced).
+/// Windows tools such as PowerShell's `Out-File` write UTF-16 with a byte order mark by default.
+pub fn decode_text_file(data: &[u8]) -> String {
+	let decode16 = |data: &[u8], big_endian: bool| decode_utf16(data.chunks(2)
+		.filter(|c| c.len() == 2)
+		.map(|c| if big_endian { (c[0] as u16) << 8 | c[1] as u16 } else { (c[1] as u16) << 8 | c[0] as u16 }))
+		.map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+		.collect::<String>();
+	match data {
+		[0xFF, 0xFE, rest @ ..] => decode16(rest, false),
+		[0xFE, 0xFF, rest @ ..] => decode16(rest, true),
+		[0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+		_ => String::from_utf8_lossy(data).into_owned(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env::temp_dir;
+	use std::fs::{create_dir_all, remove_dir_all, write};
+	use super::*;
+
+	#[test]
+	fn find_unique_paths() {
+		let dir = temp_dir().join(format!("evrecovery-unique-{}", std::process::id()));
+		create_dir_all(&dir).unwrap();
+		assert_eq!(unique_path(&dir, "report.doc"), dir.join("report.doc"));
+		write(dir.join("report.doc"), b"").unwrap();
+		write(dir.join("report (2).doc"), b"").unwrap();
+		assert_eq!(unique_path(&dir, "report.doc"), dir.join("report (3).doc"));
+		write(dir.join("Module1"), b"").unwrap();
+		assert_eq!(unique_path(&dir, Path::new("Module1")), dir.join("Module1 (2)"));
+		remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn sanitize_file_names() {
+		assert_eq!(sanitize_file_name("a:b*c?.doc. "), "a_b_c_.doc");
+		assert_eq!(sanitize_file_name("\u{1}Ole"), "_Ole");
+		assert_eq!(sanitize_file_name(".."), "unnamed");
+		assert_eq!(sanitize_file_name("..."), "unnamed");
+		assert_eq!(sanitize_file_name(""), "unnamed");
+	}
+
+	#[test]
+	fn set_directory_mtime() {
+		let dir = temp_dir().join(format!("evrecovery-mtime-{}", std::process::id()));
+		create_dir_all(&dir).unwrap();
+		let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1500000000);
+		set_dir_modified(&dir, modified).unwrap();
+		assert_eq!(dir.metadata().unwrap().modified().unwrap(), modified);
+		remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,37 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Encodes strings for the tests of the parsers that decode UTF-16 text.
+
+/// Encodes a text as UTF-16 (Little Endian), without a terminating NUL.
+pub fn utf16(text: &str) -> Vec<u8> {
+	text.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect()
+}
+
+/// Encodes a text as a NUL-terminated UTF-16 (Little Endian) string.
+pub fn utf16z(text: &str) -> Vec<u8> {
+	text.encode_utf16().chain(Some(0)).flat_map(|unit| unit.to_le_bytes()).collect()
+}
+
+/// Encodes a text as a NUL-terminated UTF-16 string that is prefixed by its length in bytes (see `metadata::decode_string`).
+pub fn encode_string(text: &str) -> Vec<u8> {
+	let text = utf16z(text);
+	let mut data = (text.len() as u32).to_le_bytes().to_vec();
+	data.extend_from_slice(&text);
+	data
+}
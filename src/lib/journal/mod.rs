@@ -0,0 +1,224 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write, Error};
+use std::path::Path;
+use super::io::{Debug, escape_field as escape, unescape_field as unescape};
+
+/// A persistent, append-only record of the outcome of every processed input.
+/// Each line of the journal file describes one attempt, as tab-separated fields:
+/// `<outcome> <size> <mtime> <input path> <output path> [<encryption>]`.
+/// The encryption is only written for encrypted documents, so that journals of earlier versions can still be read (and vice versa).
+/// If the same input appears several times, the last line wins.
+pub struct Journal {
+	file: File,
+	entries: HashMap<String, Entry>,
+}
+
+#[derive(Clone)]
+pub struct Entry {
+	/// The path of the input file, as it was given when processing it.
+	pub input: String,
+	/// The size of the input file (in bytes) at the time it was processed.
+	pub size: u64,
+	/// The modification time of the input file (UNIX timestamp) at the time it was processed.
+	pub mtime: u64,
+	pub outcome: Outcome,
+	/// The path of the restored file, or `None` if nothing was written to a file.
+	pub output: Option<String>,
+	/// The encryption of the restored document (see `sniff::encryption`), if it is encrypted or IRM-protected.
+	pub encryption: Option<String>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Outcome {
+	/// The input has been processed successfully.
+	Ok,
+	/// Processing the input failed.
+	Failed,
+	/// The input has been processed, but the restored content failed verification.
+	Mismatch,
+}
+
+impl Journal {
+	/// Opens the journal at the given path, reading all entries that it already contains.
+	/// The journal file is created if it does not exist yet.
+	pub fn open(path: &Path, debug: &mut Debug) -> Result<Journal, Error> {
+		let mut entries = HashMap::new();
+		if path.exists() {
+			debug.logln(1, format!("[open] Reading journal {:?} ...", path));
+			let reader = BufReader::new(File::open(path)?);
+			for (index, line) in reader.lines().enumerate() {
+				let line = line?;
+				// An interrupted run may have left a partial line behind; ignore it
+				match parse_line(&line) {
+					Some(entry) => { entries.insert(entry.input.clone(), entry); },
+					None => debug.logln(1, format!("[open] Ignoring malformed line {} in journal.", index + 1)),
+				}
+			}
+			debug.logln(1, format!("[open] Read {} journal entries.", entries.len()));
+		}
+
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		Ok(Journal { file, entries })
+	}
+
+	/// Retrieves the most recent entry for the given input, if there is one.
+	pub fn get(&self, input: &str) -> Option<&Entry> {
+		self.entries.get(input)
+	}
+
+	/// Determines whether the given input has already been processed successfully,
+	/// and has not been modified since.
+	pub fn is_done(&self, input: &str, size: u64, mtime: u64) -> bool {
+		match self.entries.get(input) {
+			Some(entry) => entry.outcome == Outcome::Ok && entry.size == size && entry.mtime == mtime,
+			None => false,
+		}
+	}
+
+	/// Determines whether the most recent attempt of processing the given input has failed (including failed verification).
+	pub fn is_failed(&self, input: &str) -> bool {
+		match self.entries.get(input) {
+			Some(entry) => entry.outcome != Outcome::Ok,
+			None => false,
+		}
+	}
+
+	/// Lists the inputs whose most recent attempt has failed, sorted by path.
+	pub fn failed_inputs(&self) -> Vec<String> {
+		let mut result = self.entries.values()
+			.filter(|entry| entry.outcome != Outcome::Ok)
+			.map(|entry| entry.input.clone())
+			.collect::<Vec<String>>();
+		result.sort();
+		result
+	}
+
+	/// Appends an entry to the journal file, and flushes it immediately so that it survives an interruption.
+	pub fn record(&mut self, entry: Entry) -> Result<(), Error> {
+		let line = format!("{}\t{}\t{}\t{}\t{}{}\n",
+			match entry.outcome { Outcome::Ok => "ok", Outcome::Failed => "failed", Outcome::Mismatch => "mismatch" },
+			entry.size,
+			entry.mtime,
+			escape(&entry.input),
+			match entry.output { Some(ref output) => escape(output), None => "-".to_owned() },
+			match entry.encryption { Some(ref encryption) => format!("\t{}", escape(encryption)), None => String::new() });
+		self.file.write_all(line.as_bytes())?;
+		self.file.flush()?;
+		self.entries.insert(entry.input.clone(), entry);
+		Ok(())
+	}
+}
+
+/// Reads the most recent entry of every input from a journal, without opening it for recording.
+/// Malformed lines are ignored. The entries are sorted by input.
+pub fn read_entries(input: impl Read) -> Result<Vec<Entry>, Error> {
+	let mut entries = HashMap::new();
+	for line in BufReader::new(input).lines() {
+		if let Some(entry) = parse_line(&line?) {
+			entries.insert(entry.input.clone(), entry);
+		}
+	}
+	let mut result = entries.into_values().collect::<Vec<Entry>>();
+	result.sort_by(|a, b| a.input.cmp(&b.input));
+	Ok(result)
+}
+
+fn parse_line(line: &str) -> Option<Entry> {
+	let fields = line.split('\t').collect::<Vec<&str>>();
+	if fields.len() != 5 && fields.len() != 6 {
+		return None;
+	}
+	let outcome = match fields[0] {
+		"ok" => Outcome::Ok,
+		"failed" => Outcome::Failed,
+		"mismatch" => Outcome::Mismatch,
+		_ => return None,
+	};
+	let size = fields[1].parse::<u64>().ok()?;
+	let mtime = fields[2].parse::<u64>().ok()?;
+	let input = unescape(fields[3])?;
+	let output = match fields[4] {
+		"-" => None,
+		output => Some(unescape(output)?),
+	};
+	let encryption = match fields.get(5) {
+		Some(encryption) => Some(unescape(encryption)?),
+		None => None,
+	};
+	Some(Entry { input, size, mtime, outcome, output, encryption })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::remove_file;
+	use std::io::stderr;
+	use std::env::temp_dir;
+	use super::*;
+
+	#[test]
+	fn parse_lines() {
+		let entry = parse_line("mismatch\t1024\t1500000000\tD:\\Vault\\a%09b.dvs\t-").unwrap();
+		assert!(entry.outcome == Outcome::Mismatch);
+		assert_eq!((entry.size, entry.mtime), (1024, 1500000000));
+		assert_eq!(entry.input, "D:\\Vault\\a\tb.dvs");
+		assert_eq!(entry.output, None);
+		assert_eq!(entry.encryption, None);
+		let entry = parse_line("ok\t1024\t1500000000\ta.dvs\tR:\\a.docx\tIRM%09protected").unwrap();
+		assert_eq!(entry.output, Some("R:\\a.docx".to_owned()));
+		assert_eq!(entry.encryption, Some("IRM\tprotected".to_owned()));
+		// Partial or unknown lines are rejected
+		assert!(parse_line("ok\t1024\t1500000000\tD:\\Vault\\a.dvs").is_none());
+		assert!(parse_line("done\t1024\t1500000000\tD:\\Vault\\a.dvs\t-").is_none());
+		assert!(parse_line("ok\tmany\t1500000000\tD:\\Vault\\a.dvs\t-").is_none());
+		assert!(parse_line("ok\t1024\t1500000000\ta.dvs\t-\tRC4\textra").is_none());
+	}
+
+	#[test]
+	fn last_entry_wins() {
+		let journal = "failed\t10\t1\ta.dvs\t-\nok\t10\t1\tb.dvs\tR:\\b.txt\nok\t10\t1\ta.dvs\tR:\\a.txt\nok\t10";
+		let entries = read_entries(journal.as_bytes()).unwrap();
+		assert_eq!(entries.iter().map(|entry| entry.input.as_str()).collect::<Vec<&str>>(), ["a.dvs", "b.dvs"]);
+		assert!(entries[0].outcome == Outcome::Ok);
+		assert_eq!(entries[0].output, Some("R:\\a.txt".to_owned()));
+	}
+
+	#[test]
+	fn record_and_reopen() {
+		let path = temp_dir().join(format!("evrecovery-journal-{}.tsv", std::process::id()));
+		let _ = remove_file(&path);
+		let mut debug = Debug::new(stderr(), -1);
+		{
+			let mut journal = Journal::open(&path, &mut debug).unwrap();
+			journal.record(Entry { input: "a.dvs".to_owned(), size: 10, mtime: 1, outcome: Outcome::Failed, output: None, encryption: None }).unwrap();
+			journal.record(Entry { input: "-".to_owned(), size: 20, mtime: 2, outcome: Outcome::Ok, output: Some("R:\\with\nnewline".to_owned()), encryption: Some("RC4".to_owned()) }).unwrap();
+		}
+		let journal = Journal::open(&path, &mut debug).unwrap();
+		assert!(journal.is_failed("a.dvs"));
+		assert!(journal.is_done("-", 20, 2));
+		assert!(!journal.is_done("-", 20, 3));
+		assert_eq!(journal.get("-").unwrap().output, Some("R:\\with\nnewline".to_owned()));
+		assert_eq!(journal.get("-").unwrap().encryption, Some("RC4".to_owned()));
+		assert_eq!(journal.get("a.dvs").unwrap().encryption, None);
+		assert_eq!(journal.failed_inputs(), ["a.dvs"]);
+		remove_file(&path).unwrap();
+	}
+}
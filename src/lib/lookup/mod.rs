@@ -0,0 +1,237 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use super::catalogue::Entry;
+use super::metadata::{Metadata, parse_timestamp};
+
+/// A case-insensitive pattern for original (Windows) paths.
+/// `?` matches a single character, `*` matches any sequence of characters within a path component,
+/// and `**` matches any sequence of characters, including directory separators.
+/// Forward slashes are treated like backslashes.
+pub struct Pattern {
+	chars: Vec<char>,
+}
+
+impl Pattern {
+	pub fn new(pattern: &str) -> Pattern {
+		Pattern { chars: normalize(pattern).chars().collect() }
+	}
+
+	pub fn matches(&self, path: &str) -> bool {
+		let path = normalize(path).chars().collect::<Vec<char>>();
+		matches_at(&self.chars, &path)
+	}
+}
+
+/// Lowercases a path and unifies its directory separators.
+fn normalize(path: &str) -> String {
+	path.to_lowercase().replace('/', "\\")
+}
+
+fn matches_at(pattern: &[char], path: &[char]) -> bool {
+	match pattern.first() {
+		None => path.is_empty(),
+		Some(&'*') => {
+			let (rest, crosses_separators) = match pattern.get(1) {
+				Some(&'*') => (&pattern[2..], true),
+				_ => (&pattern[1..], false),
+			};
+			for index in 0..=path.len() {
+				if matches_at(rest, &path[index..]) {
+					return true;
+				}
+				if index < path.len() && path[index] == '\\' && !crosses_separators {
+					return false;
+				}
+			}
+			false
+		},
+		Some(&'?') => !path.is_empty() && path[0] != '\\' && matches_at(&pattern[1..], &path[1..]),
+		Some(&c) => !path.is_empty() && path[0] == c && matches_at(&pattern[1..], &path[1..]),
+	}
+}
+
+/// An inclusive range of UNIX timestamps; either end may be open.
+#[derive(Copy, Clone, Default)]
+pub struct DateRange {
+	pub from: Option<u64>,
+	pub to: Option<u64>,
+}
+
+impl DateRange {
+	/// Parses a range in the format `FROM..TO`, where either date may be omitted (e.g. `2017-01-01..`), or a single date.
+	/// Dates are ISO 8601 dates (`2017-01-01`) or date and times (`2017-01-01T12:00:00`) in UTC.
+	/// A date without a time includes the whole day as the end of a range, or as a single date.
+	pub fn parse(text: &str) -> Option<DateRange> {
+		let parse_end = |text: &str, is_end: bool| -> Option<Option<u64>> {
+			let text = text.trim();
+			if text.is_empty() {
+				return Some(None);
+			}
+			let timestamp = parse_timestamp(text)?;
+			let is_date_only = !text.contains(['T', ' ']);
+			Some(Some(if is_end && is_date_only { timestamp + 86399 } else { timestamp }))
+		};
+		match text.find("..") {
+			Some(index) => Some(DateRange { from: parse_end(&text[..index], false)?, to: parse_end(&text[index + 2..], true)? }),
+			None => Some(DateRange { from: parse_end(text, false)?, to: parse_end(text, true)? }),
+		}
+	}
+
+	/// Whether the timestamp lies within this range. Missing timestamps only match unbounded ranges.
+	pub fn contains(&self, timestamp: Option<u64>) -> bool {
+		match (timestamp, self.from, self.to) {
+			(_, None, None) => true,
+			(None, _, _) => false,
+			(Some(timestamp), from, to) => from.is_none_or(|from| timestamp >= from) && to.is_none_or(|to| timestamp <= to),
+		}
+	}
+}
+
+/// A query for archived items by their original path and dates.
+pub struct Query {
+	pub pattern: Pattern,
+	pub created: DateRange,
+	pub modified: DateRange,
+	pub archived: DateRange,
+}
+
+impl Query {
+	pub fn matches(&self, metadata: &Metadata) -> bool {
+		match metadata.original_path() {
+			Some(path) => self.pattern.matches(&path)
+				&& self.created.contains(metadata.created_time)
+				&& self.modified.contains(metadata.modified_time)
+				&& self.archived.contains(metadata.archived_date),
+			None => false,
+		}
+	}
+}
+
+/// All archived versions of a single original file.
+pub struct Versions<'a> {
+	/// The original path, as recorded in the first version.
+	pub original_path: String,
+	/// The versions, from the oldest to the most recent one.
+	pub entries: Vec<&'a Entry>,
+}
+
+/// Groups catalogue entries by their (case-insensitive) original path.
+/// The versions of a file are ordered by their modification time, then by their archival date.
+/// The groups are ordered by their original path.
+pub fn group_versions(entries: &[Entry]) -> Vec<Versions<'_>> {
+	let mut groups: HashMap<String, Vec<&Entry>> = HashMap::new();
+	for entry in entries {
+		if let Some(path) = entry.metadata.original_path() {
+			groups.entry(normalize(&path)).or_default().push(entry);
+		}
+	}
+
+	let mut result = groups.into_values().map(|mut entries| {
+		entries.sort_by_key(|entry| (entry.metadata.modified_time, entry.metadata.archived_date, entry.dvs_path.clone()));
+		Versions { original_path: entries[0].metadata.original_path().unwrap_or_default(), entries }
+	}).collect::<Vec<Versions>>();
+	result.sort_by_key(|versions| normalize(&versions.original_path));
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::metadata::ContentLocation;
+	use super::super::propset::Summary;
+
+	fn entry(dvs_path: &str, original_path: &str, modified_time: Option<u64>) -> Entry {
+		let (folder_path, title) = original_path.rsplit_once('\\').unwrap();
+		Entry {
+			dvs_path: dvs_path.to_owned(),
+			dvs_size: 0,
+			dvf_path: None,
+			metadata: Metadata {
+				folder_path: Some(folder_path.to_owned()),
+				title: Some(title.to_owned()),
+				modified_time,
+				content: ContentLocation::Embedded,
+				..Metadata::default()
+			},
+			document: Summary::default(),
+			encryption: None,
+			error: None,
+			notes: None,
+		}
+	}
+
+	#[test]
+	fn match_patterns() {
+		let pattern = Pattern::new("F:/Projects/*/report?.doc");
+		assert!(pattern.matches("f:\\projects\\2018\\Report1.DOC"));
+		assert!(!pattern.matches("F:\\Projects\\2018\\Q1\\Report1.doc"));
+		assert!(!pattern.matches("F:\\Projects\\2018\\Report12.doc"));
+		let pattern = Pattern::new("F:\\**\\*.xls");
+		assert!(pattern.matches("F:\\Projects\\2018\\Q1\\budget.xls"));
+		assert!(!pattern.matches("G:\\Projects\\budget.xls"));
+		// `?` does not match a directory separator
+		assert!(!Pattern::new("F:\\a?b").matches("F:\\a\\b"));
+		assert!(Pattern::new("**").matches("anything\\at\\all"));
+	}
+
+	#[test]
+	fn parse_date_ranges() {
+		let day = DateRange::parse("2018-03-02").unwrap();
+		assert_eq!((day.from, day.to), (Some(1519948800), Some(1519948800 + 86399)));
+		let range = DateRange::parse("2018-03-02T12:00:00..").unwrap();
+		assert_eq!((range.from, range.to), (Some(1519992000), None));
+		let range = DateRange::parse("..2018-03-02").unwrap();
+		assert_eq!((range.from, range.to), (None, Some(1519948800 + 86399)));
+		assert!(DateRange::parse("March 2018").is_none());
+		assert!(DateRange::parse("2018-03-02..never").is_none());
+
+		assert!(day.contains(Some(1520000000)));
+		assert!(!day.contains(Some(1520035200)));
+		assert!(!day.contains(None));
+		assert!(DateRange::default().contains(None));
+	}
+
+	#[test]
+	fn query_metadata() {
+		let query = Query {
+			pattern: Pattern::new("F:\\Projects\\**"),
+			created: DateRange::default(),
+			modified: DateRange::parse("2018-03-02").unwrap(),
+			archived: DateRange::default(),
+		};
+		assert!(query.matches(&entry("a.dvs", "F:\\Projects\\a.doc", Some(1520000000)).metadata));
+		assert!(!query.matches(&entry("a.dvs", "F:\\Projects\\a.doc", Some(1530000000)).metadata));
+		assert!(!query.matches(&entry("a.dvs", "F:\\Other\\a.doc", Some(1520000000)).metadata));
+		assert!(!query.matches(&Metadata::default()));
+	}
+
+	#[test]
+	fn group_file_versions() {
+		let entries = [
+			entry("3.dvs", "F:\\b.doc", Some(30)),
+			entry("2.dvs", "F:\\A.doc", Some(20)),
+			entry("1.dvs", "f:\\a.doc", Some(10)),
+			entry("4.dvs", "F:\\a.doc", None),
+		];
+		let groups = group_versions(&entries);
+		assert_eq!(groups.iter().map(|versions| versions.original_path.as_str()).collect::<Vec<&str>>(), ["F:\\a.doc", "F:\\b.doc"]);
+		assert_eq!(groups[0].entries.iter().map(|entry| entry.dvs_path.as_str()).collect::<Vec<&str>>(), ["4.dvs", "1.dvs", "2.dvs"]);
+	}
+}
@@ -0,0 +1,146 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+extern crate libflate;
+
+use std::io::{Read, Seek, Error};
+use self::libflate::zlib::Decoder;
+use super::{read_stream, decode_string};
+use super::interpret::{Leniency, decode_unicode_text};
+use super::properties::PropertyMap;
+use super::super::cfbf::Container;
+use super::super::io::Debug;
+
+/// What Enterprise Vault recorded for indexing an archived item (`/Indexable Item`).
+/// This may be all that is left of an item whose content is missing.
+#[derive(Clone, Default)]
+pub struct IndexedItem {
+	/// `/Indexable Item/Indexable Item Stream`: the text of the item, if it can be decoded.
+	pub text: Option<String>,
+	/// `/Indexable Item/Indexable Item Properties`
+	pub properties: Option<PropertyMap>,
+}
+
+impl IndexedItem {
+	/// Reads and decodes the indexed text and properties from the payload.
+	pub fn read<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<IndexedItem, Error> where TFile: Read + Seek {
+		Ok(IndexedItem {
+			text: read_stream(container, "Indexable Item Stream", debug)?.and_then(|data| decode_indexed_text(&data)),
+			properties: read_stream(container, "Indexable Item Properties", debug)?.map(|data| PropertyMap::decode(&data)),
+		})
+	}
+}
+
+/// How often the indexed text may be zlib-compressed within itself.
+/// The limit keeps streams that inflate to further zlib streams from recursing endlessly.
+pub const MAX_ZLIB_DEPTH: usize = 4;
+
+/// The maximum size to which a zlib-compressed indexed text is inflated (as with the members of ZIP files in `sniff`).
+const MAX_INFLATED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Decodes the indexed text: a length-prefixed UTF-16 string, UTF-16 or UTF-8 text, possibly zlib-compressed.
+/// The text is decoded leniently, since it is only recovered for reading: control characters (such as the line and page breaks `\x0B` and `\x0C` of Word) are replaced.
+/// Returns `None` if the data is in none of these formats, or holds no text.
+pub fn decode_indexed_text(data: &[u8]) -> Option<String> {
+	decode_indexed_text_recursive(data, 0)
+}
+
+fn decode_indexed_text_recursive(data: &[u8], depth: usize) -> Option<String> {
+	// A zlib stream starts with the compression method 8 (deflate), and its first two bytes are a multiple of 31
+	if depth < MAX_ZLIB_DEPTH && data.len() > 2 && data[0] & 0x0F == 8 && ((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31) {
+		let mut inflated = Vec::new();
+		if Decoder::new(data).and_then(|decoder| decoder.take(MAX_INFLATED_SIZE).read_to_end(&mut inflated)).is_ok() {
+			return decode_indexed_text_recursive(&inflated, depth + 1);
+		}
+	}
+	let text = decode_string(data).or_else(|| decode_unicode_text(data, Leniency::Lenient))?;
+	let text = text.chars().filter(|&c| c != '\u{FEFF}').map(|c| match c {
+		'\t' | '\r' | '\n' => c,
+		'\u{B}' | '\u{C}' => '\n',
+		c if c.is_control() => ' ',
+		c => c,
+	}).collect::<String>();
+	Some(text).filter(|text| !text.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::super::io::testing::utf16;
+	use super::super::super::sniff::testing::deflate_stored;
+
+	/// Wraps data into a zlib stream (of uncompressed deflate blocks).
+	fn zlib(data: &[u8]) -> Vec<u8> {
+		let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+			let a = (a + byte as u32) % 65521;
+			(a, (b + a) % 65521)
+		});
+		let mut result = vec![0x78, 0x01];
+		result.extend(deflate_stored(data));
+		result.extend_from_slice(&(b << 16 | a).to_be_bytes());
+		result
+	}
+
+	#[test]
+	fn decode_encodings() {
+		// A length-prefixed UTF-16 string
+		let mut data = (utf16("Minutes").len() as u32).to_le_bytes().to_vec();
+		data.extend(utf16("Minutes"));
+		assert_eq!(decode_indexed_text(&data), Some("Minutes".to_owned()));
+		// UTF-16 with and without a byte order mark, and UTF-8
+		assert_eq!(decode_indexed_text(&utf16("\u{FEFF}Budget 2018\0")), Some("Budget 2018".to_owned()));
+		assert_eq!(decode_indexed_text(&utf16("Budget 2018")), Some("Budget 2018".to_owned()));
+		assert_eq!(decode_indexed_text("Übersicht".as_bytes()), Some("Übersicht".to_owned()));
+		assert_eq!(decode_indexed_text(b"\xEF\xBB\xBFplain\0\0"), Some("plain".to_owned()));
+	}
+
+	#[test]
+	fn decode_other_scripts() {
+		for text in &["会议纪要 第三季度", "Протокол совещания", "議事録とメモ"] {
+			assert_eq!(decode_indexed_text(&utf16(text)).as_deref(), Some(*text));
+			assert_eq!(decode_indexed_text(text.as_bytes()).as_deref(), Some(*text));
+		}
+	}
+
+	#[test]
+	fn replace_control_characters() {
+		assert_eq!(decode_indexed_text(&utf16("Title\u{B}Line\u{C}Page\u{7}end")), Some("Title\nLine\nPage end".to_owned()));
+		assert_eq!(decode_indexed_text(b"tab\there\r\n"), Some("tab\there\r\n".to_owned()));
+	}
+
+	#[test]
+	fn reject_binary_and_empty_data() {
+		assert_eq!(decode_indexed_text(b""), None);
+		assert_eq!(decode_indexed_text(&utf16("  \r\n")), None);
+		// Unpaired surrogates, and private-use characters
+		assert_eq!(decode_indexed_text(&[0x00, 0xD8, 0x41, 0x00, 0xFF, 0xFE]), None);
+		assert_eq!(decode_indexed_text(&[0x00, 0xE0, 0x01, 0xE0, 0x02, 0xE0, 0x41, 0x00]), None);
+	}
+
+	#[test]
+	fn inflate_zlib_streams() {
+		assert_eq!(decode_indexed_text(&zlib(&utf16("Compressed text"))), Some("Compressed text".to_owned()));
+		let mut nested = b"nested".to_vec();
+		for _ in 0..MAX_ZLIB_DEPTH {
+			nested = zlib(&nested);
+		}
+		assert_eq!(decode_indexed_text(&nested), Some("nested".to_owned()));
+		// Deeper nesting is not inflated
+		assert_ne!(decode_indexed_text(&zlib(&nested)), Some("nested".to_owned()));
+	}
+}
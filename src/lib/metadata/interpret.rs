@@ -0,0 +1,286 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// Candidate interpretations for metadata streams whose format is not documented.
+
+use std::char::decode_utf16;
+use super::{decode_string, filetime_to_unix, format_timestamp};
+use super::super::io::format_guid;
+
+/// Timestamps outside this range (1980-01-01 to 2100-01-01) are not considered plausible.
+const PLAUSIBLE_TIMESTAMPS: (u64, u64) = (315_532_800, 4_102_444_800);
+
+/// A possible interpretation of the content of a stream.
+pub struct Interpretation {
+	/// The name of the encoding, e.g. `FILETIME`.
+	pub encoding: &'static str,
+	/// The decoded value.
+	pub value: String,
+	/// The parts of the data that this interpretation explains.
+	pub spans: Vec<Span>,
+}
+
+/// A range of bytes, with a description of what it holds.
+pub struct Span {
+	pub start: usize,
+	pub end: usize,
+	pub description: String,
+}
+
+impl Span {
+	fn new(start: usize, end: usize, description: String) -> Span {
+		Span { start, end, description }
+	}
+}
+
+/// Tries all known encodings on the data, and returns the plausible interpretations, the most specific first:
+/// length-prefixed UTF-16 strings (the common Enterprise Vault format), XML documents, GUIDs, FILETIMEs, UNIX timestamps, plain text and integers.
+pub fn interpret(data: &[u8]) -> Vec<Interpretation> {
+	let mut result = Vec::new();
+	let string = decode_string(data);
+	if let Some(ref string) = string {
+		let text_length = string.encode_utf16().count() * 2;
+		let mut spans = vec![Span::new(0, 4, format!("length: {} bytes", data.len() - 4))];
+		if text_length > 0 {
+			spans.push(Span::new(4, 4 + text_length, "UTF-16 text".to_owned()));
+		}
+		if 4 + text_length < data.len() {
+			spans.push(Span::new(4 + text_length, data.len(), "NUL terminator".to_owned()));
+		}
+		result.push(Interpretation { encoding: "length-prefixed UTF-16 string", value: quote(string), spans });
+	}
+
+	let text = decode_utf16_text(data, Leniency::Strict);
+	let utf8 = decode_utf8_text(data, Leniency::Strict);
+	let xml = string.as_ref().or(text.as_ref()).or(utf8.as_ref()).and_then(|text| xml_root(text));
+	if let Some(root) = xml {
+		result.push(Interpretation { encoding: "XML document", value: format!("root element <{}>", root), spans: vec![Span::new(0, data.len(), "XML document".to_owned())] });
+	}
+
+	match data.len() {
+		16 => {
+			let mut guid = [0; 16];
+			guid.copy_from_slice(data);
+			result.push(Interpretation { encoding: "GUID", value: format_guid(&guid), spans: vec![Span::new(0, 16, "GUID".to_owned())] });
+		},
+		8 => {
+			let value = integer(data);
+			if let Some(timestamp) = filetime_to_unix(value).filter(|&timestamp| is_plausible(timestamp)) {
+				result.push(Interpretation { encoding: "FILETIME", value: format_timestamp(timestamp), spans: vec![Span::new(0, 8, "FILETIME".to_owned())] });
+			}
+		},
+		4 => {
+			let value = integer(data);
+			if is_plausible(value) {
+				result.push(Interpretation { encoding: "UNIX timestamp", value: format_timestamp(value), spans: vec![Span::new(0, 4, "UNIX timestamp".to_owned())] });
+			}
+		},
+		_ => {},
+	}
+
+	if string.is_none() {
+		if let Some(text) = text {
+			let spans = vec![Span::new(0, data.len(), "UTF-16 text".to_owned())];
+			result.push(Interpretation { encoding: "UTF-16 text", value: quote(&text), spans });
+		}
+		if let Some(text) = utf8 {
+			let spans = vec![Span::new(0, data.len(), "text".to_owned())];
+			result.push(Interpretation { encoding: "ASCII/UTF-8 text", value: quote(&text), spans });
+		}
+	}
+
+	let bits = data.len() * 8;
+	if let 1 | 2 | 4 | 8 = data.len() {
+		let value = integer(data);
+		let encoding = match bits {
+			8 => "8-bit integer",
+			16 => "16-bit integer",
+			32 => "32-bit integer",
+			_ => "64-bit integer",
+		};
+		result.push(Interpretation { encoding, value: format!("{} ({:#0width$X})", value, value, width = data.len() * 2 + 2), spans: vec![Span::new(0, data.len(), encoding.to_owned())] });
+	}
+	result
+}
+
+/// Decodes a Little Endian integer of up to 8 bytes.
+fn integer(data: &[u8]) -> u64 {
+	data.iter().rev().fold(0u64, |result, &byte| result << 8 | byte as u64)
+}
+
+fn is_plausible(timestamp: u64) -> bool {
+	timestamp >= PLAUSIBLE_TIMESTAMPS.0 && timestamp < PLAUSIBLE_TIMESTAMPS.1
+}
+
+/// Whether a text consists of printable characters (and whitespace) only.
+fn is_printable(text: &str) -> bool {
+	!text.is_empty() && text.chars().all(|c| !c.is_control() || c == '\t' || c == '\r' || c == '\n')
+}
+
+/// How readily data is taken for text by `decode_utf16_text` and `decode_utf8_text`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Leniency {
+	/// For data of unknown type: at least half of the UTF-16 characters must be Latin-1 characters,
+	/// and the text must not be empty, nor hold control characters other than tabs and line breaks.
+	Strict,
+	/// For data that is known to hold text, in any script: at least three quarters of the UTF-16 characters must be letters, digits or whitespace.
+	/// Control characters (such as the line and page breaks `\x0B` and `\x0C` of Word) are allowed, except for NULs within the text.
+	Lenient,
+}
+
+/// Decodes UTF-16 (Little Endian) text without a length prefix, with an optional byte order mark and trailing NULs.
+/// Since almost any even number of bytes decodes to some UTF-16 text, text without a byte order mark must be plausible (see `Leniency`);
+/// unpaired surrogates are never allowed.
+pub fn decode_utf16_text(data: &[u8], leniency: Leniency) -> Option<String> {
+	if data.len() < 2 || !data.len().is_multiple_of(2) {
+		return None;
+	}
+	let mut units = data.chunks(2).map(|c| (c[0] as u16) | (c[1] as u16) << 8).collect::<Vec<u16>>();
+	while units.last() == Some(&0) {
+		units.pop();
+	}
+	let (units, has_bom) = match units.first() {
+		Some(&0xFEFF) => (&units[1..], true),
+		_ => (&units[..], false),
+	};
+	let text = decode_utf16(units.iter().cloned()).collect::<Result<String, _>>().ok()?;
+	let is_plausible = has_bom || match leniency {
+		Leniency::Strict => units.iter().filter(|&&unit| unit < 0x100).count() * 2 >= units.len(),
+		Leniency::Lenient => text.chars().filter(|c| c.is_alphanumeric() || c.is_whitespace()).count() * 4 >= text.chars().count() * 3,
+	};
+	Some(text).filter(|text| is_plausible && is_allowed(text, leniency))
+}
+
+/// Decodes UTF-8 text, with an optional byte order mark and trailing NULs.
+pub fn decode_utf8_text(data: &[u8], leniency: Leniency) -> Option<String> {
+	let data = if data.starts_with(&[0xEF, 0xBB, 0xBF]) { &data[3..] } else { data };
+	let end = data.iter().rposition(|&byte| byte != 0).map(|index| index + 1).unwrap_or(0);
+	let text = String::from_utf8(data[..end].to_vec()).ok()?;
+	Some(text).filter(|text| is_allowed(text, leniency))
+}
+
+/// Decodes UTF-8 or UTF-16 (Little Endian) text without a length prefix, with an optional byte order mark and trailing NULs.
+/// UTF-8 is tried first: UTF-16 text of Latin characters is full of NULs, which UTF-8 text does not hold.
+pub fn decode_unicode_text(data: &[u8], leniency: Leniency) -> Option<String> {
+	decode_utf8_text(data, leniency).or_else(|| decode_utf16_text(data, leniency))
+}
+
+/// Whether a decoded text may be taken for text (see `Leniency`).
+fn is_allowed(text: &str, leniency: Leniency) -> bool {
+	match leniency {
+		Leniency::Strict => is_printable(text),
+		Leniency::Lenient => !text.contains('\0'),
+	}
+}
+
+/// Determines the name of the root element, if the text is an XML document.
+fn xml_root(text: &str) -> Option<String> {
+	let mut rest = text.trim();
+	if !rest.ends_with('>') {
+		return None;
+	}
+	// Skip the XML declaration, processing instructions, comments and the document type declaration
+	while rest.starts_with("<?") || rest.starts_with("<!") {
+		let end = if rest.starts_with("<!--") { rest.find("-->")? + 3 } else { rest.find('>')? + 1 };
+		rest = rest[end..].trim_start();
+	}
+	let name = rest.strip_prefix('<')?.split(|c: char| c.is_whitespace() || c == '>' || c == '/').next()?;
+	Some(name.to_owned()).filter(|name| !name.is_empty())
+}
+
+/// Quotes a text for display, escaping control characters.
+fn quote(text: &str) -> String {
+	let escaped = text.chars().map(|c| if c.is_control() { c.escape_default().to_string() } else { c.to_string() }).collect::<String>();
+	format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::super::io::testing::utf16;
+
+	fn encodings(data: &[u8]) -> Vec<&'static str> {
+		interpret(data).iter().map(|interpretation| interpretation.encoding).collect()
+	}
+
+	#[test]
+	fn interpret_strings() {
+		let mut data = 8u32.to_le_bytes().to_vec();
+		data.extend(utf16("Q1\0"));
+		data.extend(&[0, 0]);
+		let interpretations = interpret(&data);
+		assert_eq!(interpretations[0].encoding, "length-prefixed UTF-16 string");
+		assert_eq!(interpretations[0].value, "\"Q1\"");
+		assert_eq!(interpretations[0].spans.iter().map(|span| (span.start, span.end)).collect::<Vec<(usize, usize)>>(), [(0, 4), (4, 8), (8, 12)]);
+		// Plain text is only offered if there is no length prefix
+		assert!(!encodings(&data).contains(&"UTF-16 text"));
+		assert_eq!(encodings(&utf16("Projects\0")), ["UTF-16 text"]);
+		assert_eq!(encodings(b"Projects"), ["ASCII/UTF-8 text", "64-bit integer"]);
+		assert_eq!(interpret(b"tab\there")[0].value, "\"tab\\there\"");
+	}
+
+	#[test]
+	fn interpret_xml() {
+		let interpretations = interpret(&utf16("\u{FEFF}<?xml version=\"1.0\"?><!-- c --><Props a=\"1\"/>"));
+		assert_eq!(interpretations[0].encoding, "XML document");
+		assert_eq!(interpretations[0].value, "root element <Props>");
+		assert!(!encodings(b"<not xml").contains(&"XML document"));
+	}
+
+	#[test]
+	fn interpret_numbers() {
+		let clsid = [0x11; 16];
+		assert_eq!(interpret(&clsid)[0].value, format_guid(&clsid));
+		// 2018-03-02 14:13:20 UTC, as FILETIME and as UNIX timestamp
+		let filetime = (1_520_000_000u64 + 11_644_473_600) * 10_000_000;
+		assert_eq!(encodings(&filetime.to_le_bytes()), ["FILETIME", "64-bit integer"]);
+		assert_eq!(interpret(&filetime.to_le_bytes())[0].value, format_timestamp(1_520_000_000));
+		assert_eq!(encodings(&1_520_000_000u32.to_le_bytes()), ["UNIX timestamp", "32-bit integer"]);
+		// Implausible timestamps are only integers
+		assert_eq!(encodings(&7u32.to_le_bytes()), ["32-bit integer"]);
+		assert_eq!(interpret(&[0x2A, 0x01])[0].value, "298 (0x012A)");
+		assert!(interpret(&[0xFF, 0x00, 0xFE]).is_empty());
+	}
+
+	#[test]
+	fn decode_texts() {
+		let strict = Leniency::Strict;
+		assert_eq!(decode_utf16_text(&utf16("\u{FEFF}Owner\0\0"), strict), Some("Owner".to_owned()));
+		// Mostly non-Latin-1 characters, odd lengths and control characters are rejected
+		assert_eq!(decode_utf16_text(&[0x00, 0x4E, 0x01, 0x4E, 0x41, 0x00], strict), None);
+		assert_eq!(decode_utf16_text(b"abc", strict), None);
+		assert_eq!(decode_utf16_text(&utf16("a\u{7}"), strict), None);
+		assert_eq!(decode_utf8_text(b"\xEF\xBB\xBFOwner\0", strict), Some("Owner".to_owned()));
+		assert_eq!(decode_utf8_text(b"\xFFOwner", strict), None);
+		assert_eq!(decode_utf8_text(b"", strict), None);
+	}
+
+	#[test]
+	fn decode_texts_leniently() {
+		let lenient = Leniency::Lenient;
+		// Text in any script, and control characters
+		assert_eq!(decode_utf16_text(&[0x00, 0x4E, 0x01, 0x4E, 0x41, 0x00], lenient), Some("\u{4E00}\u{4E01}A".to_owned()));
+		assert_eq!(decode_utf16_text(&utf16("a\u{7}b\u{C}"), lenient), Some("a\u{7}b\u{C}".to_owned()));
+		assert_eq!(decode_unicode_text(b"a\x0Bb", lenient), Some("a\u{B}b".to_owned()));
+		// UTF-16 of Latin characters is not taken for UTF-8 (with NULs)
+		assert_eq!(decode_unicode_text(&utf16("Owner"), lenient), Some("Owner".to_owned()));
+		// Private-use characters and unpaired surrogates are rejected
+		assert_eq!(decode_utf16_text(&[0x00, 0xE0, 0x01, 0xE0, 0x02, 0xE0, 0x41, 0x00], lenient), None);
+		assert_eq!(decode_utf16_text(&[0x00, 0xD8, 0x41, 0x00], lenient), None);
+	}
+}
@@ -0,0 +1,313 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+pub mod indexed;
+pub mod interpret;
+pub mod properties;
+
+use std::io::{Read, Seek, Error};
+use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use super::cfbf::{Container, ObjectResult};
+use super::io::Debug;
+use self::properties::PropertyMap;
+
+/// The metadata of an archived item, as recorded in the payload of a DVS file.
+/// All properties are optional, since their presence varies across Enterprise Vault versions and item types.
+#[derive(Clone, Default)]
+pub struct Metadata {
+	/// `/User Information/Location/ExchangeLocation/FolderPath`: the directory of the original file.
+	pub folder_path: Option<String>,
+	/// `/User Information/User Archivable Item/Title`: the name of the original file.
+	pub title: Option<String>,
+	/// `/User Information/User Archivable Item/OriginalSize`
+	pub original_size: Option<u64>,
+	/// `/LargeFile/LargeFileSize`
+	pub large_file_size: Option<u64>,
+	/// `/User Information/User Archivable Item/CreatedTime` (UNIX timestamp)
+	pub created_time: Option<u64>,
+	/// `/User Information/User Archivable Item/LastModTime` (UNIX timestamp)
+	pub modified_time: Option<u64>,
+	/// `/User Information/User Archivable Item/ArchivedDate` (UNIX timestamp)
+	pub archived_date: Option<u64>,
+	/// `/User Information/User Archivable Item/MIMEType`
+	pub mime_type: Option<String>,
+	/// `/User Information/User Archivable Item/Author`
+	pub author: Option<String>,
+	/// `/User Information/VaultID`
+	pub vault_id: Option<String>,
+	/// `/User Information/Retention Category`
+	pub retention_category: Option<String>,
+	/// `/File Extension`
+	pub file_extension: Option<String>,
+	/// `/User Information/UserXMLStream`: custom properties, such as those used for indexing.
+	pub user_properties: Option<PropertyMap>,
+	/// `/User Information/AgentProperties`: properties recorded by the archiving agent, such as share names or owners.
+	pub agent_properties: Option<PropertyMap>,
+	/// Where the content of the archived file is stored.
+	pub content: ContentLocation,
+}
+
+#[derive(Copy, Clone, PartialEq, Default)]
+pub enum ContentLocation {
+	/// The content is embedded in the payload (`/Archivable Item/FileContentStream`).
+	Embedded,
+	/// The content is out-sourced to a separate file (indicated by `/LargeFile`).
+	Outsourced,
+	/// The content is held by a sharable part (indicated by a `/Sharable Content` storage without `FileContentStream`).
+	Sharable,
+	/// Neither embedded, out-sourced nor sharable content is indicated.
+	#[default]
+	None,
+}
+
+impl ContentLocation {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			ContentLocation::Embedded => "embedded",
+			ContentLocation::Outsourced => "outsourced",
+			ContentLocation::Sharable => "sharable",
+			ContentLocation::None => "none",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<ContentLocation> {
+		match name {
+			"embedded" => Some(ContentLocation::Embedded),
+			"outsourced" => Some(ContentLocation::Outsourced),
+			"sharable" => Some(ContentLocation::Sharable),
+			"none" => Some(ContentLocation::None),
+			_ => None,
+		}
+	}
+}
+
+impl Metadata {
+	/// Reads all known metadata streams from the payload, without extracting the archived content.
+	pub fn read<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Metadata, Error> where TFile: Read + Seek {
+		let content = match (container.find_child_by_name("FileContentStream", debug)?, container.find_child_by_name("LargeFile", debug)?) {
+			(ObjectResult::Ok(_), _) => ContentLocation::Embedded,
+			(ObjectResult::None, ObjectResult::Ok(_)) => ContentLocation::Outsourced,
+			(ObjectResult::None, ObjectResult::None) => match container.find_child_by_name("Sharable Content", debug)? {
+				ObjectResult::Ok(_) => ContentLocation::Sharable,
+				ObjectResult::None => ContentLocation::None,
+			},
+		};
+
+		Ok(Metadata {
+			folder_path: read_stream(container, "FolderPath", debug)?.and_then(|data| decode_string(&data)),
+			title: read_stream(container, "Title", debug)?.and_then(|data| decode_string(&data)),
+			original_size: read_stream(container, "OriginalSize", debug)?.and_then(|data| decode_size(&data)),
+			large_file_size: read_stream(container, "LargeFileSize", debug)?.and_then(|data| decode_size(&data)),
+			created_time: read_stream(container, "CreatedTime", debug)?.and_then(|data| decode_timestamp(&data)),
+			modified_time: read_stream(container, "LastModTime", debug)?.and_then(|data| decode_timestamp(&data)),
+			archived_date: read_stream(container, "ArchivedDate", debug)?.and_then(|data| decode_timestamp(&data)),
+			mime_type: read_stream(container, "MIMEType", debug)?.map(|data| decode_text(&data)),
+			author: read_stream(container, "Author", debug)?.map(|data| decode_text(&data)),
+			vault_id: read_stream(container, "VaultID", debug)?.map(|data| decode_text(&data)),
+			retention_category: read_stream(container, "Retention Category", debug)?.map(|data| decode_text(&data)),
+			file_extension: read_stream(container, "File Extension", debug)?.map(|data| decode_text(&data)),
+			user_properties: read_stream(container, "UserXMLStream", debug)?.map(|data| PropertyMap::decode(&data)),
+			agent_properties: read_stream(container, "AgentProperties", debug)?.map(|data| PropertyMap::decode(&data)),
+			content,
+		})
+	}
+
+	/// The original path of the archived file, as recorded (directory and file name, separated by a backslash).
+	pub fn original_path(&self) -> Option<String> {
+		match (&self.folder_path, &self.title) {
+			(Some(folder_path), Some(title)) if folder_path.is_empty() => Some(title.clone()),
+			(Some(folder_path), Some(title)) => Some(format!("{}\\{}", folder_path.trim_end_matches('\\'), title)),
+			(None, Some(title)) => Some(title.clone()),
+			(_, None) => None,
+		}
+	}
+}
+
+/// Reads the content of the first stream with the given name (anywhere in the container).
+/// Returns `None` if there is no such stream.
+pub fn read_stream<TFile>(container: &mut Container<TFile>, name: &str, debug: &mut Debug) -> Result<Option<Vec<u8>>, Error> where TFile: Read + Seek {
+	match container.find_child_by_name(name, debug)? {
+		ObjectResult::Ok(object) => {
+			let mut buffer: Vec<u8> = Vec::new();
+			container.dump_stream(&object, &mut buffer, debug)?;
+			Ok(Some(buffer))
+		},
+		ObjectResult::None => Ok(None),
+	}
+}
+
+/// Decodes the string format used by most Enterprise Vault metadata streams:
+/// a 32-bit integer denoting the byte length, followed by a NUL-terminated UTF16-encoded string.
+/// Returns `None` if the data is not in this format.
+pub fn decode_string(data: &[u8]) -> Option<String> {
+	if data.len() < 4 {
+		return None;
+	}
+	let length = decode_integer(&data[0..4])? as usize;
+	if !length.is_multiple_of(2) || data.len() != 4 + length {
+		return None;
+	}
+
+	// Extract the Unicode code points (as u16's), and remove the trailing NUL character(s)
+	let mut buffer16 = data[4..].chunks(2).map(|c| (c[0] as u16) | (c[1] as u16) << 8).collect::<Vec<u16>>();
+	while buffer16.last() == Some(&0) {
+		buffer16.pop();
+	}
+	Some(decode_utf16(buffer16.iter().cloned())
+		.map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+		.collect::<String>())
+}
+
+/// Decodes a 32-bit or 64-bit Little Endian integer.
+/// Returns `None` if the data has any other length.
+pub fn decode_integer(data: &[u8]) -> Option<u64> {
+	match data.len() {
+		4 | 8 => Some(data.iter().rev().fold(0u64, |result, &byte| result << 8 | byte as u64)),
+		_ => None,
+	}
+}
+
+/// Decodes a size, which is either stored as an integer or as a string of digits.
+pub fn decode_size(data: &[u8]) -> Option<u64> {
+	decode_integer(data).or_else(|| decode_string(data).and_then(|string| string.trim().parse::<u64>().ok()))
+}
+
+/// Decodes a timestamp into a UNIX timestamp.
+/// 32-bit integers are UNIX timestamps already; 64-bit integers are interpreted as Windows FILETIMEs (100ns intervals since 1601-01-01).
+pub fn decode_timestamp(data: &[u8]) -> Option<u64> {
+	match data.len() {
+		4 => decode_integer(data),
+		8 => decode_integer(data).and_then(filetime_to_unix),
+		_ => None,
+	}
+}
+
+/// Converts a Windows FILETIME (100ns intervals since 1601-01-01) into a UNIX timestamp.
+/// Returns `None` for timestamps before 1970-01-01.
+pub fn filetime_to_unix(filetime: u64) -> Option<u64> {
+	(filetime / 10_000_000).checked_sub(11_644_473_600)
+}
+
+/// Decodes a string property; if the data is not in the string format, it is rendered as hexadecimal digits instead.
+pub fn decode_text(data: &[u8]) -> String {
+	match decode_string(data) {
+		Some(string) => string,
+		None => data.iter().map(|byte| format!("{:02X}", byte)).collect(),
+	}
+}
+
+/// Formats a UNIX timestamp as ISO 8601 date and time (in UTC), e.g. `2018-03-01T12:00:00Z`.
+pub fn format_timestamp(timestamp: u64) -> String {
+	let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+	let seconds = timestamp % 86400;
+	format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Formats a UNIX timestamp as date and time in the format of RFC 5322 (in UTC), e.g. `Thu, 01 Mar 2018 12:00:00 +0000`.
+pub fn format_rfc5322_timestamp(timestamp: u64) -> String {
+	let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+	let seconds = timestamp % 86400;
+	format!("{}, {:02} {} {:04} {:02}:{:02}:{:02} +0000", WEEKDAYS[(timestamp / 86400 % 7) as usize], day, MONTHS[month as usize - 1], year, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Formats a UNIX timestamp in the format of the C function `asctime` (in UTC), e.g. `Thu Mar  1 12:00:00 2018`, as used by mbox files.
+pub fn format_asctime(timestamp: u64) -> String {
+	let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+	let seconds = timestamp % 86400;
+	format!("{} {} {:2} {:02}:{:02}:{:02} {:04}", WEEKDAYS[(timestamp / 86400 % 7) as usize], MONTHS[month as usize - 1], day, seconds / 3600, seconds / 60 % 60, seconds % 60, year)
+}
+
+/// The abbreviated names of the week days, starting with 1970-01-01 (a Thursday).
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Parses an ISO 8601 date (`2018-03-01`) or date and time (`2018-03-01T12:00:00`, optionally followed by `Z`) in UTC into a UNIX timestamp.
+/// A space may be used instead of the `T`. Returns `None` if the text is not in this format, or before 1970-01-01.
+pub fn parse_timestamp(text: &str) -> Option<u64> {
+	let text = text.trim().trim_end_matches('Z');
+	let (date, time) = match text.find(['T', ' ']) {
+		Some(index) => (&text[..index], Some(&text[index + 1..])),
+		None => (text, None),
+	};
+
+	let date = date.split('-').map(|part| part.parse::<u32>().ok()).collect::<Option<Vec<u32>>>()?;
+	if date.len() != 3 || date[1] < 1 || date[1] > 12 || date[2] < 1 || date[2] > 31 {
+		return None;
+	}
+	let mut seconds = 0;
+	if let Some(time) = time {
+		let time = time.split(':').map(|part| part.parse::<u32>().ok()).collect::<Option<Vec<u32>>>()?;
+		if time.len() < 2 || time.len() > 3 || time[0] > 23 || time[1] > 59 || time.get(2).is_some_and(|&second| second > 60) {
+			return None;
+		}
+		seconds = time[0] as i64 * 3600 + time[1] as i64 * 60 + time.get(2).cloned().unwrap_or(0) as i64;
+	}
+
+	let timestamp = days_from_civil(date[0] as i64, date[1], date[2]) * 86400 + seconds;
+	if timestamp < 0 { None } else { Some(timestamp as u64) }
+}
+
+/// Converts a (year, month, day) date of the proleptic Gregorian calendar into a number of days since 1970-01-01.
+/// Also see: http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = if year >= 0 { year } else { year - 399 } / 400;
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) as i64 + 2) / 5 + day as i64 - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	era * 146097 + day_of_era - 719468
+}
+
+/// Converts a number of days since 1970-01-01 into a (year, month, day) date of the proleptic Gregorian calendar.
+/// Also see: http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let z = days + 719468;
+	let era = if z >= 0 { z } else { z - 146096 } / 146097;
+	let day_of_era = z - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::io::testing::encode_string;
+
+	/// Encodes a string in the Enterprise Vault string format (length prefix, UTF-16, NUL-terminated).
+	#[test]
+	fn decode_strings() {
+		assert_eq!(decode_string(&encode_string("F:\\Data\\Projekte – 2018")), Some("F:\\Data\\Projekte – 2018".to_owned()));
+		assert_eq!(decode_string(&encode_string("")), Some(String::new()));
+		// The length prefix must match the data, and denote whole UTF-16 code units
+		assert_eq!(decode_string(&[4, 0, 0, 0, b'a', 0]), None);
+		assert_eq!(decode_string(&[1, 0, 0, 0, b'a']), None);
+		assert_eq!(decode_string(&[0, 0]), None);
+	}
+
+	#[test]
+	fn decode_integers() {
+		assert_eq!(decode_integer(&[0x78, 0x56, 0x34, 0x12]), Some(0x12345678));
+		assert_eq!(decode_integer(&[1, 0, 0, 0, 0, 0, 0, 1]), Some(0x0100000000000001));
+		assert_eq!(decode_integer(&[1, 2]), None);
+	}
+}
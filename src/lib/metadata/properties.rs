@@ -0,0 +1,316 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use super::decode_string;
+use super::interpret::{Leniency, decode_unicode_text};
+
+/// The decoded content of a property stream, such as `UserXMLStream` or `AgentProperties`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PropertyMap {
+	/// Key/value pairs, in the order in which they are recorded (keys may repeat).
+	Map(Vec<(String, String)>),
+	/// The content of the stream, if its layout is not known.
+	Raw(Vec<u8>),
+}
+
+impl PropertyMap {
+	/// Decodes a property stream. The content is decoded as text (a length-prefixed UTF-16 string, UTF-16 or UTF-8),
+	/// which is then parsed as an XML document or as `key=value` pairs (separated by line breaks or semicolons).
+	/// If neither succeeds, the content is kept raw. Empty content (or blank text) holds no properties, and is never kept raw.
+	pub fn decode(data: &[u8]) -> PropertyMap {
+		if data.is_empty() {
+			return PropertyMap::Map(Vec::new());
+		}
+		let text = decode_string(data).or_else(|| decode_unicode_text(data, Leniency::Strict));
+		match text {
+			Some(ref text) if text.trim().is_empty() => PropertyMap::Map(Vec::new()),
+			_ => match text.and_then(|text| parse_xml(&text).or_else(|| parse_pairs(&text))) {
+				Some(pairs) => PropertyMap::Map(pairs),
+				None => PropertyMap::Raw(data.to_vec()),
+			},
+		}
+	}
+}
+
+/// Parses `key=value` pairs, one per line, or separated by semicolons if there is a single line.
+/// Returns `None` unless every non-empty part is such a pair.
+fn parse_pairs(text: &str) -> Option<Vec<(String, String)>> {
+	let lines = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect::<Vec<&str>>();
+	let parts = match lines.len() {
+		1 => lines[0].split(';').map(|part| part.trim()).filter(|part| !part.is_empty()).collect(),
+		_ => lines,
+	};
+	if parts.is_empty() {
+		return None;
+	}
+	parts.iter().map(|part| {
+		let (key, value) = part.split_once('=')?;
+		let key = key.trim();
+		if key.is_empty() { None } else { Some((key.to_owned(), value.trim().to_owned())) }
+	}).collect()
+}
+
+/// Flattens an XML document into key/value pairs.
+/// The text of an element is keyed by the path of the element beneath the root element (e.g. `Share/Name`), and its attributes by the path followed by `@` and the attribute name.
+/// The text and attributes of the root element itself are keyed by its name.
+/// Elements with a `name` attribute (such as `<Property name="Owner" value="..."/>`) are keyed by that name instead, with their `value` attribute or text as value,
+/// and their other attributes by that name followed by `@` and the attribute name.
+/// Returns `None` if the text is not an XML document.
+fn parse_xml(text: &str) -> Option<Vec<(String, String)>> {
+	let text = text.trim();
+	if !text.starts_with('<') || !text.ends_with('>') {
+		return None;
+	}
+
+	let mut pairs = Vec::new();
+	// The open elements, with their names and whether they are keyed by a `name` attribute
+	let mut stack: Vec<(String, Option<usize>)> = Vec::new();
+	let mut has_root = false;
+	let mut rest = text;
+	while !rest.is_empty() {
+		if !rest.starts_with('<') {
+			// Text content
+			let end = rest.find('<').unwrap_or(rest.len());
+			let content = decode_entities(rest[..end].trim());
+			rest = &rest[end..];
+			if content.is_empty() {
+				continue;
+			}
+			add_text(&mut pairs, &stack, content)?;
+		}
+		else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+			let end = cdata.find("]]>")?;
+			let content = cdata[..end].to_owned();
+			rest = &cdata[end + 3..];
+			add_text(&mut pairs, &stack, content)?;
+		}
+		else if rest.starts_with("<!--") {
+			rest = &rest[rest.find("-->")? + 3..];
+		}
+		else if rest.starts_with("<?") || rest.starts_with("<!") {
+			rest = &rest[rest.find('>')? + 1..];
+		}
+		else if let Some(tag) = rest.strip_prefix("</") {
+			let end = tag.find('>')?;
+			let (name, _) = stack.pop()?;
+			if tag[..end].trim() != name {
+				return None;
+			}
+			rest = &tag[end + 1..];
+		}
+		else {
+			let end = rest.find('>')?;
+			let tag = &rest[1..end];
+			rest = &rest[end + 1..];
+			let (tag, empty) = match tag.strip_suffix('/') {
+				Some(tag) => (tag, true),
+				None => (tag, false),
+			};
+			let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+			let name = &tag[..name_end];
+			if name.is_empty() || (stack.is_empty() && has_root) {
+				return None;
+			}
+			let attributes = parse_attributes(&tag[name_end..])?;
+			has_root = true;
+
+			// Elements that are named by an attribute are recorded as a single pair
+			let named = match stack.is_empty() {
+				true => None,
+				false => attributes.iter().find(|&(key, _)| key.eq_ignore_ascii_case("name")).map(|(_, value)| value.clone()),
+			};
+			let keyed = match named {
+				Some(key) => {
+					let value = attributes.iter().find(|&(key, _)| key.eq_ignore_ascii_case("value")).map(|(_, value)| value.clone()).unwrap_or_default();
+					pairs.push((key.clone(), value));
+					let index = pairs.len() - 1;
+					// Any further attributes are keyed by the name, followed by `@` and the attribute name
+					for (attribute, value) in attributes {
+						if !attribute.eq_ignore_ascii_case("name") && !attribute.eq_ignore_ascii_case("value") {
+							pairs.push((format!("{}@{}", key, attribute), value));
+						}
+					}
+					Some(index)
+				},
+				None => {
+					// The attributes of the root element are keyed by its name, like its text
+					let path = match element_path(&stack, name) {
+						path if path.is_empty() => name.to_owned(),
+						path => path,
+					};
+					for (key, value) in attributes {
+						pairs.push((format!("{}@{}", path, key), value));
+					}
+					None
+				},
+			};
+			if !empty {
+				stack.push((name.to_owned(), keyed));
+			}
+		}
+	}
+	if stack.is_empty() && has_root { Some(pairs) } else { None }
+}
+
+/// Records the text content of the innermost open element.
+/// Returns `None` if there is text outside of the root element.
+fn add_text(pairs: &mut Vec<(String, String)>, stack: &[(String, Option<usize>)], content: String) -> Option<()> {
+	let &(ref name, keyed) = stack.last()?;
+	match keyed {
+		Some(index) if pairs[index].1.is_empty() => pairs[index].1 = content,
+		_ => {
+			// The text of the root element is keyed by its name
+			let path = match element_path(&stack[..stack.len() - 1], name) {
+				path if path.is_empty() => name.clone(),
+				path => path,
+			};
+			pairs.push((path, content));
+		},
+	}
+	Some(())
+}
+
+/// The path of an element beneath the root element, given its open ancestors.
+/// The root element itself has an empty path.
+fn element_path(ancestors: &[(String, Option<usize>)], name: &str) -> String {
+	match ancestors.len() {
+		0 => String::new(),
+		_ => ancestors[1..].iter().map(|(name, _)| name.as_str()).chain(std::iter::once(name)).collect::<Vec<&str>>().join("/"),
+	}
+}
+
+/// Parses the attributes of a start tag (`name="value"` or `name='value'`).
+fn parse_attributes(text: &str) -> Option<Vec<(String, String)>> {
+	let mut attributes = Vec::new();
+	let mut rest = text.trim_start();
+	while !rest.is_empty() {
+		let equals = rest.find('=')?;
+		let name = rest[..equals].trim();
+		let value = rest[equals + 1..].trim_start();
+		let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+		let end = value[1..].find(quote)? + 1;
+		if name.is_empty() {
+			return None;
+		}
+		attributes.push((name.to_owned(), decode_entities(&value[1..end])));
+		rest = value[end + 1..].trim_start();
+	}
+	Some(attributes)
+}
+
+/// Replaces the predefined XML entities and character references.
+/// Unknown entities are kept as they are.
+fn decode_entities(text: &str) -> String {
+	let mut result = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('&') {
+		result.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let decoded = rest.find(';').and_then(|end| {
+			let c = match &rest[1..end] {
+				"lt" => '<',
+				"gt" => '>',
+				"amp" => '&',
+				"quot" => '"',
+				"apos" => '\'',
+				entity => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+					Some(hex) => std::char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+					None => std::char::from_u32(entity.strip_prefix('#')?.parse::<u32>().ok()?)?,
+				},
+			};
+			Some((c, end))
+		});
+		match decoded {
+			Some((c, end)) => {
+				result.push(c);
+				rest = &rest[end + 1..];
+			},
+			None => {
+				result.push('&');
+				rest = &rest[1..];
+			},
+		}
+	}
+	result.push_str(rest);
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::super::io::testing::utf16;
+
+	fn pairs(pairs: &[(&str, &str)]) -> PropertyMap {
+		PropertyMap::Map(pairs.iter().map(|&(key, value)| (key.to_owned(), value.to_owned())).collect())
+	}
+
+	#[test]
+	fn decode_pairs() {
+		assert_eq!(PropertyMap::decode(b"Owner=DOMAIN\\jdoe\r\nShare = Projects\n\nEmpty="), pairs(&[("Owner", "DOMAIN\\jdoe"), ("Share", "Projects"), ("Empty", "")]));
+		assert_eq!(PropertyMap::decode(b"a=1; b=2;a=3"), pairs(&[("a", "1"), ("b", "2"), ("a", "3")]));
+		// Values may contain further equal signs
+		assert_eq!(PropertyMap::decode(b"query=x=y"), pairs(&[("query", "x=y")]));
+		// Every part must be a pair with a key
+		assert_eq!(PropertyMap::decode(b"a=1\nno pair"), PropertyMap::Raw(b"a=1\nno pair".to_vec()));
+		assert_eq!(PropertyMap::decode(b"=1"), PropertyMap::Raw(b"=1".to_vec()));
+	}
+
+	#[test]
+	fn decode_xml() {
+		let xml = "<?xml version=\"1.0\"?><!-- agent --><Props version='2'><Share><Name>Projects &amp; more</Name></Share>\
+			<Property name=\"Owner\" value=\"jdoe\" type=\"string\"/><Property Name=\"Note\"><![CDATA[<raw>]]></Property><Code>&#x41;&#66;&unknown;</Code></Props>";
+		assert_eq!(PropertyMap::decode(xml.as_bytes()), pairs(&[
+			("Props@version", "2"),
+			("Share/Name", "Projects & more"),
+			("Owner", "jdoe"),
+			("Owner@type", "string"),
+			("Note", "<raw>"),
+			("Code", "AB&unknown;"),
+		]));
+		// The text of the root element is keyed by its name
+		assert_eq!(PropertyMap::decode(b"<Value>42</Value>"), pairs(&[("Value", "42")]));
+	}
+
+	#[test]
+	fn reject_malformed_xml() {
+		for xml in &["<a><b></a></b>", "<a>", "<a/><b/>", "<a x=1/>", "<a>text</a>trailing"] {
+			assert_eq!(parse_xml(xml), None, "{}", xml);
+		}
+	}
+
+	#[test]
+	fn decode_encodings() {
+		// A length-prefixed UTF-16 string, as written by Enterprise Vault
+		let mut data = ((utf16("a=1;b=2").len() + 2) as u32).to_le_bytes().to_vec();
+		data.extend(utf16("a=1;b=2"));
+		data.extend(&[0, 0]);
+		assert_eq!(PropertyMap::decode(&data), pairs(&[("a", "1"), ("b", "2")]));
+		// UTF-16 with a byte order mark, and UTF-8 with a byte order mark
+		assert_eq!(PropertyMap::decode(&utf16("\u{FEFF}<P k=\"\u{e9}\"/>")), pairs(&[("P@k", "\u{e9}")]));
+		assert_eq!(PropertyMap::decode("\u{FEFF}k=\u{e9}".as_bytes()), pairs(&[("k", "\u{e9}")]));
+		// Binary content is kept raw
+		assert_eq!(PropertyMap::decode(&[0x01, 0xFF, 0x80]), PropertyMap::Raw(vec![0x01, 0xFF, 0x80]));
+	}
+
+	#[test]
+	fn decode_empty_content() {
+		assert_eq!(PropertyMap::decode(b""), pairs(&[]));
+		assert_eq!(PropertyMap::decode(b" \r\n"), pairs(&[]));
+		assert_eq!(PropertyMap::decode(&[0, 0, 0, 0]), pairs(&[]));
+	}
+}
@@ -16,6 +16,23 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
+pub mod cab;
+pub mod catalogue;
 pub mod cfbf;
+pub mod dvf;
 pub mod dvs;
+pub mod embedded;
+pub mod exchange;
 pub mod io;
+pub mod journal;
+pub mod lookup;
+pub mod metadata;
+pub mod msg;
+pub mod propset;
+pub mod reconcile;
+pub mod reparse;
+pub mod restore;
+pub mod sis;
+pub mod sniff;
+pub mod vba;
+pub mod verify;
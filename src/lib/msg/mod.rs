@@ -0,0 +1,527 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+pub mod properties;
+
+use std::io::{Read, Seek, Write, Error};
+use std::collections::BTreeMap;
+use super::cfbf::{Container, Object, ObjectType};
+use super::exchange::mime::{Attachment as MimeAttachment, MessageBuilder, format_mailbox};
+use super::exchange::parse_headers;
+use super::io::{Debug, SeekableRead};
+use super::metadata::format_rfc5322_timestamp;
+use self::properties::{Properties, PropertyValue, MV_FLAG, PT_OBJECT};
+
+/// An Outlook message (`.msg` file, MS-OXMSG): a CFBF file that holds the MAPI properties of the message,
+/// with one storage per recipient (`__recip_version1.0_#XXXXXXXX`) and per attachment (`__attach_version1.0_#XXXXXXXX`).
+///
+/// Fixed-length properties are stored in the `__properties_version1.0` stream; all others are stored in streams
+/// named `__substg1.0_XXXXYYYY`, where `XXXX` is the property ID and `YYYY` the property type.
+pub struct Message {
+	pub properties: Properties,
+	pub recipients: Vec<Recipient>,
+	pub attachments: Vec<Attachment>,
+}
+
+pub struct Recipient {
+	pub properties: Properties,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum RecipientKind {
+	To,
+	Cc,
+	Bcc,
+	/// Any other value of `PidTagRecipientType`, e.g. the originator.
+	Other,
+}
+
+pub struct Attachment {
+	pub properties: Properties,
+	/// The content of the attachment (`PidTagAttachDataBinary`), if it is a file.
+	pub data: Option<Vec<u8>>,
+	/// The attached message, if the attachment is an embedded message (`PidTagAttachDataObject`).
+	pub embedded_message: Option<Box<Message>>,
+}
+
+/// The maximum nesting depth of embedded messages.
+const MAX_DEPTH: usize = 8;
+
+/// The headers that are kept from the original transport headers of a message.
+/// All others (in particular, those that describe the original MIME structure) are dropped, since the body is rebuilt.
+const KEPT_HEADERS: [&str; 10] = ["Date", "From", "Sender", "Reply-To", "To", "Cc", "Subject", "Message-ID", "In-Reply-To", "References"];
+
+/// Determines whether the data is an Outlook message, i.e. a CFBF file with MAPI property streams.
+pub fn is_msg(data: &[u8], debug: &mut Debug) -> bool {
+	if !data.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+		return false;
+	}
+	let mut container = match SeekableRead::new(data).and_then(|input| Container::new(input, debug)) {
+		Ok(container) => container,
+		Err(_) => return false,
+	};
+	is_msg_container(&mut container, debug)
+}
+
+/// Determines whether a CFBF container holds an Outlook message.
+pub fn is_msg_container<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> bool where TFile: Read + Seek {
+	let root = match container.get_root_object(debug) {
+		Ok(root) => root,
+		Err(_) => return false,
+	};
+	container.get_children(&root, debug)
+		.map(|children| children.iter().any(|child| child.name.starts_with("__substg1.0_") || child.name == "__properties_version1.0"))
+		.unwrap_or(false)
+}
+
+impl Message {
+	/// Reads the message from the root storage of a container.
+	pub fn read<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Message, Error> where TFile: Read + Seek {
+		let root = container.get_root_object(debug)?;
+		// The header of the property stream is 32 bytes long for the top-level message
+		Message::read_storage(container, &root, 32, 0, debug)
+	}
+
+	fn read_storage<TFile>(container: &mut Container<TFile>, storage: &Object, header_size: usize, depth: usize, debug: &mut Debug) -> Result<Message, Error> where TFile: Read + Seek {
+		let properties = read_properties(container, storage, header_size, debug)?;
+		let mut recipients = Vec::new();
+		let mut attachments = Vec::new();
+		for child in container.get_children(storage, debug)? {
+			if child.object_type != ObjectType::Storage {
+				continue;
+			}
+			if child.name.starts_with("__recip_version1.0_") {
+				debug.logln(1, format!("[Message::read_storage] Reading recipient '{}' ...", child.name));
+				recipients.push(Recipient { properties: read_properties(container, &child, 8, debug)? });
+			}
+			else if child.name.starts_with("__attach_version1.0_") {
+				debug.logln(1, format!("[Message::read_storage] Reading attachment '{}' ...", child.name));
+				attachments.push(Attachment::read(container, &child, depth, debug)?);
+			}
+		}
+		Ok(Message { properties, recipients, attachments })
+	}
+
+	/// `PidTagSubject`
+	pub fn subject(&self) -> Option<&str> {
+		self.properties.get_string(0x0037)
+	}
+
+	/// `PidTagSenderName`, or else `PidTagSentRepresentingName`.
+	pub fn sender_name(&self) -> Option<&str> {
+		self.properties.get_string(0x0C1A).or_else(|| self.properties.get_string(0x0042))
+	}
+
+	/// The SMTP address of the sender (`PidTagSenderSmtpAddress`, or any sender address that looks like one).
+	pub fn sender_address(&self) -> Option<&str> {
+		[0x5D01, 0x0C1F, 0x5D02, 0x0065].iter()
+			.filter_map(|&id| self.properties.get_string(id))
+			.find(|address| address.contains('@'))
+	}
+
+	/// The time when the message was sent (`PidTagClientSubmitTime`) or else delivered (`PidTagMessageDeliveryTime`), as a UNIX timestamp.
+	pub fn date(&self) -> Option<u64> {
+		self.properties.get_time(0x0039).or_else(|| self.properties.get_time(0x0E06))
+	}
+
+	/// `PidTagBody`
+	pub fn body(&self) -> Option<&str> {
+		self.properties.get_string(0x1000)
+	}
+
+	/// `PidTagBodyHtml`, which is usually stored as binary data.
+	pub fn html_body(&self) -> Option<String> {
+		match self.properties.get(0x1013) {
+			Some(PropertyValue::String(html)) => Some(html.clone()),
+			Some(PropertyValue::Binary(html)) => Some(String::from_utf8_lossy(html).trim_end_matches('\0').to_owned()),
+			_ => None,
+		}
+	}
+
+	/// `PidTagTransportMessageHeaders`: the original Internet headers of a received message.
+	pub fn transport_headers(&self) -> Option<&str> {
+		self.properties.get_string(0x007D)
+	}
+
+	/// Converts the message into the Internet Message Format (RFC 5322).
+	/// Headers are taken from the original transport headers (if recorded), and otherwise from the MAPI properties.
+	/// Embedded messages are attached as `message/rfc822`.
+	pub fn to_builder(&self) -> Result<MessageBuilder, Error> {
+		let mut builder = MessageBuilder::new();
+		if let Some(transport_headers) = self.transport_headers() {
+			for (name, value) in parse_headers(transport_headers) {
+				if KEPT_HEADERS.iter().any(|kept| kept.eq_ignore_ascii_case(&name)) {
+					builder.header(&name, &value);
+				}
+			}
+		}
+		if !builder.has_header("Date") {
+			if let Some(date) = self.date() {
+				builder.header("Date", &format_rfc5322_timestamp(date));
+			}
+		}
+		if !builder.has_header("From") {
+			if let Some(from) = format_mailbox(self.sender_name(), self.sender_address()) {
+				builder.header("From", &from);
+			}
+		}
+		for &(header, kind, display_id) in &[("To", RecipientKind::To, 0x0E04), ("Cc", RecipientKind::Cc, 0x0E03), ("Bcc", RecipientKind::Bcc, 0x0E02)] {
+			if builder.has_header(header) {
+				continue;
+			}
+			let mailboxes = self.recipients.iter()
+				.filter(|recipient| recipient.kind() == kind)
+				.filter_map(|recipient| format_mailbox(recipient.name(), recipient.address()))
+				.collect::<Vec<String>>();
+			match mailboxes.is_empty() {
+				true => builder.header(header, self.properties.get_string(display_id).unwrap_or("")),
+				false => builder.header(header, &mailboxes.join(", ")),
+			};
+		}
+		if !builder.has_header("Subject") {
+			builder.header("Subject", self.subject().unwrap_or(""));
+		}
+		for &(header, id) in &[("Message-ID", 0x1035), ("In-Reply-To", 0x1042), ("References", 0x1039)] {
+			if !builder.has_header(header) {
+				builder.header(header, self.properties.get_string(id).unwrap_or(""));
+			}
+		}
+
+		if let Some(body) = self.body() {
+			builder.text(body);
+		}
+		if let Some(html) = self.html_body() {
+			builder.html(&html);
+		}
+		for attachment in &self.attachments {
+			if let Some(ref data) = attachment.data {
+				builder.attachment(MimeAttachment {
+					filename: attachment.filename().map(|filename| filename.to_owned()),
+					content_type: attachment.mime_type().map(|mime_type| mime_type.to_owned()),
+					content_id: attachment.content_id().map(|content_id| content_id.to_owned()),
+					data: data.clone(),
+				});
+			}
+			else if let Some(ref message) = attachment.embedded_message {
+				let mut data: Vec<u8> = Vec::new();
+				message.write_eml(&mut data)?;
+				let filename = attachment.filename().or(message.subject()).unwrap_or("message");
+				builder.attachment(MimeAttachment {
+					filename: Some(format!("{}.eml", filename.trim_end_matches(".msg"))),
+					content_type: Some("message/rfc822".to_owned()),
+					content_id: None,
+					data,
+				});
+			}
+		}
+		Ok(builder)
+	}
+
+	/// Writes the message in the Internet Message Format (RFC 5322) to the output.
+	pub fn write_eml(&self, output: &mut dyn Write) -> Result<(), Error> {
+		self.to_builder()?.write(output)
+	}
+}
+
+impl Recipient {
+	/// `PidTagRecipientType`
+	pub fn kind(&self) -> RecipientKind {
+		match self.properties.get_integer(0x0C15).map(|kind| kind & 0x0F) {
+			Some(1) => RecipientKind::To,
+			Some(2) => RecipientKind::Cc,
+			Some(3) => RecipientKind::Bcc,
+			_ => RecipientKind::Other,
+		}
+	}
+
+	/// `PidTagDisplayName`
+	pub fn name(&self) -> Option<&str> {
+		self.properties.get_string(0x3001)
+	}
+
+	/// The SMTP address of the recipient (`PidTagSmtpAddress`, or else `PidTagEmailAddress` if it looks like one).
+	pub fn address(&self) -> Option<&str> {
+		self.properties.get_string(0x39FE)
+			.or_else(|| self.properties.get_string(0x3003).filter(|address| address.contains('@')))
+	}
+}
+
+impl RecipientKind {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			RecipientKind::To => "To",
+			RecipientKind::Cc => "Cc",
+			RecipientKind::Bcc => "Bcc",
+			RecipientKind::Other => "Other",
+		}
+	}
+}
+
+impl Attachment {
+	fn read<TFile>(container: &mut Container<TFile>, storage: &Object, depth: usize, debug: &mut Debug) -> Result<Attachment, Error> where TFile: Read + Seek {
+		let mut properties = read_properties(container, storage, 8, debug)?;
+		let data = match properties.remove(0x3701) {
+			Some(PropertyValue::Binary(data)) => Some(data),
+			Some(value) => {
+				properties.insert(0x3701, PT_OBJECT, value);
+				None
+			},
+			None => None,
+		};
+
+		// An embedded message is stored in the storage '__substg1.0_3701000D', with a property stream header of 24 bytes
+		let mut embedded_message = None;
+		for child in container.get_children(storage, debug)? {
+			if child.object_type == ObjectType::Storage && child.name == "__substg1.0_3701000D" {
+				let children = container.get_children(&child, debug)?;
+				if depth < MAX_DEPTH && children.iter().any(|child| child.name == "__properties_version1.0" || child.name.starts_with("__substg1.0_")) {
+					debug.logln(1, "[Attachment::read] Reading embedded message ...");
+					embedded_message = Some(Box::new(Message::read_storage(container, &child, 24, depth + 1, debug)?));
+				}
+			}
+		}
+		Ok(Attachment { properties, data, embedded_message })
+	}
+
+	/// `PidTagAttachLongFilename`, or else `PidTagAttachFilename` or `PidTagDisplayName`.
+	pub fn filename(&self) -> Option<&str> {
+		self.properties.get_string(0x3707)
+			.or_else(|| self.properties.get_string(0x3704))
+			.or_else(|| self.properties.get_string(0x3001))
+	}
+
+	/// `PidTagAttachMimeTag`
+	pub fn mime_type(&self) -> Option<&str> {
+		self.properties.get_string(0x370E)
+	}
+
+	/// `PidTagAttachContentId`
+	pub fn content_id(&self) -> Option<&str> {
+		self.properties.get_string(0x3712)
+	}
+}
+
+/// Reads all properties of a storage: the fixed-length ones from the `__properties_version1.0` stream (after a header of the given size),
+/// and all others from the `__substg1.0_XXXXYYYY` streams.
+fn read_properties<TFile>(container: &mut Container<TFile>, storage: &Object, header_size: usize, debug: &mut Debug) -> Result<Properties, Error> where TFile: Read + Seek {
+	let mut properties = Properties::new();
+	// The elements of variable-length multi-valued properties, by property ID and type, and by index
+	let mut elements: BTreeMap<(u16, u16), BTreeMap<u32, PropertyValue>> = BTreeMap::new();
+
+	for child in container.get_children(storage, debug)? {
+		if child.object_type == ObjectType::Storage {
+			if let Some((id, property_type, None)) = parse_stream_name(&child.name) {
+				properties.insert(id, property_type, PropertyValue::Object);
+			}
+			continue;
+		}
+		if child.object_type != ObjectType::Stream {
+			continue;
+		}
+
+		if child.name == "__properties_version1.0" {
+			let mut data: Vec<u8> = Vec::new();
+			container.dump_stream(&child, &mut data, debug)?;
+			for entry in data.get(header_size..).unwrap_or(&[]).chunks(16).filter(|entry| entry.len() == 16) {
+				let property_type = (entry[0] as u16) | (entry[1] as u16) << 8;
+				let id = (entry[2] as u16) | (entry[3] as u16) << 8;
+				// Variable-length properties only record their size here
+				match PropertyValue::fixed_size(property_type) {
+					Some(size) if size <= 8 && property_type & MV_FLAG == 0 => {
+						if let Some(value) = PropertyValue::decode(property_type, &entry[8..16]) {
+							properties.insert(id, property_type, value);
+						}
+					},
+					_ => {},
+				}
+			}
+			continue;
+		}
+
+		let (id, property_type, index) = match parse_stream_name(&child.name) {
+			Some(property) => property,
+			None => continue,
+		};
+		let mut data: Vec<u8> = Vec::new();
+		container.dump_stream(&child, &mut data, debug)?;
+		match (index, property_type & MV_FLAG != 0, PropertyValue::fixed_size(property_type)) {
+			// An element of a variable-length multi-valued property
+			(Some(index), _, _) => {
+				if let Some(value) = PropertyValue::decode(property_type & !MV_FLAG, &data) {
+					elements.entry((id, property_type)).or_default().insert(index, value);
+				}
+			},
+			// A fixed-length multi-valued property: all elements in a row
+			(None, true, Some(size)) => {
+				let values = data.chunks(size)
+					.filter_map(|chunk| PropertyValue::decode(property_type & !MV_FLAG, chunk))
+					.collect::<Vec<PropertyValue>>();
+				properties.insert(id, property_type, PropertyValue::Multiple(values));
+			},
+			// The lengths of the elements of a variable-length multi-valued property
+			(None, true, None) => {
+				elements.entry((id, property_type)).or_default();
+			},
+			(None, false, _) => {
+				if let Some(value) = PropertyValue::decode(property_type, &data) {
+					properties.insert(id, property_type, value);
+				}
+			},
+		}
+	}
+
+	for ((id, property_type), values) in elements {
+		properties.insert(id, property_type, PropertyValue::Multiple(values.into_values().collect()));
+	}
+	debug.logln(2, format!("[read_properties] Read {} properties from '{}'.", properties.len(), storage.name));
+	Ok(properties)
+}
+
+/// Parses the name of a property stream (`__substg1.0_XXXXYYYY`, optionally followed by `-NNNNNNNN` for elements of multi-valued properties)
+/// into the property ID (`XXXX`), the property type (`YYYY`) and the index of the element (`NNNNNNNN`).
+fn parse_stream_name(name: &str) -> Option<(u16, u16, Option<u32>)> {
+	let is_hex = |digits: &[u8]| !digits.is_empty() && digits.iter().all(u8::is_ascii_hexdigit);
+	if !name.starts_with("__substg1.0_") || name.len() < 20 || !is_hex(&name.as_bytes()[12..20]) {
+		return None;
+	}
+	let id = u16::from_str_radix(&name[12..16], 16).ok()?;
+	let property_type = u16::from_str_radix(&name[16..20], 16).ok()?;
+	let index = match &name[20..] {
+		"" => None,
+		suffix if suffix.starts_with('-') && is_hex(&suffix.as_bytes()[1..]) => Some(u32::from_str_radix(&suffix[1..], 16).ok()?),
+		_ => return None,
+	};
+	Some((id, property_type, index))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::stderr;
+	use super::super::cfbf::testing::build_cfbf;
+	use super::super::io::testing::utf16z;
+	use super::properties::{PT_LONG, PT_SYSTIME};
+	use super::*;
+
+	/// A property stream with a header of the given size, and the given fixed-length properties.
+	fn property_stream(header_size: usize, properties: &[(u16, u16, u64)]) -> Vec<u8> {
+		let mut data = vec![0; header_size];
+		for &(id, property_type, value) in properties {
+			data.extend(&property_type.to_le_bytes());
+			data.extend(&id.to_le_bytes());
+			data.extend(&[6, 0, 0, 0]);
+			data.extend(&value.to_le_bytes());
+		}
+		data
+	}
+
+	#[test]
+	fn parse_stream_names() {
+		assert_eq!(parse_stream_name("__substg1.0_0037001F"), Some((0x0037, 0x001F, None)));
+		assert_eq!(parse_stream_name("__substg1.0_8000101F-0000000a"), Some((0x8000, 0x101F, Some(10))));
+		assert_eq!(parse_stream_name("__substg1.0_0037001"), None);
+		assert_eq!(parse_stream_name("__substg1.0_+037001F"), None);
+		assert_eq!(parse_stream_name("__substg1.0_8000101F-+1"), None);
+		assert_eq!(parse_stream_name("__substg1.0_8000101F_1"), None);
+		assert_eq!(parse_stream_name("__substg1.0_8000101F-"), None);
+		// Multi-byte characters within the property ID and type must not panic
+		assert_eq!(parse_stream_name("__substg1.0_003\u{e9}001F"), None);
+		assert_eq!(parse_stream_name("__substg1.0_0037\u{20ac}01F"), None);
+	}
+
+	#[test]
+	fn decode_property_values() {
+		let decode = |property_type: u16, data: &[u8]| PropertyValue::decode(property_type, data).map(|value| value.to_string());
+		assert_eq!(decode(0x0002, &[0xFE, 0xFF]), Some("-2".to_owned()));
+		assert_eq!(decode(0x0003, &[1, 0]), None);
+		assert_eq!(decode(0x000B, &[1, 0, 0, 0]), Some("true".to_owned()));
+		assert_eq!(decode(0x0005, &1.5f64.to_bits().to_le_bytes()), Some("1.5".to_owned()));
+		assert_eq!(decode(0x0040, &116444736000000000u64.to_le_bytes()), Some("1970-01-01T00:00:00Z".to_owned()));
+		assert_eq!(decode(0x001E, b"Caf\xe9\0junk"), Some("\"Caf\u{e9}\"".to_owned()));
+		assert_eq!(decode(0x001F, &utf16z("Gr\u{fc}\u{df}e")), Some("\"Gr\u{fc}\u{df}e\"".to_owned()));
+		assert_eq!(decode(0x0048, &[0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]),
+			Some("{00112233-4455-6677-8899-AABBCCDDEEFF}".to_owned()));
+		assert_eq!(decode(0x0102, &[1, 2]), Some("2 bytes: 01 02".to_owned()));
+		assert_eq!(decode(0x0999, &[]), None);
+	}
+
+	#[test]
+	fn read_messages() {
+		let (subject, body, name, address, filename, keyword1, keyword2) = (utf16z("Status"), utf16z("All fine."), utf16z("Doe, John"), utf16z("jdoe@example.com"), utf16z("report.txt"), utf16z("a"), utf16z("b"));
+		let headers = utf16z("Received: from mx\r\nMessage-ID: <1@example.com>\r\nContent-Type: text/html\r\n\r\n");
+		// 2018-03-01T12:00:00Z as FILETIME
+		let date = (1519905600u64 + 11644473600) * 10000000;
+		let data = build_cfbf(&[
+			("__properties_version1.0", &property_stream(32, &[(0x0039, PT_SYSTIME, date)])),
+			("__substg1.0_0037001F", &subject),
+			("__substg1.0_1000001F", &body),
+			("__substg1.0_007D001F", &headers),
+			("__substg1.0_8000101F", &[4, 0, 0, 0, 4, 0, 0, 0]),
+			("__substg1.0_8000101F-00000001", &keyword2),
+			("__substg1.0_8000101F-00000000", &keyword1),
+			("__recip_version1.0_#00000000/__properties_version1.0", &property_stream(8, &[(0x0C15, PT_LONG, 2)])),
+			("__recip_version1.0_#00000000/__substg1.0_3001001F", &name),
+			("__recip_version1.0_#00000000/__substg1.0_39FE001F", &address),
+			("__attach_version1.0_#00000000/__substg1.0_3707001F", &filename),
+			("__attach_version1.0_#00000000/__substg1.0_37010102", b"content"),
+			// A forwarded message with a plain text and an HTML body
+			("__attach_version1.0_#00000001/__substg1.0_3701000D/__properties_version1.0", &property_stream(24, &[])),
+			("__attach_version1.0_#00000001/__substg1.0_3701000D/__substg1.0_0037001F", &utf16z("Forwarded")),
+			("__attach_version1.0_#00000001/__substg1.0_3701000D/__substg1.0_1000001F", &body),
+			("__attach_version1.0_#00000001/__substg1.0_3701000D/__substg1.0_10130102", b"<p>All fine.</p>"),
+		]);
+		let mut debug = Debug::new(stderr(), -1);
+		assert!(is_msg(&data, &mut debug));
+		assert!(!is_msg(&build_cfbf(&[("WordDocument", b"")]), &mut debug));
+		assert!(!is_msg(b"Subject: no", &mut debug));
+
+		let mut container = Container::new(SeekableRead::new(&data[..]).unwrap(), &mut debug).unwrap();
+		let message = Message::read(&mut container, &mut debug).unwrap();
+		assert_eq!(message.subject(), Some("Status"));
+		assert_eq!(message.date(), Some(1519905600));
+		assert_eq!(message.properties.get(0x8000).map(|value| value.to_string()), Some("[\"a\", \"b\"]".to_owned()));
+		assert!(message.recipients[0].kind() == RecipientKind::Cc);
+		assert_eq!(message.attachments[0].filename(), Some("report.txt"));
+		assert_eq!(message.attachments[0].data, Some(b"content".to_vec()));
+
+		let mut eml = Vec::new();
+		message.write_eml(&mut eml).unwrap();
+		let eml = String::from_utf8(eml).unwrap();
+		// Only the kept transport headers are taken over; the others are rebuilt from the properties
+		assert!(eml.starts_with("Message-ID: <1@example.com>\r\nDate: Thu, 01 Mar 2018 12:00:00 +0000\r\nCc: \"Doe, John\" <jdoe@example.com>\r\nSubject: Status\r\nMIME-Version: 1.0\r\n"));
+		let (head, _) = eml.split_at(eml.find("\r\n\r\n").unwrap());
+		assert!(!eml.contains("Received:") && !head.contains("text/html"));
+		assert!(eml.contains("\r\n\r\nAll fine.\r\n"));
+		assert!(eml.contains("filename=\"report.txt\"\r\nContent-Transfer-Encoding: base64\r\n\r\nY29udGVudA==\r\n"));
+		assert!(message.attachments[1].embedded_message.is_some());
+		assert!(eml.contains("Content-Type: message/rfc822; name=\"Forwarded.eml\"\r\n"));
+
+		// The boundaries of the forwarded message must not end the parts of the outer message early
+		let boundary_of = |text: &str, content_type: &str| {
+			let start = text.find(&format!("Content-Type: {}; boundary=\"", content_type)).unwrap() + content_type.len() + 26;
+			text[start..start + text[start..].find('"').unwrap()].to_owned()
+		};
+		let outer = boundary_of(&eml, "multipart/mixed");
+		let inner = boundary_of(&eml[eml.find("message/rfc822").unwrap()..], "multipart/alternative");
+		assert!(!inner.starts_with(&outer) && !outer.starts_with(&inner));
+		let delimiters = eml.lines().filter(|line| line.starts_with(&format!("--{}", outer))).collect::<Vec<&str>>();
+		assert_eq!(delimiters.len(), 4);
+		assert_eq!(delimiters[3], format!("--{}--", outer));
+		assert!(eml.ends_with(&format!("--{}--\r\n", outer)));
+		assert_eq!(eml.matches(&format!("--{}--", inner)).count(), 1);
+	}
+}
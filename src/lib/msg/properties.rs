@@ -0,0 +1,304 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+use std::fmt;
+use super::super::io::{decode_utf16le, format_guid};
+use super::super::metadata::{filetime_to_unix, format_timestamp};
+
+/// The property types of MAPI (MS-OXCDATA, section 2.11.1).
+pub const PT_SHORT: u16 = 0x0002;
+pub const PT_LONG: u16 = 0x0003;
+pub const PT_FLOAT: u16 = 0x0004;
+pub const PT_DOUBLE: u16 = 0x0005;
+pub const PT_CURRENCY: u16 = 0x0006;
+pub const PT_APPTIME: u16 = 0x0007;
+pub const PT_ERROR: u16 = 0x000A;
+pub const PT_BOOLEAN: u16 = 0x000B;
+pub const PT_OBJECT: u16 = 0x000D;
+pub const PT_LONGLONG: u16 = 0x0014;
+pub const PT_STRING8: u16 = 0x001E;
+pub const PT_UNICODE: u16 = 0x001F;
+pub const PT_SYSTIME: u16 = 0x0040;
+pub const PT_CLSID: u16 = 0x0048;
+pub const PT_BINARY: u16 = 0x0102;
+/// The flag that marks multi-valued property types.
+pub const MV_FLAG: u16 = 0x1000;
+
+/// The value of a MAPI property.
+#[derive(Clone)]
+pub enum PropertyValue {
+	/// `PT_SHORT`, `PT_LONG`, `PT_LONGLONG`, `PT_CURRENCY` (in units of 1/10000) and `PT_ERROR`.
+	Integer(i64),
+	/// `PT_FLOAT`, `PT_DOUBLE` and `PT_APPTIME`.
+	Float(f64),
+	Boolean(bool),
+	/// `PT_SYSTIME`, as a Windows FILETIME (100ns intervals since 1601-01-01).
+	Time(u64),
+	/// `PT_STRING8` and `PT_UNICODE`.
+	String(String),
+	Binary(Vec<u8>),
+	Guid([u8; 16]),
+	/// A multi-valued property.
+	Multiple(Vec<PropertyValue>),
+	/// `PT_OBJECT`: the value is a storage (such as an embedded message), which is not decoded here.
+	Object,
+}
+
+impl PropertyValue {
+	/// Decodes a value of the given (single-valued) type from its binary representation.
+	/// Returns `None` if the type is unknown or the data is too short.
+	pub fn decode(property_type: u16, data: &[u8]) -> Option<PropertyValue> {
+		let integer = |length: usize| match data.len() >= length {
+			true => Some(data[..length].iter().rev().fold(0u64, |result, &byte| result << 8 | byte as u64)),
+			false => None,
+		};
+		match property_type {
+			PT_SHORT => integer(2).map(|value| PropertyValue::Integer(value as u16 as i16 as i64)),
+			PT_LONG | PT_ERROR => integer(4).map(|value| PropertyValue::Integer(value as u32 as i32 as i64)),
+			PT_LONGLONG | PT_CURRENCY => integer(8).map(|value| PropertyValue::Integer(value as i64)),
+			PT_FLOAT => integer(4).map(|value| PropertyValue::Float(f32::from_bits(value as u32) as f64)),
+			PT_DOUBLE | PT_APPTIME => integer(8).map(|value| PropertyValue::Float(f64::from_bits(value))),
+			PT_BOOLEAN => integer(2).map(|value| PropertyValue::Boolean(value != 0)),
+			PT_SYSTIME => integer(8).map(PropertyValue::Time),
+			PT_CLSID if data.len() >= 16 => {
+				let mut guid = [0u8; 16];
+				guid.copy_from_slice(&data[..16]);
+				Some(PropertyValue::Guid(guid))
+			},
+			PT_STRING8 => Some(PropertyValue::String(decode_string8(data))),
+			PT_UNICODE => Some(PropertyValue::String(decode_utf16le(data))),
+			PT_BINARY => Some(PropertyValue::Binary(data.to_vec())),
+			PT_OBJECT => Some(PropertyValue::Object),
+			_ => None,
+		}
+	}
+
+	/// The size of a single value of the given fixed-length type, or `None` for variable-length types.
+	pub fn fixed_size(property_type: u16) -> Option<usize> {
+		match property_type & !MV_FLAG {
+			PT_SHORT | PT_BOOLEAN => Some(2),
+			PT_LONG | PT_FLOAT | PT_ERROR => Some(4),
+			PT_DOUBLE | PT_CURRENCY | PT_APPTIME | PT_LONGLONG | PT_SYSTIME => Some(8),
+			PT_CLSID => Some(16),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for PropertyValue {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			PropertyValue::Integer(value) => write!(f, "{}", value),
+			PropertyValue::Float(value) => write!(f, "{}", value),
+			PropertyValue::Boolean(value) => write!(f, "{}", value),
+			PropertyValue::Time(value) => match filetime_to_unix(value) {
+				Some(timestamp) => write!(f, "{}", format_timestamp(timestamp)),
+				None => write!(f, "FILETIME {}", value),
+			},
+			PropertyValue::String(ref value) => write!(f, "{:?}", value),
+			PropertyValue::Binary(ref value) => {
+				write!(f, "{} bytes:", value.len())?;
+				for byte in value.iter().take(32) {
+					write!(f, " {:02X}", byte)?;
+				}
+				if value.len() > 32 {
+					write!(f, " ...")?;
+				}
+				Ok(())
+			},
+			PropertyValue::Guid(ref guid) => write!(f, "{}", format_guid(guid)),
+			PropertyValue::Multiple(ref values) => {
+				write!(f, "[")?;
+				for (index, value) in values.iter().enumerate() {
+					if index > 0 {
+						write!(f, ", ")?;
+					}
+					write!(f, "{}", value)?;
+				}
+				write!(f, "]")
+			},
+			PropertyValue::Object => write!(f, "(object)"),
+		}
+	}
+}
+
+/// Decodes an 8-bit string: UTF-8 if it is valid UTF-8, or else Windows-1252 (approximated by ISO 8859-1).
+fn decode_string8(data: &[u8]) -> String {
+	let data = match data.iter().position(|&byte| byte == 0) {
+		Some(end) => &data[..end],
+		None => data,
+	};
+	match String::from_utf8(data.to_vec()) {
+		Ok(string) => string,
+		Err(_) => data.iter().map(|&byte| byte as char).collect(),
+	}
+}
+
+/// The properties of a message, recipient or attachment, by property ID.
+#[derive(Clone, Default)]
+pub struct Properties {
+	values: BTreeMap<u16, (u16, PropertyValue)>,
+}
+
+impl Properties {
+	pub fn new() -> Properties {
+		Properties::default()
+	}
+
+	pub fn insert(&mut self, id: u16, property_type: u16, value: PropertyValue) {
+		self.values.insert(id, (property_type, value));
+	}
+
+	pub fn get(&self, id: u16) -> Option<&PropertyValue> {
+		self.values.get(&id).map(|(_, value)| value)
+	}
+
+	pub fn remove(&mut self, id: u16) -> Option<PropertyValue> {
+		self.values.remove(&id).map(|(_, value)| value)
+	}
+
+	pub fn contains(&self, id: u16) -> bool {
+		self.values.contains_key(&id)
+	}
+
+	/// The value of a string property; empty strings are treated as missing.
+	pub fn get_string(&self, id: u16) -> Option<&str> {
+		match self.get(id) {
+			Some(PropertyValue::String(value)) if !value.is_empty() => Some(value),
+			_ => None,
+		}
+	}
+
+	pub fn get_binary(&self, id: u16) -> Option<&[u8]> {
+		match self.get(id) {
+			Some(PropertyValue::Binary(value)) => Some(value),
+			_ => None,
+		}
+	}
+
+	pub fn get_integer(&self, id: u16) -> Option<i64> {
+		match self.get(id) {
+			Some(&PropertyValue::Integer(value)) => Some(value),
+			_ => None,
+		}
+	}
+
+	/// The value of a time property, as a UNIX timestamp.
+	pub fn get_time(&self, id: u16) -> Option<u64> {
+		match self.get(id) {
+			Some(&PropertyValue::Time(value)) => filetime_to_unix(value),
+			_ => None,
+		}
+	}
+
+	/// Iterates over all properties as (ID, type, value), ordered by ID.
+	pub fn iter(&self) -> impl Iterator<Item = (u16, u16, &PropertyValue)> {
+		self.values.iter().map(|(&id, (property_type, value))| (id, *property_type, value))
+	}
+
+	pub fn len(&self) -> usize {
+		self.values.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.values.is_empty()
+	}
+}
+
+/// The name of a well-known property (MS-OXPROPS), or `None` if it is not known.
+/// Named properties (IDs from 0x8000) are specific to every message and have no fixed name.
+pub fn property_name(id: u16) -> Option<&'static str> {
+	Some(match id {
+		0x001A => "PidTagMessageClass",
+		0x0017 => "PidTagImportance",
+		0x0026 => "PidTagPriority",
+		0x0036 => "PidTagSensitivity",
+		0x0037 => "PidTagSubject",
+		0x0039 => "PidTagClientSubmitTime",
+		0x003D => "PidTagSubjectPrefix",
+		0x0042 => "PidTagSentRepresentingName",
+		0x0064 => "PidTagSentRepresentingAddressType",
+		0x0065 => "PidTagSentRepresentingEmailAddress",
+		0x0070 => "PidTagConversationTopic",
+		0x0071 => "PidTagConversationIndex",
+		0x007D => "PidTagTransportMessageHeaders",
+		0x0C15 => "PidTagRecipientType",
+		0x0C1A => "PidTagSenderName",
+		0x0C1E => "PidTagSenderAddressType",
+		0x0C1F => "PidTagSenderEmailAddress",
+		0x0E03 => "PidTagDisplayCc",
+		0x0E04 => "PidTagDisplayTo",
+		0x0E02 => "PidTagDisplayBcc",
+		0x0E06 => "PidTagMessageDeliveryTime",
+		0x0E07 => "PidTagMessageFlags",
+		0x0E08 => "PidTagMessageSize",
+		0x0E1B => "PidTagHasAttachments",
+		0x0E20 => "PidTagAttachSize",
+		0x0E21 => "PidTagAttachNumber",
+		0x1000 => "PidTagBody",
+		0x1009 => "PidTagRtfCompressed",
+		0x1013 => "PidTagBodyHtml",
+		0x1035 => "PidTagInternetMessageId",
+		0x1039 => "PidTagInternetReferences",
+		0x1042 => "PidTagInReplyToId",
+		0x3001 => "PidTagDisplayName",
+		0x3002 => "PidTagAddressType",
+		0x3003 => "PidTagEmailAddress",
+		0x3007 => "PidTagCreationTime",
+		0x3008 => "PidTagLastModificationTime",
+		0x3701 => "PidTagAttachDataBinary",
+		0x3703 => "PidTagAttachExtension",
+		0x3704 => "PidTagAttachFilename",
+		0x3705 => "PidTagAttachMethod",
+		0x3707 => "PidTagAttachLongFilename",
+		0x370E => "PidTagAttachMimeTag",
+		0x3712 => "PidTagAttachContentId",
+		0x3714 => "PidTagAttachFlags",
+		0x39FE => "PidTagSmtpAddress",
+		0x3FDE => "PidTagInternetCodepage",
+		0x3FFA => "PidTagLastModifierName",
+		0x3FFD => "PidTagMessageCodepage",
+		0x5D01 => "PidTagSenderSmtpAddress",
+		0x5D02 => "PidTagSentRepresentingSmtpAddress",
+		_ => return None,
+	})
+}
+
+/// The name of a property type (e.g. `PT_UNICODE`), including the `PT_MV_` prefix for multi-valued types.
+pub fn type_name(property_type: u16) -> String {
+	let name = match property_type & !MV_FLAG {
+		PT_SHORT => "SHORT",
+		PT_LONG => "LONG",
+		PT_FLOAT => "FLOAT",
+		PT_DOUBLE => "DOUBLE",
+		PT_CURRENCY => "CURRENCY",
+		PT_APPTIME => "APPTIME",
+		PT_ERROR => "ERROR",
+		PT_BOOLEAN => "BOOLEAN",
+		PT_OBJECT => "OBJECT",
+		PT_LONGLONG => "LONGLONG",
+		PT_STRING8 => "STRING8",
+		PT_UNICODE => "UNICODE",
+		PT_SYSTIME => "SYSTIME",
+		PT_CLSID => "CLSID",
+		PT_BINARY => "BINARY",
+		other => return format!("PT_{}{:04X}", if property_type & MV_FLAG != 0 { "MV_" } else { "" }, other),
+	};
+	format!("PT_{}{}", if property_type & MV_FLAG != 0 { "MV_" } else { "" }, name)
+}
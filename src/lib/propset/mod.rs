@@ -0,0 +1,718 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::io::{Read, Seek, Error, ErrorKind};
+use std::collections::BTreeMap;
+use std::fmt;
+use super::cfbf::{Container, ObjectType};
+use super::io::{Debug, decode_utf16le, format_guid};
+use super::metadata::{filetime_to_unix, format_timestamp};
+
+/// The name of the stream that holds the summary information of a document (MS-OLEPS, section 3.1).
+pub const SUMMARY_INFORMATION: &str = "\u{5}SummaryInformation";
+/// The name of the stream that holds the document summary information and the user-defined properties of a document.
+pub const DOCUMENT_SUMMARY_INFORMATION: &str = "\u{5}DocumentSummaryInformation";
+
+/// `{F29F85E0-4FF9-1068-AB91-08002B27B3D9}`, as stored (little-endian fields).
+pub const FMTID_SUMMARY_INFORMATION: [u8; 16] = [0xE0, 0x85, 0x9F, 0xF2, 0xF9, 0x4F, 0x68, 0x10, 0xAB, 0x91, 0x08, 0x00, 0x2B, 0x27, 0xB3, 0xD9];
+/// `{D5CDD502-2E9C-101B-9397-08002B2CF9AE}`
+pub const FMTID_DOC_SUMMARY_INFORMATION: [u8; 16] = [0x02, 0xD5, 0xCD, 0xD5, 0x9C, 0x2E, 0x1B, 0x10, 0x93, 0x97, 0x08, 0x00, 0x2B, 0x2C, 0xF9, 0xAE];
+/// `{D5CDD505-2E9C-101B-9397-08002B2CF9AE}`: the user-defined properties, named in the dictionary.
+pub const FMTID_USER_DEFINED_PROPERTIES: [u8; 16] = [0x05, 0xD5, 0xCD, 0xD5, 0x9C, 0x2E, 0x1B, 0x10, 0x93, 0x97, 0x08, 0x00, 0x2B, 0x2C, 0xF9, 0xAE];
+
+/// The variant types of property values (MS-OLEPS, section 2.15).
+pub const VT_EMPTY: u16 = 0x0000;
+pub const VT_NULL: u16 = 0x0001;
+pub const VT_I2: u16 = 0x0002;
+pub const VT_I4: u16 = 0x0003;
+pub const VT_R4: u16 = 0x0004;
+pub const VT_R8: u16 = 0x0005;
+pub const VT_CY: u16 = 0x0006;
+pub const VT_DATE: u16 = 0x0007;
+pub const VT_BSTR: u16 = 0x0008;
+pub const VT_ERROR: u16 = 0x000A;
+pub const VT_BOOL: u16 = 0x000B;
+pub const VT_VARIANT: u16 = 0x000C;
+pub const VT_I1: u16 = 0x0010;
+pub const VT_UI1: u16 = 0x0011;
+pub const VT_UI2: u16 = 0x0012;
+pub const VT_UI4: u16 = 0x0013;
+pub const VT_I8: u16 = 0x0014;
+pub const VT_UI8: u16 = 0x0015;
+pub const VT_INT: u16 = 0x0016;
+pub const VT_UINT: u16 = 0x0017;
+pub const VT_LPSTR: u16 = 0x001E;
+pub const VT_LPWSTR: u16 = 0x001F;
+pub const VT_FILETIME: u16 = 0x0040;
+pub const VT_BLOB: u16 = 0x0041;
+pub const VT_CF: u16 = 0x0047;
+pub const VT_CLSID: u16 = 0x0048;
+pub const VT_VECTOR: u16 = 0x1000;
+
+/// The code page of Unicode (UTF-16LE) strings.
+const CP_UTF16: u16 = 1200;
+const CP_UTF8: u16 = 65001;
+const CP_WINDOWS_1252: u16 = 1252;
+
+/// A property set stream (such as `\x05SummaryInformation`), holding one or more property sets.
+pub struct PropertySetStream {
+	pub clsid: [u8; 16],
+	pub sets: Vec<PropertySet>,
+}
+
+/// A property set: the properties of one format (identified by its FMTID).
+pub struct PropertySet {
+	pub fmtid: [u8; 16],
+	/// The code page of all 8-bit strings (property 1), if specified.
+	pub code_page: Option<u16>,
+	/// The names of properties (property 0), if a dictionary is present.
+	pub dictionary: BTreeMap<u32, String>,
+	/// All other properties, in the order in which they are stored.
+	pub properties: Vec<Property>,
+}
+
+pub struct Property {
+	pub id: u32,
+	/// The variant type of the value, as stored.
+	pub value_type: u16,
+	pub value: Value,
+}
+
+/// A property value.
+#[derive(Clone)]
+pub enum Value {
+	Empty,
+	Integer(i64),
+	Float(f64),
+	Boolean(bool),
+	String(String),
+	/// A Windows FILETIME (100ns intervals since 1601-01-01); also used for durations, such as the total editing time.
+	Time(u64),
+	Binary(Vec<u8>),
+	Clsid([u8; 16]),
+	Vector(Vec<Value>),
+	/// A value of a type that is not decoded, such as a stream or storage reference.
+	Unsupported(u16),
+}
+
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Value::Empty => write!(f, "(empty)"),
+			Value::Integer(value) => write!(f, "{}", value),
+			Value::Float(value) => write!(f, "{}", value),
+			Value::Boolean(value) => write!(f, "{}", value),
+			Value::String(ref value) => write!(f, "{:?}", value),
+			Value::Time(value) => match filetime_to_unix(value) {
+				Some(timestamp) => write!(f, "{}", format_timestamp(timestamp)),
+				// Durations (and timestamps before 1970) are written in seconds
+				None => write!(f, "{} s", value / 10_000_000),
+			},
+			Value::Binary(ref value) => write!(f, "({} bytes)", value.len()),
+			Value::Clsid(ref clsid) => write!(f, "{}", format_guid(clsid)),
+			Value::Vector(ref values) => {
+				write!(f, "[")?;
+				for (index, value) in values.iter().enumerate() {
+					if index > 0 {
+						write!(f, ", ")?;
+					}
+					write!(f, "{}", value)?;
+				}
+				write!(f, "]")
+			},
+			Value::Unsupported(value_type) => write!(f, "(unsupported type 0x{:04X})", value_type),
+		}
+	}
+}
+
+impl Value {
+	pub fn as_str(&self) -> Option<&str> {
+		match *self {
+			Value::String(ref value) => Some(value),
+			_ => None,
+		}
+	}
+
+	pub fn as_integer(&self) -> Option<i64> {
+		match *self {
+			Value::Integer(value) => Some(value),
+			_ => None,
+		}
+	}
+
+	/// The value as a UNIX timestamp, if it is a time (and not a duration).
+	pub fn as_timestamp(&self) -> Option<u64> {
+		match *self {
+			Value::Time(value) => filetime_to_unix(value),
+			_ => None,
+		}
+	}
+}
+
+impl PropertySetStream {
+	/// Parses a property set stream (MS-OLEPS, section 2.21).
+	pub fn parse(data: &[u8]) -> Result<PropertySetStream, Error> {
+		let reader = Reader { data };
+		if reader.u16(0)? != 0xFFFE {
+			return Err(Error::new(ErrorKind::InvalidData, "The stream is not a property set stream (invalid byte order mark)"));
+		}
+		let clsid = reader.guid(8)?;
+		let num_sets = reader.u32(24)? as usize;
+		if num_sets > 16 {
+			return Err(Error::new(ErrorKind::InvalidData, format!("Implausible number of property sets: {}", num_sets)));
+		}
+
+		let mut sets = Vec::new();
+		for index in 0..num_sets {
+			let fmtid = reader.guid(28 + index * 20)?;
+			let offset = reader.u32(28 + index * 20 + 16)? as usize;
+			sets.push(PropertySet::parse(fmtid, data.get(offset..).unwrap_or(&[]))?);
+		}
+		Ok(PropertySetStream { clsid, sets })
+	}
+
+	/// Finds the property set of the given format.
+	pub fn get(&self, fmtid: &[u8; 16]) -> Option<&PropertySet> {
+		self.sets.iter().find(|set| set.fmtid == *fmtid)
+	}
+}
+
+impl PropertySet {
+	/// Parses a property set (MS-OLEPS, section 2.20); the data starts at the beginning of the set.
+	fn parse(fmtid: [u8; 16], data: &[u8]) -> Result<PropertySet, Error> {
+		let reader = Reader { data };
+		let size = (reader.u32(0)? as usize).min(data.len());
+		let reader = Reader { data: &data[..size] };
+		let num_properties = reader.u32(4)? as usize;
+		if num_properties > size / 8 {
+			return Err(Error::new(ErrorKind::InvalidData, format!("Implausible number of properties: {}", num_properties)));
+		}
+		let entries = (0..num_properties)
+			.map(|index| Ok((reader.u32(8 + index * 8)?, reader.u32(12 + index * 8)? as usize)))
+			.collect::<Result<Vec<(u32, usize)>, Error>>()?;
+
+		// The code page is needed to decode all strings (including the dictionary), so it is read first
+		let mut code_page = None;
+		for &(id, offset) in &entries {
+			if id == 1 && reader.u16(offset)? == VT_I2 {
+				code_page = Some(reader.u16(offset + 4)?);
+			}
+		}
+
+		let mut set = PropertySet { fmtid, code_page, dictionary: BTreeMap::new(), properties: Vec::new() };
+		for (id, offset) in entries {
+			match id {
+				0 => set.dictionary = reader.dictionary(offset, code_page.unwrap_or(CP_WINDOWS_1252))?,
+				1 => {},
+				_ => {
+					let value_type = reader.u16(offset)?;
+					let (value, _) = reader.value(offset + 4, value_type, code_page.unwrap_or(CP_WINDOWS_1252), 0)?;
+					set.properties.push(Property { id, value_type, value });
+				},
+			}
+		}
+		Ok(set)
+	}
+
+	pub fn get(&self, id: u32) -> Option<&Value> {
+		self.properties.iter().find(|property| property.id == id).map(|property| &property.value)
+	}
+
+	/// The name of a property: from the dictionary, or else the well-known name for this format.
+	pub fn property_name(&self, id: u32) -> Option<String> {
+		if let Some(name) = self.dictionary.get(&id) {
+			return Some(name.clone());
+		}
+		let name = match (self.fmtid, id) {
+			(FMTID_SUMMARY_INFORMATION, _) => summary_property_name(id),
+			(FMTID_DOC_SUMMARY_INFORMATION, _) => doc_summary_property_name(id),
+			(_, 0x80000000) => Some("Locale"),
+			(_, 0x80000003) => Some("Behavior"),
+			_ => None,
+		};
+		name.map(|name| name.to_owned())
+	}
+
+	/// The name of the format, if it is a well-known one.
+	pub fn format_name(&self) -> Option<&'static str> {
+		match self.fmtid {
+			FMTID_SUMMARY_INFORMATION => Some("SummaryInformation"),
+			FMTID_DOC_SUMMARY_INFORMATION => Some("DocumentSummaryInformation"),
+			FMTID_USER_DEFINED_PROPERTIES => Some("UserDefinedProperties"),
+			_ => None,
+		}
+	}
+}
+
+fn summary_property_name(id: u32) -> Option<&'static str> {
+	Some(match id {
+		2 => "Title",
+		3 => "Subject",
+		4 => "Author",
+		5 => "Keywords",
+		6 => "Comments",
+		7 => "Template",
+		8 => "LastAuthor",
+		9 => "RevNumber",
+		10 => "EditTime",
+		11 => "LastPrinted",
+		12 => "CreateDateTime",
+		13 => "LastSaveDateTime",
+		14 => "PageCount",
+		15 => "WordCount",
+		16 => "CharCount",
+		17 => "Thumbnail",
+		18 => "AppName",
+		19 => "DocSecurity",
+		_ => return None,
+	})
+}
+
+fn doc_summary_property_name(id: u32) -> Option<&'static str> {
+	Some(match id {
+		2 => "Category",
+		3 => "PresentationTarget",
+		4 => "Bytes",
+		5 => "Lines",
+		6 => "Paragraphs",
+		7 => "Slides",
+		8 => "Notes",
+		9 => "HiddenSlides",
+		10 => "MMClips",
+		11 => "ScaleCrop",
+		12 => "HeadingPairs",
+		13 => "DocParts",
+		14 => "Manager",
+		15 => "Company",
+		16 => "LinksDirty",
+		17 => "CharsWithSpaces",
+		19 => "SharedDoc",
+		22 => "HyperlinksChanged",
+		23 => "AppVersion",
+		_ => return None,
+	})
+}
+
+/// Bounds-checked reading of little-endian values.
+struct Reader<'a> {
+	data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+	fn bytes(&self, offset: usize, length: usize) -> Result<&'a [u8], Error> {
+		match offset.checked_add(length) {
+			Some(end) if end <= self.data.len() => Ok(&self.data[offset..end]),
+			_ => Err(Error::new(ErrorKind::UnexpectedEof, format!("Property set is truncated (reading {} bytes at offset {})", length, offset))),
+		}
+	}
+
+	fn integer(&self, offset: usize, length: usize) -> Result<u64, Error> {
+		Ok(self.bytes(offset, length)?.iter().rev().fold(0u64, |result, &byte| result << 8 | byte as u64))
+	}
+
+	fn u16(&self, offset: usize) -> Result<u16, Error> {
+		self.integer(offset, 2).map(|value| value as u16)
+	}
+
+	fn u32(&self, offset: usize) -> Result<u32, Error> {
+		self.integer(offset, 4).map(|value| value as u32)
+	}
+
+	fn guid(&self, offset: usize) -> Result<[u8; 16], Error> {
+		let mut guid = [0u8; 16];
+		guid.copy_from_slice(self.bytes(offset, 16)?);
+		Ok(guid)
+	}
+
+	/// Reads a value of the given type at the offset (after the type and padding fields).
+	/// Returns the value and the number of bytes it occupies (including padding to a multiple of 4).
+	fn value(&self, offset: usize, value_type: u16, code_page: u16, depth: usize) -> Result<(Value, usize), Error> {
+		if value_type & VT_VECTOR != 0 {
+			if depth > 0 {
+				return Err(Error::new(ErrorKind::InvalidData, "Nested vectors are not allowed in property sets"));
+			}
+			let count = self.u32(offset)? as usize;
+			if count > self.data.len() {
+				return Err(Error::new(ErrorKind::InvalidData, format!("Implausible number of vector elements: {}", count)));
+			}
+			let mut values = Vec::with_capacity(count);
+			let mut position = offset + 4;
+			for _ in 0..count {
+				let (value, length) = match value_type & !VT_VECTOR {
+					// Every element of a vector of variants has its own type
+					VT_VARIANT => {
+						let element_type = self.u16(position)?;
+						let (value, length) = self.value(position + 4, element_type, code_page, depth + 1)?;
+						(value, length + 4)
+					},
+					element_type => self.value_in_vector(position, element_type, code_page)?,
+				};
+				values.push(value);
+				position += length;
+			}
+			return Ok((Value::Vector(values), position - offset));
+		}
+
+		Ok(match value_type {
+			VT_EMPTY | VT_NULL => (Value::Empty, 0),
+			VT_I2 => (Value::Integer(self.u16(offset)? as i16 as i64), 4),
+			VT_UI2 => (Value::Integer(self.u16(offset)? as i64), 4),
+			VT_I1 => (Value::Integer(self.integer(offset, 1)? as i8 as i64), 4),
+			VT_UI1 => (Value::Integer(self.integer(offset, 1)? as i64), 4),
+			VT_I4 | VT_INT | VT_ERROR => (Value::Integer(self.u32(offset)? as i32 as i64), 4),
+			VT_UI4 | VT_UINT => (Value::Integer(self.u32(offset)? as i64), 4),
+			VT_I8 | VT_CY => (Value::Integer(self.integer(offset, 8)? as i64), 8),
+			VT_UI8 => (Value::Integer(self.integer(offset, 8)? as i64), 8),
+			VT_R4 => (Value::Float(f32::from_bits(self.u32(offset)?) as f64), 4),
+			VT_R8 | VT_DATE => (Value::Float(f64::from_bits(self.integer(offset, 8)?)), 8),
+			VT_BOOL => (Value::Boolean(self.u16(offset)? != 0), 4),
+			VT_FILETIME => (Value::Time(self.integer(offset, 8)?), 8),
+			VT_CLSID => (Value::Clsid(self.guid(offset)?), 16),
+			VT_LPSTR | VT_BSTR => {
+				let length = self.u32(offset)? as usize;
+				let string = match code_page {
+					CP_UTF16 => decode_utf16le(self.bytes(offset + 4, length)?),
+					_ => decode_code_page(self.bytes(offset + 4, length)?, code_page),
+				};
+				(Value::String(string), padded(4 + length))
+			},
+			VT_LPWSTR => {
+				let length = self.u32(offset)? as usize;
+				(Value::String(decode_utf16le(self.bytes(offset + 4, length.saturating_mul(2))?)), padded(4 + length * 2))
+			},
+			VT_BLOB | VT_CF => {
+				let length = self.u32(offset)? as usize;
+				(Value::Binary(self.bytes(offset + 4, length)?.to_vec()), padded(4 + length))
+			},
+			other => (Value::Unsupported(other), 0),
+		})
+	}
+
+	/// Reads an element of a vector. Fixed-length elements are packed without padding (except for strings and blobs).
+	fn value_in_vector(&self, offset: usize, value_type: u16, code_page: u16) -> Result<(Value, usize), Error> {
+		let (value, length) = self.value(offset, value_type, code_page, 1)?;
+		let length = match value_type {
+			VT_I2 | VT_UI2 | VT_BOOL => 2,
+			VT_I1 | VT_UI1 => 1,
+			_ => length,
+		};
+		if let Value::Unsupported(_) = value {
+			return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported vector element type 0x{:04X}", value_type)));
+		}
+		Ok((value, length))
+	}
+
+	/// Reads a dictionary (MS-OLEPS, section 2.17): a list of property IDs and their names.
+	fn dictionary(&self, offset: usize, code_page: u16) -> Result<BTreeMap<u32, String>, Error> {
+		let count = self.u32(offset)? as usize;
+		let mut dictionary = BTreeMap::new();
+		let mut position = offset + 4;
+		for _ in 0..count.min(self.data.len() / 8) {
+			let id = self.u32(position)?;
+			let length = self.u32(position + 4)? as usize;
+			let name = match code_page {
+				// Unicode names are counted in characters, and padded to a multiple of 4 bytes
+				CP_UTF16 => {
+					let name = decode_utf16le(self.bytes(position + 8, length.saturating_mul(2))?);
+					position = padded(position + 8 + length * 2);
+					name
+				},
+				_ => {
+					let name = decode_code_page(self.bytes(position + 8, length)?, code_page);
+					position += 8 + length;
+					name
+				},
+			};
+			dictionary.insert(id, name);
+		}
+		Ok(dictionary)
+	}
+}
+
+fn padded(length: usize) -> usize {
+	length.div_ceil(4) * 4
+}
+
+/// Decodes an 8-bit string in the given code page, up to the first NUL character.
+/// UTF-8 and Windows-1252 are decoded exactly; all other code pages are approximated by ISO 8859-1.
+pub fn decode_code_page(data: &[u8], code_page: u16) -> String {
+	let data = match data.iter().position(|&byte| byte == 0) {
+		Some(end) => &data[..end],
+		None => data,
+	};
+	match code_page {
+		CP_UTF8 => String::from_utf8_lossy(data).into_owned(),
+		CP_WINDOWS_1252 => data.iter().map(|&byte| match byte {
+			0x80..=0x9F => WINDOWS_1252[(byte - 0x80) as usize],
+			_ => byte as char,
+		}).collect(),
+		_ => data.iter().map(|&byte| byte as char).collect(),
+	}
+}
+
+/// The characters 0x80 to 0x9F of Windows-1252 (which differ from ISO 8859-1); undefined ones are mapped to U+FFFD.
+const WINDOWS_1252: [char; 32] = [
+	'€', '\u{FFFD}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{FFFD}', 'Ž', '\u{FFFD}',
+	'\u{FFFD}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{FFFD}', 'ž', 'Ÿ',
+];
+
+/// The most relevant properties of a document, from its summary information and document summary information.
+#[derive(Clone, Default)]
+pub struct Summary {
+	pub title: Option<String>,
+	pub subject: Option<String>,
+	pub author: Option<String>,
+	pub keywords: Option<String>,
+	pub comments: Option<String>,
+	pub last_author: Option<String>,
+	/// The name of the application that created the document.
+	pub application: Option<String>,
+	/// UNIX timestamp
+	pub created: Option<u64>,
+	/// UNIX timestamp
+	pub modified: Option<u64>,
+	/// UNIX timestamp
+	pub last_printed: Option<u64>,
+	pub page_count: Option<u64>,
+	pub word_count: Option<u64>,
+	pub char_count: Option<u64>,
+	pub category: Option<String>,
+	pub manager: Option<String>,
+	pub company: Option<String>,
+}
+
+impl Summary {
+	/// Reads the summary information streams beneath the root of the container; missing streams yield an empty summary.
+	pub fn read<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Summary, Error> where TFile: Read + Seek {
+		let mut summary = Summary::default();
+		let root = container.get_root_object(debug)?;
+		for child in container.get_children(&root, debug)? {
+			if child.object_type != ObjectType::Stream || (child.name != SUMMARY_INFORMATION && child.name != DOCUMENT_SUMMARY_INFORMATION) {
+				continue;
+			}
+			let mut data: Vec<u8> = Vec::new();
+			container.dump_stream(&child, &mut data, debug)?;
+			debug.logln(1, format!("[Summary::read] Parsing '{}' ({} bytes) ...", child.name.escape_default(), data.len()));
+			summary.add(&PropertySetStream::parse(&data)?);
+		}
+		Ok(summary)
+	}
+
+	/// Takes the properties of all well-known property sets in the stream.
+	pub fn add(&mut self, stream: &PropertySetStream) {
+		let text = |set: &PropertySet, id: u32| set.get(id).and_then(|value| value.as_str()).map(|value| value.trim().to_owned()).filter(|value| !value.is_empty());
+		let number = |set: &PropertySet, id: u32| set.get(id).and_then(|value| value.as_integer()).filter(|&value| value >= 0).map(|value| value as u64);
+		let timestamp = |set: &PropertySet, id: u32| set.get(id).and_then(|value| value.as_timestamp()).filter(|&value| value > 0);
+		if let Some(set) = stream.get(&FMTID_SUMMARY_INFORMATION) {
+			self.title = text(set, 2);
+			self.subject = text(set, 3);
+			self.author = text(set, 4);
+			self.keywords = text(set, 5);
+			self.comments = text(set, 6);
+			self.last_author = text(set, 8);
+			self.last_printed = timestamp(set, 11);
+			self.created = timestamp(set, 12);
+			self.modified = timestamp(set, 13);
+			self.page_count = number(set, 14);
+			self.word_count = number(set, 15);
+			self.char_count = number(set, 16);
+			self.application = text(set, 18);
+		}
+		if let Some(set) = stream.get(&FMTID_DOC_SUMMARY_INFORMATION) {
+			self.category = text(set, 2);
+			self.manager = text(set, 14);
+			self.company = text(set, 15);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::stderr;
+	use super::super::cfbf::testing::build_cfbf;
+	use super::super::io::SeekableRead;
+	use super::*;
+
+	/// A typed value: the type, the padding, and the given data (padded to a multiple of 4 bytes).
+	fn typed(value_type: u16, data: &[u8]) -> Vec<u8> {
+		let mut value = value_type.to_le_bytes().to_vec();
+		value.extend_from_slice(&[0, 0]);
+		value.extend_from_slice(data);
+		value.resize(padded(value.len()), 0);
+		value
+	}
+
+	fn counted(data: &[u8]) -> Vec<u8> {
+		let mut value = (data.len() as u32).to_le_bytes().to_vec();
+		value.extend_from_slice(data);
+		value
+	}
+
+	fn property_set(properties: &[(u32, Vec<u8>)]) -> Vec<u8> {
+		let mut entries = Vec::new();
+		let mut values = Vec::new();
+		let start = 8 + properties.len() * 8;
+		for &(id, ref value) in properties {
+			entries.extend_from_slice(&id.to_le_bytes());
+			entries.extend_from_slice(&((start + values.len()) as u32).to_le_bytes());
+			values.extend_from_slice(value);
+		}
+		let mut set = ((start + values.len()) as u32).to_le_bytes().to_vec();
+		set.extend_from_slice(&(properties.len() as u32).to_le_bytes());
+		set.extend(entries);
+		set.extend(values);
+		set
+	}
+
+	fn property_set_stream(sets: &[([u8; 16], Vec<u8>)]) -> Vec<u8> {
+		let mut data = vec![0xFE, 0xFF, 0, 0, 0x0A, 0, 0x02, 0];
+		data.extend_from_slice(&[0x11; 16]);
+		data.extend_from_slice(&(sets.len() as u32).to_le_bytes());
+		let mut offset = 28 + sets.len() * 20;
+		for &(fmtid, ref set) in sets {
+			data.extend_from_slice(&fmtid);
+			data.extend_from_slice(&(offset as u32).to_le_bytes());
+			offset += set.len();
+		}
+		for (_, set) in sets {
+			data.extend_from_slice(set);
+		}
+		data
+	}
+
+	#[test]
+	fn format_clsids() {
+		assert_eq!(format_guid(&FMTID_SUMMARY_INFORMATION), "{F29F85E0-4FF9-1068-AB91-08002B27B3D9}");
+		assert_eq!(format_guid(&FMTID_DOC_SUMMARY_INFORMATION), "{D5CDD502-2E9C-101B-9397-08002B2CF9AE}");
+	}
+
+	#[test]
+	fn decode_code_pages() {
+		assert_eq!(decode_code_page(b"Caf\xe9 \x80\x81\0junk", 1252), "Caf\u{e9} \u{20ac}\u{FFFD}");
+		assert_eq!(decode_code_page("Caf\u{e9}".as_bytes(), 65001), "Caf\u{e9}");
+		assert_eq!(decode_code_page(b"\x80\xe9", 28591), "\u{80}\u{e9}");
+	}
+
+	#[test]
+	fn parse_values() {
+		// 2018-03-01T12:00:00Z as FILETIME
+		let date = (1519905600u64 + 11644473600) * 10000000;
+		let mut vector = 2u32.to_le_bytes().to_vec();
+		vector.extend(counted(b"ab\0"));
+		vector.extend_from_slice(&[0]);
+		vector.extend(counted(b"c\0"));
+		let set = property_set(&[
+			(1, typed(VT_I2, &1252u16.to_le_bytes())),
+			(2, typed(VT_LPSTR, &counted(b"Caf\xe9\0"))),
+			(4, typed(VT_LPWSTR, &counted(&[0x42, 0, 0xFC, 0, 0, 0])[..])),
+			(12, typed(VT_FILETIME, &date.to_le_bytes())),
+			(14, typed(VT_I4, &(-3i32).to_le_bytes())),
+			(19, typed(VT_BOOL, &[0xFF, 0xFF])),
+			(20, typed(VT_VECTOR | VT_LPSTR, &vector)),
+			(21, typed(0x0049, &[])),
+		]);
+		let stream = PropertySetStream::parse(&property_set_stream(&[(FMTID_SUMMARY_INFORMATION, set)])).unwrap();
+		assert_eq!(format_guid(&stream.clsid), "{11111111-1111-1111-1111-111111111111}");
+		let set = stream.get(&FMTID_SUMMARY_INFORMATION).unwrap();
+		assert_eq!(set.code_page, Some(1252));
+		assert_eq!(set.format_name(), Some("SummaryInformation"));
+		assert_eq!(set.properties.len(), 7);
+		assert_eq!(set.get(2).and_then(|value| value.as_str()), Some("Caf\u{e9}"));
+		assert_eq!(set.get(4).and_then(|value| value.as_str()), Some("B\u{fc}"));
+		assert_eq!(set.get(12).and_then(|value| value.as_timestamp()), Some(1519905600));
+		assert_eq!(set.get(14).and_then(|value| value.as_integer()), Some(-3));
+		assert_eq!(set.get(19).map(|value| value.to_string()), Some("true".to_owned()));
+		assert_eq!(set.get(20).map(|value| value.to_string()), Some("[\"ab\", \"c\"]".to_owned()));
+		assert_eq!(set.get(21).map(|value| value.to_string()), Some("(unsupported type 0x0049)".to_owned()));
+		assert_eq!(set.property_name(2), Some("Title".to_owned()));
+	}
+
+	#[test]
+	fn parse_dictionary() {
+		let mut dictionary = 1u32.to_le_bytes().to_vec();
+		dictionary.extend_from_slice(&2u32.to_le_bytes());
+		// The name length is counted in characters, including the terminator
+		dictionary.extend_from_slice(&4u32.to_le_bytes());
+		dictionary.extend_from_slice(&[0x4E, 0, 0x72, 0, 0x2E, 0, 0, 0]);
+		let set = property_set(&[
+			(0, dictionary),
+			(1, typed(VT_I2, &1200u16.to_le_bytes())),
+			(2, typed(VT_LPSTR, &counted(&[0x4F, 0, 0x4B, 0, 0, 0]))),
+		]);
+		let stream = PropertySetStream::parse(&property_set_stream(&[(FMTID_USER_DEFINED_PROPERTIES, set)])).unwrap();
+		let set = stream.get(&FMTID_USER_DEFINED_PROPERTIES).unwrap();
+		assert_eq!(set.format_name(), Some("UserDefinedProperties"));
+		assert_eq!(set.property_name(2), Some("Nr.".to_owned()));
+		assert_eq!(set.get(2).and_then(|value| value.as_str()), Some("OK"));
+		assert_eq!(set.property_name(3), None);
+	}
+
+	#[test]
+	fn reject_invalid_streams() {
+		let set = property_set(&[(2, typed(VT_LPSTR, &counted(b"Title\0")))]);
+		let stream = property_set_stream(&[(FMTID_SUMMARY_INFORMATION, set)]);
+		assert!(PropertySetStream::parse(&stream).is_ok());
+
+		let mut wrong_byte_order = stream.clone();
+		wrong_byte_order[0] = 0xFF;
+		assert!(PropertySetStream::parse(&wrong_byte_order).is_err());
+		assert!(PropertySetStream::parse(&stream[..20]).is_err());
+		// The string exceeds the end of the stream
+		assert!(PropertySetStream::parse(&stream[..stream.len() - 8]).is_err());
+
+		let mut many_sets = stream.clone();
+		many_sets[24..28].copy_from_slice(&1000u32.to_le_bytes());
+		assert!(PropertySetStream::parse(&many_sets).is_err());
+		let mut many_properties = stream.clone();
+		many_properties[52..56].copy_from_slice(&1000u32.to_le_bytes());
+		assert!(PropertySetStream::parse(&many_properties).is_err());
+	}
+
+	#[test]
+	fn read_summary() {
+		let summary_information = property_set_stream(&[(FMTID_SUMMARY_INFORMATION, property_set(&[
+			(1, typed(VT_I2, &65001u16.to_le_bytes())),
+			(2, typed(VT_LPSTR, &counted(" Quarterly report \0".as_bytes()))),
+			(4, typed(VT_LPSTR, &counted("J\u{fc}rgen\0".as_bytes()))),
+			(5, typed(VT_LPSTR, &counted(b"\0"))),
+			(14, typed(VT_I4, &12u32.to_le_bytes())),
+			(18, typed(VT_LPSTR, &counted(b"Microsoft Office Word\0"))),
+		]))]);
+		let document_summary_information = property_set_stream(&[(FMTID_DOC_SUMMARY_INFORMATION, property_set(&[
+			(15, typed(VT_LPSTR, &counted(b"ACME\0"))),
+		]))]);
+		let data = build_cfbf(&[
+			("WordDocument", b""),
+			(SUMMARY_INFORMATION, &summary_information),
+			(DOCUMENT_SUMMARY_INFORMATION, &document_summary_information),
+		]);
+		let mut debug = Debug::new(stderr(), -1);
+		let mut container = Container::new(SeekableRead::new(&data[..]).unwrap(), &mut debug).unwrap();
+		let summary = Summary::read(&mut container, &mut debug).unwrap();
+		assert_eq!(summary.title.as_deref(), Some("Quarterly report"));
+		assert_eq!(summary.author.as_deref(), Some("J\u{fc}rgen"));
+		assert_eq!(summary.keywords, None);
+		assert_eq!(summary.page_count, Some(12));
+		assert_eq!(summary.application.as_deref(), Some("Microsoft Office Word"));
+		assert_eq!(summary.company.as_deref(), Some("ACME"));
+
+		let data = build_cfbf(&[("WordDocument", b"")]);
+		let mut container = Container::new(SeekableRead::new(&data[..]).unwrap(), &mut debug).unwrap();
+		assert_eq!(Summary::read(&mut container, &mut debug).unwrap().title, None);
+	}
+}
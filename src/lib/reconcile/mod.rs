@@ -0,0 +1,352 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::io::{Read, Write, Error};
+use std::collections::HashMap;
+use std::fs::metadata;
+use std::path::Path;
+use super::journal::{Outcome, read_entries as read_journal_entries};
+use super::restore::plan::Plan;
+use super::reparse::{is_fsutil_output, parse_fsutil};
+
+/// A placeholder on the file server, as listed by a placeholder list.
+pub struct Placeholder {
+	/// The path of the placeholder.
+	pub path: String,
+	/// The size of the original file, if the list records it.
+	pub size: Option<u64>,
+}
+
+/// Parses a list of placeholders, which is one of:
+/// - the output of `fsutil reparsepoint query` (each preceded by a line with the path of the placeholder), which includes the size;
+/// - a CSV file written by `Export-Csv`, with a `FullName` and an optional `Length` column;
+/// - a list of paths, one per line (as written by `Select FullName | Out-File`; the header is skipped).
+pub fn parse_placeholders(text: &str) -> Result<Vec<Placeholder>, Error> {
+	if is_fsutil_output(text) {
+		return Ok(parse_fsutil(text)?.into_iter()
+			.filter_map(|placeholder| {
+				let size = placeholder.reparse_point.file_size;
+				placeholder.path.map(|path| Placeholder { path, size })
+			})
+			.collect());
+	}
+
+	let mut lines = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty() && !line.starts_with("#TYPE")).peekable();
+	let header = lines.peek().map(|line| split_csv_line(line)).unwrap_or_default();
+	if header.len() > 1 && header.iter().any(|column| column.eq_ignore_ascii_case("FullName")) {
+		lines.next();
+		let path_column = header.iter().position(|column| column.eq_ignore_ascii_case("FullName")).unwrap();
+		let size_column = header.iter().position(|column| column.eq_ignore_ascii_case("Length"));
+		return Ok(lines.filter_map(|line| {
+			let fields = split_csv_line(line);
+			let path = fields.get(path_column)?.clone();
+			let size = size_column.and_then(|column| fields.get(column)).and_then(|size| size.parse::<u64>().ok());
+			Some(Placeholder { path, size })
+		}).collect());
+	}
+
+	Ok(lines
+		.filter(|line| !line.eq_ignore_ascii_case("FullName") && !line.chars().all(|c| c == '-'))
+		.map(|line| Placeholder { path: line.to_owned(), size: None })
+		.collect())
+}
+
+/// Splits a single line of CSV into its fields (quoted fields must not contain line breaks).
+fn split_csv_line(line: &str) -> Vec<String> {
+	let mut fields = Vec::new();
+	let mut field = String::new();
+	let mut chars = line.chars().peekable();
+	let mut quoted = false;
+	while let Some(c) = chars.next() {
+		match (quoted, c) {
+			(true, '"') if chars.peek() == Some(&'"') => { chars.next(); field.push('"'); },
+			(true, '"') => quoted = false,
+			(false, '"') => quoted = true,
+			(false, ',') => fields.push(std::mem::take(&mut field)),
+			(_, c) => field.push(c),
+		}
+	}
+	fields.push(field);
+	fields
+}
+
+/// A file that has been restored from the vault, as listed by a restore manifest.
+pub struct Restored {
+	/// The path of the restored file.
+	pub path: String,
+	/// The size of the restored content, if the manifest records it.
+	pub size: Option<u64>,
+}
+
+/// Reads the restored files from a restore manifest: either a journal written by `dvsrestore --journal`
+/// (only successful entries are used), or a plan written by `dvsrestore --dry-run --plan`.
+pub fn read_manifest(mut input: impl Read) -> Result<Vec<Restored>, Error> {
+	let mut data = Vec::new();
+	input.read_to_end(&mut data)?;
+	if data.starts_with(b"# evrecovery restore plan") {
+		return Ok(Plan::read(&data[..])?.steps.into_iter()
+			.filter_map(|step| {
+				let size = step.size;
+				step.target.map(|target| Restored { path: target.to_string_lossy().into_owned(), size })
+			})
+			.collect());
+	}
+	Ok(read_journal_entries(&data[..])?.into_iter()
+		.filter(|entry| entry.outcome == Outcome::Ok)
+		.filter_map(|entry| entry.output.map(|path| Restored { path, size: None }))
+		.collect())
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Status {
+	/// A restored original has been found, and its size matches.
+	Matched,
+	/// No restored original has been found.
+	Missing,
+	/// A restored original has been found, but its size differs from the size of the original file.
+	SizeMismatch,
+}
+
+impl Status {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Status::Matched => "matched",
+			Status::Missing => "missing",
+			Status::SizeMismatch => "size-mismatch",
+		}
+	}
+}
+
+/// The result of reconciling a single placeholder.
+pub struct Reconciliation {
+	pub placeholder: String,
+	/// The restored original that replaces the placeholder.
+	pub original: Option<String>,
+	/// The size the original should have (from the placeholder list, or else from the manifest).
+	pub expected_size: Option<u64>,
+	/// The actual size of the restored original.
+	pub actual_size: Option<u64>,
+	pub status: Status,
+}
+
+/// Splits a Windows path into its components, dropping its root (`\\?\`, drive letter or UNC server and share).
+/// Both `\` and `/` are accepted as separators.
+fn relative_components(path: &str) -> Vec<String> {
+	let path = path.trim().replace('/', "\\");
+	let is_unc = path.starts_with("\\\\?\\UNC\\") || (path.starts_with("\\\\") && !path.starts_with("\\\\?\\"));
+	let path = path.trim_start_matches("\\\\?\\UNC\\").trim_start_matches("\\\\?\\");
+	let mut components = path.split('\\').filter(|component| !component.is_empty()).map(|component| component.to_owned()).collect::<Vec<String>>();
+	if is_unc {
+		// UNC path (server and share)
+		components.drain(..components.len().min(2));
+	}
+	else if components.first().is_some_and(|first| first.len() == 2 && first.ends_with(':')) {
+		components.remove(0);
+	}
+	components
+}
+
+fn lowercase(components: Vec<String>) -> Vec<String> {
+	components.into_iter().map(|component| component.to_lowercase()).collect()
+}
+
+/// Matches every placeholder to a restored original.
+/// Restored files are matched by their path: the path of the placeholder (without its root) must be a suffix of the path of the restored file,
+/// which holds if the restored file was written beneath a target directory under its original path.
+/// Additionally, every restore root is probed for the path of the placeholder (without its root), in the given order.
+/// The size of every match is checked on the file system.
+pub fn reconcile(placeholders: &[Placeholder], restored: &[Restored], restore_roots: &[&Path]) -> Vec<Reconciliation> {
+	// Index the restored files by every suffix of their path
+	let mut by_suffix: HashMap<Vec<String>, &Restored> = HashMap::new();
+	for restored in restored {
+		let components = lowercase(relative_components(&restored.path));
+		for start in (0..components.len()).rev() {
+			by_suffix.entry(components[start..].to_vec()).or_insert(restored);
+		}
+	}
+
+	placeholders.iter().map(|placeholder| {
+		let components = relative_components(&placeholder.path);
+		let mut original = match components.is_empty() {
+			true => None,
+			false => by_suffix.get(&lowercase(components.clone())).map(|restored| (restored.path.clone(), restored.size)),
+		};
+		if original.is_none() && !components.is_empty() {
+			original = restore_roots.iter()
+				.map(|root| components.iter().fold(root.to_path_buf(), |path, component| path.join(component)))
+				.find(|path| path.is_file())
+				.map(|path| (path.to_string_lossy().into_owned(), None));
+		}
+
+		let (original, manifest_size) = match original {
+			Some((path, size)) => (Some(path), size),
+			None => (None, None),
+		};
+		let actual_size = original.as_ref().and_then(|path| metadata(path).ok()).filter(|metadata| metadata.is_file()).map(|metadata| metadata.len());
+		let expected_size = placeholder.size.or(manifest_size);
+		let status = match (&original, actual_size, expected_size) {
+			(&None, _, _) | (&Some(_), None, _) => Status::Missing,
+			(&Some(_), Some(actual), Some(expected)) if actual != expected => Status::SizeMismatch,
+			_ => Status::Matched,
+		};
+		Reconciliation { placeholder: placeholder.path.clone(), original, expected_size, actual_size, status }
+	}).collect()
+}
+
+/// Writes a report with one tab-separated line per placeholder: `<status> <placeholder> <original> <expected size> <actual size>`.
+pub fn write_report(reconciliations: &[Reconciliation], output: &mut dyn Write) -> Result<(), Error> {
+	let size = |size: Option<u64>| size.map(|size| size.to_string()).unwrap_or_else(|| "-".to_owned());
+	for reconciliation in reconciliations {
+		writeln!(output, "{}\t{}\t{}\t{}\t{}",
+			reconciliation.status.name(),
+			reconciliation.placeholder,
+			reconciliation.original.as_deref().unwrap_or("-"),
+			size(reconciliation.expected_size),
+			size(reconciliation.actual_size))?;
+	}
+	Ok(())
+}
+
+/// Writes a PowerShell script that replaces every matched placeholder by its restored original.
+/// For every placeholder, it takes ownership, deletes the reparse point and copies the original over it.
+/// If copying fails, it grants access on the parent directory, resets the permissions of the placeholder,
+/// removes its read-only flag, and tries again. The script reports `[OK]`, `[OK_FORCED]` or `[ERROR]` for every placeholder.
+pub fn write_script(reconciliations: &[Reconciliation], output: &mut dyn Write) -> Result<(), Error> {
+	write!(output, "{}", concat!(
+		"# evrecovery restore-back script\r\n",
+		"# Run as an administrator, e.g.: .\\restore-back.ps1 > result.log\r\n",
+		"function Restore-Placeholder([string]$placeholder, [string]$original) {\r\n",
+		"\ttakeown /F $placeholder > $null 2> $null;\r\n",
+		"\tfsutil reparsepoint delete $placeholder > $null 2> $null;\r\n",
+		"\tCopy-Item -LiteralPath $original -Destination $placeholder -Force > $null 2> $null;\r\n",
+		"\tif (!$?) {\r\n",
+		"\t\ticacls $(Split-Path -Parent $placeholder) /grant administrator:F > $null 2> $null;\r\n",
+		"\t\ticacls $placeholder /reset > $null 2> $null;\r\n",
+		"\t\tattrib -R $placeholder > $null 2> $null;\r\n",
+		"\t\tfsutil reparsepoint delete $placeholder > $null 2> $null;\r\n",
+		"\t\tCopy-Item -LiteralPath $original -Destination $placeholder -Force > $null 2> $null;\r\n",
+		"\t\tif (!$?) {\r\n",
+		"\t\t\techo \"[ERROR] $placeholder\"\r\n",
+		"\t\t}\r\n",
+		"\t\telse {\r\n",
+		"\t\t\techo \"[OK_FORCED] $placeholder\"\r\n",
+		"\t\t}\r\n",
+		"\t}\r\n",
+		"\telse {\r\n",
+		"\t\techo \"[OK] $placeholder\"\r\n",
+		"\t}\r\n",
+		"}\r\n",
+		"\r\n"))?;
+	for reconciliation in reconciliations.iter().filter(|reconciliation| reconciliation.status == Status::Matched) {
+		if let Some(ref original) = reconciliation.original {
+			write!(output, "Restore-Placeholder {} {}\r\n", powershell_quote(&reconciliation.placeholder), powershell_quote(original))?;
+		}
+	}
+	Ok(())
+}
+
+/// Quotes a string as a PowerShell literal (single quotes, which do not expand variables).
+fn powershell_quote(text: &str) -> String {
+	format!("'{}'", text.replace('\'', "''").replace('\u{2018}', "\u{2018}\u{2018}").replace('\u{2019}', "\u{2019}\u{2019}"))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env::temp_dir;
+	use std::fs::{create_dir_all, remove_dir_all, write};
+	use super::*;
+
+	fn paths(placeholders: &[Placeholder]) -> Vec<(&str, Option<u64>)> {
+		placeholders.iter().map(|placeholder| (placeholder.path.as_str(), placeholder.size)).collect()
+	}
+
+	#[test]
+	fn parse_placeholder_lists() {
+		let csv = "#TYPE System.IO.FileInfo\r\n\"Name\",\"FullName\",\"Length\"\r\n\"a.doc\",\"F:\\Data\\a.doc\",\"1234\"\r\n\"b,c.doc\",\"F:\\Data\\b,c.doc\",\"\"\r\n";
+		assert_eq!(paths(&parse_placeholders(csv).unwrap()), [("F:\\Data\\a.doc", Some(1234)), ("F:\\Data\\b,c.doc", None)]);
+		let list = "\r\nFullName\r\n--------\r\nF:\\Data\\a.doc\r\n\r\nF:\\Data\\b.doc   \r\n";
+		assert_eq!(paths(&parse_placeholders(list).unwrap()), [("F:\\Data\\a.doc", None), ("F:\\Data\\b.doc", None)]);
+		assert!(parse_placeholders("").unwrap().is_empty());
+	}
+
+	#[test]
+	fn split_csv_lines() {
+		assert_eq!(split_csv_line("a,\"b,\"\"c\"\"\",,d"), ["a", "b,\"c\"", "", "d"]);
+		assert_eq!(split_csv_line(""), [""]);
+	}
+
+	#[test]
+	fn split_relative_components() {
+		assert_eq!(relative_components("F:\\Data\\a.doc"), ["Data", "a.doc"]);
+		assert_eq!(relative_components("\\\\server\\share\\Data\\a.doc"), ["Data", "a.doc"]);
+		assert_eq!(relative_components("\\\\?\\UNC\\server\\share\\Data/a.doc"), ["Data", "a.doc"]);
+		assert_eq!(relative_components("\\\\?\\R:\\F\\Data\\a.doc"), ["F", "Data", "a.doc"]);
+		assert!(relative_components("F:\\").is_empty());
+	}
+
+	#[test]
+	fn read_manifests() {
+		let journal = "ok\t10\t1\ta.dvs\tR:\\F\\a.doc\nfailed\t10\t1\tb.dvs\t-\nok\t10\t1\tc.dvs\t-\n";
+		assert_eq!(read_manifest(journal.as_bytes()).unwrap().iter().map(|restored| restored.path.as_str()).collect::<Vec<&str>>(), ["R:\\F\\a.doc"]);
+		let plan = "# evrecovery restore plan\nembedded\t12\ta.dvs\t-\tR:\\F\\a.doc\nmissing\t-\tb.dvs\t-\t-\n";
+		let restored = read_manifest(plan.as_bytes()).unwrap();
+		assert_eq!(restored.iter().map(|restored| (restored.path.as_str(), restored.size)).collect::<Vec<(&str, Option<u64>)>>(), [("R:\\F\\a.doc", Some(12))]);
+	}
+
+	#[test]
+	fn reconcile_placeholders() {
+		let dir = temp_dir().join(format!("evrecovery-reconcile-{}", std::process::id()));
+		create_dir_all(dir.join("R").join("Data")).unwrap();
+		create_dir_all(dir.join("S").join("Other")).unwrap();
+		write(dir.join("R").join("Data").join("a.doc"), b"1234").unwrap();
+		write(dir.join("R").join("Data").join("b.doc"), b"12").unwrap();
+		write(dir.join("S").join("Other").join("c.doc"), b"123").unwrap();
+		let restored = [
+			Restored { path: dir.join("R").join("Data").join("a.doc").to_string_lossy().into_owned(), size: None },
+			Restored { path: dir.join("R").join("Data").join("b.doc").to_string_lossy().into_owned(), size: Some(3) },
+		];
+		let placeholders = [
+			Placeholder { path: "F:\\data\\A.doc".to_owned(), size: Some(4) },
+			Placeholder { path: "F:\\Data\\b.doc".to_owned(), size: None },
+			Placeholder { path: "\\\\server\\share\\Other\\c.doc".to_owned(), size: None },
+			Placeholder { path: "F:\\Data\\d.doc".to_owned(), size: None },
+		];
+		let reconciliations = reconcile(&placeholders, &restored, &[&dir.join("S")]);
+		remove_dir_all(&dir).unwrap();
+
+		let statuses = reconciliations.iter().map(|reconciliation| reconciliation.status.name()).collect::<Vec<&str>>();
+		assert_eq!(statuses, ["matched", "size-mismatch", "matched", "missing"]);
+		assert_eq!(reconciliations[0].original, Some(restored[0].path.clone()));
+		assert_eq!((reconciliations[1].expected_size, reconciliations[1].actual_size), (Some(3), Some(2)));
+		assert_eq!(reconciliations[2].original, Some(dir.join("S").join("Other").join("c.doc").to_string_lossy().into_owned()));
+
+		let mut report = Vec::new();
+		write_report(&reconciliations[3..], &mut report).unwrap();
+		assert_eq!(report, b"missing\tF:\\Data\\d.doc\t-\t-\t-\n");
+		let mut script = Vec::new();
+		write_script(&reconciliations, &mut script).unwrap();
+		let script = String::from_utf8(script).unwrap();
+		assert_eq!(script.matches("Restore-Placeholder '").count(), 2);
+		assert!(script.contains(&format!("Restore-Placeholder 'F:\\data\\A.doc' '{}'\r\n", restored[0].path)));
+	}
+
+	#[test]
+	fn quote_for_powershell() {
+		assert_eq!(powershell_quote("F:\\Bob's $files\u{2019}"), "'F:\\Bob''s $files\u{2019}\u{2019}'");
+	}
+}
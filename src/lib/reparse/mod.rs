@@ -0,0 +1,449 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::io::{Error, ErrorKind};
+use std::collections::HashMap;
+use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use std::path::{Path, PathBuf};
+use super::io::format_guid;
+
+/// The reparse tag of Enterprise Vault placeholders.
+pub const EV_REPARSE_TAG: u32 = 0x00000010;
+/// The reparse GUID of Enterprise Vault placeholders.
+pub const EV_REPARSE_GUID: &str = "{9DD58ACD-4BE7-4F36-9CE3-B7738EE3C702}";
+
+/// The reparse point of an Enterprise Vault placeholder.
+///
+/// The layout of the reparse data is not documented; it has been inferred from placeholders in the wild:
+/// a 32-bit integer, the NUL-terminated UTF-16 name of the vault store (padded to a multiple of 8 bytes),
+/// two 64-bit sizes, a 32-bit integer, and the NUL-terminated UTF-16 URL from which the archived item can be downloaded.
+/// The URL contains the ID of the vault and the ID of the saveset.
+#[derive(Clone, Default)]
+pub struct ReparsePoint {
+	/// The reparse tag (`0x10` for Enterprise Vault).
+	pub tag: u32,
+	/// The reparse GUID, if known.
+	pub guid: Option<String>,
+	/// The raw reparse data.
+	pub data: Vec<u8>,
+	/// The 32-bit integer at the start of the reparse data (meaning unknown).
+	pub header: Option<u32>,
+	/// The name of the vault store, e.g. `E-VAULT012345678`.
+	pub vault_store: Option<String>,
+	/// The size of the original file.
+	pub file_size: Option<u64>,
+	/// The allocation size of the original file (the size rounded up to whole clusters).
+	pub allocation_size: Option<u64>,
+	/// The 32-bit integer following the sizes (meaning unknown; possibly flags).
+	pub flags: Option<u32>,
+	/// The download URL of the archived item.
+	pub url: Option<String>,
+	/// The `VaultID` parameter of the download URL.
+	pub vault_id: Option<String>,
+	/// The `SavesetID` parameter of the download URL.
+	pub saveset_id: Option<String>,
+}
+
+impl ReparsePoint {
+	/// Parses a reparse buffer as returned by `FSCTL_GET_REPARSE_POINT` (tag, length, GUID and data),
+	/// or only the reparse data (in which case the Enterprise Vault tag is assumed).
+	pub fn from_bytes(buffer: &[u8]) -> Result<ReparsePoint, Error> {
+		if buffer.len() >= 24 && read_u32(buffer, 0) == Some(EV_REPARSE_TAG) {
+			let mut guid = [0; 16];
+			guid.copy_from_slice(&buffer[8..24]);
+			if format_guid(&guid) == EV_REPARSE_GUID {
+				let length = read_u16(buffer, 4).unwrap() as usize;
+				let data = &buffer[24..buffer.len().min(24 + length)];
+				return Ok(ReparsePoint::from_data(EV_REPARSE_TAG, Some(EV_REPARSE_GUID.to_owned()), data));
+			}
+		}
+		Ok(ReparsePoint::from_data(EV_REPARSE_TAG, None, buffer))
+	}
+
+	/// Parses the reparse data of an Enterprise Vault placeholder.
+	/// Fields that cannot be found are left empty; the raw data is always retained.
+	pub fn from_data(tag: u32, guid: Option<String>, data: &[u8]) -> ReparsePoint {
+		let mut result = ReparsePoint { tag, guid, data: data.to_vec(), header: read_u32(data, 0), ..Default::default() };
+
+		// The vault store name, followed by the sizes and flags at the next 8-byte boundary
+		let mut offset = 4;
+		if let Some((vault_store, end)) = read_string16(data, 4) {
+			if !vault_store.is_empty() {
+				result.vault_store = Some(vault_store);
+			}
+			offset = end.div_ceil(8) * 8;
+			result.file_size = read_u64(data, offset);
+			result.allocation_size = read_u64(data, offset + 8);
+			result.flags = read_u32(data, offset + 16);
+			offset += 20;
+		}
+
+		// The download URL; if it is not where it is expected, look for it anywhere in the data
+		let url_start = find_string16(data, offset, "http").or_else(|| find_string16(data, 0, "http"));
+		if let Some((url, _)) = url_start.and_then(|start| read_string16(data, start)) {
+			let parameters = url_parameters(&url);
+			result.vault_id = parameters.get("vaultid").cloned();
+			result.saveset_id = parameters.get("savesetid").cloned();
+			result.url = Some(url);
+		}
+		result
+	}
+
+	/// The transaction ID of the saveset, which is also the name of its DVS file (without extension).
+	/// Saveset IDs have the format `<date>~<date>~Z~<transaction ID>`; IDs without a `~` are returned as they are.
+	pub fn transaction_id(&self) -> Option<&str> {
+		self.saveset_id.as_ref().map(|saveset_id| saveset_id.rsplit('~').next().unwrap_or(saveset_id).trim())
+	}
+
+	/// Whether the given DVS file belongs to this placeholder, by comparing its name to the saveset's transaction ID.
+	pub fn matches_dvs(&self, dvs_path: &Path) -> bool {
+		match (self.transaction_id(), dvs_path.file_stem()) {
+			(Some(transaction_id), Some(stem)) => !transaction_id.is_empty() && stem.to_string_lossy().eq_ignore_ascii_case(transaction_id),
+			_ => false,
+		}
+	}
+}
+
+/// A placeholder file, together with its reparse point.
+pub struct Placeholder {
+	/// The path of the placeholder, if known.
+	pub path: Option<String>,
+	pub reparse_point: ReparsePoint,
+}
+
+/// A single `fsutil reparsepoint query` output, while it is being parsed.
+struct FsutilOutput {
+	path: Option<String>,
+	tag: u32,
+	guid: Option<String>,
+	length: Option<usize>,
+	data: Vec<u8>,
+}
+
+/// Parses the output of one or more invocations of `fsutil reparsepoint query <FILE>`.
+/// A line preceding an output that does not belong to any output (e.g. written by `echo $placeholder`) is taken as the path of the placeholder.
+/// Outputs whose reparse tag is not the Enterprise Vault one are skipped.
+pub fn parse_fsutil(text: &str) -> Result<Vec<Placeholder>, Error> {
+	let mut result = Vec::new();
+	let mut path: Option<String> = None;
+	let mut current: Option<FsutilOutput> = None;
+
+	let mut finish = |current: Option<FsutilOutput>| {
+		if let Some(mut output) = current {
+			if let Some(length) = output.length {
+				output.data.truncate(length);
+			}
+			if output.tag == EV_REPARSE_TAG {
+				result.push(Placeholder { path: output.path, reparse_point: ReparsePoint::from_data(output.tag, output.guid, &output.data) });
+			}
+		}
+	};
+
+	for (index, line) in text.lines().enumerate() {
+		let trimmed = line.trim();
+		if trimmed.is_empty() {
+			continue;
+		}
+
+		// The first line of an output: "Reparse Tag Value : 0x00000010"
+		if is_tag_line(trimmed) {
+			finish(current.take());
+			let tag = parse_hex_value(trimmed).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Invalid reparse tag in line {}", index + 1)))?;
+			current = Some(FsutilOutput { path: path.take(), tag: tag as u32, guid: None, length: None, data: Vec::new() });
+			continue;
+		}
+
+		if !is_output_line(trimmed) {
+			// Not part of an output: this is the path of the next placeholder
+			finish(current.take());
+			path = Some(trimmed.to_owned());
+			continue;
+		}
+		if let Some(ref mut output) = current {
+			if let (Some(start), Some(end)) = (trimmed.find('{'), trimmed.find('}')) {
+				if output.guid.is_none() && start < end {
+					output.guid = Some(trimmed[start..=end].to_uppercase());
+					continue;
+				}
+			}
+			if let Some(bytes) = parse_hexdump_line(trimmed) {
+				output.data.extend(bytes);
+			}
+			else if output.length.is_none() && trimmed.to_lowercase().contains("length") {
+				output.length = parse_hex_value(trimmed).map(|length| length as usize);
+			}
+		}
+	}
+	finish(current.take());
+	Ok(result)
+}
+
+/// Whether the text contains the output of `fsutil reparsepoint query`.
+pub fn is_fsutil_output(text: &str) -> bool {
+	text.lines().any(|line| is_tag_line(line.trim()))
+}
+
+/// Whether the line is the first line of an `fsutil reparsepoint query` output.
+fn is_tag_line(line: &str) -> bool {
+	let lowercase = line.to_lowercase();
+	lowercase.starts_with("reparse tag") || (lowercase.contains("tag") && lowercase.contains(": 0x"))
+}
+
+/// Whether the line belongs to an `fsutil reparsepoint query` output (other than its first line).
+fn is_output_line(line: &str) -> bool {
+	let lowercase = line.to_lowercase();
+	parse_hexdump_line(line).is_some()
+		|| lowercase.contains("reparse")
+		|| lowercase.starts_with("tag value")
+		|| lowercase.starts_with("guid")
+		|| lowercase.ends_with(':')
+		|| lowercase.starts_with("...")
+}
+
+/// Parses the hexadecimal value (`0x...`) after the colon of a line.
+fn parse_hex_value(line: &str) -> Option<u64> {
+	let value = line.rsplit(':').next()?.trim();
+	let value = value.trim_start_matches("0x").trim_start_matches("0X");
+	u64::from_str_radix(value.split_whitespace().next()?, 16).ok()
+}
+
+/// Parses a line of a hex dump (`0000:  61 1e 00 00 ...  a...`) into up to 16 bytes.
+fn parse_hexdump_line(line: &str) -> Option<Vec<u8>> {
+	let colon = line.find(':')?;
+	let offset = &line[..colon];
+	if offset.len() < 4 || !offset.chars().all(|c| c.is_ascii_hexdigit()) {
+		return None;
+	}
+	let mut bytes = Vec::new();
+	for token in line[colon + 1..].split_whitespace() {
+		if bytes.len() == 16 || token.len() != 2 {
+			break;
+		}
+		match u8::from_str_radix(token, 16) {
+			Ok(byte) => bytes.push(byte),
+			Err(_) => break,
+		}
+	}
+	if bytes.is_empty() { None } else { Some(bytes) }
+}
+
+/// Reads a NUL-terminated UTF-16 string at the given offset.
+/// Returns the string and the offset just behind its NUL character (or the end of the data).
+fn read_string16(data: &[u8], offset: usize) -> Option<(String, usize)> {
+	if offset >= data.len() {
+		return None;
+	}
+	let mut buffer16 = Vec::new();
+	let mut end = offset;
+	while end + 1 < data.len() {
+		let c = data[end] as u16 | (data[end + 1] as u16) << 8;
+		end += 2;
+		if c == 0 {
+			break;
+		}
+		buffer16.push(c);
+	}
+	let string = decode_utf16(buffer16.iter().cloned()).map(|c| c.unwrap_or(REPLACEMENT_CHARACTER)).collect::<String>();
+	Some((string, end))
+}
+
+/// Finds the first occurrence of an (ASCII) text, encoded as UTF-16, at an even offset not before the given one.
+fn find_string16(data: &[u8], offset: usize, text: &str) -> Option<usize> {
+	let needle = text.bytes().flat_map(|byte| vec![byte, 0]).collect::<Vec<u8>>();
+	let mut start = offset + offset % 2;
+	while start + needle.len() <= data.len() {
+		if data[start..start + needle.len()].eq_ignore_ascii_case(&needle) {
+			return Some(start);
+		}
+		start += 2;
+	}
+	None
+}
+
+/// Extracts the (percent-decoded) query parameters of a URL; their names are lowercased.
+fn url_parameters(url: &str) -> HashMap<String, String> {
+	let query = match url.find('?') {
+		Some(index) => &url[index + 1..],
+		None => return HashMap::new(),
+	};
+	query.split('&').filter_map(|parameter| {
+		let mut parts = parameter.splitn(2, '=');
+		let name = parts.next()?;
+		Some((percent_decode(name).to_lowercase(), percent_decode(parts.next().unwrap_or(""))))
+	}).collect()
+}
+
+fn percent_decode(text: &str) -> String {
+	let bytes = text.as_bytes();
+	let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+	let mut result = Vec::with_capacity(bytes.len());
+	let mut index = 0;
+	while index < bytes.len() {
+		match (bytes[index], bytes.get(index + 1).cloned().and_then(hex), bytes.get(index + 2).cloned().and_then(hex)) {
+			(b'%', Some(high), Some(low)) => {
+				result.push(high << 4 | low);
+				index += 3;
+			},
+			(b'+', _, _) => { result.push(b' '); index += 1; },
+			(byte, _, _) => { result.push(byte); index += 1; },
+		}
+	}
+	String::from_utf8_lossy(&result).into_owned()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+	data.get(offset..offset + 2).map(|bytes| bytes[0] as u16 | (bytes[1] as u16) << 8)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+	data.get(offset..offset + 4).map(|bytes| bytes.iter().rev().fold(0u32, |result, &byte| result << 8 | byte as u32))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+	data.get(offset..offset + 8).map(|bytes| bytes.iter().rev().fold(0u64, |result, &byte| result << 8 | byte as u64))
+}
+
+/// Matches placeholders to DVS files by the transaction ID of their saveset.
+/// Returns, for every placeholder, the DVS files whose names match (usually at most one).
+pub fn match_dvs_files(placeholders: &[Placeholder], dvs_files: &[PathBuf]) -> Vec<Vec<PathBuf>> {
+	let mut by_name: HashMap<String, Vec<PathBuf>> = HashMap::new();
+	for dvs_file in dvs_files {
+		if let Some(stem) = dvs_file.file_stem() {
+			by_name.entry(stem.to_string_lossy().to_uppercase()).or_default().push(dvs_file.clone());
+		}
+	}
+	placeholders.iter().map(|placeholder| match placeholder.reparse_point.transaction_id() {
+		Some(transaction_id) => by_name.get(&transaction_id.to_uppercase()).cloned().unwrap_or_default(),
+		None => Vec::new(),
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::io::testing::utf16z;
+
+	const URL: &str = "http://ev.example.com/EnterpriseVault/download.asp?VaultID=1A2B%2D3C&SavesetID=201501011200000~201412311100000~Z~ABCDEF0123456789";
+
+	/// Builds reparse data in the layout of an Enterprise Vault placeholder.
+	fn reparse_data() -> Vec<u8> {
+		let mut data = vec![0x61, 0x1e, 0, 0];
+		data.extend(utf16z("E-VAULT01"));
+		data.resize(data.len().div_ceil(8) * 8, 0);
+		data.extend(&1234u64.to_le_bytes());
+		data.extend(&4096u64.to_le_bytes());
+		data.extend(&7u32.to_le_bytes());
+		data.extend(utf16z(URL));
+		data
+	}
+
+	fn guid_bytes() -> [u8; 16] {
+		[0xcd, 0x8a, 0xd5, 0x9d, 0xe7, 0x4b, 0x36, 0x4f, 0x9c, 0xe3, 0xb7, 0x73, 0x8e, 0xe3, 0xc7, 0x02]
+	}
+
+	#[test]
+	fn format_guids() {
+		assert_eq!(format_guid(&guid_bytes()), EV_REPARSE_GUID);
+	}
+
+	#[test]
+	fn parse_reparse_data() {
+		let reparse_point = ReparsePoint::from_bytes(&reparse_data()).unwrap();
+		assert_eq!(reparse_point.tag, EV_REPARSE_TAG);
+		assert_eq!(reparse_point.guid, None);
+		assert_eq!(reparse_point.header, Some(0x1e61));
+		assert_eq!(reparse_point.vault_store, Some("E-VAULT01".to_owned()));
+		assert_eq!((reparse_point.file_size, reparse_point.allocation_size, reparse_point.flags), (Some(1234), Some(4096), Some(7)));
+		assert_eq!(reparse_point.url, Some(URL.to_owned()));
+		assert_eq!(reparse_point.vault_id, Some("1A2B-3C".to_owned()));
+		assert_eq!(reparse_point.transaction_id(), Some("ABCDEF0123456789"));
+		assert!(reparse_point.matches_dvs(Path::new("vault/2015/abcdef0123456789.DVS")));
+		assert!(!reparse_point.matches_dvs(Path::new("vault/2015/ABCDEF012345678.DVS")));
+	}
+
+	#[test]
+	fn parse_reparse_buffer() {
+		let data = reparse_data();
+		let mut buffer = EV_REPARSE_TAG.to_le_bytes().to_vec();
+		buffer.extend(&(data.len() as u16).to_le_bytes());
+		buffer.extend(&[0, 0]);
+		buffer.extend(guid_bytes());
+		buffer.extend(&data);
+		// Trailing bytes beyond the reparse data length are ignored
+		buffer.extend(&[0xff; 8]);
+		let reparse_point = ReparsePoint::from_bytes(&buffer).unwrap();
+		assert_eq!(reparse_point.guid, Some(EV_REPARSE_GUID.to_owned()));
+		assert_eq!(reparse_point.data, data);
+		assert_eq!(reparse_point.transaction_id(), Some("ABCDEF0123456789"));
+	}
+
+	#[test]
+	fn parse_truncated_data() {
+		// Without the sizes, the URL is still found
+		let mut data = vec![0, 0, 0, 0];
+		data.extend(utf16z(URL));
+		let reparse_point = ReparsePoint::from_data(EV_REPARSE_TAG, None, &data);
+		assert_eq!(reparse_point.url, Some(URL.to_owned()));
+		let reparse_point = ReparsePoint::from_data(EV_REPARSE_TAG, None, &[1, 2]);
+		assert!(reparse_point.header.is_none() && reparse_point.url.is_none() && reparse_point.transaction_id().is_none());
+	}
+
+	#[test]
+	fn parse_fsutil_output() {
+		let data = reparse_data();
+		let mut text = "D:\\Shares\\report.docx\r\nReparse Tag Value : 0x00000010\r\nTag value: Microsoft\r\n".to_owned();
+		text.push_str(&format!("GUID : {}\r\n\r\nReparse Data Length: 0x{:08x}\r\nReparse Data:\r\n", EV_REPARSE_GUID.to_lowercase(), data.len()));
+		for (index, chunk) in data.chunks(16).enumerate() {
+			let hex = chunk.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ");
+			text.push_str(&format!("{:04x}:  {}  ....\r\n", index * 16, hex));
+		}
+		text.push_str("D:\\Shares\\link\r\nReparse Tag Value : 0xa000000c\r\nReparse Data Length: 0x00000002\r\n0000:  01 02\r\n");
+		assert!(is_fsutil_output(&text));
+		let placeholders = parse_fsutil(&text).unwrap();
+		// Only the Enterprise Vault reparse point is kept
+		assert_eq!(placeholders.len(), 1);
+		assert_eq!(placeholders[0].path, Some("D:\\Shares\\report.docx".to_owned()));
+		assert_eq!(placeholders[0].reparse_point.guid, Some(EV_REPARSE_GUID.to_owned()));
+		assert_eq!(placeholders[0].reparse_point.data, data);
+		assert_eq!(placeholders[0].reparse_point.vault_store, Some("E-VAULT01".to_owned()));
+
+		assert!(!is_fsutil_output("D:\\Shares\\report.docx"));
+		assert!(parse_fsutil("Reparse Tag Value : 0xzz").is_err());
+	}
+
+	#[test]
+	fn decode_url_parameters() {
+		let parameters = url_parameters("http://host/a?First=a%20b+c&second=%zz&third");
+		assert_eq!(parameters.get("first").map(String::as_str), Some("a b c"));
+		assert_eq!(parameters.get("second").map(String::as_str), Some("%zz"));
+		assert_eq!(parameters.get("third").map(String::as_str), Some(""));
+		assert!(url_parameters("http://host/a").is_empty());
+	}
+
+	#[test]
+	fn match_placeholders() {
+		let placeholders = vec![
+			Placeholder { path: None, reparse_point: ReparsePoint::from_data(EV_REPARSE_TAG, None, &reparse_data()) },
+			Placeholder { path: None, reparse_point: ReparsePoint::default() },
+		];
+		let dvs_files = vec![PathBuf::from("a/ABCDEF0123456789.DVS"), PathBuf::from("b/other.dvs"), PathBuf::from("c/abcdef0123456789.dvs")];
+		let matches = match_dvs_files(&placeholders, &dvs_files);
+		assert_eq!(matches[0], vec![PathBuf::from("a/ABCDEF0123456789.DVS"), PathBuf::from("c/abcdef0123456789.dvs")]);
+		assert!(matches[1].is_empty());
+	}
+}
@@ -0,0 +1,531 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+pub mod plan;
+
+use std::io::{Read, Write, Seek, SeekFrom, Error, ErrorKind};
+use std::io::{Cursor, copy};
+use std::fs::{File, metadata};
+use std::path::{Component, Path, PathBuf};
+use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use super::cab::{CabinetCache, is_cabinet, member_paths, split_member_path};
+use super::cfbf::{Container, Object, ObjectResult};
+use super::dvs::File as DvsFile;
+use super::io::{Debug, ReadSeek, SeekableRead, find_files};
+use super::metadata::{read_stream, decode_string};
+use super::propset::Summary;
+use super::sis::{Index as SisIndex, MissingPart, read_reference, write_part_content};
+use super::sniff::{FileType, read_zip_member, sniff};
+use super::sniff::encryption::{Encryption, detect_container_encryption};
+use super::vba::{Project, read_projects};
+
+/// The signature at the start of every CFBF file.
+const CFBF_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// The maximum number of bytes of embedded or sharable content that are read into memory for inspecting it.
+const MAX_INSPECTED_SIZE: usize = 64 * 1024 * 1024;
+
+/// Expands an input path into a list of DVS files.
+/// Directories are searched recursively for DVS files and cabinet (`.cab`) files; the DVS files are sorted for every directory.
+/// Cabinet files are treated like directories: their DVS members are listed (in the order in which they are stored) as paths beneath the cabinet file, which `open_input` can open.
+/// Cabinet files that cannot be read are logged and skipped.
+pub fn find_inputs(path: &Path, debug: &mut Debug) -> Result<Vec<PathBuf>, Error> {
+	if is_cabinet(path) && path.is_file() {
+		return Ok(cabinet_inputs(path, debug));
+	}
+	if !path.is_dir() {
+		return Ok(vec![path.to_path_buf()]);
+	}
+
+	let mut result = find_files(path, "dvs")?;
+	for cabinet_path in find_files(path, "cab")? {
+		result.extend(cabinet_inputs(&cabinet_path, debug));
+	}
+	Ok(result)
+}
+
+/// Lists the DVS members of a cabinet file, or none if the cabinet file cannot be read.
+fn cabinet_inputs(cabinet_path: &Path, debug: &mut Debug) -> Vec<PathBuf> {
+	match member_paths(cabinet_path, "dvs", debug) {
+		Ok(paths) => paths,
+		Err(e) => {
+			debug.logln(0, format!("Skipping cabinet {:?}, which cannot be read: {}", cabinet_path, e));
+			Vec::new()
+		},
+	}
+}
+
+/// Opens an input DVS file, which is either a regular file or a member of a cabinet file (see `find_inputs`).
+/// The most recently used cabinet file is kept open in `cabinets`.
+pub fn open_input(path: &Path, cabinets: &mut CabinetCache, debug: &mut Debug) -> Result<Box<dyn Read>, Error> {
+	if !path.exists() && split_member_path(path).is_some() {
+		return Ok(Box::new(Cursor::new(cabinets.read_member_path(path, debug)?)));
+	}
+	Ok(Box::new(File::open(path)?))
+}
+
+/// Decompresses a DVS file and parses the contained CFBF payload.
+pub fn open_payload(input: impl Read, debug: &mut Debug) -> Result<Container<SeekableRead>, Error> {
+	debug.logln(1, "[open_payload] Decompressing DVS file ...");
+	let mut cfbfdata: Vec<u8> = Vec::new();
+	DvsFile::new(input, debug)?.decompress(&mut cfbfdata, debug)?;
+
+	debug.logln(1, format!("[open_payload] Parsing CFBF payload ({} bytes) ...", cfbfdata.len()));
+	Container::new(SeekableRead::new(&cfbfdata[..])?, debug)
+}
+
+/// The original location of an archived file, as recorded in the payload.
+pub struct OriginalPath {
+	/// The raw content of `/User Information/Location/ExchangeLocation/FolderPath`.
+	pub folder: String,
+	/// The raw content of `/User Information/User Archivable Item/Title`.
+	pub title: String,
+	/// The directory of the original file, converted into a path.
+	pub dir: PathBuf,
+	/// The name of the original file, converted into a single path component.
+	pub file: PathBuf,
+	/// Human-readable descriptions of all changes that were necessary to convert the raw strings into a path.
+	pub rewrites: Vec<String>,
+}
+
+impl OriginalPath {
+	/// The full path of the original file (directory and file name).
+	pub fn path(&self) -> PathBuf {
+		self.dir.join(&self.file)
+	}
+}
+
+/// Reads the original directory and file name of the archived file from the payload.
+/// If `strip_root` is set, all prefixes (such as drive letters), root directories, `.` and `..` are removed, so that the path is relative to the target directory.
+pub fn read_original_path(container: &mut Container<SeekableRead>, strip_root: bool, debug: &mut Debug) -> Result<OriginalPath, Error> {
+	// In the CFBF file, there are two embedded files that hold the original file path:
+	// (1) '/User Information/Location/ExchangeLocation/FolderPath' for the directory name
+	// (2) '/User Information/User Archivable Item/Title' for the file name
+	let folder = read_raw_string(container, "FolderPath", debug)?;
+	let title = read_raw_string(container, "Title", debug)?;
+
+	let mut rewrites = Vec::new();
+	let dir = convert_to_path(&folder, false, strip_root, &mut rewrites);
+	let file = convert_to_path(&title, true, strip_root, &mut rewrites);
+	if file.as_os_str().is_empty() {
+		return Err(Error::new(ErrorKind::InvalidData, "The original file name is empty!"));
+	}
+	debug.logln(1, format!("[read_original_path] Original path is {:?} / {:?}.", dir, file));
+	Ok(OriginalPath { folder, title, dir, file, rewrites })
+}
+
+/// Like `read_original_path`, but an archived file without a file name (i.e. with an empty `Title`) is named after the title
+/// that is recorded in its content, if it is a legacy Office document (see `read_document_summary`).
+/// The extension is taken from `/File Extension`.
+pub fn read_original_path_or_title(container: &mut Container<SeekableRead>, content: &mut Content, strip_root: bool, debug: &mut Debug) -> Result<OriginalPath, Error> {
+	let title = read_raw_string(container, "Title", debug).unwrap_or_default();
+	if !title.trim().is_empty() {
+		return read_original_path(container, strip_root, debug);
+	}
+	// A document whose summary information cannot be read is treated like any other file without a name
+	let document_title = match read_document_summary(container, content, debug) {
+		Ok(summary) => summary.and_then(|summary| summary.title),
+		Err(e) => {
+			debug.logln(1, format!("[read_original_path_or_title] Unable to read the summary information: {}", e));
+			None
+		},
+	};
+	let document_title = match document_title {
+		Some(document_title) => document_title,
+		None => return read_original_path(container, strip_root, debug),
+	};
+
+	let folder = read_raw_string(container, "FolderPath", debug)?;
+	let extension = read_stream(container, "File Extension", debug)?.and_then(|data| decode_string(&data)).unwrap_or_default();
+	let name = match extension.trim_start_matches('.') {
+		"" => document_title.clone(),
+		extension if document_title.to_lowercase().ends_with(&format!(".{}", extension.to_lowercase())) => document_title.clone(),
+		extension => format!("{}.{}", document_title, extension),
+	};
+	let mut rewrites = vec![format!("named after the document title '{}', since the original file name is empty", document_title)];
+	let dir = convert_to_path(&folder, false, strip_root, &mut rewrites);
+	let file = convert_to_path(&name, true, strip_root, &mut rewrites);
+	if file.as_os_str().is_empty() {
+		return Err(Error::new(ErrorKind::InvalidData, "The original file name is empty!"));
+	}
+	debug.logln(1, format!("[read_original_path_or_title] Original path is {:?} / {:?} (named after the document title).", dir, file));
+	Ok(OriginalPath { folder, title, dir, file, rewrites })
+}
+
+/// Reads an embedded file and interpretes it as a UTF-16 string (prefixed by a byte length).
+fn read_raw_string(container: &mut Container<SeekableRead>, name: &str, debug: &mut Debug) -> Result<String, Error> {
+	match container.find_child_by_name(name, debug)? {
+		ObjectResult::None => {
+			debug.logln(1, format!("[read_raw_string] Embedded file '/**/{}' could not be found!", name));
+			Err(Error::new(ErrorKind::InvalidData, "Unable to find an embedded file!"))
+		},
+		ObjectResult::Ok(object) => {
+			// Read the content from that embedded file
+			let mut buffer: Vec<u8> = Vec::new();
+			container.dump_stream(&object, &mut buffer, debug)?;
+			debug.logln(1, format!("[read_raw_string] Read {} bytes from '/**/{}'.", buffer.len(), name));
+			if buffer.len() < 6 {
+				return Err(Error::new(ErrorKind::InvalidData, "Embedded string is too short!"));
+			}
+
+			// The file content is a 32bit integer denoting the byte length, followed by a NUL-terminated UTF16-encoded string
+			// Extract the Unicode code points (as u16's); also remove the trailing NUL character
+			let mut buffer16 = vec![0u16; (buffer.len() - 4) / 2 - 1];
+			for (i, c) in buffer16.iter_mut().enumerate() {
+				*c = (buffer[i * 2 + 4] as u16) | (buffer[i * 2 + 5] as u16) << 8;
+			}
+			Ok(decode_utf16(buffer16.iter().cloned())
+				.map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+				.collect::<String>())
+		},
+	}
+}
+
+/// Converts a string into a (possibly absolute) path.
+/// The `as_single_component` argument specifies whether all directory separators shall be escaped (`true`) or not (`false`).
+/// If `strip_root` is set, the path is made relative to a target directory: prefixes, root directories, `.` and `..` are removed.
+/// All changes that go beyond normalising directory separators are described in `rewrites`.
+pub fn convert_to_path(string: &str, as_single_component: bool, strip_root: bool, rewrites: &mut Vec<String>) -> PathBuf {
+	if string.contains('\x7F') {
+		rewrites.push(format!("replaced DEL characters in '{}'", string.escape_default()));
+	}
+	if as_single_component && (string.contains('\\') || string.contains('/')) {
+		rewrites.push(format!("replaced directory separators in file name '{}' by '_'", string));
+	}
+	let path = PathBuf::from(string.chars()
+		.map(|c| if c == '\x7F' || c == '\\' || c == '/' { if as_single_component { '_' } else { '/' } } else { c })
+		.collect::<String>());
+
+	// For target paths, make sure that the path is not absolute by removing prefixes and root directories,
+	// and make sure that it does not escape the target directory
+	if strip_root && path.components().any(|c| c == Component::ParentDir || c == Component::CurDir) {
+		rewrites.push(format!("removed '.' and '..' from '{}'", string));
+	}
+	path
+		.components()
+		.filter(|c| match c {
+			Component::Prefix(_) | Component::RootDir | Component::ParentDir | Component::CurDir => !strip_root,
+			Component::Normal(_) => true,
+		})
+		.collect::<PathBuf>()
+}
+
+/// The location of the content of an archived file.
+pub enum Source {
+	/// The content is out-sourced to the given file.
+	Outsourced(PathBuf),
+	/// The content is embedded in the payload, in the given stream object.
+	Embedded(Object),
+	/// The content is held by the given sharable part (Single Instance Storage).
+	Sharable(PathBuf),
+	/// The content can be found nowhere.
+	Missing,
+}
+
+impl Source {
+	/// The size of the content, if it can be determined.
+	pub fn size(&self) -> Option<u64> {
+		match *self {
+			Source::Outsourced(ref path) => metadata(path).ok().map(|metadata| metadata.len()),
+			Source::Embedded(ref object) => Some(object.stream_size),
+			Source::Sharable(_) | Source::Missing => None,
+		}
+	}
+}
+
+/// Determines where the content of the archived file is located.
+/// An out-sourced file takes precedence, if it exists; otherwise the stream '/**/FileContentStream' is used.
+pub fn locate_content(container: &mut Container<SeekableRead>, outsourced: Option<&Path>, debug: &mut Debug) -> Result<Source, Error> {
+	// First look for an outsourced file
+	if let Some(outsourced) = outsourced {
+		debug.logln(1, format!("[locate_content] Looking for outsourced file {:?} ...", outsourced));
+		if let Ok(outsourced) = outsourced.canonicalize() {
+			return Ok(Source::Outsourced(outsourced));
+		}
+	}
+
+	// Find the object that contains the archived data
+	// In the CFBF file, this is the embedded file '/Sharable Content/Archivable Item/FileContentStream'
+	// But let's be more generous and search for any 'FileContentStream' in the entire file
+	debug.logln(1, "[locate_content] Locating archived data in the CFBF file ...");
+	match container.find_child_by_name("FileContentStream", debug)? {
+		ObjectResult::Ok(object) => Ok(Source::Embedded(object)),
+		ObjectResult::None => {
+			debug.logln(1, "[locate_content] The embedded file '/**/FileContentStream' does not exist in this file!");
+			Ok(Source::Missing)
+		},
+	}
+}
+
+/// Copies the content of the archived file from the given source to the output.
+pub fn write_content(container: &mut Container<SeekableRead>, source: &Source, output: &mut dyn Write, debug: &mut Debug) -> Result<(), Error> {
+	match *source {
+		Source::Outsourced(ref path) => {
+			debug.logln(1, format!("[write_content] Copying outsourced file {:?} ...", path));
+			copy(&mut File::open(path)?, output)?;
+			Ok(())
+		},
+		Source::Embedded(ref object) => {
+			debug.logln(1, "[write_content] Dumping embedded file '/**/FileContentStream' ...");
+			container.dump_stream(object, output, debug)
+		},
+		Source::Sharable(ref path) => {
+			debug.logln(1, format!("[write_content] Dumping sharable part {:?} ...", path));
+			write_part_content(path, output, debug)
+		},
+		Source::Missing => Err(Error::new(ErrorKind::InvalidData, "Unable to find embedded file '/**/FileContentStream', and no outsourced file can be found!")),
+	}
+}
+
+/// The content of an archived file, opened for inspecting its format, summary information, encryption and macros.
+/// Embedded and sharable content is read into memory only once (on first use), and only up to `MAX_INSPECTED_SIZE` bytes;
+/// out-sourced files are read in place.
+pub struct Content<'a> {
+	source: &'a Source,
+	/// The start of embedded or sharable content, once it has been read, and whether the content is longer than that.
+	prefix: Option<(Vec<u8>, bool)>,
+}
+
+impl<'a> Content<'a> {
+	pub fn new(source: &'a Source) -> Content<'a> {
+		Content { source, prefix: None }
+	}
+
+	/// Reads embedded or sharable content into memory, unless this has been done already.
+	/// Returns whether only the start of the content is available.
+	fn load(&mut self, container: &mut Container<SeekableRead>, debug: &mut Debug) -> Result<bool, Error> {
+		if let (&Source::Embedded(_), &None) | (&Source::Sharable(_), &None) = (self.source, &self.prefix) {
+			let mut prefix = Prefix { data: Vec::new(), truncated: false };
+			match write_content(container, self.source, &mut prefix, debug) {
+				Ok(()) => {},
+				// The copy is aborted once the limit is exceeded
+				Err(_) if prefix.truncated => debug.logln(1, format!("[Content::load] Only the first {} bytes of the content are inspected.", MAX_INSPECTED_SIZE)),
+				Err(e) => return Err(e),
+			}
+			self.prefix = Some((prefix.data, prefix.truncated));
+		}
+		Ok(self.prefix.as_ref().is_some_and(|&(_, truncated)| truncated))
+	}
+
+	/// Opens the content as a seekable input, once it has been loaded. Returns `None` if no content can be found.
+	fn open<'b>(&'b self) -> Result<Option<Box<dyn ReadSeek + 'b>>, Error> {
+		match (self.source, &self.prefix) {
+			(Source::Outsourced(path), _) => Ok(Some(Box::new(File::open(path)?))),
+			(_, &Some((ref data, _))) => Ok(Some(Box::new(Cursor::new(&data[..])))),
+			_ => Ok(None),
+		}
+	}
+
+	/// Opens the content as a CFBF container, if it is a CFBF file (such as a legacy Office document).
+	/// Returns `None` for all other files, and an error for CFBF files that are too large to be inspected.
+	fn open_cfbf<'b>(&'b mut self, container: &mut Container<SeekableRead>, debug: &mut Debug) -> Result<Option<Container<Box<dyn ReadSeek + 'b>>>, Error> {
+		let truncated = self.load(container, debug)?;
+		let mut input = match self.open()? {
+			Some(input) => input,
+			None => return Ok(None),
+		};
+		let mut signature = [0u8; 8];
+		if input.read_exact(&mut signature).is_err() || signature != CFBF_SIGNATURE {
+			return Ok(None);
+		}
+		if truncated {
+			return Err(Error::new(ErrorKind::InvalidData, format!("The content is larger than {} bytes, and cannot be inspected", MAX_INSPECTED_SIZE)));
+		}
+		input.seek(SeekFrom::Start(0))?;
+		Ok(Some(Container::new(input, debug)?))
+	}
+}
+
+/// Keeps the first `MAX_INSPECTED_SIZE` bytes that are written, and fails as soon as there are more.
+struct Prefix {
+	data: Vec<u8>,
+	truncated: bool,
+}
+
+impl Write for Prefix {
+	fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+		let length = buf.len().min(MAX_INSPECTED_SIZE - self.data.len());
+		if length == 0 && !buf.is_empty() {
+			self.truncated = true;
+			return Err(Error::other("The content exceeds the inspected size"));
+		}
+		self.data.extend_from_slice(&buf[..length]);
+		Ok(length)
+	}
+
+	fn flush(&mut self) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+/// Reads the summary information (title, author, dates etc.) that is recorded in the content of the archived file,
+/// if it is a CFBF file (such as a legacy Office document). Returns `None` for all other files.
+pub fn read_document_summary(container: &mut Container<SeekableRead>, content: &mut Content, debug: &mut Debug) -> Result<Option<Summary>, Error> {
+	match content.open_cfbf(container, debug)? {
+		Some(mut content) => Ok(Some(Summary::read(&mut content, debug)?)),
+		None => Ok(None),
+	}
+}
+
+/// Reads the VBA projects (macros) in the content of the archived file, if it is a legacy Office document (a CFBF file),
+/// or a macro-enabled Office Open XML document (such as `.docm` or `.xlsm`, which holds the projects in a CFBF member `vbaProject.bin`).
+/// Returns an empty list for all other files.
+pub fn read_vba_projects(container: &mut Container<SeekableRead>, content: &mut Content, debug: &mut Debug) -> Result<Vec<Project>, Error> {
+	if let Some(mut content) = content.open_cfbf(container, debug)? {
+		return read_projects(&mut content, debug);
+	}
+	let truncated = content.load(container, debug)?;
+	let mut input = match content.open()? {
+		Some(input) => input,
+		None => return Ok(Vec::new()),
+	};
+	let mut signature = [0u8; 4];
+	if input.read_exact(&mut signature).is_err() || signature != *b"PK\x03\x04" {
+		return Ok(Vec::new());
+	}
+	if truncated {
+		return Err(Error::new(ErrorKind::InvalidData, format!("The content is larger than {} bytes, and cannot be scanned for VBA macros", MAX_INSPECTED_SIZE)));
+	}
+	let (name, data) = match read_zip_member(&mut input, |name| name.rsplit('/').next().is_some_and(|name| name.eq_ignore_ascii_case("vbaProject.bin")))? {
+		Some(member) => member,
+		None => return Ok(Vec::new()),
+	};
+	debug.logln(1, format!("[read_vba_projects] Reading VBA projects in ZIP member '{}' ...", name));
+	let mut projects = read_projects(&mut Container::new(Cursor::new(data), debug)?, debug)?;
+	for project in projects.iter_mut() {
+		project.path = format!("/{}!{}", name, project.path);
+	}
+	Ok(projects)
+}
+
+/// Determines whether the archived file is an encrypted or IRM-protected Office document (see `sniff::encryption`).
+/// Returns `None` for all other files.
+pub fn detect_content_encryption(container: &mut Container<SeekableRead>, content: &mut Content, debug: &mut Debug) -> Result<Option<Encryption>, Error> {
+	match content.open_cfbf(container, debug)? {
+		Some(mut content) => detect_container_encryption(&mut content, debug),
+		None => Ok(None),
+	}
+}
+
+/// Determines the type of the archived file by inspecting its content (see `sniff::sniff`).
+/// Returns `None` if no content can be found, or if its type is not recognized.
+pub fn sniff_content(container: &mut Container<SeekableRead>, content: &mut Content, debug: &mut Debug) -> Result<Option<&'static FileType>, Error> {
+	content.load(container, debug)?;
+	match content.open()? {
+		Some(mut input) => sniff(&mut input, debug),
+		None => Ok(None),
+	}
+}
+
+/// Determines the sharable part that holds the content of the archived file, if the payload refers to one (Single Instance Storage).
+/// Returns `Source::Missing` if the payload does not refer to a sharable part, and a `MissingPart` error if the part cannot be found
+/// (which names the converted content of the archived file, if it can be found).
+pub fn locate_sharable(container: &mut Container<SeekableRead>, sis_index: &mut SisIndex, dvs_path: &Path, debug: &mut Debug) -> Result<Source, Error> {
+	let reference = match read_reference(container, debug)? {
+		Some(reference) => reference,
+		None => return Ok(Source::Missing),
+	};
+	debug.logln(1, format!("[locate_sharable] Looking for sharable part ({}) ...", reference));
+	if let Some(path) = sis_index.resolve_sharable(&reference, dvs_path, debug) {
+		return Ok(Source::Sharable(path));
+	}
+	// Point to the converted content instead, if there is any
+	let converted = sis_index.resolve_converted(&reference, dvs_path, debug);
+	Err(MissingPart::new(&reference, converted).into_error())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::stderr;
+	use std::fs::{create_dir_all, remove_dir_all, write};
+	use std::env::temp_dir;
+	use std::path::PathBuf;
+	use super::super::cfbf::Container;
+	use super::super::cfbf::testing::build_cfbf;
+	use super::super::io::{Debug, SeekableRead};
+	use super::super::sniff::testing::build_zip;
+	use super::{Content, Source, convert_to_path, find_inputs, locate_content, sniff_content, read_document_summary, read_vba_projects};
+
+	fn convert(string: &str, as_single_component: bool, strip_root: bool) -> (PathBuf, usize) {
+		let mut rewrites = Vec::new();
+		let path = convert_to_path(string, as_single_component, strip_root, &mut rewrites);
+		(path, rewrites.len())
+	}
+
+	#[test]
+	fn convert_target_paths() {
+		assert_eq!(convert("\\Data\\..\\Projects\\.\\2018", false, true), (PathBuf::from("Data/Projects/2018"), 1));
+		assert_eq!(convert("a/b\\c.doc", true, true), (PathBuf::from("a_b_c.doc"), 1));
+		assert_eq!(convert("del\x7F.doc", true, true), (PathBuf::from("del_.doc"), 1));
+	}
+
+	#[test]
+	fn keep_original_paths() {
+		// Without `strip_root`, the path is reported as recorded
+		assert_eq!(convert("/Data/../Projects/./2018", false, false), (PathBuf::from("/Data/../Projects/./2018"), 0));
+	}
+
+	#[test]
+	fn skip_unreadable_cabinets() {
+		let dir = temp_dir().join(format!("evrecovery-inputs-{}", std::process::id()));
+		create_dir_all(&dir).unwrap();
+		write(dir.join("a.dvs"), b"").unwrap();
+		write(dir.join("broken.cab"), b"MSCF").unwrap();
+
+		let mut debug = Debug::new(stderr(), -1);
+		assert_eq!(find_inputs(&dir, &mut debug).unwrap(), vec![dir.join("a.dvs")]);
+		assert!(find_inputs(&dir.join("broken.cab"), &mut debug).unwrap().is_empty());
+		remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn inspect_embedded_content() {
+		let document = build_cfbf(&[("WordDocument", b"")]);
+		let data = build_cfbf(&[("Archivable Item/FileContentStream", &document)]);
+		let mut debug = Debug::new(stderr(), -1);
+		let mut container = Container::new(SeekableRead::new(&data[..]).unwrap(), &mut debug).unwrap();
+		let source = locate_content(&mut container, Option::None, &mut debug).unwrap();
+		assert!(matches!(source, Source::Embedded(_)));
+
+		let mut content = Content::new(&source);
+		assert_eq!(sniff_content(&mut container, &mut content, &mut debug).unwrap().map(|file_type| file_type.description), Some("Word 97-2003 document"));
+		assert!(content.prefix.as_ref().is_some_and(|&(ref prefix, truncated)| *prefix == document && !truncated));
+		assert!(read_document_summary(&mut container, &mut content, &mut debug).unwrap().is_some_and(|summary| summary.title.is_none()));
+		assert!(read_vba_projects(&mut container, &mut content, &mut debug).unwrap().is_empty());
+
+		let missing = Source::Missing;
+		assert!(sniff_content(&mut container, &mut Content::new(&missing), &mut debug).unwrap().is_none());
+	}
+
+	#[test]
+	fn read_ooxml_vba_projects() {
+		// A project without modules: the `dir` stream is a compressed container with a single uncompressed chunk
+		let mut dir = vec![1, 0xFF, 0x3F, 0x04, 0x00, 0x07, 0x00, 0x00, 0x00];
+		dir.extend_from_slice(b"Project");
+		let project = build_cfbf(&[("VBA/dir", &dir)]);
+		let document = build_zip(true, &[("[Content_Types].xml", b"<Types/>"), ("word/vbaProject.bin", &project)], true);
+		let data = build_cfbf(&[("Archivable Item/FileContentStream", &document)]);
+		let mut debug = Debug::new(stderr(), -1);
+		let mut container = Container::new(SeekableRead::new(&data[..]).unwrap(), &mut debug).unwrap();
+		let source = locate_content(&mut container, Option::None, &mut debug).unwrap();
+
+		let projects = read_vba_projects(&mut container, &mut Content::new(&source), &mut debug).unwrap();
+		assert_eq!(projects.len(), 1);
+		assert_eq!((projects[0].path.as_str(), projects[0].name.as_str()), ("/word/vbaProject.bin!/VBA", "Project"));
+	}
+}
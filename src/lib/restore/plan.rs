@@ -0,0 +1,204 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write, Error, ErrorKind};
+use std::path::PathBuf;
+use super::super::io::{escape_field, unescape_field};
+
+/// A restore plan: the list of files that a restore would write, and where their content would come from.
+/// A plan is produced by a dry run, can be reviewed (and edited) as a text file, and can then be executed exactly as written.
+///
+/// In the text file, every step is a line of tab-separated fields: `<source> <size> <input> <outsourced file> <target>`,
+/// where `<source>` is one of `embedded`, `outsourced`, `sharable`, `missing` or `error`.
+/// For `sharable` steps, the path of the sharable part is written in place of the outsourced file.
+/// Notes on a step are written as comment lines (starting with `#`) right before the step, and are ignored when reading the plan.
+#[derive(Default)]
+pub struct Plan {
+	pub steps: Vec<Step>,
+}
+
+pub struct Step {
+	/// The path of the DVS file.
+	pub input: String,
+	pub source: PlannedSource,
+	/// The number of bytes that would be written, if known.
+	pub size: Option<u64>,
+	/// The path of the file that would be written, if known.
+	pub target: Option<PathBuf>,
+	/// Remarks for the reviewer, such as path rewrites and conflicts.
+	pub notes: Vec<String>,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum PlannedSource {
+	/// The content is embedded in the DVS file.
+	Embedded,
+	/// The content is out-sourced to the given file.
+	Outsourced(PathBuf),
+	/// The content is held by the given sharable part (Single Instance Storage).
+	Sharable(PathBuf),
+	/// The content can be found nowhere.
+	Missing,
+	/// The DVS file could not be processed.
+	Error,
+}
+
+impl Plan {
+	/// Adds a note to every step whose target is also used by another step, or already exists.
+	/// Targets are compared case-insensitively, as they would be on Windows.
+	/// Returns the number of conflicting steps.
+	pub fn detect_conflicts(&mut self) -> usize {
+		let mut targets: HashMap<String, Vec<usize>> = HashMap::new();
+		for (index, step) in self.steps.iter().enumerate() {
+			if let Some(ref target) = step.target {
+				targets.entry(target.to_string_lossy().to_lowercase()).or_default().push(index);
+			}
+		}
+
+		let mut num_conflicts = 0;
+		for index in 0..self.steps.len() {
+			let mut notes = Vec::new();
+			if let Some(ref target) = self.steps[index].target {
+				for &other in &targets[&target.to_string_lossy().to_lowercase()] {
+					if other != index {
+						notes.push(format!("conflict: same target as '{}'", self.steps[other].input));
+					}
+				}
+				if target.exists() {
+					notes.push(format!("conflict: target {:?} already exists", target));
+				}
+			}
+			if !notes.is_empty() {
+				num_conflicts += 1;
+				self.steps[index].notes.extend(notes);
+			}
+		}
+		num_conflicts
+	}
+
+	/// The total number of bytes that would be written.
+	pub fn total_size(&self) -> u64 {
+		self.steps.iter().filter_map(|step| step.size).sum()
+	}
+
+	pub fn write(&self, output: &mut dyn Write) -> Result<(), Error> {
+		writeln!(output, "# evrecovery restore plan")?;
+		writeln!(output, "# <source>\t<size>\t<input>\t<outsourced file>\t<target>")?;
+		for step in &self.steps {
+			for note in &step.notes {
+				writeln!(output, "# {}", note.replace('\n', " "))?;
+			}
+			let (source, outsourced) = match step.source {
+				PlannedSource::Embedded => ("embedded", "-".to_owned()),
+				PlannedSource::Outsourced(ref path) => ("outsourced", escape_field(&path.to_string_lossy())),
+				PlannedSource::Sharable(ref path) => ("sharable", escape_field(&path.to_string_lossy())),
+				PlannedSource::Missing => ("missing", "-".to_owned()),
+				PlannedSource::Error => ("error", "-".to_owned()),
+			};
+			writeln!(output, "{}\t{}\t{}\t{}\t{}",
+				source,
+				step.size.map(|size| size.to_string()).unwrap_or_else(|| "-".to_owned()),
+				escape_field(&step.input),
+				outsourced,
+				step.target.as_ref().map(|target| escape_field(&target.to_string_lossy())).unwrap_or_else(|| "-".to_owned()))?;
+		}
+		writeln!(output, "# {} steps, {} bytes", self.steps.len(), self.total_size())?;
+		Ok(())
+	}
+
+	pub fn read(input: impl Read) -> Result<Plan, Error> {
+		let mut steps = Vec::new();
+		for (index, line) in BufReader::new(input).lines().enumerate() {
+			let line = line?;
+			if line.starts_with('#') || line.trim().is_empty() {
+				continue;
+			}
+			match parse_step(&line) {
+				Some(step) => steps.push(step),
+				None => return Err(Error::new(ErrorKind::InvalidData, format!("Malformed step in line {} of the plan", index + 1))),
+			}
+		}
+		Ok(Plan { steps })
+	}
+}
+
+fn parse_step(line: &str) -> Option<Step> {
+	let fields = line.split('\t').collect::<Vec<&str>>();
+	if fields.len() != 5 {
+		return None;
+	}
+	let source = match (fields[0], fields[3]) {
+		("embedded", _) => PlannedSource::Embedded,
+		("outsourced", "-") => return None,
+		("outsourced", path) => PlannedSource::Outsourced(PathBuf::from(unescape_field(path)?)),
+		("sharable", "-") => return None,
+		("sharable", path) => PlannedSource::Sharable(PathBuf::from(unescape_field(path)?)),
+		("missing", _) => PlannedSource::Missing,
+		("error", _) => PlannedSource::Error,
+		_ => return None,
+	};
+	let size = match fields[1] {
+		"-" => None,
+		size => Some(size.parse::<u64>().ok()?),
+	};
+	let input = unescape_field(fields[2])?;
+	let target = match fields[4] {
+		"-" => None,
+		target => Some(PathBuf::from(unescape_field(target)?)),
+	};
+	Some(Step { input, source, size, target, notes: Vec::new() })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn step(input: &str, source: PlannedSource, target: Option<&str>) -> Step {
+		Step { input: input.to_owned(), source, size: Some(12), target: target.map(PathBuf::from), notes: Vec::new() }
+	}
+
+	#[test]
+	fn write_and_read() {
+		let mut plan = Plan::default();
+		plan.steps.push(step("V:\\a\tb.dvs", PlannedSource::Embedded, Some("R:\\F\\a.doc")));
+		plan.steps.push(step("V:\\c.dvs", PlannedSource::Outsourced(PathBuf::from("V:\\sub\\c.dvf")), Some("R:\\F\\a.DOC")));
+		plan.steps.push(step("V:\\d.dvs", PlannedSource::Missing, None));
+		assert_eq!(plan.detect_conflicts(), 2);
+
+		let mut text = Vec::new();
+		plan.write(&mut text).unwrap();
+		let text = String::from_utf8(text).unwrap();
+		assert!(text.contains("# conflict: same target as 'V:\\c.dvs'\nembedded\t12\tV:\\a%09b.dvs\t-\tR:\\F\\a.doc\n"));
+
+		let read = Plan::read(text.as_bytes()).unwrap();
+		assert_eq!(read.steps.len(), 3);
+		assert_eq!(read.steps[0].input, "V:\\a\tb.dvs");
+		assert!(read.steps[1].source == PlannedSource::Outsourced(PathBuf::from("V:\\sub\\c.dvf")));
+		assert!(read.steps[2].source == PlannedSource::Missing);
+		assert_eq!(read.steps[2].target, None);
+		assert_eq!(read.total_size(), 36);
+	}
+
+	#[test]
+	fn reject_malformed_steps() {
+		assert!(Plan::read("outsourced\t12\tV:\\c.dvs\t-\tR:\\c.doc\n".as_bytes()).is_err());
+		assert!(Plan::read("embedded\tlarge\tV:\\c.dvs\t-\tR:\\c.doc\n".as_bytes()).is_err());
+		assert!(Plan::read("embedded\t12\tV:\\c.dvs\tR:\\c.doc\n".as_bytes()).is_err());
+	}
+}
@@ -0,0 +1,299 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::io::{Read, Write, Error, ErrorKind};
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use super::cfbf::{Container, Object, ObjectResult, ObjectType};
+use super::dvf::Index as FileIndex;
+use super::io::{Debug, SeekableRead};
+use super::metadata::decode_text;
+use super::restore::open_payload;
+
+/// The name of the stream that holds the fingerprint of the sharable part.
+pub const FINGERPRINT_STREAM: &str = "Fingerprint";
+/// The name of the stream that holds the ID of the sharable part.
+pub const PART_ID_STREAM: &str = "Sharable Part ID";
+/// The name of the stream that holds the ID of the converted content.
+pub const CONVERTED_ID_STREAM: &str = "Converted Content ID";
+
+/// The reference from a DVS file to the sharable part that holds its content (Single Instance Storage).
+///
+/// In newer versions of Enterprise Vault, the content of an item may be stored once in a sharable part (`.dvssp` file)
+/// that is shared by all DVS files archiving the same content; converted content (e.g. for indexing) is stored in a `.dvscc` file.
+/// The `/Sharable Content` storage of such a DVS file holds no `FileContentStream`, but the streams `Fingerprint`,
+/// `Sharable Part ID` and `Converted Content ID`, which name the files of the sharable part and of the converted content.
+#[derive(Clone, Default)]
+pub struct Reference {
+	/// The fingerprint of the sharable part.
+	pub fingerprint: Option<String>,
+	/// The ID of the sharable part.
+	pub part_id: Option<String>,
+	/// The ID of the converted content.
+	pub converted_id: Option<String>,
+}
+
+impl fmt::Display for Reference {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut parts = Vec::new();
+		if let Some(ref fingerprint) = self.fingerprint {
+			parts.push(format!("fingerprint {}", fingerprint));
+		}
+		if let Some(ref part_id) = self.part_id {
+			parts.push(format!("ID {}", part_id));
+		}
+		match parts.is_empty() {
+			true => write!(f, "no fingerprint or ID"),
+			false => write!(f, "{}", parts.join(", ")),
+		}
+	}
+}
+
+/// Reads the reference to the sharable part from the `/Sharable Content` storage of a DVS payload.
+/// Returns `None` if there is no such storage, or if it holds the content itself.
+/// Other streams in the storage are logged and ignored.
+pub fn read_reference<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Option<Reference>, Error> where TFile: Read + std::io::Seek {
+	let storage = match container.find_child_by_name("Sharable Content", debug)? {
+		ObjectResult::Ok(ref object) if object.object_type == ObjectType::Storage => object.clone(),
+		_ => return Ok(None),
+	};
+
+	let mut streams = Vec::new();
+	collect_streams(container, &storage, &mut streams, 0, debug)?;
+	if streams.iter().any(|stream| stream.name == "FileContentStream") {
+		return Ok(None);
+	}
+
+	let mut reference = Reference::default();
+	for stream in streams {
+		let field = match stream.name.as_str() {
+			FINGERPRINT_STREAM => &mut reference.fingerprint,
+			PART_ID_STREAM => &mut reference.part_id,
+			CONVERTED_ID_STREAM => &mut reference.converted_id,
+			_ => {
+				debug.logln(1, format!("[read_reference] Ignoring unknown stream '{}' in the sharable content.", stream.name));
+				continue;
+			},
+		};
+		let mut data: Vec<u8> = Vec::new();
+		container.dump_stream(&stream, &mut data, debug)?;
+		let value = decode_text(&data).trim().to_owned();
+		debug.logln(1, format!("[read_reference] Sharable content reference '{}': {}", stream.name, value));
+		if !value.is_empty() && field.is_none() {
+			*field = Some(value);
+		}
+	}
+	Ok(Some(reference))
+}
+
+/// Collects all streams beneath the given storage.
+fn collect_streams<TFile>(container: &mut Container<TFile>, storage: &Object, result: &mut Vec<Object>, depth: usize, debug: &mut Debug) -> Result<(), Error> where TFile: Read + std::io::Seek {
+	if depth > 32 {
+		return Ok(());
+	}
+	for child in container.get_children(storage, debug)? {
+		match child.object_type {
+			ObjectType::Stream => result.push(child),
+			ObjectType::Storage => collect_streams(container, &child, result, depth + 1, debug)?,
+			_ => {},
+		}
+	}
+	Ok(())
+}
+
+/// Opens a sharable part (`.dvssp`) or converted content (`.dvscc`) file.
+/// Both are CFBF containers, either wrapped into the same compressed envelope as DVS files, or stored plainly.
+pub fn open_part(path: &Path, debug: &mut Debug) -> Result<Container<SeekableRead>, Error> {
+	let mut data: Vec<u8> = Vec::new();
+	File::open(path)?.read_to_end(&mut data)?;
+	if data.starts_with(&[0xFF, 0xEE, 0xEE, 0xDD]) {
+		debug.logln(1, format!("[open_part] Decompressing {:?} ...", path));
+		open_payload(&data[..], debug)
+	}
+	else if data.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+		debug.logln(1, format!("[open_part] Parsing {:?} ...", path));
+		Container::new(SeekableRead::new(&data[..])?, debug)
+	}
+	else {
+		Err(Error::new(ErrorKind::InvalidData, format!("{:?} is neither a DVS envelope nor a CFBF file", path)))
+	}
+}
+
+/// Copies the content held by a sharable part (its `FileContentStream`) to the output.
+pub fn write_part_content(path: &Path, output: &mut dyn Write, debug: &mut Debug) -> Result<(), Error> {
+	let mut container = open_part(path, debug)?;
+	match container.find_child_by_name("FileContentStream", debug)? {
+		ObjectResult::Ok(object) => container.dump_stream(&object, output, debug),
+		ObjectResult::None => Err(Error::new(ErrorKind::InvalidData, format!("The sharable part {:?} holds no '/**/FileContentStream'", path))),
+	}
+}
+
+/// An index of sharable parts (`.dvssp`) and converted content (`.dvscc`) files, by name.
+pub struct Index {
+	sharable: FileIndex,
+	converted: FileIndex,
+}
+
+impl Default for Index {
+	fn default() -> Index {
+		Index::new()
+	}
+}
+
+impl Index {
+	pub fn new() -> Index {
+		Index { sharable: FileIndex::new("dvssp"), converted: FileIndex::new("dvscc") }
+	}
+
+	/// Recursively searches the given directory for sharable parts and converted content.
+	pub fn add_root(&mut self, root: &Path, debug: &mut Debug) -> Result<(), Error> {
+		self.sharable.add_root(root, debug)?;
+		self.converted.add_root(root, debug)
+	}
+
+	/// Resolves the sharable part of a DVS file: by fingerprint first, and then by ID.
+	/// The directory of the DVS file (including all sub-folders) is searched, in addition to the search roots.
+	/// Sharable parts are never looked for by the name of the DVS file, since they are shared by several DVS files.
+	pub fn resolve_sharable(&mut self, reference: &Reference, dvs_path: &Path, debug: &mut Debug) -> Option<PathBuf> {
+		self.sharable.add_dvs_dir(dvs_path, debug);
+		for name in reference.fingerprint.iter().chain(reference.part_id.iter()) {
+			if let Some(path) = self.sharable.resolve_name(name, dvs_path, debug) {
+				return Some(path);
+			}
+		}
+		None
+	}
+
+	/// Resolves the converted content of a DVS file: by ID first, and then by the name of the DVS file
+	/// (converted content belongs to a single item, unlike sharable parts).
+	pub fn resolve_converted(&mut self, reference: &Reference, dvs_path: &Path, debug: &mut Debug) -> Option<PathBuf> {
+		self.converted.add_dvs_dir(dvs_path, debug);
+		if let Some(path) = reference.converted_id.as_ref().and_then(|converted_id| self.converted.resolve_name(converted_id, dvs_path, debug)) {
+			return Some(path);
+		}
+		self.converted.resolve(dvs_path, debug)
+	}
+}
+
+/// An error that describes a sharable part that cannot be found.
+/// It is wrapped into an `std::io::Error` (of kind `NotFound`), so that it can be told apart from other errors.
+#[derive(Debug)]
+pub struct MissingPart {
+	pub reference: String,
+	/// The converted content of the item, if it can be found; it may be the only remaining copy of the content.
+	pub converted: Option<PathBuf>,
+}
+
+impl MissingPart {
+	pub fn new(reference: &Reference, converted: Option<PathBuf>) -> MissingPart {
+		MissingPart { reference: reference.to_string(), converted }
+	}
+
+	pub fn into_error(self) -> Error {
+		Error::new(ErrorKind::NotFound, self)
+	}
+
+	/// Retrieves the missing part from an error, if the error describes one.
+	pub fn from_error(error: &Error) -> Option<&MissingPart> {
+		error.get_ref().and_then(|inner| inner.downcast_ref::<MissingPart>())
+	}
+}
+
+impl fmt::Display for MissingPart {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "The sharable part ({}) cannot be found", self.reference)?;
+		if let Some(ref converted) = self.converted {
+			write!(f, "; its converted content is {:?}", converted)?;
+		}
+		Ok(())
+	}
+}
+
+impl StdError for MissingPart {}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Cursor, stderr};
+	use std::fs::{create_dir_all, remove_dir_all, write};
+	use std::env::temp_dir;
+	use super::super::cfbf::testing::build_cfbf;
+	use super::super::io::testing::encode_string;
+	use super::*;
+
+	/// Encodes a string property: its length in bytes, followed by the NUL-terminated UTF-16 text.
+	fn reference_of(streams: &[(&str, &[u8])]) -> Option<Reference> {
+		let mut debug = Debug::new(stderr(), -1);
+		let mut container = Container::new(Cursor::new(build_cfbf(streams)), &mut debug).unwrap();
+		read_reference(&mut container, &mut debug).unwrap()
+	}
+
+	#[test]
+	fn read_references() {
+		let (fingerprint, part_id, converted_id) = (encode_string("0A1B2C3D4E5F"), encode_string(" 1234~Z~ABCD "), encode_string(""));
+		let reference = reference_of(&[
+			("Sharable Content/Fingerprint", &fingerprint),
+			("Sharable Content/Sharable Part ID", &part_id),
+			("Sharable Content/Converted Content ID", &converted_id),
+			("Sharable Content/Fingerprint Algorithm", b"SHA1"),
+		]).unwrap();
+		assert_eq!(reference.fingerprint, Some("0A1B2C3D4E5F".to_owned()));
+		assert_eq!(reference.part_id, Some("1234~Z~ABCD".to_owned()));
+		assert_eq!(reference.converted_id, None);
+		assert_eq!(reference.to_string(), "fingerprint 0A1B2C3D4E5F, ID 1234~Z~ABCD");
+
+		// Embedded content, and no sharable content at all
+		assert!(reference_of(&[("Sharable Content/Archivable Item/FileContentStream", b"content")]).is_none());
+		assert!(reference_of(&[("Sharable", b"content")]).is_none());
+		assert_eq!(reference_of(&[("Sharable Content/Other", b"x")]).unwrap().to_string(), "no fingerprint or ID");
+	}
+
+	#[test]
+	fn resolve_parts() {
+		let dir = temp_dir().join(format!("evrecovery-sis-{}", std::process::id()));
+		create_dir_all(dir.join("parts")).unwrap();
+		let dvs_path = dir.join("1234.dvs");
+		write(&dvs_path, b"").unwrap();
+		write(dir.join("parts").join("0A1B2C.DVSSP"), build_cfbf(&[("Archivable Item/FileContentStream", b"shared content")])).unwrap();
+		write(dir.join("1234.dvssp"), b"").unwrap();
+		write(dir.join("parts").join("1234.dvscc"), b"").unwrap();
+
+		let mut debug = Debug::new(stderr(), -1);
+		let mut index = Index::new();
+		let reference = Reference { fingerprint: Some("0a1b2c".to_owned()), ..Default::default() };
+		let part = index.resolve_sharable(&reference, &dvs_path, &mut debug).unwrap();
+		assert!(part.ends_with("parts/0A1B2C.DVSSP"));
+		let mut content = Vec::new();
+		write_part_content(&part, &mut content, &mut debug).unwrap();
+		assert_eq!(content, b"shared content");
+
+		// A sharable part is not looked for by the name of the DVS file, but converted content is
+		let reference = Reference { part_id: Some("5678".to_owned()), ..Default::default() };
+		assert_eq!(index.resolve_sharable(&reference, &dvs_path, &mut debug), None);
+		let converted = index.resolve_converted(&reference, &dvs_path, &mut debug).unwrap();
+		assert!(converted.ends_with("parts/1234.dvscc"));
+		assert!(open_part(&converted, &mut debug).is_err());
+
+		let error = MissingPart::new(&reference, Some(PathBuf::from("1234.dvscc"))).into_error();
+		assert_eq!(error.kind(), ErrorKind::NotFound);
+		assert_eq!(MissingPart::from_error(&error).unwrap().to_string(), "The sharable part (ID 5678) cannot be found; its converted content is \"1234.dvscc\"");
+		assert!(MissingPart::from_error(&Error::new(ErrorKind::NotFound, "other")).is_none());
+		remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,401 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Error};
+use super::super::cfbf::{Container, Object, ObjectType};
+use super::super::io::{Debug, ReadSeek};
+
+// The CryptoAPI algorithm identifiers (ALG_ID) used in encryption headers
+const CALG_RC4: u32 = 0x6801;
+const CALG_AES_128: u32 = 0x660E;
+const CALG_AES_192: u32 = 0x660F;
+const CALG_AES_256: u32 = 0x6610;
+const CALG_SHA1: u32 = 0x8004;
+
+/// The flag of an encryption header that indicates AES (if no algorithm is given).
+const FLAG_AES: u32 = 0x20;
+
+/// The header token of the `Current User` stream of an encrypted PowerPoint presentation.
+const PPT_ENCRYPTED_TOKEN: u32 = 0xF3D1_C4DF;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Scheme {
+	/// Agile encryption of an OOXML document (Office 2010 and later).
+	Agile,
+	/// Standard encryption of an OOXML document (Office 2007).
+	Standard,
+	/// Extensible encryption of an OOXML document (third-party providers).
+	Extensible,
+	/// RC4 CryptoAPI encryption of a legacy Office document (Office XP and 2003).
+	Rc4CryptoApi,
+	/// RC4 encryption of a legacy Office document (Office 97 and 2000).
+	Rc4,
+	/// XOR obfuscation of a legacy Office document (Office 95 and earlier).
+	Xor,
+	/// Information Rights Management, which encrypts the document for the users who are granted rights.
+	Irm,
+}
+
+impl fmt::Display for Scheme {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			Scheme::Agile => "agile encryption",
+			Scheme::Standard => "standard encryption",
+			Scheme::Extensible => "extensible encryption",
+			Scheme::Rc4CryptoApi => "RC4 CryptoAPI encryption",
+			Scheme::Rc4 => "RC4 encryption",
+			Scheme::Xor => "XOR obfuscation",
+			Scheme::Irm => "IRM protection",
+		})
+	}
+}
+
+/// The encryption of a document, with the parameters that are recorded in plain text.
+pub struct Encryption {
+	pub scheme: Scheme,
+	/// The cipher and the key size, e.g. "AES-256" or "RC4-128".
+	pub cipher: Option<String>,
+	/// The hash algorithm, e.g. "SHA-1" or "SHA512".
+	pub hash: Option<String>,
+	/// The number of hash iterations applied to the password (agile encryption).
+	pub spin_count: Option<u32>,
+	/// The name of the cryptographic service provider.
+	pub provider: Option<String>,
+}
+
+impl Encryption {
+	fn new(scheme: Scheme) -> Encryption {
+		Encryption { scheme, cipher: None, hash: None, spin_count: None, provider: None }
+	}
+}
+
+impl fmt::Display for Encryption {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut parameters = Vec::new();
+		parameters.extend(self.cipher.clone());
+		parameters.extend(self.hash.clone());
+		parameters.extend(self.spin_count.map(|spin_count| format!("{} spins", spin_count)));
+		parameters.extend(self.provider.as_ref().map(|provider| format!("'{}'", provider)));
+		match parameters.is_empty() {
+			true => write!(f, "{}", self.scheme),
+			false => write!(f, "{} ({})", self.scheme, parameters.join(", ")),
+		}
+	}
+}
+
+/// Determines whether a file is an encrypted or IRM-protected Office document.
+/// Returns `None` for all other files (including files that are not CFBF files).
+pub fn detect_encryption(input: &mut dyn ReadSeek, debug: &mut Debug) -> Result<Option<Encryption>, Error> {
+	input.seek(SeekFrom::Start(0))?;
+	let mut signature = [0u8; 8];
+	if input.read_exact(&mut signature).is_err() || signature != [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1] {
+		return Ok(None);
+	}
+	input.seek(SeekFrom::Start(0))?;
+	detect_container_encryption(&mut Container::new(input, debug)?, debug)
+}
+
+/// Determines whether a CFBF container is an encrypted or IRM-protected Office document:
+///
+/// * OOXML documents are encrypted into an `EncryptedPackage` stream, described by an `EncryptionInfo` stream.
+/// * IRM-protected documents declare a DRM transform in their `\x06DataSpaces` storage.
+/// * Word documents flag the encryption in the FIB of their `WordDocument` stream, and store the encryption header in their table stream.
+/// * Excel workbooks hold a `FILEPASS` record in the globals substream of their `Workbook` stream.
+/// * PowerPoint presentations use a special header token in their `Current User` stream.
+pub fn detect_container_encryption<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Option<Encryption>, Error> where TFile: Read + Seek {
+	let root = container.get_root_object(debug)?;
+	let children = container.get_children(&root, debug)?;
+	let find = |name: &str| children.iter().find(|child| child.name == name).cloned();
+
+	if let Some(data_spaces) = find("\u{6}DataSpaces") {
+		if has_drm_transform(container, &data_spaces, debug)? {
+			debug.logln(1, "[detect_container_encryption] The document is protected by IRM.");
+			return Ok(Some(Encryption::new(Scheme::Irm)));
+		}
+	}
+	let result = if let Some(stream) = find("EncryptionInfo") {
+		Some(parse_encryption_info(&read_stream(container, &stream, debug)?))
+	}
+	else if let Some(stream) = find("WordDocument") {
+		detect_word_encryption(container, &stream, &children, debug)?
+	}
+	else if let Some(stream) = find("Workbook").or_else(|| find("Book")) {
+		detect_excel_encryption(&read_stream(container, &stream, debug)?)
+	}
+	else if let Some(stream) = find("Current User") {
+		let data = read_stream(container, &stream, debug)?;
+		match u32_at(&data, 12) {
+			Some(PPT_ENCRYPTED_TOKEN) => Some(Encryption::new(Scheme::Rc4CryptoApi)),
+			_ => None,
+		}
+	}
+	else {
+		None
+	};
+	if let Some(ref encryption) = result {
+		debug.logln(1, format!("[detect_container_encryption] The document is encrypted: {}.", encryption));
+	}
+	Ok(result)
+}
+
+/// Determines whether a `\x06DataSpaces` storage declares a DRM data space or transform (rather than the strong encryption of password-protected documents).
+fn has_drm_transform<TFile>(container: &mut Container<TFile>, storage: &Object, debug: &mut Debug) -> Result<bool, Error> where TFile: Read + Seek {
+	for child in container.get_children(storage, debug)? {
+		if child.name.contains("DRM") {
+			return Ok(true);
+		}
+		if child.object_type == ObjectType::Storage && has_drm_transform(container, &child, debug)? {
+			return Ok(true);
+		}
+	}
+	Ok(false)
+}
+
+fn read_stream<TFile>(container: &mut Container<TFile>, stream: &Object, debug: &mut Debug) -> Result<Vec<u8>, Error> where TFile: Read + Seek {
+	let mut data: Vec<u8> = Vec::new();
+	container.dump_stream(stream, &mut data, debug)?;
+	Ok(data)
+}
+
+/// Parses the `EncryptionInfo` stream of an encrypted OOXML document, which starts with a version number that identifies the scheme.
+fn parse_encryption_info(data: &[u8]) -> Encryption {
+	match (u16_at(data, 0), u16_at(data, 2)) {
+		(Some(4), Some(4)) => parse_agile_descriptor(&String::from_utf8_lossy(&data[8.min(data.len())..])),
+		(Some(3), Some(3)) | (Some(4), Some(3)) => Encryption::new(Scheme::Extensible),
+		_ => parse_crypto_api_info(data, Scheme::Standard),
+	}
+}
+
+/// Parses the XML descriptor of agile encryption: the `keyData` element names the cipher and the hash algorithm,
+/// and the `encryptedKey` element of the password key encryptor names the spin count.
+fn parse_agile_descriptor(xml: &str) -> Encryption {
+	let mut encryption = Encryption::new(Scheme::Agile);
+	let cipher = attribute(xml, "keyData", "cipherAlgorithm");
+	let key_bits = attribute(xml, "keyData", "keyBits");
+	encryption.cipher = match (cipher, key_bits) {
+		(Some(cipher), Some(key_bits)) => Some(format!("{}-{}", cipher, key_bits)),
+		(cipher, _) => cipher,
+	};
+	encryption.hash = attribute(xml, "keyData", "hashAlgorithm");
+	encryption.spin_count = attribute(xml, "encryptedKey", "spinCount").and_then(|spin_count| spin_count.parse().ok());
+	encryption
+}
+
+/// Finds the value of an attribute of the first element with the given local name.
+fn attribute(xml: &str, element: &str, name: &str) -> Option<String> {
+	let start = xml.match_indices(element).map(|(index, _)| index).find(|&index| {
+		let before = xml[..index].chars().next_back();
+		let after = xml[index + element.len()..].chars().next();
+		(before == Some('<') || before == Some(':')) && after.map(|c| c.is_whitespace()).unwrap_or(false)
+	})?;
+	let tag = &xml[start..start + xml[start..].find('>')?];
+	let value_start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+	let value_end = value_start + tag[value_start..].find('"')?;
+	Some(tag[value_start..value_end].to_owned())
+}
+
+/// Parses an encryption header as used by standard encryption and RC4 CryptoAPI encryption:
+/// the version, flags, the size of the header, and the header itself (flags, size of extra data, algorithm, hash algorithm, key size,
+/// provider type, two reserved fields and the name of the cryptographic service provider).
+fn parse_crypto_api_info(data: &[u8], scheme: Scheme) -> Encryption {
+	let mut encryption = Encryption::new(scheme);
+	let header = &data[12.min(data.len())..];
+	let (flags, algorithm, hash, key_bits) = match (u32_at(header, 0), u32_at(header, 8), u32_at(header, 12), u32_at(header, 16)) {
+		(Some(flags), Some(algorithm), Some(hash), Some(key_bits)) => (flags, algorithm, hash, key_bits),
+		_ => return encryption,
+	};
+	encryption.cipher = match algorithm {
+		CALG_AES_128 => Some("AES-128".to_owned()),
+		CALG_AES_192 => Some("AES-192".to_owned()),
+		CALG_AES_256 => Some("AES-256".to_owned()),
+		0 if flags & FLAG_AES != 0 => Some(format!("AES-{}", if key_bits == 0 { 128 } else { key_bits })),
+		// A key size of 0 denotes 40 bits
+		CALG_RC4 | 0 => Some(format!("RC4-{}", if key_bits == 0 { 40 } else { key_bits })),
+		algorithm => Some(format!("algorithm 0x{:04X}", algorithm)),
+	};
+	encryption.hash = match hash {
+		CALG_SHA1 | 0 => Some("SHA-1".to_owned()),
+		hash => Some(format!("hash algorithm 0x{:04X}", hash)),
+	};
+	let provider = header.get(32..).unwrap_or(&[]).chunks(2)
+		.filter(|c| c.len() == 2)
+		.map(|c| c[0] as u16 | (c[1] as u16) << 8)
+		.take_while(|&c| c != 0)
+		.collect::<Vec<u16>>();
+	encryption.provider = Some(String::from_utf16_lossy(&provider)).filter(|provider| !provider.is_empty());
+	encryption
+}
+
+/// Parses the encryption header of RC4 encryption or RC4 CryptoAPI encryption, depending on its version.
+fn parse_rc4_info(data: &[u8]) -> Encryption {
+	match (u16_at(data, 0), u16_at(data, 2)) {
+		(Some(1), Some(1)) => {
+			let mut encryption = Encryption::new(Scheme::Rc4);
+			encryption.cipher = Some("RC4-40".to_owned());
+			encryption.hash = Some("MD5".to_owned());
+			encryption
+		},
+		_ => parse_crypto_api_info(data, Scheme::Rc4CryptoApi),
+	}
+}
+
+/// Checks the flags in the FIB (at the start of the `WordDocument` stream) of a Word document.
+/// If it is encrypted (rather than obfuscated), the encryption header is found at the start of the table stream.
+fn detect_word_encryption<TFile>(container: &mut Container<TFile>, stream: &Object, children: &[Object], debug: &mut Debug) -> Result<Option<Encryption>, Error> where TFile: Read + Seek {
+	const F_ENCRYPTED: u16 = 0x0100;
+	const F_WHICH_TBL_STM: u16 = 0x0200;
+	const F_OBFUSCATED: u16 = 0x8000;
+	let data = read_stream(container, stream, debug)?;
+	let flags = match (u16_at(&data, 0), u16_at(&data, 0x0A)) {
+		(Some(0xA5EC), Some(flags)) => flags,
+		_ => return Ok(None),
+	};
+	if flags & F_ENCRYPTED == 0 {
+		return Ok(None);
+	}
+	if flags & F_OBFUSCATED != 0 {
+		return Ok(Some(Encryption::new(Scheme::Xor)));
+	}
+	let table_name = if flags & F_WHICH_TBL_STM != 0 { "1Table" } else { "0Table" };
+	match children.iter().find(|child| child.name == table_name) {
+		Some(table) => Ok(Some(parse_rc4_info(&read_stream(container, table, debug)?))),
+		None => Ok(Some(Encryption::new(Scheme::Rc4))),
+	}
+}
+
+/// Looks for a `FILEPASS` record in the globals substream (up to its `EOF` record) of the `Workbook` stream of an Excel workbook.
+fn detect_excel_encryption(data: &[u8]) -> Option<Encryption> {
+	const FILEPASS: u16 = 0x002F;
+	const EOF: u16 = 0x000A;
+	let mut index = 0;
+	while let (Some(record_type), Some(size)) = (u16_at(data, index), u16_at(data, index + 2)) {
+		let record = data.get(index + 4..index + 4 + size as usize)?;
+		match record_type {
+			FILEPASS => return match u16_at(record, 0) {
+				Some(0) => Some(Encryption::new(Scheme::Xor)),
+				_ => Some(parse_rc4_info(&record[2.min(record.len())..])),
+			},
+			EOF => return None,
+			_ => index += 4 + size as usize,
+		}
+	}
+	None
+}
+
+fn u16_at(data: &[u8], index: usize) -> Option<u16> {
+	data.get(index..index + 2).map(|bytes| bytes[0] as u16 | (bytes[1] as u16) << 8)
+}
+
+fn u32_at(data: &[u8], index: usize) -> Option<u32> {
+	data.get(index..index + 4).map(|bytes| bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Cursor, stderr};
+	use super::super::super::cfbf::testing::build_cfbf;
+	use super::super::super::io::testing::utf16z;
+	use super::*;
+
+	fn detect(streams: &[(&str, &[u8])]) -> Option<String> {
+		let mut debug = Debug::new(stderr(), -1);
+		detect_encryption(&mut Cursor::new(build_cfbf(streams)), &mut debug).unwrap().map(|encryption| encryption.to_string())
+	}
+
+	/// Builds an encryption header as used by standard encryption and RC4 CryptoAPI encryption.
+	fn crypto_api_info(version: (u16, u16), flags: u32, algorithm: u32, key_bits: u32, provider: &str) -> Vec<u8> {
+		let mut header = Vec::new();
+		for value in &[flags, 0, algorithm, CALG_SHA1, key_bits, 0x18, 0, 0] {
+			header.extend_from_slice(&value.to_le_bytes());
+		}
+		header.extend(utf16z(provider));
+		let mut data = Vec::new();
+		data.extend_from_slice(&version.0.to_le_bytes());
+		data.extend_from_slice(&version.1.to_le_bytes());
+		data.extend_from_slice(&flags.to_le_bytes());
+		data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+		data.extend(header);
+		data
+	}
+
+	#[test]
+	fn detect_ooxml_encryption() {
+		let mut agile = vec![4, 0, 4, 0, 0x40, 0, 0, 0];
+		agile.extend_from_slice(b"<?xml version=\"1.0\"?><encryption xmlns:p=\"http://schemas.microsoft.com/office/2006/keyEncryptor/password\">\
+			<keyData saltSize=\"16\" keyBits=\"256\" cipherAlgorithm=\"AES\" hashAlgorithm=\"SHA512\"/>\
+			<keyEncryptors><keyEncryptor><p:encryptedKey spinCount=\"100000\" keyBits=\"256\"/></keyEncryptor></keyEncryptors></encryption>");
+		assert_eq!(detect(&[("EncryptionInfo", &agile), ("EncryptedPackage", b"...")]).as_deref(), Some("agile encryption (AES-256, SHA512, 100000 spins)"));
+
+		let standard = crypto_api_info((3, 2), 0x24, CALG_AES_128, 128, "Microsoft Enhanced RSA and AES Cryptographic Provider");
+		assert_eq!(detect(&[("EncryptionInfo", &standard)]).as_deref(), Some("standard encryption (AES-128, SHA-1, 'Microsoft Enhanced RSA and AES Cryptographic Provider')"));
+		assert_eq!(detect(&[("EncryptionInfo", &[4, 0, 3, 0])]).as_deref(), Some("extensible encryption"));
+	}
+
+	#[test]
+	fn detect_irm_protection() {
+		assert_eq!(detect(&[("\u{6}DataSpaces/TransformInfo/DRMEncryptedTransform/\u{6}Primary", b""), ("EncryptionInfo", &[4, 0, 4, 0])]).as_deref(), Some("IRM protection"));
+		// Password-protected documents declare a data space as well
+		assert_eq!(detect(&[("\u{6}DataSpaces/TransformInfo/StrongEncryptionTransform/\u{6}Primary", b""), ("EncryptionInfo", &[4, 0, 3, 0])]).as_deref(), Some("extensible encryption"));
+	}
+
+	#[test]
+	fn detect_word_encryption_flags() {
+		let fib = |flags: u16| {
+			let mut data = vec![0; 0x20];
+			data[0..2].copy_from_slice(&0xA5ECu16.to_le_bytes());
+			data[0x0A..0x0C].copy_from_slice(&flags.to_le_bytes());
+			data
+		};
+		let rc4 = [1, 0, 1, 0, 0, 0, 0, 0];
+		assert_eq!(detect(&[("WordDocument", &fib(0x0300)), ("1Table", &rc4)]).as_deref(), Some("RC4 encryption (RC4-40, MD5)"));
+		let crypto_api = crypto_api_info((4, 2), 0x04, CALG_RC4, 128, "");
+		assert_eq!(detect(&[("WordDocument", &fib(0x0100)), ("0Table", &crypto_api)]).as_deref(), Some("RC4 CryptoAPI encryption (RC4-128, SHA-1)"));
+		assert_eq!(detect(&[("WordDocument", &fib(0x8100))]).as_deref(), Some("XOR obfuscation"));
+		assert_eq!(detect(&[("WordDocument", &fib(0x0200)), ("1Table", &rc4)]), None);
+	}
+
+	#[test]
+	fn detect_excel_and_powerpoint_encryption() {
+		let record = |record_type: u16, content: &[u8]| {
+			let mut data = record_type.to_le_bytes().to_vec();
+			data.extend_from_slice(&(content.len() as u16).to_le_bytes());
+			data.extend_from_slice(content);
+			data
+		};
+		let bof = record(0x0809, &[0, 6, 5, 0]);
+		let workbook = [bof.clone(), record(0x002F, &[0, 0, 0x12, 0x34, 0x56, 0x78])].concat();
+		assert_eq!(detect(&[("Workbook", &workbook)]).as_deref(), Some("XOR obfuscation"));
+		let workbook = [bof.clone(), record(0x002F, &[[1, 0].as_ref(), &crypto_api_info((2, 2), 0x04, CALG_RC4, 0, "")].concat())].concat();
+		assert_eq!(detect(&[("Workbook", &workbook)]).as_deref(), Some("RC4 CryptoAPI encryption (RC4-40, SHA-1)"));
+		// A FILEPASS record after the globals substream does not count
+		let workbook = [bof.clone(), record(0x000A, &[]), record(0x002F, &[0, 0])].concat();
+		assert_eq!(detect(&[("Workbook", &workbook)]), None);
+
+		let mut current_user = vec![0; 16];
+		current_user[12..16].copy_from_slice(&PPT_ENCRYPTED_TOKEN.to_le_bytes());
+		assert_eq!(detect(&[("Current User", &current_user)]).as_deref(), Some("RC4 CryptoAPI encryption"));
+		assert_eq!(detect(&[("Current User", &[0; 16])]), None);
+	}
+
+	#[test]
+	fn ignore_other_files() {
+		let mut debug = Debug::new(stderr(), -1);
+		assert!(detect_encryption(&mut Cursor::new(b"%PDF-1.7".to_vec()), &mut debug).unwrap().is_none());
+		assert_eq!(detect(&[("Contents", b"plain")]), None);
+	}
+}
@@ -0,0 +1,503 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+pub mod encryption;
+#[cfg(test)]
+pub mod testing;
+
+extern crate libflate;
+
+use std::fmt;
+use std::io::{Read, SeekFrom, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use self::libflate::deflate::Decoder;
+use super::cfbf::Container;
+use super::io::{Debug, ReadSeek};
+use super::metadata::Metadata;
+use super::msg::is_msg_container;
+
+/// The number of bytes at the start of the content that are inspected.
+const HEAD_SIZE: usize = 8192;
+
+/// The maximum number of bytes at the end of a ZIP file that are searched for its central directory.
+const ZIP_TAIL_SIZE: u64 = 0x10000 + 22;
+
+/// The maximum number of bytes that are read from a ZIP member.
+const MAX_ZIP_MEMBER_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A file format that can be recognized by its content.
+pub struct FileType {
+	/// A human-readable description, e.g. "Word 97-2003 document".
+	pub description: &'static str,
+	/// The file extensions that are commonly used for this format (without the dot); the first one is preferred.
+	pub extensions: &'static [&'static str],
+	/// The MIME types that are commonly used for this format; the first one is preferred.
+	pub mime_types: &'static [&'static str],
+	/// Whether the format is a generic container or encoding (such as ZIP or plain text), which is shared by many file types.
+	/// The extension and MIME type of such files are not checked.
+	pub generic: bool,
+}
+
+impl fmt::Display for FileType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} ({})", self.description, self.mime_types[0])
+	}
+}
+
+macro_rules! file_type {
+	($name:ident, $description:expr, [$($extension:expr),*], [$($mime_type:expr),*], $generic:expr) => {
+		pub static $name: FileType = FileType { description: $description, extensions: &[$($extension),*], mime_types: &[$($mime_type),*], generic: $generic };
+	};
+}
+
+file_type!(DOC, "Word 97-2003 document", ["doc", "dot"], ["application/msword"], false);
+file_type!(XLS, "Excel 97-2003 workbook", ["xls", "xlt", "xla"], ["application/vnd.ms-excel"], false);
+file_type!(PPT, "PowerPoint 97-2003 presentation", ["ppt", "pps", "pot"], ["application/vnd.ms-powerpoint"], false);
+file_type!(VSD, "Visio 2003-2010 drawing", ["vsd", "vss", "vst"], ["application/vnd.visio"], false);
+file_type!(MSG, "Outlook message", ["msg"], ["application/vnd.ms-outlook"], false);
+file_type!(DOCX, "Word document", ["docx", "docm", "dotx", "dotm"], ["application/vnd.openxmlformats-officedocument.wordprocessingml.document"], false);
+file_type!(XLSX, "Excel workbook", ["xlsx", "xlsm", "xltx", "xltm"], ["application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"], false);
+file_type!(PPTX, "PowerPoint presentation", ["pptx", "pptm", "ppsx", "potx"], ["application/vnd.openxmlformats-officedocument.presentationml.presentation"], false);
+file_type!(VSDX, "Visio drawing", ["vsdx", "vsdm"], ["application/vnd.ms-visio.drawing"], false);
+file_type!(ODT, "OpenDocument text", ["odt"], ["application/vnd.oasis.opendocument.text"], false);
+file_type!(ODS, "OpenDocument spreadsheet", ["ods"], ["application/vnd.oasis.opendocument.spreadsheet"], false);
+file_type!(ODP, "OpenDocument presentation", ["odp"], ["application/vnd.oasis.opendocument.presentation"], false);
+file_type!(ZIP, "ZIP archive", ["zip"], ["application/zip", "application/x-zip-compressed"], true);
+file_type!(PDF, "PDF document", ["pdf"], ["application/pdf"], false);
+file_type!(RTF, "Rich Text Format document", ["rtf"], ["application/rtf", "text/rtf", "application/msword"], false);
+file_type!(PNG, "PNG image", ["png"], ["image/png", "image/x-png"], false);
+file_type!(JPEG, "JPEG image", ["jpg", "jpeg", "jpe", "jfif"], ["image/jpeg", "image/pjpeg"], false);
+file_type!(GIF, "GIF image", ["gif"], ["image/gif"], false);
+file_type!(BMP, "BMP image", ["bmp", "dib"], ["image/bmp", "image/x-ms-bmp"], false);
+file_type!(TIFF, "TIFF image", ["tif", "tiff"], ["image/tiff"], false);
+file_type!(EML, "Internet mail message", ["eml"], ["message/rfc822"], false);
+file_type!(HTML, "HTML document", ["html", "htm"], ["text/html"], false);
+file_type!(XML, "XML document", ["xml"], ["text/xml", "application/xml"], true);
+file_type!(TEXT, "plain text", ["txt"], ["text/plain"], true);
+
+/// All recognized file formats.
+pub static FILE_TYPES: [&FileType; 24] = [&DOC, &XLS, &PPT, &VSD, &MSG, &DOCX, &XLSX, &PPTX, &VSDX, &ODT, &ODS, &ODP, &ZIP, &PDF, &RTF, &PNG, &JPEG, &GIF, &BMP, &TIFF, &EML, &HTML, &XML, &TEXT];
+
+/// Determines the format of a file by inspecting its content.
+///
+/// Mostly, only the first kilobytes are read. Legacy Office documents are CFBF files, which are told apart by the streams in their root storage
+/// (`WordDocument`, `Workbook`, `PowerPoint Document` etc.); Office Open XML and OpenDocument files are ZIP archives, which are told apart by
+/// the names of their members (read from the central directory at the end of the file).
+/// Returns `None` if the format is not recognized.
+pub fn sniff(input: &mut dyn ReadSeek, debug: &mut Debug) -> Result<Option<&'static FileType>, Error> {
+	input.seek(SeekFrom::Start(0))?;
+	let mut head: Vec<u8> = Vec::new();
+	input.take(HEAD_SIZE as u64).read_to_end(&mut head)?;
+
+	let result = if head.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+		sniff_cfbf(input, debug)
+	}
+	else if head.starts_with(b"PK\x03\x04") {
+		Some(sniff_zip(input, &head, debug)?)
+	}
+	else {
+		sniff_head(&head)
+	};
+	match result {
+		Some(file_type) => debug.logln(1, format!("[sniff] The content is a {}.", file_type)),
+		None => debug.logln(1, "[sniff] The format of the content is not recognized."),
+	}
+	Ok(result)
+}
+
+/// Recognizes a format by the signature at the start of the content.
+fn sniff_head(head: &[u8]) -> Option<&'static FileType> {
+	let signatures: [(&[u8], &'static FileType); 9] = [
+		(b"%PDF-", &PDF),
+		(b"{\\rtf", &RTF),
+		(b"\x89PNG\r\n\x1A\n", &PNG),
+		(b"\xFF\xD8\xFF", &JPEG),
+		(b"GIF87a", &GIF),
+		(b"GIF89a", &GIF),
+		(b"BM", &BMP),
+		(b"II*\x00", &TIFF),
+		(b"MM\x00*", &TIFF),
+	];
+	for &(signature, file_type) in signatures.iter() {
+		// The header of a bitmap continues with the file size, so only accept a plausible one
+		if head.starts_with(signature) && (!::std::ptr::eq(file_type, &BMP) || (head.len() >= 14 && head[6..10] == [0, 0, 0, 0])) {
+			return Some(file_type);
+		}
+	}
+	// PDF files may be preceded by some garbage
+	if head.len() > 5 && head[..head.len().min(1024)].windows(5).any(|window| window == b"%PDF-") {
+		return Some(&PDF);
+	}
+	sniff_text(head)
+}
+
+/// Recognizes text files, including HTML, XML and mail messages.
+fn sniff_text(head: &[u8]) -> Option<&'static FileType> {
+	let text = match head {
+		[0xFF, 0xFE, ..] | [0xFE, 0xFF, ..] => return Some(&TEXT),
+		[0xEF, 0xBB, 0xBF, rest @ ..] => rest,
+		_ => head,
+	};
+	if text.is_empty() {
+		return None;
+	}
+	// Only tabs, line breaks, form feeds and escapes are accepted as control characters
+	if text.iter().any(|&byte| byte < 0x20 && byte != b'\t' && byte != b'\n' && byte != b'\r' && byte != 0x0C && byte != 0x1B) {
+		return None;
+	}
+	// The head may end in the middle of a UTF-8 sequence; anything else is not UTF-8
+	let text = match ::std::str::from_utf8(text) {
+		Ok(text) => text,
+		Err(e) if e.error_len().is_none() => ::std::str::from_utf8(&text[..e.valid_up_to()]).unwrap(),
+		Err(_) => return Some(&TEXT),
+	};
+
+	let start = text.trim_start().chars().take(64).collect::<String>().to_lowercase();
+	if start.starts_with("<!doctype html") || start.starts_with("<html") {
+		return Some(&HTML);
+	}
+	if start.starts_with("<?xml") {
+		return Some(&XML);
+	}
+	if is_mail_message(text) {
+		return Some(&EML);
+	}
+	Some(&TEXT)
+}
+
+/// Determines whether a text starts with the header of a mail message:
+/// a block of header fields that includes `From` and at least one of `Date`, `Received` and `Message-ID`.
+fn is_mail_message(text: &str) -> bool {
+	let mut names: Vec<String> = Vec::new();
+	for line in text.lines() {
+		if line.is_empty() {
+			break;
+		}
+		if line.starts_with(' ') || line.starts_with('\t') {
+			continue;
+		}
+		match line.find(':') {
+			Some(index) if index > 0 && line[..index].bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-') => names.push(line[..index].to_lowercase()),
+			_ => return false,
+		}
+	}
+	names.iter().any(|name| name == "from") && names.iter().any(|name| name == "date" || name == "received" || name == "message-id")
+}
+
+/// Tells the legacy Office formats and Outlook messages apart by the streams in the root storage.
+fn sniff_cfbf(input: &mut dyn ReadSeek, debug: &mut Debug) -> Option<&'static FileType> {
+	input.seek(SeekFrom::Start(0)).ok()?;
+	let mut container = match Container::new(input, debug) {
+		Ok(container) => container,
+		Err(e) => {
+			debug.logln(1, format!("[sniff_cfbf] Unable to parse the CFBF file: {}", e));
+			return None;
+		},
+	};
+	let root = container.get_root_object(debug).ok()?;
+	let names = container.get_children(&root, debug).ok()?.into_iter().map(|child| child.name).collect::<Vec<String>>();
+	let has = |name: &str| names.iter().any(|child| child == name);
+	if has("WordDocument") {
+		Some(&DOC)
+	}
+	else if has("Workbook") || has("Book") {
+		Some(&XLS)
+	}
+	else if has("PowerPoint Document") {
+		Some(&PPT)
+	}
+	else if has("VisioDocument") {
+		Some(&VSD)
+	}
+	else if is_msg_container(&mut container, debug) {
+		Some(&MSG)
+	}
+	else {
+		None
+	}
+}
+
+/// Tells the ZIP-based formats apart by the names of their members.
+fn sniff_zip(input: &mut dyn ReadSeek, head: &[u8], debug: &mut Debug) -> Result<&'static FileType, Error> {
+	// OpenDocument files start with an uncompressed member 'mimetype', which holds the MIME type
+	if head.len() > 38 && &head[30..38] == b"mimetype" {
+		let content = &head[38..head.len().min(38 + 128)];
+		for file_type in [&ODT, &ODS, &ODP].iter() {
+			if content.starts_with(file_type.mime_types[0].as_bytes()) {
+				return Ok(file_type);
+			}
+		}
+	}
+
+	let names = match read_zip_names(input)? {
+		Some(names) => names,
+		None => {
+			// Without a central directory, only the name of the first member is known
+			debug.logln(1, "[sniff_zip] Unable to find the central directory of the ZIP file.");
+			let length = if head.len() >= 30 { head[26] as usize | (head[27] as usize) << 8 } else { 0 };
+			vec![String::from_utf8_lossy(&head[30.min(head.len())..(30 + length).min(head.len())]).into_owned()]
+		},
+	};
+	debug.logln(2, format!("[sniff_zip] The ZIP file has {} members.", names.len()));
+	let has_prefix = |prefix: &str| names.iter().any(|name| name.starts_with(prefix));
+	if has_prefix("[Content_Types].xml") || has_prefix("_rels/") {
+		if has_prefix("word/") {
+			return Ok(&DOCX);
+		}
+		if has_prefix("xl/") {
+			return Ok(&XLSX);
+		}
+		if has_prefix("ppt/") {
+			return Ok(&PPTX);
+		}
+		if has_prefix("visio/") {
+			return Ok(&VSDX);
+		}
+	}
+	Ok(&ZIP)
+}
+
+/// A member of a ZIP file, as listed in its central directory.
+struct ZipMember {
+	name: String,
+	/// The compression method (0 for stored, 8 for deflated).
+	method: usize,
+	compressed_size: u64,
+	/// The offset of the local file header.
+	offset: u64,
+}
+
+/// Reads the names of all members of a ZIP file from its central directory.
+/// Returns `None` if the central directory cannot be found.
+fn read_zip_names(input: &mut dyn ReadSeek) -> Result<Option<Vec<String>>, Error> {
+	Ok(read_zip_directory(input)?.map(|members| members.into_iter().map(|member| member.name).collect()))
+}
+
+/// Reads the central directory of a ZIP file. Returns `None` if it cannot be found.
+fn read_zip_directory(input: &mut dyn ReadSeek) -> Result<Option<Vec<ZipMember>>, Error> {
+	let size = input.seek(SeekFrom::End(0))?;
+	let tail_size = size.min(ZIP_TAIL_SIZE);
+	input.seek(SeekFrom::Start(size - tail_size))?;
+	let mut tail: Vec<u8> = Vec::new();
+	input.take(tail_size).read_to_end(&mut tail)?;
+
+	// The end of central directory record is followed by a comment of variable length, so search backwards
+	let index = match (0..tail.len().saturating_sub(21)).rev().find(|&index| tail[index..].starts_with(b"PK\x05\x06")) {
+		Some(index) => index,
+		None => return Ok(None),
+	};
+	let directory_size = u32_at(&tail, index + 12);
+	let directory_offset = u32_at(&tail, index + 16) as u64;
+	if directory_offset + directory_size as u64 > size || directory_size > 0x100_0000 {
+		return Ok(None);
+	}
+
+	input.seek(SeekFrom::Start(directory_offset))?;
+	let mut directory = vec![0u8; directory_size];
+	input.read_exact(&mut directory)?;
+	let mut members = Vec::new();
+	let mut offset = 0;
+	while offset + 46 <= directory.len() && directory[offset..].starts_with(b"PK\x01\x02") {
+		let name_length = u16_at(&directory, offset + 28);
+		let extra_length = u16_at(&directory, offset + 30);
+		let comment_length = u16_at(&directory, offset + 32);
+		let name_end = (offset + 46 + name_length).min(directory.len());
+		members.push(ZipMember {
+			name: String::from_utf8_lossy(&directory[offset + 46..name_end]).into_owned(),
+			method: u16_at(&directory, offset + 10),
+			compressed_size: u32_at(&directory, offset + 20) as u64,
+			offset: u32_at(&directory, offset + 42) as u64,
+		});
+		offset += 46 + name_length + extra_length + comment_length;
+	}
+	Ok(Some(members))
+}
+
+/// Reads the (stored or deflated) member of a ZIP file whose name matches, up to `MAX_ZIP_MEMBER_SIZE` bytes.
+/// Returns the name and content of the first matching member, or `None` if there is none (or if the central directory cannot be found).
+pub fn read_zip_member(input: &mut dyn ReadSeek, matches: impl Fn(&str) -> bool) -> Result<Option<(String, Vec<u8>)>, Error> {
+	let member = match read_zip_directory(input)?.and_then(|members| members.into_iter().find(|member| matches(&member.name))) {
+		Some(member) => member,
+		None => return Ok(None),
+	};
+	input.seek(SeekFrom::Start(member.offset))?;
+	let mut header = [0u8; 30];
+	input.read_exact(&mut header)?;
+	if !header.starts_with(b"PK\x03\x04") {
+		return Err(Error::new(ErrorKind::InvalidData, format!("The local header of the ZIP member '{}' is missing", member.name)));
+	}
+	input.seek(SeekFrom::Current((u16_at(&header, 26) + u16_at(&header, 28)) as i64))?;
+
+	let compressed = input.take(member.compressed_size);
+	let mut data: Vec<u8> = Vec::new();
+	match member.method {
+		0 => compressed.take(MAX_ZIP_MEMBER_SIZE).read_to_end(&mut data)?,
+		8 => Decoder::new(compressed).take(MAX_ZIP_MEMBER_SIZE).read_to_end(&mut data)?,
+		method => return Err(Error::new(ErrorKind::InvalidData, format!("The ZIP member '{}' is compressed with the unsupported method {}", member.name, method))),
+	};
+	Ok(Some((member.name, data)))
+}
+
+fn u16_at(data: &[u8], index: usize) -> usize {
+	data[index] as usize | (data[index + 1] as usize) << 8
+}
+
+fn u32_at(data: &[u8], index: usize) -> usize {
+	u16_at(data, index) | u16_at(data, index + 2) << 16
+}
+
+/// Determines whether an extension (with or without a leading dot) is commonly used for the given format.
+pub fn matches_extension(file_type: &FileType, extension: &str) -> bool {
+	let extension = extension.trim_start_matches('.').to_lowercase();
+	file_type.generic || file_type.extensions.iter().any(|candidate| *candidate == extension)
+}
+
+/// Determines whether a MIME type (possibly with parameters) is commonly used for the given format.
+/// The unspecific type `application/octet-stream` matches every format.
+pub fn matches_mime_type(file_type: &FileType, mime_type: &str) -> bool {
+	let mime_type = mime_type.split(';').next().unwrap_or("").trim().to_lowercase();
+	file_type.generic || mime_type == "application/octet-stream" || file_type.mime_types.iter().any(|candidate| *candidate == mime_type)
+}
+
+/// Compares the format of the content against the `MIMEType` and `File Extension` recorded in the metadata.
+/// Returns a description of every mismatch.
+pub fn check_metadata(file_type: &FileType, metadata: &Metadata) -> Vec<String> {
+	let mut result = Vec::new();
+	if let Some(ref mime_type) = metadata.mime_type {
+		if !mime_type.trim().is_empty() && !matches_mime_type(file_type, mime_type) {
+			result.push(format!("MIMEType '{}' does not match the content, which is a {}", mime_type, file_type));
+		}
+	}
+	if let Some(ref extension) = metadata.file_extension {
+		if !extension.trim().is_empty() && !matches_extension(file_type, extension.trim()) {
+			result.push(format!("File Extension '{}' does not match the content, which is a {}", extension, file_type));
+		}
+	}
+	result
+}
+
+/// Checks the extension of a file name against the format of the content.
+///
+/// Returns `None` if the extension matches, or if it is not known to belong to another format (such as `.xlsb` or `.2017`).
+/// Otherwise, returns the corrected path and a description of the mismatch: an extension that is commonly used for another format is replaced,
+/// and the preferred extension is appended to a file name without one (e.g. `Report` becomes `Report.pdf`).
+pub fn fix_extension(file_type: &FileType, path: &Path) -> Option<(PathBuf, String)> {
+	let file_name = path.file_name()?.to_string_lossy().into_owned();
+	let preferred = file_type.extensions.first()?;
+	let (fixed, description) = match path.extension().map(|extension| extension.to_string_lossy().into_owned()) {
+		None => (format!("{}.{}", file_name, preferred), format!("the file name has no extension, but the content is a {}", file_type)),
+		Some(ref extension) if matches_extension(file_type, extension) => return None,
+		Some(ref extension) if FILE_TYPES.iter().any(|other| other.extensions.iter().any(|candidate| *candidate == extension.to_lowercase())) => {
+			let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+			(format!("{}.{}", stem, preferred), format!("the extension '.{}' does not match the content, which is a {}", extension, file_type))
+		},
+		Some(_) => return None,
+	};
+	Some((path.with_file_name(fixed), description))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Cursor, stderr};
+	use super::super::cfbf::testing::build_cfbf;
+	use super::testing::build_zip;
+	use super::*;
+
+	fn sniff_bytes(data: &[u8]) -> Option<&'static str> {
+		let mut debug = Debug::new(stderr(), -1);
+		sniff(&mut Cursor::new(data), &mut debug).unwrap().map(|file_type| file_type.description)
+	}
+
+	#[test]
+	fn sniff_signatures() {
+		assert_eq!(sniff_bytes(b"%PDF-1.7\n"), Some("PDF document"));
+		assert_eq!(sniff_bytes(b"\r\n\r\n%PDF-1.4\n"), Some("PDF document"));
+		assert_eq!(sniff_bytes(b"{\\rtf1\\ansi"), Some("Rich Text Format document"));
+		assert_eq!(sniff_bytes(b"\x89PNG\r\n\x1A\n\0\0\0\x0DIHDR"), Some("PNG image"));
+		assert_eq!(sniff_bytes(b"GIF89a\x01\x00"), Some("GIF image"));
+		assert_eq!(sniff_bytes(b"BM\x46\0\0\0\0\0\0\0\x36\0\0\0"), Some("BMP image"));
+		assert_eq!(sniff_bytes(b"\xFF\xD8\xFF\xE0"), Some("JPEG image"));
+		assert_eq!(sniff_bytes(b""), None);
+		assert_eq!(sniff_bytes(b"\x00\x01\x02\x03"), None);
+	}
+
+	#[test]
+	fn sniff_texts() {
+		assert_eq!(sniff_bytes(b"Hello,\r\nworld\x0C"), Some("plain text"));
+		assert_eq!(sniff_bytes("\u{feff}Gr\u{fc}\u{df}e".as_bytes()), Some("plain text"));
+		// A multi-byte character may be cut off at the end of the inspected head
+		assert_eq!(sniff_bytes(&"\u{fc}".repeat(HEAD_SIZE).as_bytes()[..HEAD_SIZE + 1]), Some("plain text"));
+		assert_eq!(sniff_bytes(b"  <!DOCTYPE html><html>"), Some("HTML document"));
+		assert_eq!(sniff_bytes(b"<?xml version=\"1.0\"?><a/>"), Some("XML document"));
+		assert_eq!(sniff_bytes(b"From: a@example.com\r\nSubject: Hi\r\n  there\r\nDate: Thu, 1 Mar 2018\r\n\r\nBody"), Some("Internet mail message"));
+		assert_eq!(sniff_bytes(b"From: a@example.com\r\nSubject: Hi\r\n\r\nBody"), Some("plain text"));
+	}
+
+	#[test]
+	fn sniff_containers() {
+		assert_eq!(sniff_bytes(&build_cfbf(&[("WordDocument", b""), ("1Table", b"")])), Some("Word 97-2003 document"));
+		assert_eq!(sniff_bytes(&build_cfbf(&[("Workbook", b"")])), Some("Excel 97-2003 workbook"));
+		assert_eq!(sniff_bytes(&build_cfbf(&[("Contents", b"")])), None);
+
+		let content_types: (&str, &[u8]) = ("[Content_Types].xml", b"<Types/>");
+		assert_eq!(sniff_bytes(&build_zip(false, &[content_types, ("word/document.xml", b"")], true)), Some("Word document"));
+		assert_eq!(sniff_bytes(&build_zip(false, &[content_types, ("xl/workbook.bin", b"")], true)), Some("Excel workbook"));
+		assert_eq!(sniff_bytes(&build_zip(false, &[("mimetype", b"application/vnd.oasis.opendocument.text")], true)), Some("OpenDocument text"));
+		assert_eq!(sniff_bytes(&build_zip(false, &[("notes.txt", b"")], true)), Some("ZIP archive"));
+		// Without a central directory, only the first member is known
+		assert_eq!(sniff_bytes(&build_zip(false, &[content_types, ("ppt/presentation.xml", b"")], false)), Some("ZIP archive"));
+	}
+
+	#[test]
+	fn read_zip_members() {
+		let is_vba_project = |name: &str| name.ends_with("/vbaProject.bin");
+		for &deflate in [false, true].iter() {
+			let data = build_zip(deflate, &[("[Content_Types].xml", b"<Types/>"), ("word/vbaProject.bin", b"project data")], true);
+			let member = read_zip_member(&mut Cursor::new(&data[..]), is_vba_project).unwrap();
+			assert_eq!(member, Some(("word/vbaProject.bin".to_owned(), b"project data".to_vec())));
+			assert_eq!(read_zip_member(&mut Cursor::new(&data[..]), |name| name == "missing").unwrap(), None);
+		}
+		let data = build_zip(false, &[("word/vbaProject.bin", b"")], false);
+		assert_eq!(read_zip_member(&mut Cursor::new(&data[..]), is_vba_project).unwrap(), None);
+	}
+
+	#[test]
+	fn match_metadata() {
+		assert!(matches_extension(&DOCX, ".DOCM"));
+		assert!(!matches_extension(&DOCX, "doc"));
+		assert!(matches_extension(&ZIP, "jar"));
+		assert!(matches_mime_type(&PDF, "application/pdf; charset=binary"));
+		assert!(matches_mime_type(&PDF, "application/octet-stream"));
+		assert!(!matches_mime_type(&PDF, "image/png"));
+
+		let metadata = Metadata { mime_type: Some("image/png".to_owned()), file_extension: Some(" ".to_owned()), ..Metadata::default() };
+		assert_eq!(check_metadata(&PDF, &metadata), vec!["MIMEType 'image/png' does not match the content, which is a PDF document (application/pdf)".to_owned()]);
+	}
+
+	#[test]
+	fn fix_extensions() {
+		let fix = |file_type: &FileType, path: &str| fix_extension(file_type, Path::new(path)).map(|(path, _)| path.to_string_lossy().into_owned());
+		assert_eq!(fix(&DOCX, "dir/report.docx"), None);
+		assert_eq!(fix(&DOCX, "dir/report.doc"), Some("dir/report.docx".to_owned()));
+		assert_eq!(fix(&PNG, "dir/scan"), Some("dir/scan.png".to_owned()));
+		// Extensions that are not known to belong to another format are kept
+		assert_eq!(fix(&XLSX, "report.xlsb"), None);
+		assert_eq!(fix(&ODT, "x.ott"), None);
+		assert_eq!(fix(&PDF, "Report.2017"), None);
+		assert_eq!(fix(&TEXT, "notes.log"), None);
+	}
+}
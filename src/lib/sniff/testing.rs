@@ -0,0 +1,85 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Builds small ZIP files (and deflate streams) for the tests of the parsers that read from ZIP files.
+
+/// Builds a ZIP file with the given members, and a central directory unless `with_directory` is unset.
+/// If `deflate` is set, the members are stored as deflate streams (of uncompressed blocks).
+pub fn build_zip(deflate: bool, members: &[(&str, &[u8])], with_directory: bool) -> Vec<u8> {
+	let u16_le = |value: usize| (value as u16).to_le_bytes().to_vec();
+	let u32_le = |value: usize| (value as u32).to_le_bytes().to_vec();
+	let mut data = Vec::new();
+	let mut directory = Vec::new();
+	for &(name, content) in members {
+		let content = match deflate {
+			true => deflate_stored(content),
+			false => content.to_vec(),
+		};
+		let method = if deflate { 8 } else { 0 };
+		let offset = data.len();
+		data.extend_from_slice(b"PK\x03\x04");
+		data.extend(vec![0; 4]);
+		data.extend(u16_le(method));
+		data.extend(vec![0; 8]);
+		data.extend(u32_le(content.len()));
+		data.extend(u32_le(content.len()));
+		data.extend(u16_le(name.len()));
+		data.extend(u16_le(0));
+		data.extend_from_slice(name.as_bytes());
+		data.extend_from_slice(&content);
+
+		directory.extend_from_slice(b"PK\x01\x02");
+		directory.extend(vec![0; 6]);
+		directory.extend(u16_le(method));
+		directory.extend(vec![0; 8]);
+		directory.extend(u32_le(content.len()));
+		directory.extend(u32_le(content.len()));
+		directory.extend(u16_le(name.len()));
+		directory.extend(vec![0; 12]);
+		directory.extend(u32_le(offset));
+		directory.extend_from_slice(name.as_bytes());
+	}
+	if with_directory {
+		let offset = data.len();
+		data.extend(directory.iter().cloned());
+		data.extend_from_slice(b"PK\x05\x06");
+		data.extend(vec![0; 4]);
+		data.extend(u16_le(members.len()));
+		data.extend(u16_le(members.len()));
+		data.extend(u32_le(directory.len()));
+		data.extend(u32_le(offset));
+		data.extend(u16_le(0));
+	}
+	data
+}
+
+/// Encodes data as a deflate stream of uncompressed blocks.
+pub fn deflate_stored(data: &[u8]) -> Vec<u8> {
+	let mut result = Vec::new();
+	let mut blocks = data.chunks(0xFFFF).peekable();
+	if blocks.peek().is_none() {
+		return vec![0x01, 0x00, 0x00, 0xFF, 0xFF];
+	}
+	while let Some(block) = blocks.next() {
+		result.push(if blocks.peek().is_none() { 0x01 } else { 0x00 });
+		result.extend_from_slice(&(block.len() as u16).to_le_bytes());
+		result.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+		result.extend_from_slice(block);
+	}
+	result
+}
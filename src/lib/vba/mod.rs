@@ -0,0 +1,329 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use std::io::{Read, Seek, Write, Error, ErrorKind};
+use std::fs::{File, create_dir_all};
+use std::path::{Path, PathBuf};
+use super::cfbf::{Container, Object, ObjectType};
+use super::io::{Debug, decode_utf16le, sanitize_file_name, unique_path};
+use super::propset::decode_code_page;
+
+/// The size of a decompressed chunk (MS-OVBA 2.4.1).
+const CHUNK_SIZE: usize = 4096;
+
+// The records of the `dir` stream (MS-OVBA 2.3.4.2) that are evaluated
+const PROJECTCODEPAGE: u16 = 0x0003;
+const PROJECTNAME: u16 = 0x0004;
+const PROJECTVERSION: u16 = 0x0009;
+const MODULENAME: u16 = 0x0019;
+const MODULENAMEUNICODE: u16 = 0x0047;
+const MODULESTREAMNAME: u16 = 0x001A;
+const MODULEOFFSET: u16 = 0x0031;
+const MODULETYPEPROCEDURAL: u16 = 0x0021;
+const MODULETYPEDOCUMENT: u16 = 0x0022;
+const DIRTERMINATOR: u16 = 0x0010;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ModuleKind {
+	/// A standard module (exported as `.bas` file).
+	Procedural,
+	/// A document module (such as `ThisDocument` or `Sheet1`) or a class module (exported as `.cls` file).
+	Document,
+}
+
+/// A VBA project, as stored in a `VBA` storage.
+pub struct Project {
+	/// The path of the `VBA` storage, e.g. `/Macros/VBA` (Word) or `/_VBA_PROJECT_CUR/VBA` (Excel).
+	/// Projects of Office Open XML documents are prefixed with their ZIP member, e.g. `/word/vbaProject.bin!/VBA`.
+	pub path: String,
+	pub name: String,
+	/// The code page of the module sources.
+	pub code_page: u16,
+	pub modules: Vec<Module>,
+}
+
+pub struct Module {
+	pub name: String,
+	/// The name of the stream (in the `VBA` storage) that holds the module.
+	pub stream_name: String,
+	pub kind: ModuleKind,
+	/// The offset of the compressed source code in the module stream (the performance cache precedes it).
+	pub offset: usize,
+	/// The decompressed source code.
+	pub source: String,
+}
+
+impl Module {
+	/// Returns the file name under which the module is exported by the VBA editor (`<name>.bas` or `<name>.cls`).
+	pub fn file_name(&self) -> String {
+		let name = sanitize_file_name(&self.name);
+		match self.kind {
+			ModuleKind::Procedural => format!("{}.bas", name),
+			ModuleKind::Document => format!("{}.cls", name),
+		}
+	}
+}
+
+impl Project {
+	/// Writes the source code of all modules into the directory (which is created if necessary), and returns the paths of the written files.
+	/// Modules whose file names exist already are written as `<name> (2).bas` etc.
+	pub fn write_modules(&self, dir: &Path) -> Result<Vec<PathBuf>, Error> {
+		create_dir_all(dir)?;
+		let mut result = Vec::new();
+		for module in &self.modules {
+			let path = unique_path(dir, module.file_name());
+			File::create(&path)?.write_all(module.source.as_bytes())?;
+			result.push(path);
+		}
+		Ok(result)
+	}
+}
+
+/// Reads all VBA projects in a compound file, i.e. all storages named `VBA` that hold a `dir` stream.
+pub fn read_projects<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Vec<Project>, Error> where TFile: Read + Seek {
+	let root = container.get_root_object(debug)?;
+	let mut storages = Vec::new();
+	find_vba_storages(container, &root, "", &mut storages, debug)?;
+	let mut result = Vec::new();
+	for (path, storage) in storages {
+		debug.logln(1, format!("[read_projects] Reading VBA project in '{}' ...", path));
+		result.push(Project::read(container, &storage, &path, debug)?);
+	}
+	Ok(result)
+}
+
+fn find_vba_storages<TFile>(container: &mut Container<TFile>, storage: &Object, prefix: &str, result: &mut Vec<(String, Object)>, debug: &mut Debug) -> Result<(), Error> where TFile: Read + Seek {
+	for child in container.get_children(storage, debug)? {
+		if child.object_type != ObjectType::Storage {
+			continue;
+		}
+		let path = format!("{}/{}", prefix, child.name);
+		if child.name.eq_ignore_ascii_case("VBA") && container.get_children(&child, debug)?.iter().any(|stream| stream.name.eq_ignore_ascii_case("dir")) {
+			result.push((path.clone(), child.clone()));
+		}
+		find_vba_storages(container, &child, &path, result, debug)?;
+	}
+	Ok(())
+}
+
+impl Project {
+	/// Reads a VBA project from a `VBA` storage: parses its `dir` stream, and decompresses the source code of all modules.
+	pub fn read<TFile>(container: &mut Container<TFile>, storage: &Object, path: &str, debug: &mut Debug) -> Result<Project, Error> where TFile: Read + Seek {
+		let streams = container.get_children(storage, debug)?;
+		let read_stream = |container: &mut Container<TFile>, name: &str, debug: &mut Debug| -> Result<Vec<u8>, Error> {
+			let stream = streams.iter().find(|stream| stream.object_type == ObjectType::Stream && stream.name.eq_ignore_ascii_case(name))
+				.ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("The VBA storage has no stream '{}'", name)))?;
+			let mut data: Vec<u8> = Vec::new();
+			container.dump_stream(stream, &mut data, debug)?;
+			Ok(data)
+		};
+
+		let dir = decompress(&read_stream(container, "dir", debug)?)?;
+		let mut project = parse_dir(&dir)?;
+		project.path = path.to_owned();
+		debug.logln(1, format!("[Project::read] Project '{}' has {} modules (code page {}).", project.name, project.modules.len(), project.code_page));
+		for module in project.modules.iter_mut() {
+			let data = read_stream(container, &module.stream_name, debug)?;
+			if module.offset > data.len() {
+				return Err(Error::new(ErrorKind::InvalidData, format!("The source code offset of module '{}' is out of bounds", module.name)));
+			}
+			module.source = decode_code_page(&decompress(&data[module.offset..])?, project.code_page);
+		}
+		Ok(project)
+	}
+}
+
+/// Parses the (decompressed) `dir` stream, which describes the project and its modules.
+/// The sources of the modules are not read yet.
+fn parse_dir(data: &[u8]) -> Result<Project, Error> {
+	let mut project = Project { path: String::new(), name: String::new(), code_page: 1252, modules: Vec::new() };
+	let mut index = 0;
+	while index + 6 <= data.len() {
+		let id = data[index] as u16 | (data[index + 1] as u16) << 8;
+		let size = data[index + 2] as usize | (data[index + 3] as usize) << 8 | (data[index + 4] as usize) << 16 | (data[index + 5] as usize) << 24;
+		// The size of PROJECTVERSION does not include its major and minor version numbers
+		let size = if id == PROJECTVERSION { size + 2 } else { size };
+		let record = data.get(index + 6..index + 6 + size).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("The record 0x{:04X} of the dir stream is truncated", id)))?;
+		index += 6 + size;
+
+		let module = project.modules.last_mut();
+		match (id, module) {
+			(PROJECTCODEPAGE, _) if size >= 2 => project.code_page = record[0] as u16 | (record[1] as u16) << 8,
+			(PROJECTNAME, _) => project.name = decode_code_page(record, project.code_page),
+			(MODULENAME, _) => {
+				let name = decode_code_page(record, project.code_page);
+				project.modules.push(Module { name: name.clone(), stream_name: name, kind: ModuleKind::Procedural, offset: 0, source: String::new() });
+			},
+			(MODULENAMEUNICODE, Some(module)) => module.name = decode_utf16le(record),
+			(MODULESTREAMNAME, Some(module)) => module.stream_name = decode_code_page(record, project.code_page),
+			(MODULEOFFSET, Some(module)) if size >= 4 => module.offset = record[0] as usize | (record[1] as usize) << 8 | (record[2] as usize) << 16 | (record[3] as usize) << 24,
+			(MODULETYPEPROCEDURAL, Some(module)) => module.kind = ModuleKind::Procedural,
+			(MODULETYPEDOCUMENT, Some(module)) => module.kind = ModuleKind::Document,
+			(DIRTERMINATOR, _) => break,
+			_ => {},
+		}
+	}
+	Ok(project)
+}
+
+/// Decompresses a compressed container (MS-OVBA 2.4.1): a signature byte (1), followed by chunks
+/// that decompress to 4096 bytes each (except for the last one). A chunk is either stored raw, or consists of
+/// token sequences, each holding a flag byte and eight tokens: literal bytes, or copy tokens that refer back
+/// into the data decompressed so far within the chunk.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+	let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("Invalid compressed container: {}", message));
+	if data.first() != Some(&1) {
+		return Err(invalid("wrong signature"));
+	}
+	let mut result: Vec<u8> = Vec::new();
+	let mut index = 1;
+	while index + 2 <= data.len() {
+		let header = data[index] as usize | (data[index + 1] as usize) << 8;
+		let chunk_end = (index + 2 + (header & 0x0FFF) + 1).min(data.len());
+		let is_compressed = header & 0x8000 != 0;
+		index += 2;
+		let chunk_start = result.len();
+
+		if !is_compressed {
+			let end = (index + CHUNK_SIZE).min(data.len());
+			result.extend_from_slice(&data[index..end]);
+			index = end;
+			continue;
+		}
+		while index < chunk_end {
+			let flags = data[index];
+			index += 1;
+			for bit in 0..8 {
+				if index >= chunk_end {
+					break;
+				}
+				if flags & (1 << bit) == 0 {
+					result.push(data[index]);
+					index += 1;
+					continue;
+				}
+				if index + 2 > chunk_end {
+					return Err(invalid("truncated copy token"));
+				}
+				let token = data[index] as usize | (data[index + 1] as usize) << 8;
+				index += 2;
+				// The split between offset and length depends on the position within the chunk
+				let position = result.len() - chunk_start;
+				let bit_count = (16 - ((position.max(1) - 1) as u16).leading_zeros() as usize).max(4);
+				let length = (token & (0xFFFF >> bit_count)) + 3;
+				let offset = (token >> (16 - bit_count)) + 1;
+				if offset > position {
+					return Err(invalid("copy token refers before the start of the chunk"));
+				}
+				// The source and destination may overlap, so copy byte by byte
+				for _ in 0..length {
+					let byte = result[result.len() - offset];
+					result.push(byte);
+				}
+			}
+		}
+	}
+	Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env::temp_dir;
+	use std::fs::{read, remove_dir_all};
+	use std::io::{Cursor, stderr};
+	use super::super::cfbf::testing::build_cfbf;
+	use super::*;
+
+	fn hex(text: &str) -> Vec<u8> {
+		text.split_whitespace().map(|byte| u8::from_str_radix(byte, 16).unwrap()).collect()
+	}
+
+	/// A compressed container with a single uncompressed chunk.
+	fn stored(data: &[u8]) -> Vec<u8> {
+		let mut container = vec![1, 0xFF, 0x3F];
+		container.extend_from_slice(data);
+		container
+	}
+
+	fn record(id: u16, data: &[u8]) -> Vec<u8> {
+		let mut record = id.to_le_bytes().to_vec();
+		record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		record.extend_from_slice(data);
+		record
+	}
+
+	fn module_records(name: &str, kind: u16) -> Vec<u8> {
+		let mut records = record(MODULENAME, name.as_bytes());
+		records.extend(record(MODULESTREAMNAME, name.as_bytes()));
+		records.extend(record(MODULEOFFSET, &0u32.to_le_bytes()));
+		records.extend(record(kind, &[]));
+		records
+	}
+
+	#[test]
+	fn decompress_containers() {
+		// The examples of MS-OVBA 3.2
+		assert_eq!(decompress(&hex("01 19 B0 00 61 62 63 64 65 66 67 68 00 69 6A 6B 6C 6D 6E 6F 70 00 71 72 73 74 75 76 2E")).unwrap(), b"abcdefghijklmnopqrstuv.");
+		assert_eq!(decompress(&hex("01 2F B0 00 23 61 61 61 62 63 64 65 82 66 00 70 61 67 68 69 6A 01 38 08 61 6B 6C 00 30 6D 6E 6F 70 06 71 02 70 04 10 72 73 74 75 76 10 77 78 79 7A 00 3C")).unwrap(),
+			b"#aaabcdefaaaaghijaaaaaklaaamnopqaaaaaaaaaaaarstuvwxyzaaa");
+		// A literal, followed by a copy token of length 14 and offset 1
+		assert_eq!(decompress(&hex("01 03 B0 02 61 0B 00")).unwrap(), b"aaaaaaaaaaaaaaa");
+		assert_eq!(decompress(&stored(b"raw")).unwrap(), b"raw");
+
+		assert!(decompress(&hex("00 03 B0 02 61 0B 00")).is_err());
+		// A copy token must not refer before the start of the chunk
+		assert!(decompress(&hex("01 01 B0 01 00 00")).is_err());
+		assert!(decompress(&hex("01 02 B0 02 61 0B")).is_err());
+	}
+
+	#[test]
+	fn read_project() {
+		let mut dir = record(PROJECTCODEPAGE, &1252u16.to_le_bytes());
+		dir.extend(record(PROJECTNAME, b"Project"));
+		dir.extend(module_records("Module1", MODULETYPEPROCEDURAL));
+		dir.extend(record(MODULENAMEUNICODE, &[0x4D, 0, 0xF6, 0, 0x64, 0]));
+		dir.extend(module_records("A\\B", MODULETYPEDOCUMENT));
+		dir.extend(module_records("A:B", MODULETYPEDOCUMENT));
+		dir.extend(record(DIRTERMINATOR, &[]));
+		let data = build_cfbf(&[
+			("WordDocument", b""),
+			("Macros/VBA/dir", &stored(&dir)),
+			("Macros/VBA/Module1", &stored(b"Sub M()\r\nEnd Sub\r\n")),
+			("Macros/VBA/A\\B", &stored(b"' first")),
+			("Macros/VBA/A:B", &stored(b"' second")),
+		]);
+		let mut debug = Debug::new(stderr(), -1);
+		let mut container = Container::new(Cursor::new(data), &mut debug).unwrap();
+		let projects = read_projects(&mut container, &mut debug).unwrap();
+		assert_eq!(projects.len(), 1);
+		let project = &projects[0];
+		assert_eq!((project.path.as_str(), project.name.as_str(), project.code_page), ("/Macros/VBA", "Project", 1252));
+		let modules = project.modules.iter().map(|module| (module.name.as_str(), module.kind, module.source.as_str())).collect::<Vec<(&str, ModuleKind, &str)>>();
+		assert_eq!(modules, vec![
+			("M\u{f6}d", ModuleKind::Procedural, "Sub M()\r\nEnd Sub\r\n"),
+			("A\\B", ModuleKind::Document, "' first"),
+			("A:B", ModuleKind::Document, "' second"),
+		]);
+
+		// Modules with the same file name are not overwritten
+		let dir = temp_dir().join(format!("evrecovery-vba-{}", std::process::id()));
+		let paths = project.write_modules(&dir).unwrap();
+		assert_eq!(paths, vec![dir.join("M\u{f6}d.bas"), dir.join("A_B.cls"), dir.join("A_B (2).cls")]);
+		assert_eq!(read(&paths[2]).unwrap(), b"' second");
+		remove_dir_all(&dir).unwrap();
+	}
+}
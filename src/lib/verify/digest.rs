@@ -0,0 +1,240 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// Minimal implementations of the digest algorithms that may be used for the `Checksum` stream.
+// Also see: RFC 1952 (CRC-32), RFC 1321 (MD5), RFC 3174 (SHA-1)
+
+/// A digest algorithm that processes data incrementally.
+pub enum Digest {
+	Crc32(u32),
+	Md5(BlockState<[u32; 4]>),
+	Sha1(BlockState<[u32; 5]>),
+}
+
+/// The internal state of a block-based digest algorithm.
+pub struct BlockState<TState> {
+	state: TState,
+	buffer: Vec<u8>,
+	length: u64,
+}
+
+impl Digest {
+	pub fn crc32() -> Digest {
+		Digest::Crc32(0xFFFFFFFF)
+	}
+
+	pub fn md5() -> Digest {
+		Digest::Md5(BlockState { state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476], buffer: Vec::with_capacity(64), length: 0 })
+	}
+
+	pub fn sha1() -> Digest {
+		Digest::Sha1(BlockState { state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0], buffer: Vec::with_capacity(64), length: 0 })
+	}
+
+	pub fn update(&mut self, data: &[u8]) {
+		match *self {
+			Digest::Crc32(ref mut crc) => {
+				for &byte in data {
+					*crc ^= byte as u32;
+					for _ in 0..8 {
+						*crc = if *crc & 1 != 0 { (*crc >> 1) ^ 0xEDB88320 } else { *crc >> 1 };
+					}
+				}
+			},
+			Digest::Md5(ref mut block_state) => block_state.update(data, md5_block),
+			Digest::Sha1(ref mut block_state) => block_state.update(data, sha1_block),
+		}
+	}
+
+	/// Finalises the computation and returns the digest, in the byte order in which it is usually written.
+	pub fn finish(self) -> Vec<u8> {
+		match self {
+			Digest::Crc32(crc) => (!crc).to_le_bytes().to_vec(),
+			Digest::Md5(mut block_state) => {
+				let length = block_state.length.wrapping_mul(8);
+				block_state.pad(&length.to_le_bytes(), md5_block);
+				block_state.state.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+			},
+			Digest::Sha1(mut block_state) => {
+				let length = block_state.length.wrapping_mul(8);
+				block_state.pad(&length.to_be_bytes(), sha1_block);
+				block_state.state.iter().flat_map(|word| word.to_be_bytes().to_vec()).collect()
+			},
+		}
+	}
+}
+
+impl<TState> BlockState<TState> {
+	fn update(&mut self, mut data: &[u8], process_block: fn(&mut TState, &[u8])) {
+		self.length = self.length.wrapping_add(data.len() as u64);
+
+		// Complete a partially filled block first
+		if !self.buffer.is_empty() {
+			let num_bytes = (64 - self.buffer.len()).min(data.len());
+			self.buffer.extend_from_slice(&data[..num_bytes]);
+			data = &data[num_bytes..];
+			if self.buffer.len() < 64 {
+				return;
+			}
+			process_block(&mut self.state, &self.buffer);
+			self.buffer.clear();
+		}
+
+		// Then process all complete blocks directly, and keep the remainder for later
+		let mut blocks = data.chunks_exact(64);
+		for block in &mut blocks {
+			process_block(&mut self.state, block);
+		}
+		self.buffer.extend_from_slice(blocks.remainder());
+	}
+
+	/// Appends the padding and the encoded message length, and processes the final block(s).
+	fn pad(&mut self, encoded_length: &[u8; 8], process_block: fn(&mut TState, &[u8])) {
+		self.buffer.push(0x80);
+		if self.buffer.len() > 56 {
+			self.buffer.resize(64, 0);
+			process_block(&mut self.state, &self.buffer);
+			self.buffer.clear();
+		}
+		self.buffer.resize(56, 0);
+		self.buffer.extend_from_slice(encoded_length);
+		process_block(&mut self.state, &self.buffer);
+		self.buffer.clear();
+	}
+}
+
+const MD5_SHIFTS: [u32; 64] = [
+	7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+	5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+	4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+	6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+fn md5_block(state: &mut [u32; 4], block: &[u8]) {
+	let mut words = [0u32; 16];
+	for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+		*word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+	}
+
+	let [mut a, mut b, mut c, mut d] = *state;
+	for (i, &shift) in MD5_SHIFTS.iter().enumerate() {
+		let (f, g) = match i / 16 {
+			0 => ((b & c) | (!b & d), i),
+			1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+			2 => (b ^ c ^ d, (3 * i + 5) % 16),
+			_ => (c ^ (b | !d), (7 * i) % 16),
+		};
+		// The constants are the integer parts of 2^32 * abs(sin(i + 1))
+		let k = ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32;
+		let rotated = a.wrapping_add(f).wrapping_add(k).wrapping_add(words[g]).rotate_left(shift);
+		a = d;
+		d = c;
+		c = b;
+		b = b.wrapping_add(rotated);
+	}
+
+	state[0] = state[0].wrapping_add(a);
+	state[1] = state[1].wrapping_add(b);
+	state[2] = state[2].wrapping_add(c);
+	state[3] = state[3].wrapping_add(d);
+}
+
+fn sha1_block(state: &mut [u32; 5], block: &[u8]) {
+	let mut words = [0u32; 80];
+	for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+		*word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+	}
+	for i in 16..80 {
+		words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+	}
+
+	let [mut a, mut b, mut c, mut d, mut e] = *state;
+	for (i, word) in words.iter().enumerate() {
+		let (f, k) = match i / 20 {
+			0 => ((b & c) | (!b & d), 0x5A827999),
+			1 => (b ^ c ^ d, 0x6ED9EBA1),
+			2 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+			_ => (b ^ c ^ d, 0xCA62C1D6),
+		};
+		let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+		e = d;
+		d = c;
+		c = b.rotate_left(30);
+		b = a;
+		a = temp;
+	}
+
+	state[0] = state[0].wrapping_add(a);
+	state[1] = state[1].wrapping_add(b);
+	state[2] = state[2].wrapping_add(c);
+	state[3] = state[3].wrapping_add(d);
+	state[4] = state[4].wrapping_add(e);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Digest;
+
+	fn hex(digest: Digest, data: &[u8]) -> String {
+		let mut digest = digest;
+		digest.update(data);
+		digest.finish().iter().map(|byte| format!("{:02x}", byte)).collect()
+	}
+
+	#[test]
+	fn crc32_check_value() {
+		// The check value of CRC-32/ISO-HDLC, written in Little Endian byte order
+		assert_eq!(hex(Digest::crc32(), b""), "00000000");
+		assert_eq!(hex(Digest::crc32(), b"123456789"), "2639f4cb");
+		assert_eq!(hex(Digest::crc32(), b"The quick brown fox jumps over the lazy dog"), "39a34f41");
+	}
+
+	#[test]
+	fn md5_rfc1321_test_suite() {
+		assert_eq!(hex(Digest::md5(), b""), "d41d8cd98f00b204e9800998ecf8427e");
+		assert_eq!(hex(Digest::md5(), b"a"), "0cc175b9c0f1b6a831c399e269772661");
+		assert_eq!(hex(Digest::md5(), b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+		assert_eq!(hex(Digest::md5(), b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+		assert_eq!(hex(Digest::md5(), b"abcdefghijklmnopqrstuvwxyz"), "c3fcd3d76192e4007dfb496cca67e13b");
+		assert_eq!(hex(Digest::md5(), b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"), "d174ab98d277d9f5a5611c2c9f419d9f");
+		assert_eq!(hex(Digest::md5(), b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"), "57edf4a22be3c955ac49da2e2107b67a");
+	}
+
+	#[test]
+	fn sha1_fips180_test_vectors() {
+		assert_eq!(hex(Digest::sha1(), b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+		assert_eq!(hex(Digest::sha1(), b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+		assert_eq!(hex(Digest::sha1(), b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+		assert_eq!(hex(Digest::sha1(), &[b'a'; 1_000_000]), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+	}
+
+	#[test]
+	fn incremental_updates() {
+		// Feeding the data in pieces that straddle block boundaries yields the same digest
+		let data = (0..1000u32).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+		for digest in &[Digest::crc32, Digest::md5, Digest::sha1] {
+			let mut incremental = digest();
+			for chunk in data.chunks(37) {
+				incremental.update(chunk);
+			}
+			let mut whole = digest();
+			whole.update(&data);
+			assert_eq!(incremental.finish(), whole.finish());
+		}
+	}
+}
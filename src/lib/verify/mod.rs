@@ -0,0 +1,274 @@
+/*
+evrecovery library & toolset
+Copyright (C) 2018 Steve Muller <steve.muller@outlook.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+mod digest;
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{Read, Seek, Write, Error, ErrorKind};
+use self::digest::Digest;
+use super::cfbf::Container;
+use super::io::Debug;
+use super::metadata::{read_stream, decode_size, decode_string};
+
+/// The properties of an archived item against which restored content can be verified.
+pub struct Expectation {
+	/// The content of `/User Information/User Archivable Item/OriginalSize`, if present and decodable.
+	pub original_size: Option<u64>,
+	/// The content of `/LargeFile/LargeFileSize`, if present and decodable.
+	pub large_file_size: Option<u64>,
+	/// The content of `/Checksum`.
+	pub checksum: Checksum,
+}
+
+/// The interpretation of the `/Checksum` stream.
+/// Its format is undocumented; it is interpreted by its length (or the length of the hexadecimal string it contains).
+#[derive(Clone, PartialEq)]
+pub enum Checksum {
+	Crc32(Vec<u8>),
+	Md5(Vec<u8>),
+	Sha1(Vec<u8>),
+	/// The checksum is present, but its format is not known.
+	Unknown(Vec<u8>),
+	/// There is no checksum.
+	None,
+}
+
+/// An error indicating that restored content does not match the properties of the archived item.
+/// It is wrapped in an `std::io::Error` of kind `InvalidData`; use `Mismatch::from_error` to tell it apart from other errors.
+#[derive(Debug)]
+pub struct Mismatch {
+	/// The name of the property that does not match.
+	pub property: String,
+	pub expected: String,
+	pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} mismatch: expected {}, but restored content has {}", self.property, self.expected, self.actual)
+	}
+}
+
+impl StdError for Mismatch { }
+
+impl Mismatch {
+	/// Retrieves the verification mismatch wrapped in the given error, if it is one.
+	pub fn from_error(error: &Error) -> Option<&Mismatch> {
+		error.get_ref().and_then(|inner| inner.downcast_ref::<Mismatch>())
+	}
+
+	fn into_error(self) -> Error {
+		Error::new(ErrorKind::InvalidData, self)
+	}
+}
+
+impl Expectation {
+	/// Reads the size and checksum properties from an Enterprise Vault payload.
+	pub fn read<TFile>(container: &mut Container<TFile>, debug: &mut Debug) -> Result<Expectation, Error> where TFile: Read + Seek {
+		let original_size = read_size(container, "OriginalSize", debug)?;
+		let large_file_size = read_size(container, "LargeFileSize", debug)?;
+		let checksum = match read_stream(container, "Checksum", debug)? {
+			Some(data) => Checksum::decode(data),
+			None => Checksum::None,
+		};
+		Ok(Expectation { original_size, large_file_size, checksum })
+	}
+}
+
+impl Default for Expectation {
+	/// An expectation without any properties, against which everything verifies.
+	fn default() -> Expectation {
+		Expectation { original_size: None, large_file_size: None, checksum: Checksum::None }
+	}
+}
+
+/// Reads a size property, which is either stored as an integer or as a string of digits.
+fn read_size<TFile>(container: &mut Container<TFile>, name: &str, debug: &mut Debug) -> Result<Option<u64>, Error> where TFile: Read + Seek {
+	match read_stream(container, name, debug)? {
+		Some(data) => {
+			let size = decode_size(&data);
+			match size {
+				Some(size) => debug.logln(1, format!("[read_size] {} is {}.", name, size)),
+				None => debug.logln(1, format!("[read_size] {} has an unknown format ({} bytes), ignoring it.", name, data.len())),
+			}
+			Ok(size)
+		},
+		None => Ok(None),
+	}
+}
+
+impl Checksum {
+	pub fn decode(data: Vec<u8>) -> Checksum {
+		// The checksum may also be stored as a hexadecimal string
+		let data = match decode_string(&data).and_then(|string| decode_hex(string.trim())) {
+			Some(bytes) => bytes,
+			None => data,
+		};
+		match data.len() {
+			4 => Checksum::Crc32(data),
+			16 => Checksum::Md5(data),
+			20 => Checksum::Sha1(data),
+			_ => Checksum::Unknown(data),
+		}
+	}
+
+	fn digest(&self) -> Option<Digest> {
+		match *self {
+			Checksum::Crc32(_) => Some(Digest::crc32()),
+			Checksum::Md5(_) => Some(Digest::md5()),
+			Checksum::Sha1(_) => Some(Digest::sha1()),
+			Checksum::Unknown(_) | Checksum::None => None,
+		}
+	}
+
+	fn name(&self) -> &'static str {
+		match *self {
+			Checksum::Crc32(_) => "CRC-32",
+			Checksum::Md5(_) => "MD5",
+			Checksum::Sha1(_) => "SHA-1",
+			Checksum::Unknown(_) => "unknown",
+			Checksum::None => "none",
+		}
+	}
+}
+
+fn decode_hex(string: &str) -> Option<Vec<u8>> {
+	if string.is_empty() || !string.len().is_multiple_of(2) || !string.chars().all(|c| c.is_ascii_hexdigit()) {
+		return None;
+	}
+	(0..string.len()).step_by(2).map(|i| u8::from_str_radix(&string[i..i + 2], 16).ok()).collect()
+}
+
+fn encode_hex(data: &[u8]) -> String {
+	data.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/// A writer that passes all data on to an inner writer, while counting the bytes and computing the checksum.
+/// Call `finish` after all data has been written in order to verify it.
+pub struct Verifier<TWrite: Write> {
+	inner: TWrite,
+	expectation: Expectation,
+	digest: Option<Digest>,
+	num_bytes: u64,
+}
+
+impl<TWrite> Verifier<TWrite> where TWrite: Write {
+	/// Creates a verifier that checks the sizes, and, if `verify_checksum` is set, also the checksum (if its format is known).
+	pub fn new(inner: TWrite, expectation: Expectation, verify_checksum: bool) -> Verifier<TWrite> {
+		let digest = if verify_checksum { expectation.checksum.digest() } else { None };
+		Verifier { inner, expectation, digest, num_bytes: 0 }
+	}
+
+	/// Compares the written data against the expected properties.
+	/// Returns the number of written bytes, or a `Mismatch` error (wrapped in an `std::io::Error`).
+	pub fn finish(mut self, is_outsourced: bool, debug: &mut Debug) -> Result<u64, Error> {
+		self.inner.flush()?;
+
+		// Embedded content is described by 'OriginalSize', out-sourced content by 'LargeFileSize'
+		let (size_name, expected_size) = match is_outsourced {
+			true => ("LargeFileSize", self.expectation.large_file_size.or(self.expectation.original_size)),
+			false => ("OriginalSize", self.expectation.original_size),
+		};
+		match expected_size {
+			Some(expected_size) => {
+				debug.logln(1, format!("[finish] Comparing {} ({}) against restored size ({}) ...", size_name, expected_size, self.num_bytes));
+				if expected_size != self.num_bytes {
+					return Err(Mismatch { property: size_name.to_owned(), expected: format!("{} bytes", expected_size), actual: format!("{} bytes", self.num_bytes) }.into_error());
+				}
+			},
+			None => debug.logln(1, "[finish] No size to verify the restored content against."),
+		}
+
+		match self.digest {
+			Some(digest) => {
+				let name = self.expectation.checksum.name();
+				let actual = digest.finish();
+				let expected = match self.expectation.checksum {
+					Checksum::Crc32(ref expected) => {
+						// The byte order of a CRC-32 checksum is ambiguous; accept both
+						let mut reversed = expected.clone();
+						reversed.reverse();
+						if reversed == actual { reversed } else { expected.clone() }
+					},
+					Checksum::Md5(ref expected) | Checksum::Sha1(ref expected) => expected.clone(),
+					Checksum::Unknown(_) | Checksum::None => unreachable!(),
+				};
+				debug.logln(1, format!("[finish] Comparing {} checksum {} against restored content ({}) ...", name, encode_hex(&expected), encode_hex(&actual)));
+				if expected != actual {
+					return Err(Mismatch { property: format!("Checksum ({})", name), expected: encode_hex(&expected), actual: encode_hex(&actual) }.into_error());
+				}
+			},
+			None => debug.logln(1, format!("[finish] No checksum to verify the restored content against (format: {}).", self.expectation.checksum.name())),
+		}
+
+		Ok(self.num_bytes)
+	}
+}
+
+impl<TWrite> Write for Verifier<TWrite> where TWrite: Write {
+	fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+		let num_bytes = self.inner.write(buf)?;
+		self.num_bytes += num_bytes as u64;
+		if let Some(ref mut digest) = self.digest {
+			digest.update(&buf[..num_bytes]);
+		}
+		Ok(num_bytes)
+	}
+
+	fn flush(&mut self) -> Result<(), Error> {
+		self.inner.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Write, sink, stderr};
+	use super::{Checksum, Expectation, Mismatch, Verifier};
+	use super::super::io::Debug;
+	use super::super::io::testing::utf16;
+
+	fn verify(data: &[u8], expectation: Expectation) -> Result<u64, String> {
+		let mut verifier = Verifier::new(sink(), expectation, true);
+		verifier.write_all(data).unwrap();
+		verifier.finish(false, &mut Debug::new(stderr(), -1)).map_err(|e| Mismatch::from_error(&e).unwrap().property.clone())
+	}
+
+	#[test]
+	fn decode_checksum() {
+		assert!(Checksum::decode(vec![0; 4]) == Checksum::Crc32(vec![0; 4]));
+		assert!(Checksum::decode(vec![0; 16]) == Checksum::Md5(vec![0; 16]));
+		assert!(Checksum::decode(vec![0; 20]) == Checksum::Sha1(vec![0; 20]));
+		assert!(Checksum::decode(vec![0; 7]) == Checksum::Unknown(vec![0; 7]));
+		// A hexadecimal string in the Enterprise Vault string format
+		let hex = utf16("CBF43926");
+		let mut data = (hex.len() as u32).to_le_bytes().to_vec();
+		data.extend_from_slice(&hex);
+		assert!(Checksum::decode(data) == Checksum::Crc32(vec![0xCB, 0xF4, 0x39, 0x26]));
+	}
+
+	#[test]
+	fn verify_size_and_checksum() {
+		let expectation = |checksum: Vec<u8>| Expectation { original_size: Some(9), large_file_size: None, checksum: Checksum::decode(checksum) };
+		// CRC-32 checksums are accepted in both byte orders
+		assert_eq!(verify(b"123456789", expectation(vec![0x26, 0x39, 0xF4, 0xCB])), Ok(9));
+		assert_eq!(verify(b"123456789", expectation(vec![0xCB, 0xF4, 0x39, 0x26])), Ok(9));
+		assert_eq!(verify(b"123456780", expectation(vec![0xCB, 0xF4, 0x39, 0x26])), Err("Checksum (CRC-32)".to_owned()));
+		assert_eq!(verify(b"12345678", expectation(vec![0xCB, 0xF4, 0x39, 0x26])), Err("OriginalSize".to_owned()));
+	}
+}